@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+
+use super::AudioSink;
+
+/// Sink storing the samples in memory, mainly for the tests. Clones
+/// share the same buffer so the samples can be retrieved after the
+/// sink has been handed over to the SPU.
+#[derive(Clone)]
+pub struct MemorySink {
+    samples: Arc<Mutex<Vec<(i16, i16)>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink {
+            samples: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Return the samples received since the last call
+    pub fn take(&self) -> Vec<(i16, i16)> {
+        std::mem::take(&mut *self.samples.lock().unwrap())
+    }
+}
+
+impl AudioSink for MemorySink {
+    fn push_sample(&mut self, sample: (i16, i16)) {
+        self.samples.lock().unwrap().push(sample);
+    }
+}
+
+#[test]
+fn shared_buffer() {
+    let sink = MemorySink::new();
+
+    let mut output: Box<dyn AudioSink> = Box::new(sink.clone());
+
+    output.push_sample((1, -1));
+    output.push_sample((2, -2));
+
+    assert_eq!(sink.take(), vec![(1, -1), (2, -2)]);
+    assert!(sink.take().is_empty());
+}
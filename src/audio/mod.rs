@@ -0,0 +1,61 @@
+//! Audio output. The SPU pushes its 44.1kHz stereo samples into an
+//! `AudioSink` selected at startup.
+
+use std::io::{Error, ErrorKind, Result};
+
+pub use self::memory::MemorySink;
+pub use self::wav::WavSink;
+#[cfg(feature = "realtime-audio")]
+pub use self::realtime::RealtimeSink;
+
+pub mod memory;
+pub mod wav;
+#[cfg(feature = "realtime-audio")]
+pub mod realtime;
+
+/// Sample rate of the SPU output in Hz
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Destination of the SPU output
+pub trait AudioSink {
+    /// Called for every output sample, left channel first
+    fn push_sample(&mut self, sample: (i16, i16));
+}
+
+/// Sink discarding all the samples
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn push_sample(&mut self, _: (i16, i16)) {
+    }
+}
+
+/// Open the sink described by `spec`: "null", "wav:<path>" or, if
+/// the `realtime-audio` feature is enabled, "realtime"
+pub fn open(spec: &str) -> Result<Box<dyn AudioSink>> {
+    if let Some(path) = spec.strip_prefix("wav:") {
+        return Ok(Box::new(WavSink::create(path)?));
+    }
+
+    match spec {
+        "null" => Ok(Box::new(NullSink)),
+        #[cfg(feature = "realtime-audio")]
+        "realtime" => Ok(Box::new(RealtimeSink::new()?)),
+        _ => Err(Error::new(ErrorKind::InvalidInput,
+                            format!("Unknown audio output '{}'", spec))),
+    }
+}
+
+#[test]
+fn open_spec() {
+    assert!(open("null").is_ok());
+    assert!(open("wav").is_err());
+    assert!(open("speakers").is_err());
+
+    let path = std::env::temp_dir().join(format!("rpsx_open_spec-{}.wav", std::process::id()));
+
+    assert!(open(&format!("wav:{}", path.display())).is_ok());
+    assert!(path.exists());
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::{AudioSink, SAMPLE_RATE};
+
+/// Number of samples buffered locally before they're handed over to
+/// the audio thread
+const BATCH_SIZE: usize = 512;
+
+/// Maximum latency, about 200ms. The emulator isn't throttled on the
+/// audio so the oldest samples are dropped when it runs too fast.
+const QUEUE_MAX: usize = SAMPLE_RATE as usize / 5;
+
+/// Sink playing the samples on the default output device
+pub struct RealtimeSink {
+    /// Samples waiting to be played, shared with the audio thread
+    queue: Arc<Mutex<VecDeque<(i16, i16)>>>,
+    batch: Vec<(i16, i16)>,
+    /// The playback stops when the stream is dropped
+    _stream: cpal::Stream,
+}
+
+impl RealtimeSink {
+    pub fn new() -> Result<RealtimeSink> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "No audio output device"))?;
+
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(SAMPLE_RATE),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let output = queue.clone();
+
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
+                let mut queue = output.lock().unwrap();
+
+                for frame in data.chunks_mut(2) {
+                    // Silence on underrun
+                    let (left, right) = queue.pop_front().unwrap_or((0, 0));
+
+                    frame[0] = left;
+                    frame[1] = right;
+                }
+            },
+            |e| warn!("Audio stream error: {}", e),
+            None).map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+        stream.play().map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+        Ok(RealtimeSink {
+            queue,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            _stream: stream,
+        })
+    }
+}
+
+impl AudioSink for RealtimeSink {
+    fn push_sample(&mut self, sample: (i16, i16)) {
+        self.batch.push(sample);
+
+        if self.batch.len() < BATCH_SIZE {
+            return;
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+
+        queue.extend(self.batch.drain(..));
+
+        let excess = queue.len().saturating_sub(QUEUE_MAX);
+
+        queue.drain(..excess);
+    }
+}
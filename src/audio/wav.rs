@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{AudioSink, SAMPLE_RATE};
+
+/// Size of the RIFF header preceding the sample data
+const HEADER_SIZE: u32 = 44;
+
+/// Largest amount of sample data whose RIFF chunk size still fits in
+/// 32 bits, rounded down to a whole frame. That's a bit more than 6
+/// hours of audio.
+const DATA_SIZE_MAX: u32 = (u32::MAX - (HEADER_SIZE - 8)) & !3;
+
+/// Sink writing a 16bit stereo WAV file. The sizes in the header are
+/// filled once the sink is finished or dropped.
+pub struct WavSink<W: Write + Seek> {
+    /// `None` once the file has been finished
+    writer: Option<W>,
+    /// Number of bytes of sample data written
+    data_size: u32,
+    /// Set once `DATA_SIZE_MAX` has been reached, the following
+    /// samples are dropped
+    full: bool,
+}
+
+impl WavSink<BufWriter<File>> {
+    /// Create the WAV file at `path`, truncating any existing file
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<WavSink<BufWriter<File>>> {
+        let file = File::create(path)?;
+
+        WavSink::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W) -> Result<WavSink<W>> {
+        write_header(&mut writer, 0)?;
+
+        Ok(WavSink {
+            writer: Some(writer),
+            data_size: 0,
+            full: false,
+        })
+    }
+
+    /// Fill the header And return the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        let mut writer = self.writer.take().unwrap();
+
+        Self::finalize(&mut writer, self.data_size)?;
+
+        Ok(writer)
+    }
+
+    fn finalize(writer: &mut W, data_size: u32) -> Result<()> {
+        writer.seek(SeekFrom::Start(0))?;
+        write_header(writer, data_size)?;
+        writer.seek(SeekFrom::End(0))?;
+
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn push_sample(&mut self, (left, right): (i16, i16)) {
+        let writer = match self.writer.as_mut() {
+            Some(w) => w,
+            None => return,
+        };
+
+        if self.data_size > DATA_SIZE_MAX - 4 {
+            if !self.full {
+                warn!("WAV file reached the RIFF size limit, stopping");
+                self.full = true;
+            }
+
+            return;
+        }
+
+        let mut frame = [0; 4];
+
+        frame[..2].copy_from_slice(&left.to_le_bytes());
+        frame[2..].copy_from_slice(&right.to_le_bytes());
+
+        match writer.write_all(&frame) {
+            Ok(()) => self.data_size += 4,
+            Err(e) => {
+                // Don't bring down the emulator, just stop recording
+                warn!("Couldn't write WAV sample, stopping: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            if let Err(e) = Self::finalize(&mut writer, self.data_size) {
+                warn!("Couldn't finalize WAV file: {}", e);
+            }
+        }
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W, data_size: u32) -> Result<()> {
+    let channels = 2u16;
+    let bits = 16u16;
+    let block_align = channels * bits / 8;
+
+    writer.write_all(b"RIFF")?;
+    let riff_size = (HEADER_SIZE - 8).checked_add(data_size).ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, "WAV data too large")
+    })?;
+
+    writer.write_all(&riff_size.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bits.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+#[test]
+fn wav_file() {
+    use std::io::Cursor;
+
+    let mut sink = WavSink::new(Cursor::new(Vec::new())).unwrap();
+
+    sink.push_sample((0x1234, -2));
+    sink.push_sample((1, 2));
+
+    let wav = sink.finish().unwrap().into_inner();
+
+    assert_eq!(wav.len(), HEADER_SIZE as usize + 8);
+
+    let u32_at = |o: usize| u32::from_le_bytes([wav[o], wav[o + 1], wav[o + 2], wav[o + 3]]);
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(4), 36 + 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(24), 44_100);
+    assert_eq!(u32_at(28), 44_100 * 4);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40), 8);
+    assert_eq!(&wav[44..], &[0x34, 0x12, 0xfe, 0xff, 1, 0, 2, 0]);
+}
+
+#[test]
+fn riff_size_limit() {
+    use std::io::Cursor;
+
+    let mut sink = WavSink::new(Cursor::new(Vec::new())).unwrap();
+
+    // Pretend we've already written almost 4GB
+    sink.data_size = DATA_SIZE_MAX - 4;
+
+    sink.push_sample((1, 2));
+    // Dropped
+    sink.push_sample((3, 4));
+
+    let wav = sink.finish().unwrap().into_inner();
+
+    assert_eq!(&wav[HEADER_SIZE as usize..], &[1, 0, 2, 0]);
+
+    let u32_at = |o: usize| u32::from_le_bytes([wav[o], wav[o + 1], wav[o + 2], wav[o + 3]]);
+
+    assert_eq!(u32_at(4), 0xffff_fffc);
+    assert_eq!(u32_at(40), DATA_SIZE_MAX);
+
+    assert!(write_header(&mut Vec::new(), u32::MAX).is_err());
+}
@@ -6,16 +6,32 @@ use std::path::Path;
 use std::sync::Arc;
 
 use winit::dpi::LogicalSize;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent};
 use winit::event_loop::EventLoop;
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::WindowBuilder;
 
+use rust_playstation_emulator::audio;
 use rust_playstation_emulator::bios::Bios;
+use rust_playstation_emulator::cdrom::disc;
 use rust_playstation_emulator::cpu::Cpu;
 use rust_playstation_emulator::cpu::interconnect::Interconnect;
 use rust_playstation_emulator::gpu::Gpu;
 use rust_playstation_emulator::gpu::opengl::Renderer;
 use rust_playstation_emulator::memory::ram::Ram;
+use rust_playstation_emulator::pad_memcard::controller::{Button, Controller};
+use rust_playstation_emulator::pad_memcard::guncon::GunCon;
+use rust_playstation_emulator::pad_memcard::justifier::Justifier;
+use rust_playstation_emulator::pad_memcard::light_gun::{GunButton, LightGunHandle};
+use rust_playstation_emulator::pad_memcard::memory_card::MemoryCard;
+use rust_playstation_emulator::pad_memcard::mouse::{Mouse, MouseHandle};
+use rust_playstation_emulator::serial;
+
+/// Audio output used when none is given on the command line
+#[cfg(feature = "realtime-audio")]
+const DEFAULT_AUDIO: &str = "realtime";
+#[cfg(not(feature = "realtime-audio"))]
+const DEFAULT_AUDIO: &str = "null";
 
 fn main() {
     env_logger::builder()
@@ -24,9 +40,63 @@ fn main() {
 //        .default_format_timestamp(false)
         .init();
 
-    let bios_filepath = match env::args().nth(1) {
+    let usage = "usage: rpsx.exe [--audio null|wav:<file>|realtime] \
+                 [--memcard1 <file>] [--memcard2 <file>] \
+                 [--port2 mouse|guncon|justifier] \
+                 [--serial none|tcp:<addr>|tcp-listen:<addr>|file:<out>[,<in>]] \
+                 rom game";
+
+    let mut audio_spec = DEFAULT_AUDIO.to_string();
+    let mut memcards = [None, None];
+    let mut port2 = None;
+    let mut serial_spec = "none".to_string();
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+
+        let mut value = || value.clone()
+            .or_else(|| args.next())
+            .unwrap_or_else(|| panic!("{}", usage));
+
+        match name.as_str() {
+            "--audio" => audio_spec = value(),
+            "--memcard1" => memcards[0] = Some(value()),
+            "--memcard2" => memcards[1] = Some(value()),
+            "--port2" => port2 = Some(value()),
+            "--serial" => serial_spec = value(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+
+    let bios_filepath = match positional.next() {
         Some(x) => x,
-        None => panic!("usage: rpsx.exe rom game")
+        None => panic!("{}", usage)
+    };
+
+    // The disc is optional, without it the BIOS boots into the shell
+    let disc = positional.next().map(|path| {
+        match disc::open(&path) {
+            Ok(disc) => disc,
+            Err(e) => panic!("Couldn't load disc image {}: {}", path, e),
+        }
+    });
+
+    let audio_sink = match audio::open(&audio_spec) {
+        Ok(sink) => sink,
+        Err(e) => panic!("Couldn't open audio output {}: {}", audio_spec, e),
+    };
+
+    let serial_link = match serial::link::open(&serial_spec) {
+        Ok(link) => link,
+        Err(e) => panic!("Couldn't open serial link {}: {}", serial_spec, e),
     };
 
     let event_loop = EventLoop::new().unwrap();
@@ -45,14 +115,66 @@ fn main() {
     let ram = Ram::new();
     let gpu = Gpu::new(display);
 
-    let inter = Interconnect::new(
+    let mut inter = Interconnect::new(
         bios,
         ram,
         gpu,
+        disc,
     );
+    inter.set_audio_sink(audio_sink);
+    inter.set_serial_link(serial_link);
+
+    let pad = Controller::dual_shock();
+    let pad_handle = pad.handle();
+
+    inter.pad_memcard_mut().connect_pad(0, Box::new(pad));
+
+    // Optional pointing device in port 2, driven by the host mouse
+    let mut pointer = match port2.as_deref() {
+        None => Pointer::None,
+        Some("mouse") => {
+            let mouse = Mouse::new();
+            let handle = mouse.handle();
+
+            inter.pad_memcard_mut().connect_pad(1, Box::new(mouse));
+
+            Pointer::Mouse(handle, None)
+        }
+        Some("guncon") => {
+            let gun = GunCon::new();
+            let handle = gun.handle();
+
+            inter.pad_memcard_mut().connect_pad(1, Box::new(gun));
+
+            Pointer::LightGun(handle)
+        }
+        Some("justifier") => {
+            let gun = Justifier::new();
+            let handle = gun.handle();
+
+            inter.pad_memcard_mut().connect_pad(1, Box::new(gun));
+
+            Pointer::LightGun(handle)
+        }
+        Some(_) => panic!("{}", usage),
+    };
+
+    // The memory card images are created on first use
+    for (port, path) in memcards.iter().enumerate() {
+        if let Some(path) = path {
+            let card = match MemoryCard::open(path) {
+                Ok(card) => card,
+                Err(e) => panic!("Couldn't open memory card {}: {}", path, e),
+            };
+
+            inter.pad_memcard_mut().connect_memory_card(port, Box::new(card));
+        }
+    }
+
     let mut cpu = Cpu::new(inter);
 
-    let mut running = true;
+    // Left And right buttons of the host mouse
+    let mut mouse_buttons = (false, false);
 
     let _ = event_loop.run(move |event, target| {
         if let Event::WindowEvent {
@@ -62,11 +184,86 @@ fn main() {
         {
             match event {
                 WindowEvent::RedrawRequested => {
-                    while running {
-                    for i in 0..1_000_000 {
-                        cpu.run_next_instruction();
-                        // window.request_redraw()
+                    // Emulated time is driven by the CPU, the
+                    // peripherals are clocked by the scheduler
+                    cpu.run_frame();
+
+                    // There's no force feedback output yet, drain the
+                    // motor events so they don't pile up
+                    for rumble in pad_handle.take_rumble_events() {
+                        log::debug!("Rumble: small {}, large {}", rumble.small, rumble.large);
                     }
+
+                    window.request_redraw();
+                }
+                WindowEvent::KeyboardInput {
+                    event: KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                    ..
+                } => {
+                    let pressed = state == ElementState::Pressed;
+
+                    if key == KeyCode::KeyA {
+                        if pressed {
+                            pad_handle.toggle_analog();
+                        }
+                    } else if let Some(button) = key_to_button(key) {
+                        let mut input = pad_handle.input();
+
+                        input.set_button(button, pressed);
+                        pad_handle.set_input(input);
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let size = window.inner_size();
+
+                    match pointer {
+                        Pointer::Mouse(ref handle, ref mut last) => {
+                            if let Some((x, y)) = *last {
+                                handle.move_by((position.x - x) as i32,
+                                               (position.y - y) as i32);
+                            }
+
+                            *last = Some((position.x, position.y));
+                        }
+                        Pointer::LightGun(ref handle) => {
+                            handle.aim(Some((position.x as f32 / size.width as f32,
+                                             position.y as f32 / size.height as f32)));
+                        }
+                        Pointer::None => (),
+                    }
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    match pointer {
+                        Pointer::Mouse(_, ref mut last) => *last = None,
+                        Pointer::LightGun(ref handle) => handle.aim(None),
+                        Pointer::None => (),
+                    }
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let pressed = state == ElementState::Pressed;
+
+                    match pointer {
+                        Pointer::Mouse(ref handle, _) => {
+                            if let MouseButton::Left = button {
+                                mouse_buttons.0 = pressed;
+                            } else if let MouseButton::Right = button {
+                                mouse_buttons.1 = pressed;
+                            }
+
+                            handle.set_buttons(mouse_buttons.0, mouse_buttons.1);
+                        }
+                        Pointer::LightGun(ref handle) => match button {
+                            MouseButton::Left => handle.set_button(GunButton::Trigger, pressed),
+                            MouseButton::Right => handle.set_button(GunButton::A, pressed),
+                            MouseButton::Middle => handle.set_button(GunButton::B, pressed),
+                            _ => (),
+                        },
+                        Pointer::None => (),
                     }
                 }
                 WindowEvent::CloseRequested => target.exit(),
@@ -75,3 +272,40 @@ fn main() {
         }
     });
 }
+
+/// Device driven by the host mouse
+enum Pointer {
+    None,
+    /// Mouse And last cursor position in the window
+    Mouse(MouseHandle, Option<(f64, f64)>),
+    /// The gun aims at the cursor. Left click pulls the trigger, the
+    /// right And middle buttons are A And B on the GunCon, Start And
+    /// Back on the Justifier.
+    LightGun(LightGunHandle),
+}
+
+/// Keyboard mapping of the controller in port 1. A toggles the analog
+/// mode.
+fn key_to_button(key: KeyCode) -> Option<Button> {
+    let button = match key {
+        KeyCode::ArrowUp => Button::DUp,
+        KeyCode::ArrowDown => Button::DDown,
+        KeyCode::ArrowLeft => Button::DLeft,
+        KeyCode::ArrowRight => Button::DRight,
+        KeyCode::Enter => Button::Start,
+        KeyCode::Backspace => Button::Select,
+        KeyCode::KeyX => Button::Cross,
+        KeyCode::KeyC => Button::Circle,
+        KeyCode::KeyZ => Button::Square,
+        KeyCode::KeyS => Button::Triangle,
+        KeyCode::KeyQ => Button::L1,
+        KeyCode::KeyW => Button::L2,
+        KeyCode::KeyE => Button::R1,
+        KeyCode::KeyR => Button::R2,
+        KeyCode::KeyD => Button::L3,
+        KeyCode::KeyF => Button::R3,
+        _ => return None,
+    };
+
+    Some(button)
+}
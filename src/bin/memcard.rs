@@ -0,0 +1,125 @@
+extern crate env_logger;
+
+use std::env;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process;
+
+use rust_playstation_emulator::memcard::Card;
+use rust_playstation_emulator::memcard::save::SaveFile;
+
+const USAGE: &str = "usage: memcard_manager <command> [args]
+
+commands:
+  list <card>                 list the saves on the card
+  copy <card>:<slot> <save>   export a save to a .mcs or .psv file
+  copy <save> <card>          import a .mcs or .psv save
+  copy <card>:<slot> <card>   copy a save between two cards
+  delete <card> <slot>        delete a save
+  format <card>               erase the card, creating it if needed
+
+Cards are raw images (.mcr, .mcd...) or DexDrive images (.gme).";
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    let res = match args.as_slice() {
+        ["list", card] => list(card),
+        ["copy", from, to] => copy(from, to),
+        ["delete", card, slot] => delete(card, slot),
+        ["format", card] => format(card),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn list(path: &str) -> Result<()> {
+    let card = Card::load(path)?;
+
+    println!("slot blocks icon filename             title");
+
+    for entry in card.entries() {
+        println!("{:>4} {:>6} {:>4} {:<20} {}",
+                 entry.slot,
+                 entry.blocks.len(),
+                 entry.icon.frame_count(),
+                 entry.filename,
+                 entry.title);
+    }
+
+    println!("{} free blocks", card.free_blocks().len());
+
+    Ok(())
+}
+
+/// Parse a slot number, the range is checked by the card
+fn parse_slot(slot: &str) -> Result<usize> {
+    slot.parse().map_err(|_| {
+        Error::new(ErrorKind::InvalidInput, format!("Invalid slot {}", slot))
+    })
+}
+
+/// Split a "<card>:<slot>" argument
+fn card_slot(arg: &str) -> Result<(&str, usize)> {
+    let (card, slot) = arg.rsplit_once(':').ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, format!("Expected <card>:<slot>, got {}", arg))
+    })?;
+
+    Ok((card, parse_slot(slot)?))
+}
+
+/// True if the file at `path` is a single save rather than a card
+fn is_save_file(path: &str) -> bool {
+    let ext = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase());
+
+    matches!(ext.as_deref(), Some("mcs") | Some("psv"))
+}
+
+fn copy(from: &str, to: &str) -> Result<()> {
+    let save = if is_save_file(from) {
+        SaveFile::load(from)?
+    } else {
+        let (card, slot) = card_slot(from)?;
+
+        Card::load(card)?.export(slot)?
+    };
+
+    if is_save_file(to) {
+        save.write(to)?;
+
+        println!("Exported {} to {}", save.filename(), to);
+    } else {
+        let mut card = Card::load(to)?;
+
+        let slot = card.import(&save)?;
+
+        card.write(to)?;
+
+        println!("Imported {} to slot {} of {}", save.filename(), slot, to);
+    }
+
+    Ok(())
+}
+
+fn delete(path: &str, slot: &str) -> Result<()> {
+    let slot = parse_slot(slot)?;
+
+    let mut card = Card::load(path)?;
+
+    card.delete(slot)?;
+    card.write(path)
+}
+
+fn format(path: &str) -> Result<()> {
+    Card::new().write(path)
+}
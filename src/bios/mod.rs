@@ -21,6 +21,12 @@ impl Bios {
         let mut data = Vec::new();
         // Load the BIOS
         file.take(BIOS_SIZE).read_to_end(&mut data)?;
+
+        Bios::from_data(data)
+    }
+
+    /// Build a BIOS from an in-memory image
+    pub fn from_data(data: Vec<u8>) -> Result<Bios> {
         if data.len() == BIOS_SIZE as usize {
             Ok(Bios {
                 data
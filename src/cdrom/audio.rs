@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+/// Maximum number of samples buffered before the oldest ones are
+/// dropped, about 370ms
+const BUFFER_MAX: usize = 0x4000;
+
+/// CD audio output: CD-DA And XA-ADPCM samples at 44.1kHz waiting to
+/// be mixed into the SPU's CD input
+pub struct CdAudio {
+    /// Samples along with a flag set for the XA-ADPCM ones
+    buffer: VecDeque<((i16, i16), bool)>,
+    /// Number of XA-ADPCM samples in `buffer`
+    adpcm_samples: usize,
+    /// Mixing volumes from the CD channels to the SPU input channels
+    /// (0x80 is 100%): left to left, left to right, right to right
+    /// And right to left
+    volume: [u8; 4],
+    /// Volumes written by the CPU, only used after they've been
+    /// applied
+    pending_volume: [u8; 4],
+    /// Set by the Mute command
+    muted: bool,
+    /// Set through the volume apply register, only mutes the ADPCM
+    /// output
+    adpcm_muted: bool,
+}
+
+/// Indexes in the volume arrays
+pub const VOLUME_LEFT_TO_LEFT: usize = 0;
+pub const VOLUME_LEFT_TO_RIGHT: usize = 1;
+pub const VOLUME_RIGHT_TO_RIGHT: usize = 2;
+pub const VOLUME_RIGHT_TO_LEFT: usize = 3;
+
+impl CdAudio {
+    pub fn new() -> CdAudio {
+        CdAudio {
+            buffer: VecDeque::new(),
+            adpcm_samples: 0,
+            volume: [0x80, 0, 0x80, 0],
+            pending_volume: [0x80, 0, 0x80, 0],
+            muted: false,
+            adpcm_muted: false,
+        }
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn set_pending_volume(&mut self, index: usize, volume: u8) {
+        self.pending_volume[index] = volume;
+    }
+
+    /// Write to the "audio volume apply changes" register
+    pub fn apply(&mut self, val: u8) {
+        self.adpcm_muted = val & 1 != 0;
+
+        if val & 0x20 != 0 {
+            self.volume = self.pending_volume;
+        }
+    }
+
+    /// Queue the 588 stereo samples of a CD-DA sector
+    pub fn push_cdda(&mut self, raw: &[u8]) {
+        for s in raw.chunks(4) {
+            let left = i16::from_le_bytes([s[0], s[1]]);
+            let right = i16::from_le_bytes([s[2], s[3]]);
+
+            self.push((left, right), false);
+        }
+    }
+
+    /// Queue decoded XA-ADPCM samples
+    pub fn push_adpcm(&mut self, samples: &[(i16, i16)]) {
+        for &s in samples {
+            let s = if self.adpcm_muted { (0, 0) } else { s };
+
+            self.push(s, true);
+        }
+    }
+
+    fn push(&mut self, sample: (i16, i16), adpcm: bool) {
+        if self.buffer.len() >= BUFFER_MAX {
+            self.pop();
+        }
+
+        self.buffer.push_back((sample, adpcm));
+        self.adpcm_samples += adpcm as usize;
+    }
+
+    fn pop(&mut self) -> Option<(i16, i16)> {
+        let (sample, adpcm) = self.buffer.pop_front()?;
+
+        self.adpcm_samples -= adpcm as usize;
+
+        Some(sample)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// True while XA-ADPCM samples are waiting to be played
+    pub fn adpcm_busy(&self) -> bool {
+        self.adpcm_samples > 0
+    }
+
+    /// Return the next 44.1kHz sample after volume mixing, silence if
+    /// the buffer is empty
+    pub fn next_sample(&mut self) -> (i16, i16) {
+        let (left, right) = match self.pop() {
+            Some(s) => s,
+            None => return (0, 0),
+        };
+
+        if self.muted {
+            return (0, 0);
+        }
+
+        let left = left as i32;
+        let right = right as i32;
+
+        let v = |i: usize| self.volume[i] as i32;
+
+        let out_left = (left * v(VOLUME_LEFT_TO_LEFT) + right * v(VOLUME_RIGHT_TO_LEFT)) >> 7;
+        let out_right = (right * v(VOLUME_RIGHT_TO_RIGHT) + left * v(VOLUME_LEFT_TO_RIGHT)) >> 7;
+
+        (out_left.clamp(-0x8000, 0x7fff) as i16,
+         out_right.clamp(-0x8000, 0x7fff) as i16)
+    }
+}
+
+#[test]
+fn volume_matrix() {
+    let mut audio = CdAudio::new();
+
+    audio.push_cdda(&[0x00, 0x10, 0x00, 0xf0, 0x00, 0x60, 0x00, 0x60]);
+
+    // Default volumes: straight through
+    assert_eq!(audio.next_sample(), (0x1000, -0x1000));
+
+    // Swap the channels, left to right at 50% And right to left at
+    // about 200%. Only used once applied.
+    audio.set_pending_volume(VOLUME_LEFT_TO_LEFT, 0);
+    audio.set_pending_volume(VOLUME_RIGHT_TO_RIGHT, 0);
+    audio.set_pending_volume(VOLUME_LEFT_TO_RIGHT, 0x40);
+    audio.set_pending_volume(VOLUME_RIGHT_TO_LEFT, 0xff);
+
+    // Still the previous volumes
+    assert_eq!(audio.volume, [0x80, 0, 0x80, 0]);
+
+    audio.apply(0x20);
+    audio.push_adpcm(&[(0x100, 0x200)]);
+
+    assert!(audio.adpcm_busy());
+
+    // Saturated
+    assert_eq!(audio.next_sample(), (0x7fff, 0x3000));
+    assert_eq!(audio.next_sample(), (0x3fc, 0x80));
+    assert!(!audio.adpcm_busy());
+
+    // ADPCM mute
+    audio.apply(0x01);
+    audio.push_adpcm(&[(0x100, 0x200)]);
+
+    assert_eq!(audio.next_sample(), (0, 0));
+
+    // Empty buffer
+    assert!(audio.is_empty());
+    assert_eq!(audio.next_sample(), (0, 0));
+}
@@ -0,0 +1,244 @@
+use std::io::{Cursor, Error, ErrorKind, Result};
+
+use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
+
+use super::super::ecc;
+use super::super::sector::{SECTOR_SIZE, SYNC_PATTERN};
+
+/// Size of the subchannel data stored after each sector
+pub const SUBCODE_SIZE: usize = 96;
+/// Size of a sector And its subchannel data in a CD CHD
+pub const FRAME_SIZE: usize = SECTOR_SIZE + SUBCODE_SIZE;
+
+/// Hunk compression algorithms
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Raw deflate
+    Zlib,
+    /// Raw LZMA
+    Lzma,
+    /// 16bit stereo FLAC
+    Flac,
+    /// CD frames: sectors compressed with deflate, subchannel with
+    /// deflate
+    CdZlib,
+    /// CD frames: sectors compressed with LZMA, subchannel with
+    /// deflate
+    CdLzma,
+    /// CD frames: sectors compressed with FLAC, subchannel with
+    /// deflate
+    CdFlac,
+}
+
+impl Codec {
+    /// Parse a codec from the tag found in the CHD header. Returns
+    /// None for unknown codecs.
+    pub fn from_tag(tag: u32) -> Option<Codec> {
+        let codec = match &tag.to_be_bytes() {
+            b"zlib" => Codec::Zlib,
+            b"lzma" => Codec::Lzma,
+            b"flac" => Codec::Flac,
+            b"cdzl" => Codec::CdZlib,
+            b"cdlz" => Codec::CdLzma,
+            b"cdfl" => Codec::CdFlac,
+            _ => return None,
+        };
+
+        Some(codec)
+    }
+
+    /// Decompress `src` into `dest`. `dest` must have the size of a
+    /// hunk.
+    pub fn decompress(self, src: &[u8], dest: &mut [u8]) -> Result<()> {
+        match self {
+            Codec::Zlib => inflate(src, dest),
+            Codec::Lzma => unlzma(src, dest),
+            Codec::Flac => {
+                let big_endian = match src.first() {
+                    Some(b'L') => false,
+                    Some(b'B') => true,
+                    _ => return Err(corrupted("Invalid FLAC endianness")),
+                };
+
+                unflac(&src[1..], dest, big_endian).map(|_| ())
+            }
+            Codec::CdZlib => decompress_cd(src, dest, inflate),
+            Codec::CdLzma => decompress_cd(src, dest, unlzma),
+            Codec::CdFlac => decompress_cd_flac(src, dest),
+        }
+    }
+}
+
+fn corrupted(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("CHD hunk: {}", msg))
+}
+
+/// Decompress raw deflate data, `dest` must be filled completely
+fn inflate(src: &[u8], dest: &mut [u8]) -> Result<()> {
+    let data = miniz_oxide::inflate::decompress_to_vec_with_limit(src, dest.len())
+        .map_err(|e| corrupted(&format!("deflate error {:?}", e.status)))?;
+
+    if data.len() != dest.len() {
+        return Err(corrupted("Truncated deflate data"));
+    }
+
+    dest.copy_from_slice(&data);
+
+    Ok(())
+}
+
+/// Decompress raw LZMA data without header. The encoder parameters
+/// are fixed: lc=3, lp=0, pb=2 And a dictionary the size of the
+/// output.
+fn unlzma(src: &[u8], dest: &mut [u8]) -> Result<()> {
+    let props = LzmaProperties { lc: 3, lp: 0, pb: 2 };
+    let params = LzmaParams::new(props, dest.len() as u32, Some(dest.len() as u64));
+
+    let mut decoder = LzmaDecoder::new(params, None)
+        .map_err(|e| corrupted(&format!("LZMA error {:?}", e)))?;
+
+    let mut input = src;
+    let mut output = Vec::with_capacity(dest.len());
+
+    decoder.decompress(&mut input, &mut output)
+        .map_err(|e| corrupted(&format!("LZMA error {:?}", e)))?;
+
+    if output.len() != dest.len() {
+        return Err(corrupted("Truncated LZMA data"));
+    }
+
+    dest.copy_from_slice(&output);
+
+    Ok(())
+}
+
+/// Decode FLAC frames containing 16bit stereo samples until `dest`
+/// is full. Returns the number of bytes of `src` consumed.
+fn unflac(src: &[u8], dest: &mut [u8], big_endian: bool) -> Result<usize> {
+    let mut reader = claxon::frame::FrameReader::new(Cursor::new(src));
+    let mut buffer = Vec::new();
+    let mut pos = 0;
+
+    while pos < dest.len() {
+        let block = match reader.read_next_or_eof(buffer) {
+            Ok(Some(block)) => block,
+            Ok(None) => return Err(corrupted("Truncated FLAC data")),
+            Err(e) => return Err(corrupted(&format!("FLAC error {}", e))),
+        };
+
+        if block.channels() != 2 {
+            return Err(corrupted("FLAC data isn't stereo"));
+        }
+
+        for (left, right) in block.stereo_samples() {
+            for sample in [left, right] {
+                if pos + 2 > dest.len() {
+                    return Err(corrupted("Too many FLAC samples"));
+                }
+
+                let sample = sample as i16;
+
+                let bytes = if big_endian {
+                    sample.to_be_bytes()
+                } else {
+                    sample.to_le_bytes()
+                };
+
+                dest[pos..pos + 2].copy_from_slice(&bytes);
+                pos += 2;
+            }
+        }
+
+        buffer = block.into_buffer();
+    }
+
+    Ok(reader.into_inner().position() as usize)
+}
+
+/// Decompress a hunk of CD frames. The hunk starts with a bitmap of
+/// the sectors whose sync And ECC have been stripped, followed by the
+/// length of the compressed sector data. The subchannel data is
+/// always compressed with deflate.
+fn decompress_cd(src: &[u8],
+                 dest: &mut [u8],
+                 base: fn(&[u8], &mut [u8]) -> Result<()>) -> Result<()> {
+    let frames = dest.len() / FRAME_SIZE;
+
+    let ecc_bytes = frames.div_ceil(8);
+    let length_bytes = if dest.len() < 0x10000 { 2 } else { 3 };
+    let header_bytes = ecc_bytes + length_bytes;
+
+    if src.len() < header_bytes {
+        return Err(corrupted("Truncated CD hunk"));
+    }
+
+    let base_length = src[ecc_bytes..header_bytes]
+        .iter()
+        .fold(0usize, |l, &b| (l << 8) | b as usize);
+
+    let base_end = header_bytes + base_length;
+
+    if base_end > src.len() {
+        return Err(corrupted("Truncated CD hunk"));
+    }
+
+    let mut sectors = vec![0; frames * SECTOR_SIZE];
+    let mut subcode = vec![0; frames * SUBCODE_SIZE];
+
+    base(&src[header_bytes..base_end], &mut sectors)?;
+    inflate(&src[base_end..], &mut subcode)?;
+
+    reassemble_cd(dest, &sectors, &subcode, Some(&src[..ecc_bytes]));
+
+    Ok(())
+}
+
+/// Decompress a hunk of CD audio frames. There's no ECC bitmap, the
+/// FLAC data is followed by the deflated subchannel data.
+fn decompress_cd_flac(src: &[u8], dest: &mut [u8]) -> Result<()> {
+    let frames = dest.len() / FRAME_SIZE;
+
+    let mut sectors = vec![0; frames * SECTOR_SIZE];
+    let mut subcode = vec![0; frames * SUBCODE_SIZE];
+
+    let offset = unflac(src, &mut sectors, true)?;
+
+    inflate(&src[offset..], &mut subcode)?;
+
+    reassemble_cd(dest, &sectors, &subcode, None);
+
+    Ok(())
+}
+
+/// Interleave the sectors And subchannel data And regenerate the
+/// stripped sync patterns And ECC
+fn reassemble_cd(dest: &mut [u8], sectors: &[u8], subcode: &[u8], ecc_map: Option<&[u8]>) {
+    let frames = sectors.len() / SECTOR_SIZE;
+
+    for f in 0..frames {
+        let frame = &mut dest[f * FRAME_SIZE..(f + 1) * FRAME_SIZE];
+
+        frame[..SECTOR_SIZE].copy_from_slice(&sectors[f * SECTOR_SIZE..(f + 1) * SECTOR_SIZE]);
+        frame[SECTOR_SIZE..].copy_from_slice(&subcode[f * SUBCODE_SIZE..(f + 1) * SUBCODE_SIZE]);
+
+        let stripped = match ecc_map {
+            Some(map) => map[f / 8] & (1 << (f % 8)) != 0,
+            None => false,
+        };
+
+        if stripped {
+            let raw: &mut [u8; SECTOR_SIZE] = (&mut frame[..SECTOR_SIZE]).try_into().unwrap();
+
+            raw[..12].copy_from_slice(&SYNC_PATTERN);
+            ecc::generate_ecc(raw, false);
+        }
+    }
+}
+
+#[test]
+fn codec_tags() {
+    assert_eq!(Codec::from_tag(0x63647a6c), Some(Codec::CdZlib));
+    assert_eq!(Codec::from_tag(0x6c7a6d61), Some(Codec::Lzma));
+    assert_eq!(Codec::from_tag(0x6364666c), Some(Codec::CdFlac));
+    assert_eq!(Codec::from_tag(0x68756666), None);
+}
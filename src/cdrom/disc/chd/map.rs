@@ -0,0 +1,489 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// Hunk compressed with one of the 4 codecs of the header
+const COMPRESSION_TYPE_0: u8 = 0;
+const COMPRESSION_TYPE_3: u8 = 3;
+/// Uncompressed hunk
+const COMPRESSION_NONE: u8 = 4;
+/// Copy of another hunk of the same file
+const COMPRESSION_SELF: u8 = 5;
+/// Hunk stored in the parent CHD
+const COMPRESSION_PARENT: u8 = 6;
+/// The following types only exist in the compressed map
+const COMPRESSION_RLE_SMALL: u8 = 7;
+const COMPRESSION_RLE_LARGE: u8 = 8;
+const COMPRESSION_SELF_0: u8 = 9;
+const COMPRESSION_SELF_1: u8 = 10;
+const COMPRESSION_PARENT_SELF: u8 = 11;
+const COMPRESSION_PARENT_0: u8 = 12;
+const COMPRESSION_PARENT_1: u8 = 13;
+
+/// Number of symbols of the map's Huffman code
+const HUFFMAN_CODES: usize = 16;
+/// Maximum length of a code
+const HUFFMAN_MAX_BITS: u32 = 8;
+
+/// Location of a hunk's data
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HunkLocation {
+    /// Compressed with codec number `codec` of the header
+    Compressed { codec: usize, offset: u64, length: u32, crc: u16 },
+    /// Stored uncompressed. Uncompressed maps don't have CRCs.
+    Uncompressed { offset: u64, crc: Option<u16> },
+    /// Same data as another hunk of the file
+    SelfRef(u32),
+    /// Stored in the parent CHD at unit `unit`
+    Parent(u64),
+    /// Hunk full of zeroes
+    Zero,
+}
+
+/// Settings needed to decode the compressed map
+pub struct MapInfo {
+    pub hunk_count: u32,
+    pub hunk_bytes: u32,
+    pub unit_bytes: u32,
+}
+
+/// Decode an uncompressed map: one big endian 32bit hunk offset per
+/// hunk, 0 meaning that the hunk is full of zeroes
+pub fn decode_uncompressed(raw: &[u8], info: &MapInfo) -> Vec<HunkLocation> {
+    raw.chunks(4)
+        .take(info.hunk_count as usize)
+        .map(|e| {
+            let offset = u32::from_be_bytes([e[0], e[1], e[2], e[3]]) as u64;
+
+            if offset == 0 {
+                HunkLocation::Zero
+            } else {
+                HunkLocation::Uncompressed {
+                    offset: offset * info.hunk_bytes as u64,
+                    crc: None,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Decode a compressed V5 map. `header` is the 16 byte map header,
+/// `data` the compressed map that follows it.
+pub fn decode_compressed(header: &[u8], data: &[u8], info: &MapInfo) -> Result<Vec<HunkLocation>> {
+    let first_offset = header[4..10].iter().fold(0u64, |o, &b| (o << 8) | b as u64);
+    let map_crc = u16::from_be_bytes([header[10], header[11]]);
+    let length_bits = header[12] as u32;
+    let self_bits = header[13] as u32;
+    let parent_bits = header[14] as u32;
+
+    let mut reader = BitReader::new(data);
+
+    let huffman = Huffman::import_tree_rle(&mut reader)?;
+
+    let hunk_count = info.hunk_count as usize;
+
+    // First pass: compression types, run length encoded
+    let mut types = Vec::with_capacity(hunk_count);
+    let mut last_type = 0;
+    let mut repeat = 0;
+
+    for _ in 0..hunk_count {
+        if repeat > 0 {
+            types.push(last_type);
+            repeat -= 1;
+            continue;
+        }
+
+        match huffman.decode_one(&mut reader) {
+            COMPRESSION_RLE_SMALL => {
+                types.push(last_type);
+                repeat = 2 + huffman.decode_one(&mut reader) as u32;
+            }
+            COMPRESSION_RLE_LARGE => {
+                types.push(last_type);
+                repeat = 2 + 16 + ((huffman.decode_one(&mut reader) as u32) << 4);
+                repeat += huffman.decode_one(&mut reader) as u32;
+            }
+            t => {
+                types.push(t);
+                last_type = t;
+            }
+        }
+    }
+
+    // Second pass: lengths, offsets And CRCs. We rebuild the raw
+    // 12 byte entries to validate the map's CRC.
+    let mut raw_map = Vec::with_capacity(hunk_count * 12);
+    let mut locations = Vec::with_capacity(hunk_count);
+
+    let mut cur_offset = first_offset;
+    let mut last_self = 0;
+    let mut last_parent = 0;
+
+    let units_per_hunk = (info.hunk_bytes / info.unit_bytes) as u64;
+
+    for (hunk, &t) in types.iter().enumerate() {
+        let mut length = 0;
+        let mut offset = cur_offset;
+        let mut crc = 0;
+
+        let compression = match t {
+            COMPRESSION_TYPE_0..=COMPRESSION_TYPE_3 => {
+                length = reader.read(length_bits);
+                cur_offset += length as u64;
+                crc = reader.read(16) as u16;
+                t
+            }
+            COMPRESSION_NONE => {
+                length = info.hunk_bytes;
+                cur_offset += length as u64;
+                crc = reader.read(16) as u16;
+                t
+            }
+            COMPRESSION_SELF => {
+                offset = reader.read(self_bits) as u64;
+                last_self = offset;
+                t
+            }
+            COMPRESSION_PARENT => {
+                offset = reader.read(parent_bits) as u64;
+                last_parent = offset;
+                t
+            }
+            COMPRESSION_SELF_0 | COMPRESSION_SELF_1 => {
+                if t == COMPRESSION_SELF_1 {
+                    last_self += 1;
+                }
+                offset = last_self;
+                COMPRESSION_SELF
+            }
+            COMPRESSION_PARENT_SELF => {
+                offset = hunk as u64 * units_per_hunk;
+                last_parent = offset;
+                COMPRESSION_PARENT
+            }
+            COMPRESSION_PARENT_0 | COMPRESSION_PARENT_1 => {
+                if t == COMPRESSION_PARENT_1 {
+                    last_parent += units_per_hunk;
+                }
+                offset = last_parent;
+                COMPRESSION_PARENT
+            }
+            _ => return Err(invalid_map("Invalid compression type")),
+        };
+
+        raw_map.push(compression);
+        raw_map.extend_from_slice(&length.to_be_bytes()[1..]);
+        raw_map.extend_from_slice(&offset.to_be_bytes()[2..]);
+        raw_map.extend_from_slice(&crc.to_be_bytes());
+
+        locations.push(match compression {
+            COMPRESSION_NONE => HunkLocation::Uncompressed { offset, crc: Some(crc) },
+            COMPRESSION_SELF => HunkLocation::SelfRef(offset as u32),
+            COMPRESSION_PARENT => HunkLocation::Parent(offset),
+            codec => HunkLocation::Compressed {
+                codec: codec as usize,
+                offset,
+                length,
+                crc,
+            },
+        });
+    }
+
+    if reader.overflow() {
+        return Err(invalid_map("Truncated map"));
+    }
+
+    if crc16(&raw_map) != map_crc {
+        return Err(invalid_map("Map CRC mismatch"));
+    }
+
+    Ok(locations)
+}
+
+fn invalid_map(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("CHD map: {}", msg))
+}
+
+/// CRC-16/CCITT used for the map And the hunks
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, &b| {
+        let mut crc = crc ^ ((b as u16) << 8);
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+
+        crc
+    })
+}
+
+/// MSB first bit reader. Reading past the end returns zeroes And
+/// sets the overflow flag.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+        }
+    }
+
+    pub fn peek(&self, bits: u32) -> u32 {
+        let mut r = 0;
+
+        for i in 0..bits as usize {
+            let pos = self.position + i;
+
+            let bit = match self.data.get(pos / 8) {
+                Some(&b) => (b >> (7 - pos % 8)) & 1,
+                None => 0,
+            };
+
+            r = (r << 1) | bit as u32;
+        }
+
+        r
+    }
+
+    pub fn remove(&mut self, bits: u32) {
+        self.position += bits as usize;
+    }
+
+    pub fn read(&mut self, bits: u32) -> u32 {
+        let r = self.peek(bits);
+
+        self.remove(bits);
+
+        r
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.position > self.data.len() * 8
+    }
+}
+
+/// Canonical Huffman decoder used by the compressed map
+struct Huffman {
+    /// Symbol And code length indexed by the next `HUFFMAN_MAX_BITS`
+    /// bits of the stream
+    lookup: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    /// Read the RLE encoded code lengths And build the decoder
+    fn import_tree_rle(reader: &mut BitReader) -> Result<Huffman> {
+        // Size of the code length fields
+        let field_bits = 4;
+
+        let mut lengths = [0u8; HUFFMAN_CODES];
+        let mut node = 0;
+
+        while node < HUFFMAN_CODES {
+            let len = reader.read(field_bits) as u8;
+
+            if len != 1 {
+                lengths[node] = len;
+                node += 1;
+                continue;
+            }
+
+            // Escape: either a literal 1 or a repetition
+            let len = reader.read(field_bits) as u8;
+
+            if len == 1 {
+                lengths[node] = len;
+                node += 1;
+            } else {
+                let repeat = reader.read(field_bits) + 3;
+
+                for _ in 0..repeat {
+                    if node >= HUFFMAN_CODES {
+                        return Err(invalid_map("Invalid Huffman tree"));
+                    }
+
+                    lengths[node] = len;
+                    node += 1;
+                }
+            }
+        }
+
+        // Assign the canonical codes, longest codes first
+        let mut histogram = [0u32; 33];
+
+        for &len in lengths.iter() {
+            if len as u32 > HUFFMAN_MAX_BITS {
+                return Err(invalid_map("Huffman code too long"));
+            }
+
+            histogram[len as usize] += 1;
+        }
+
+        let mut start = 0;
+
+        for len in (1..=32).rev() {
+            let next = (start + histogram[len]) >> 1;
+
+            histogram[len] = start;
+            start = next;
+        }
+
+        let mut lookup = vec![(0, 0); 1 << HUFFMAN_MAX_BITS];
+
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+
+            let code = histogram[len as usize];
+            histogram[len as usize] += 1;
+
+            let shift = HUFFMAN_MAX_BITS - len as u32;
+
+            let first = (code << shift) as usize;
+            let last = (((code + 1) << shift) - 1) as usize;
+
+            if last >= lookup.len() {
+                return Err(invalid_map("Invalid Huffman tree"));
+            }
+
+            for entry in &mut lookup[first..=last] {
+                *entry = (symbol as u8, len);
+            }
+        }
+
+        Ok(Huffman { lookup })
+    }
+
+    fn decode_one(&self, reader: &mut BitReader) -> u8 {
+        let (symbol, len) = self.lookup[reader.peek(HUFFMAN_MAX_BITS) as usize];
+
+        reader.remove(len as u32);
+
+        symbol
+    }
+}
+
+/// MSB first bit writer used to build test maps
+#[cfg(test)]
+pub struct BitWriter {
+    pub data: Vec<u8>,
+    bits: usize,
+}
+
+#[cfg(test)]
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter {
+            data: Vec::new(),
+            bits: 0,
+        }
+    }
+
+    pub fn write(&mut self, val: u32, bits: u32) {
+        for i in (0..bits).rev() {
+            if self.bits % 8 == 0 {
+                self.data.push(0);
+            }
+
+            let bit = ((val >> i) & 1) as u8;
+
+            *self.data.last_mut().unwrap() |= bit << (7 - self.bits % 8);
+            self.bits += 1;
+        }
+    }
+
+    /// Huffman tree where all the codes are 4 bits long, this way
+    /// each symbol is encoded as itself
+    pub fn write_flat_tree(&mut self) {
+        for _ in 0..HUFFMAN_CODES {
+            self.write(4, 4);
+        }
+    }
+}
+
+#[test]
+fn crc() {
+    assert_eq!(crc16(b"123456789"), 0x29b1);
+}
+
+#[test]
+fn compressed_map() {
+    let info = MapInfo {
+        hunk_count: 8,
+        hunk_bytes: 0x1000,
+        unit_bytes: 0x100,
+    };
+
+    let mut w = BitWriter::new();
+
+    w.write_flat_tree();
+
+    // Types: codec 1, then 3 repeats using RLE_SMALL (1 + 2 + 0),
+    // uncompressed, self, self + 1, self + 1
+    w.write(1, 4);
+    w.write(COMPRESSION_RLE_SMALL as u32, 4);
+    w.write(0, 4);
+    w.write(COMPRESSION_NONE as u32, 4);
+    w.write(COMPRESSION_SELF as u32, 4);
+    w.write(COMPRESSION_SELF_1 as u32, 4);
+    w.write(COMPRESSION_SELF_0 as u32, 4);
+
+    // Lengths And CRCs of the 4 compressed hunks
+    for i in 0..4 {
+        w.write(0x100 + i, 12);
+        w.write(0xaa00 + i, 16);
+    }
+
+    // Uncompressed hunk CRC
+    w.write(0xbeef, 16);
+    // Self reference
+    w.write(2, 8);
+
+    let expected = vec![
+        HunkLocation::Compressed { codec: 1, offset: 0x200, length: 0x100, crc: 0xaa00 },
+        HunkLocation::Compressed { codec: 1, offset: 0x300, length: 0x101, crc: 0xaa01 },
+        HunkLocation::Compressed { codec: 1, offset: 0x401, length: 0x102, crc: 0xaa02 },
+        HunkLocation::Compressed { codec: 1, offset: 0x503, length: 0x103, crc: 0xaa03 },
+        HunkLocation::Uncompressed { offset: 0x606, crc: Some(0xbeef) },
+        HunkLocation::SelfRef(2),
+        HunkLocation::SelfRef(3),
+        HunkLocation::SelfRef(3),
+    ];
+
+    // Rebuild the raw map to compute its CRC
+    let mut raw = Vec::new();
+
+    for l in &expected {
+        let (t, length, offset, crc) = match *l {
+            HunkLocation::Compressed { codec, offset, length, crc } => (codec as u8, length, offset, crc),
+            HunkLocation::Uncompressed { offset, crc } => (COMPRESSION_NONE, 0x1000, offset, crc.unwrap()),
+            HunkLocation::SelfRef(h) => (COMPRESSION_SELF, 0, h as u64, 0),
+            _ => unreachable!(),
+        };
+
+        raw.push(t);
+        raw.extend_from_slice(&length.to_be_bytes()[1..]);
+        raw.extend_from_slice(&offset.to_be_bytes()[2..]);
+        raw.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    let mut header = vec![0; 16];
+    header[4..10].copy_from_slice(&0x200u64.to_be_bytes()[2..]);
+    header[10..12].copy_from_slice(&crc16(&raw).to_be_bytes());
+    header[12] = 12;
+    header[13] = 8;
+    header[14] = 0;
+
+    assert_eq!(decode_compressed(&header, &w.data, &info).unwrap(), expected);
+
+    // Corrupted CRC
+    header[11] ^= 1;
+    assert!(decode_compressed(&header, &w.data, &info).is_err());
+}
@@ -0,0 +1,738 @@
+//! MAME's "Compressed Hunks of Data" V5 disc images (".chd")
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, SeekFrom};
+use std::path::Path;
+
+use super::{Image, Storage, Toc, Track};
+use super::msf::Msf;
+use super::sector::{Sector, SECTOR_SIZE};
+use super::trackformat::TrackFormat;
+
+use self::codec::{Codec, FRAME_SIZE};
+use self::map::{HunkLocation, MapInfo};
+
+mod codec;
+mod map;
+
+/// Size of the V5 header
+const HEADER_SIZE: usize = 124;
+
+/// Metadata tags describing the CD tracks
+const CDROM_TRACK_METADATA2_TAG: u32 = 0x43485432; // "CHT2"
+const CDROM_TRACK_METADATA_TAG: u32 = 0x43485452; // "CHTR"
+
+/// The tracks are padded to a multiple of this number of frames
+const TRACK_PADDING: u32 = 4;
+
+/// Number of frames on the longest possible disc (100 minutes)
+const MAX_FRAMES: u64 = 100 * 60 * 75;
+
+/// CD image stored in a CHD file. Each hunk contains several 2448
+/// byte frames (a raw sector followed by its subchannel data).
+pub struct ChdImage {
+    toc: Toc,
+    storage: Box<dyn Storage>,
+    /// Codecs used by the hunks, unused slots are None
+    codecs: [Option<Codec>; 4],
+    hunk_bytes: u32,
+    map: Vec<HunkLocation>,
+    tracks: Vec<ChdTrack>,
+    /// Last decompressed hunk
+    hunk: Vec<u8>,
+    /// Index of the hunk in `hunk`, if any
+    hunk_index: Option<u32>,
+}
+
+impl ChdImage {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<ChdImage> {
+        let file = File::open(path)?;
+
+        ChdImage::from_storage(Box::new(file))
+    }
+
+    pub fn from_storage(mut storage: Box<dyn Storage>) -> Result<ChdImage> {
+        let mut header = [0; HEADER_SIZE];
+
+        // Used to validate the sizes read from the file before
+        // allocating anything
+        let file_size = storage.seek(SeekFrom::End(0))?;
+
+        storage.seek(SeekFrom::Start(0))?;
+        storage.read_exact(&mut header)?;
+
+        if &header[0..8] != b"MComprHD" {
+            return Err(invalid("Missing CHD signature"));
+        }
+
+        let version = be32(&header[12..]);
+
+        if version != 5 {
+            return Err(invalid(&format!("Unsupported CHD version {}", version)));
+        }
+
+        // Parent SHA1, set for images storing only the differences
+        // with another CHD
+        if header[104..124].iter().any(|&b| b != 0) {
+            return Err(invalid("CHDs with a parent are not supported"));
+        }
+
+        let mut codecs = [None; 4];
+        let mut compressed = false;
+
+        for (i, codec) in codecs.iter_mut().enumerate() {
+            let tag = be32(&header[16 + i * 4..]);
+
+            if tag != 0 {
+                compressed = true;
+                *codec = Codec::from_tag(tag);
+
+                if codec.is_none() {
+                    warn!("Unsupported CHD codec 0x{:08x}", tag);
+                }
+            }
+        }
+
+        let logical_bytes = be64(&header[32..]);
+        let map_offset = be64(&header[40..]);
+        let meta_offset = be64(&header[48..]);
+        let hunk_bytes = be32(&header[56..]);
+        let unit_bytes = be32(&header[60..]);
+
+        if unit_bytes as usize != FRAME_SIZE
+            || hunk_bytes == 0
+            || !hunk_bytes.is_multiple_of(unit_bytes) {
+            return Err(invalid("Not a CD-ROM CHD"));
+        }
+
+        if logical_bytes > MAX_FRAMES * FRAME_SIZE as u64 {
+            return Err(invalid("CHD is too large for a CD"));
+        }
+
+        let info = MapInfo {
+            hunk_count: logical_bytes.div_ceil(hunk_bytes as u64) as u32,
+            hunk_bytes,
+            unit_bytes,
+        };
+
+        storage.seek(SeekFrom::Start(map_offset))?;
+
+        let map = if compressed {
+            let mut map_header = [0; 16];
+
+            storage.read_exact(&mut map_header)?;
+
+            let length = be32(&map_header) as u64;
+
+            check_bounds(file_size, map_offset + 16, length, "Compressed map")?;
+
+            let mut data = vec![0; length as usize];
+
+            storage.read_exact(&mut data)?;
+
+            map::decode_compressed(&map_header, &data, &info)?
+        } else {
+            let length = info.hunk_count as u64 * 4;
+
+            check_bounds(file_size, map_offset, length, "Hunk map")?;
+
+            let mut raw = vec![0; length as usize];
+
+            storage.read_exact(&mut raw)?;
+
+            map::decode_uncompressed(&raw, &info)
+        };
+
+        let tracks = read_tracks(storage.as_mut(), file_size, meta_offset)?;
+
+        let toc = Toc::new(tracks.iter().map(|t| t.track).collect());
+
+        Ok(ChdImage {
+            toc,
+            storage,
+            codecs,
+            hunk_bytes,
+            map,
+            tracks,
+            hunk: vec![0; hunk_bytes as usize],
+            hunk_index: None,
+        })
+    }
+
+    /// Decompress hunk `index` in `self.hunk`
+    fn load_hunk(&mut self, index: u32) -> Result<()> {
+        if self.hunk_index == Some(index) {
+            return Ok(());
+        }
+
+        // Invalidate the cache in case we fail
+        self.hunk_index = None;
+
+        let location = match self.map.get(index as usize) {
+            Some(&l) => l,
+            None => return Err(invalid(&format!("Hunk {} is out of the image", index))),
+        };
+
+        let crc = match location {
+            HunkLocation::Compressed { codec, offset, length, crc } => {
+                let codec = match self.codecs[codec] {
+                    Some(c) => c,
+                    None => return Err(invalid("Hunk uses an unsupported codec")),
+                };
+
+                let mut data = vec![0; length as usize];
+
+                self.storage.seek(SeekFrom::Start(offset))?;
+                self.storage.read_exact(&mut data)?;
+
+                codec.decompress(&data, &mut self.hunk)?;
+
+                Some(crc)
+            }
+            HunkLocation::Uncompressed { offset, crc } => {
+                self.storage.seek(SeekFrom::Start(offset))?;
+                self.storage.read_exact(&mut self.hunk)?;
+
+                crc
+            }
+            HunkLocation::SelfRef(other) => {
+                // References always point to previous hunks, this
+                // prevents infinite loops on corrupted images
+                if other >= index {
+                    return Err(invalid("Invalid hunk self-reference"));
+                }
+
+                self.load_hunk(other)?;
+
+                None
+            }
+            HunkLocation::Parent(_) => {
+                return Err(invalid("Hunk stored in the parent CHD"));
+            }
+            HunkLocation::Zero => {
+                self.hunk.iter_mut().for_each(|b| *b = 0);
+
+                None
+            }
+        };
+
+        if let Some(crc) = crc {
+            if map::crc16(&self.hunk) != crc {
+                return Err(invalid(&format!("Hunk {} CRC mismatch", index)));
+            }
+        }
+
+        self.hunk_index = Some(index);
+
+        Ok(())
+    }
+}
+
+impl Image for ChdImage {
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        let pos = msf.sector_index();
+
+        let track = match self.tracks.iter().find(|t| pos >= t.pregap_start && pos < t.end) {
+            Some(t) => *t,
+            None => return Err(Error::new(ErrorKind::InvalidInput,
+                                          format!("Sector {:?} is out of the disc", msf))),
+        };
+
+        let format = track.track.format;
+
+        if pos < track.data_start {
+            // Pregap not stored in the image
+            return Ok(Sector::empty(msf, format));
+        }
+
+        let frame = track.chd_start + (pos - track.data_start);
+
+        let frames_per_hunk = self.hunk_bytes / FRAME_SIZE as u32;
+
+        self.load_hunk(frame / frames_per_hunk)?;
+
+        let offset = (frame % frames_per_hunk) as usize * FRAME_SIZE;
+
+        let mut raw = [0; SECTOR_SIZE];
+
+        raw.copy_from_slice(&self.hunk[offset..offset + SECTOR_SIZE]);
+
+        // Audio samples are stored big endian
+        if format.is_audio() {
+            for sample in raw.chunks_mut(2) {
+                sample.swap(0, 1);
+            }
+        }
+
+        Ok(Sector::new(raw))
+    }
+}
+
+/// Position of a track on the disc And in the CHD
+#[derive(Copy, Clone, Debug)]
+struct ChdTrack {
+    track: Track,
+    /// Absolute position of the start of the pregap
+    pregap_start: u32,
+    /// Absolute position of the first sector stored in the image
+    data_start: u32,
+    /// Absolute position of the end of the track
+    end: u32,
+    /// Index of the track's first frame in the CHD
+    chd_start: u32,
+}
+
+/// Track description parsed from the metadata
+#[derive(Debug)]
+struct TrackMetadata {
+    number: u8,
+    format: TrackFormat,
+    /// Number of frames stored in the image, including the stored
+    /// pregap
+    frames: u32,
+    pregap: u32,
+    /// True if the pregap is stored in the image
+    pregap_stored: bool,
+}
+
+/// Walk through the metadata chain looking for the track
+/// descriptions And lay the tracks out on the disc
+fn read_tracks(storage: &mut dyn Storage,
+               file_size: u64,
+               mut offset: u64) -> Result<Vec<ChdTrack>> {
+    let mut metadata = Vec::new();
+    // Offsets of the entries already parsed, a corrupted chain could
+    // loop forever
+    let mut visited = HashSet::new();
+
+    while offset != 0 {
+        if !visited.insert(offset) {
+            return Err(invalid("Metadata chain loops"));
+        }
+
+        let mut header = [0; 16];
+
+        storage.seek(SeekFrom::Start(offset))?;
+        storage.read_exact(&mut header)?;
+
+        let tag = be32(&header);
+        let length = be32(&header[4..]) & 0xff_ffff;
+
+        offset = be64(&header[8..]);
+
+        if tag != CDROM_TRACK_METADATA2_TAG && tag != CDROM_TRACK_METADATA_TAG {
+            continue;
+        }
+
+        check_bounds(file_size, offset + 16, length as u64, "Metadata entry")?;
+
+        let mut data = vec![0; length as usize];
+
+        storage.read_exact(&mut data)?;
+
+        metadata.push(parse_track_metadata(&data)?);
+    }
+
+    if metadata.is_empty() {
+        return Err(invalid("Missing CD track metadata"));
+    }
+
+    metadata.sort_by_key(|m| m.number);
+
+    Ok(layout(&metadata))
+}
+
+/// Parse a track description such as
+/// "TRACK:1 TYPE:MODE2_RAW SUBTYPE:NONE FRAMES:1234 PREGAP:0
+/// PGTYPE:MODE2_RAW PGSUB:NONE POSTGAP:0"
+fn parse_track_metadata(data: &[u8]) -> Result<TrackMetadata> {
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_end_matches('\0');
+
+    let mut number = None;
+    let mut format = None;
+    let mut frames = None;
+    let mut pregap = 0;
+    let mut pregap_stored = false;
+
+    for field in text.split_whitespace() {
+        let (key, value) = match field.split_once(':') {
+            Some(kv) => kv,
+            None => return Err(invalid(&format!("Invalid track metadata \"{}\"", text))),
+        };
+
+        let parse_number = || {
+            value.parse::<u32>()
+                .map_err(|_| invalid(&format!("Invalid track metadata \"{}\"", text)))
+        };
+
+        match key {
+            "TRACK" => number = Some(parse_number()?),
+            "TYPE" => {
+                format = Some(match value {
+                    "MODE1_RAW" | "MODE1/2352" => TrackFormat::Mode1,
+                    "MODE2_RAW" | "MODE2/2352" | "CDI/2352" => TrackFormat::Mode2,
+                    "AUDIO" => TrackFormat::Audio,
+                    _ => return Err(invalid(&format!("Unsupported track type {}", value))),
+                })
+            }
+            "FRAMES" => frames = Some(parse_number()?),
+            "PREGAP" => pregap = parse_number()?,
+            // A 'V' prefix means that the pregap data is stored in
+            // the image
+            "PGTYPE" => pregap_stored = value.starts_with('V'),
+            _ => (),
+        }
+    }
+
+    match (number, format, frames) {
+        (Some(number), Some(format), Some(frames)) if number > 0 && number < 100 => {
+            Ok(TrackMetadata {
+                number: number as u8,
+                format,
+                frames,
+                pregap,
+                pregap_stored,
+            })
+        }
+        _ => Err(invalid(&format!("Incomplete track metadata \"{}\"", text))),
+    }
+}
+
+/// Compute the position of each track. The first track's data starts
+/// at 00:02:00, the following tracks are placed right after their
+/// predecessor, preceded by their pregap.
+fn layout(metadata: &[TrackMetadata]) -> Vec<ChdTrack> {
+    let mut tracks = Vec::with_capacity(metadata.len());
+
+    let mut disc_pos = 0;
+    let mut chd_frame = 0;
+
+    for (i, m) in metadata.iter().enumerate() {
+        let stored = if m.pregap_stored { m.pregap } else { 0 };
+
+        let pregap_start = disc_pos;
+
+        let data_start = if i == 0 {
+            // The 2 second lead-in is never stored
+            150 - stored.min(150)
+        } else {
+            disc_pos + (m.pregap - stored)
+        };
+
+        let start = data_start + stored;
+        let end = data_start + m.frames;
+
+        tracks.push(ChdTrack {
+            track: Track {
+                number: m.number,
+                format: m.format,
+                start: Msf::from_sector_index(start),
+                length: end.saturating_sub(start),
+            },
+            pregap_start,
+            data_start,
+            end,
+            chd_start: chd_frame,
+        });
+
+        disc_pos = end;
+        chd_frame += m.frames.div_ceil(TRACK_PADDING) * TRACK_PADDING;
+    }
+
+    tracks
+}
+
+/// Make sure that `length` bytes at `offset` are within the file
+fn check_bounds(file_size: u64, offset: u64, length: u64, what: &str) -> Result<()> {
+    match offset.checked_add(length) {
+        Some(end) if end <= file_size => Ok(()),
+        _ => Err(invalid(&format!("{} is out of the file", what))),
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn be64(b: &[u8]) -> u64 {
+    (be32(b) as u64) << 32 | be32(&b[4..]) as u64
+}
+
+#[test]
+fn track_layout() {
+    let meta = [
+        "TRACK:1 TYPE:MODE2_RAW SUBTYPE:NONE FRAMES:1000 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0",
+        "TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:650 PREGAP:150 PGTYPE:VAUDIO PGSUB:RW POSTGAP:0",
+        "TRACK:3 TYPE:AUDIO SUBTYPE:NONE FRAMES:500 PREGAP:75 PGTYPE:AUDIO PGSUB:RW POSTGAP:0",
+    ];
+
+    let meta: Vec<_> = meta.iter()
+        .map(|m| parse_track_metadata(m.as_bytes()).unwrap())
+        .collect();
+
+    let tracks = layout(&meta);
+
+    assert_eq!(tracks[0].track.start, Msf::new(0, 2, 0));
+    assert_eq!(tracks[0].track.length, 1000);
+    assert_eq!(tracks[0].chd_start, 0);
+
+    // Stored pregap
+    assert_eq!(tracks[1].pregap_start, 1150);
+    assert_eq!(tracks[1].data_start, 1150);
+    assert_eq!(tracks[1].track.start, Msf::from_sector_index(1300));
+    assert_eq!(tracks[1].track.length, 500);
+    assert_eq!(tracks[1].chd_start, 1000);
+
+    // Pregap not stored
+    assert_eq!(tracks[2].pregap_start, 1800);
+    assert_eq!(tracks[2].data_start, 1875);
+    assert_eq!(tracks[2].track.start, Msf::from_sector_index(1875));
+    assert_eq!(tracks[2].track.length, 500);
+    // 650 padded to 652
+    assert_eq!(tracks[2].chd_start, 1652);
+
+    assert!(parse_track_metadata(b"TRACK:1 TYPE:MODE2_FORM1 FRAMES:10").is_err());
+    assert!(parse_track_metadata(b"TRACK:1 TYPE:AUDIO").is_err());
+}
+
+/// Build an in-memory CHD containing a single Mode 2 track of 10
+/// sectors, using 4 frames per hunk:
+///
+/// * hunk 0: cdzl, with the sync And ECC of sector 1 stripped
+/// * hunk 1: cdlz, contains the license sector
+/// * hunk 2: self reference to hunk 0
+///
+/// Returns the image And the expected sectors.
+#[cfg(test)]
+fn test_chd() -> (Vec<u8>, Vec<Sector>) {
+    use super::ecc;
+    use self::map::{crc16, BitWriter};
+
+    let frames_per_hunk = 4;
+    let hunk_bytes = frames_per_hunk * FRAME_SIZE;
+
+    let mut sectors = Vec::new();
+
+    for i in 0..12 {
+        let msf = Msf::from_lba(i as u32);
+
+        let mut data = [i as u8; 2048];
+
+        if i == 4 {
+            let license = b"Licensed  by  Sony Computer Entertainment Euro pe ";
+
+            data[..license.len()].copy_from_slice(license);
+        }
+
+        let mut sector = Sector::from_user_data(msf, &data);
+
+        if i == 1 {
+            // ECC computed with the actual header, the only kind the
+            // compressor strips
+            ecc::generate_ecc(sector.raw_mut(), false);
+        }
+
+        sectors.push(Sector::new(*sector.raw()));
+    }
+
+    // Sectors 8 to 11 are a copy of hunk 0 through the self reference
+    for i in 8..12 {
+        sectors[i] = sectors[i - 8].clone();
+    }
+
+    let build_hunk = |first: usize| {
+        let mut base = Vec::new();
+        let mut subcode = Vec::new();
+        let mut raw = Vec::new();
+
+        for (f, sector) in sectors[first..first + frames_per_hunk].iter().enumerate() {
+            let mut s = *sector.raw();
+
+            raw.extend_from_slice(&s);
+            raw.extend_from_slice(&[f as u8; 96]);
+
+            if first + f == 1 {
+                s[..12].copy_from_slice(&[0; 12]);
+                s[0x81c..0x930].copy_from_slice(&[0; 0x930 - 0x81c]);
+            }
+
+            base.extend_from_slice(&s);
+            subcode.extend_from_slice(&[f as u8; 96]);
+        }
+
+        (raw, base, miniz_oxide::deflate::compress_to_vec(&subcode, 6))
+    };
+
+    // Hunk 0, cdzl
+    let (raw0, base, subcode) = build_hunk(0);
+    let base = miniz_oxide::deflate::compress_to_vec(&base, 6);
+
+    let mut hunk0 = vec![1 << 1];
+    hunk0.extend_from_slice(&(base.len() as u16).to_be_bytes());
+    hunk0.extend_from_slice(&base);
+    hunk0.extend_from_slice(&subcode);
+
+    // Hunk 1, cdlz
+    let (raw1, base, subcode) = build_hunk(4);
+
+    let mut lzma = Vec::new();
+    let options = lzma_rs::compress::Options {
+        unpacked_size: lzma_rs::compress::UnpackedSize::WriteToHeader(Some(base.len() as u64)),
+    };
+
+    lzma_rs::lzma_compress_with_options(&mut &base[..], &mut lzma, &options).unwrap();
+
+    // Strip the LZMA header
+    let base = &lzma[13..];
+
+    let mut hunk1 = vec![0];
+    hunk1.extend_from_slice(&(base.len() as u16).to_be_bytes());
+    hunk1.extend_from_slice(base);
+    hunk1.extend_from_slice(&subcode);
+
+    let meta = b"TRACK:1 TYPE:MODE2_RAW SUBTYPE:NONE FRAMES:10 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0";
+
+    // Map: codec 0, codec 1, self reference to hunk 0
+    let mut w = BitWriter::new();
+
+    w.write_flat_tree();
+    w.write(0, 4);
+    w.write(1, 4);
+    w.write(5, 4);
+    w.write(hunk0.len() as u32, 16);
+    w.write(crc16(&raw0) as u32, 16);
+    w.write(hunk1.len() as u32, 16);
+    w.write(crc16(&raw1) as u32, 16);
+    w.write(0, 8);
+
+    let meta_offset = HEADER_SIZE;
+    let hunk0_offset = meta_offset + 16 + meta.len();
+    let hunk1_offset = hunk0_offset + hunk0.len();
+    let map_offset = hunk1_offset + hunk1.len();
+
+    let mut raw_map = Vec::new();
+
+    for (t, len, off, crc) in [(0u8, hunk0.len(), hunk0_offset, crc16(&raw0)),
+                               (1, hunk1.len(), hunk1_offset, crc16(&raw1)),
+                               (5, 0, 0, 0)] {
+        raw_map.push(t);
+        raw_map.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+        raw_map.extend_from_slice(&(off as u64).to_be_bytes()[2..]);
+        raw_map.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    let mut chd = vec![0; HEADER_SIZE];
+
+    chd[0..8].copy_from_slice(b"MComprHD");
+    chd[8..12].copy_from_slice(&(HEADER_SIZE as u32).to_be_bytes());
+    chd[12..16].copy_from_slice(&5u32.to_be_bytes());
+    chd[16..20].copy_from_slice(b"cdzl");
+    chd[20..24].copy_from_slice(b"cdlz");
+    chd[32..40].copy_from_slice(&(3 * hunk_bytes as u64).to_be_bytes());
+    chd[40..48].copy_from_slice(&(map_offset as u64).to_be_bytes());
+    chd[48..56].copy_from_slice(&(meta_offset as u64).to_be_bytes());
+    chd[56..60].copy_from_slice(&(hunk_bytes as u32).to_be_bytes());
+    chd[60..64].copy_from_slice(&(FRAME_SIZE as u32).to_be_bytes());
+
+    // Metadata entry
+    chd.extend_from_slice(&CDROM_TRACK_METADATA2_TAG.to_be_bytes());
+    chd.extend_from_slice(&(meta.len() as u32 | 0x0100_0000).to_be_bytes());
+    chd.extend_from_slice(&0u64.to_be_bytes());
+    chd.extend_from_slice(meta);
+
+    chd.extend_from_slice(&hunk0);
+    chd.extend_from_slice(&hunk1);
+
+    // Map header
+    chd.extend_from_slice(&(w.data.len() as u32).to_be_bytes());
+    chd.extend_from_slice(&(hunk0_offset as u64).to_be_bytes()[2..]);
+    chd.extend_from_slice(&crc16(&raw_map).to_be_bytes());
+    chd.extend_from_slice(&[16, 8, 0, 0]);
+    chd.extend_from_slice(&w.data);
+
+    (chd, sectors)
+}
+
+#[test]
+fn chd_image() {
+    use std::io::Cursor;
+    use super::{Disc, region::Region};
+
+    let (chd, sectors) = test_chd();
+
+    let mut image = ChdImage::from_storage(Box::new(Cursor::new(chd.clone()))).unwrap();
+
+    assert_eq!(image.toc().first_track().length, 10);
+    assert_eq!(image.toc().lead_out(), Msf::from_lba(10));
+
+    // Read out of order to exercise the hunk cache
+    for &i in &[4, 0, 1, 9, 2, 5, 3, 6, 7, 8] {
+        let sector = image.read_sector(Msf::from_lba(i)).unwrap();
+
+        assert!(sector.raw()[..] == sectors[i as usize].raw()[..], "sector {}", i);
+    }
+
+    // Pregap
+    let sector = image.read_sector(Msf::new(0, 1, 0)).unwrap();
+
+    assert!(sector.is_synthesized());
+    assert!(image.read_sector(Msf::from_lba(10)).is_err());
+
+    let disc = Disc::new(Box::new(image)).unwrap();
+
+    assert_eq!(disc.region(), Region::Europe);
+
+    // Clear the ECC flag of sector 1 in hunk 0, the hunk's CRC
+    // doesn't match anymore
+    let mut corrupted = chd;
+    let hunk0 = corrupted.windows(10).position(|w| w == b"POSTGAP:0\0").unwrap() + 10;
+
+    corrupted[hunk0] &= !2;
+
+    let mut image = ChdImage::from_storage(Box::new(Cursor::new(corrupted))).unwrap();
+
+    assert!(image.read_sector(Msf::from_lba(0)).is_err());
+    assert!(image.read_sector(Msf::from_lba(4)).is_ok());
+}
+
+#[test]
+fn corrupted_chd() {
+    use std::io::Cursor;
+
+    let (chd, _) = test_chd();
+
+    let open = |chd: &[u8]| ChdImage::from_storage(Box::new(Cursor::new(chd.to_vec())));
+
+    let map_offset = be64(&chd[40..]) as usize;
+
+    // Compressed map larger than the file
+    let mut corrupted = chd.clone();
+    corrupted[map_offset..map_offset + 4].copy_from_slice(&[0xff; 4]);
+
+    assert!(open(&corrupted).is_err());
+
+    // Way too many hunks for a CD
+    let mut corrupted = chd.clone();
+    corrupted[32..40].copy_from_slice(&[0xff; 8]);
+
+    assert!(open(&corrupted).is_err());
+
+    // Metadata entry pointing to itself
+    let mut corrupted = chd.clone();
+    corrupted[HEADER_SIZE + 8..HEADER_SIZE + 16]
+        .copy_from_slice(&(HEADER_SIZE as u64).to_be_bytes());
+
+    assert!(open(&corrupted).is_err());
+
+    assert!(open(&chd).is_ok());
+}
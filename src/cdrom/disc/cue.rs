@@ -0,0 +1,521 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result, SeekFrom};
+use std::path::Path;
+
+use super::{Image, Storage, Toc, Track};
+use super::ecm::EcmStorage;
+use super::msf::Msf;
+use super::sector::{Sector, SECTOR_SIZE};
+use super::trackformat::TrackFormat;
+
+/// Disc image described by a CUE sheet referencing one or more raw
+/// 2352 byte per sector BIN files
+pub struct CueImage {
+    toc: Toc,
+    files: Vec<Box<dyn Storage>>,
+    tracks: Vec<CueTrack>,
+}
+
+/// Layout of a track on the disc. All the positions are absolute
+/// sector indexes.
+struct CueTrack {
+    format: TrackFormat,
+    /// Index of the BIN file containing the track
+    file: usize,
+    /// Position of the first sector of `file`, taking the PREGAPs
+    /// which aren't stored in the file into account
+    file_base: u32,
+    /// First sector of the track's pregap
+    pregap_start: u32,
+    /// First sector stored in the file. The sectors between
+    /// `pregap_start` And `data_start` are generated silence.
+    data_start: u32,
+    /// One past the last sector of the track
+    end: u32,
+}
+
+/// Track as described in the CUE sheet, positions are relative to
+/// the start of the file
+struct CueSheetTrack {
+    number: u8,
+    format: TrackFormat,
+    file: usize,
+    /// Length of the PREGAP not stored in the file
+    pregap: u32,
+    index0: Option<u32>,
+    index1: Option<u32>,
+}
+
+impl CueImage {
+    /// Load the CUE sheet at `path`. The BIN files are looked up
+    /// relative to the sheet's directory. Missing BIN files are
+    /// replaced by their ECM compressed version ("game.bin.ecm") if
+    /// there's one.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<CueImage> {
+        let path = path.as_ref();
+
+        let sheet = fs::read_to_string(path)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        CueImage::from_sheet(&sheet, |name| {
+            let bin = dir.join(name);
+            let ecm = dir.join(format!("{}.ecm", name));
+
+            if !bin.exists() && ecm.exists() {
+                return Ok(Box::new(EcmStorage::new(ecm)?));
+            }
+
+            let file = File::open(bin)?;
+
+            Ok(Box::new(file))
+        })
+    }
+
+    /// Parse a CUE sheet, `open` is used to open the BIN files
+    pub fn from_sheet<F>(sheet: &str, mut open: F) -> Result<CueImage>
+        where F: FnMut(&str) -> Result<Box<dyn Storage>> {
+
+        let (file_names, sheet_tracks) = parse(sheet)?;
+
+        let mut files = Vec::new();
+
+        for name in &file_names {
+            files.push(open(name)?);
+        }
+
+        // File lengths in sectors
+        let mut lengths = Vec::new();
+
+        for f in files.iter_mut() {
+            let len = f.seek(SeekFrom::End(0))?;
+
+            if len % SECTOR_SIZE as u64 != 0 {
+                warn!("BIN file size isn't a multiple of the sector size");
+            }
+
+            lengths.push((len / SECTOR_SIZE as u64) as u32);
+        }
+
+        let tracks = layout(&sheet_tracks, &lengths)?;
+
+        let toc_tracks = sheet_tracks.iter().zip(tracks.iter())
+            .map(|(s, t)| {
+                let start = t.file_base + s.index1.unwrap();
+
+                Track {
+                    number: s.number,
+                    format: t.format,
+                    start: Msf::from_sector_index(start),
+                    length: t.end - start,
+                }
+            })
+            .collect();
+
+        Ok(CueImage {
+            toc: Toc::new(toc_tracks),
+            files,
+            tracks,
+        })
+    }
+}
+
+impl Image for CueImage {
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        let index = msf.sector_index();
+
+        let track = self.tracks.iter().rev()
+            .find(|t| t.pregap_start <= index && index < t.end)
+            .ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, format!("Sector {:?} is out of the disc", msf))
+            })?;
+
+        if index < track.data_start {
+            // The pregap isn't stored in the file
+            return Ok(Sector::empty(msf, track.format));
+        }
+
+        let offset = (index - track.file_base) as u64 * SECTOR_SIZE as u64;
+
+        let file = &mut self.files[track.file];
+
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut raw = [0; SECTOR_SIZE];
+
+        file.read_exact(&mut raw)?;
+
+        Ok(Sector::new(raw))
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Parse the CUE sheet, returns the BIN file names And the tracks
+fn parse(sheet: &str) -> Result<(Vec<String>, Vec<CueSheetTrack>)> {
+    let mut files = Vec::new();
+    let mut tracks: Vec<CueSheetTrack> = Vec::new();
+
+    for (line_no, line) in sheet.lines().enumerate() {
+        let tokens = tokenize(line);
+
+        let err = |msg: &str| invalid(format!("CUE line {}: {}", line_no + 1, msg));
+
+        let command = match tokens.first() {
+            Some(c) => c.to_ascii_uppercase(),
+            None => continue,
+        };
+
+        match command.as_str() {
+            "FILE" => {
+                if tokens.len() != 3 {
+                    return Err(err("Invalid FILE"));
+                }
+
+                if !tokens[2].eq_ignore_ascii_case("BINARY") {
+                    return Err(err(&format!("Unsupported file type {}", tokens[2])));
+                }
+
+                files.push(tokens[1].clone());
+            }
+            "TRACK" => {
+                if files.is_empty() {
+                    return Err(err("TRACK without FILE"));
+                }
+
+                if tokens.len() != 3 {
+                    return Err(err("Invalid TRACK"));
+                }
+
+                let number = tokens[1].parse::<u8>()
+                    .map_err(|_| err("Invalid track number"))?;
+
+                let expected = tracks.last().map(|t| t.number + 1).unwrap_or(1);
+
+                if number != expected || number > 99 {
+                    return Err(err("Tracks out of order"));
+                }
+
+                let format = match tokens[2].to_ascii_uppercase().as_str() {
+                    "AUDIO" => TrackFormat::Audio,
+                    "MODE1/2352" => TrackFormat::Mode1,
+                    "MODE2/2352" => TrackFormat::Mode2,
+                    f => return Err(err(&format!("Unsupported track mode {}", f))),
+                };
+
+                tracks.push(CueSheetTrack {
+                    number,
+                    format,
+                    file: files.len() - 1,
+                    pregap: 0,
+                    index0: None,
+                    index1: None,
+                });
+            }
+            "INDEX" => {
+                let track = tracks.last_mut().ok_or_else(|| err("INDEX without TRACK"))?;
+
+                if tokens.len() != 3 {
+                    return Err(err("Invalid INDEX"));
+                }
+
+                let position = parse_msf(&tokens[2]).ok_or_else(|| err("Invalid INDEX position"))?;
+
+                match tokens[1].parse::<u8>() {
+                    Ok(0) => track.index0 = Some(position),
+                    Ok(1) => track.index1 = Some(position),
+                    // Sub-indexes don't matter for the emulation
+                    Ok(_) => (),
+                    Err(_) => return Err(err("Invalid INDEX number")),
+                }
+            }
+            "PREGAP" => {
+                let track = tracks.last_mut().ok_or_else(|| err("PREGAP without TRACK"))?;
+
+                if tokens.len() != 2 {
+                    return Err(err("Invalid PREGAP"));
+                }
+
+                track.pregap = parse_msf(&tokens[1]).ok_or_else(|| err("Invalid PREGAP length"))?;
+            }
+            "POSTGAP" => return Err(err("POSTGAP isn't supported")),
+            "REM" | "CATALOG" | "CDTEXTFILE" | "FLAGS" | "ISRC" |
+            "PERFORMER" | "SONGWRITER" | "TITLE" => (),
+            _ => return Err(err(&format!("Unknown command {}", command))),
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err(invalid("CUE sheet without any track".to_string()));
+    }
+
+    Ok((files, tracks))
+}
+
+/// Place the tracks on the disc. `lengths` contains the length of
+/// each file in sectors.
+fn layout(sheet_tracks: &[CueSheetTrack], lengths: &[u32]) -> Result<Vec<CueTrack>> {
+    let mut tracks: Vec<CueTrack> = Vec::new();
+
+    // Position of the start of the current file
+    let mut file_start = 0;
+    // Position of the end of the current file
+    let mut file_end = 0;
+    // Number of PREGAP sectors inserted in the current file so far
+    let mut shift = 0;
+
+    for (i, t) in sheet_tracks.iter().enumerate() {
+        let index1 = t.index1.ok_or_else(|| {
+            invalid(format!("Track {} doesn't have an INDEX 01", t.number))
+        })?;
+
+        let first = t.index0.unwrap_or(index1);
+
+        if first > index1 {
+            return Err(invalid(format!("Track {}: INDEX 00 after INDEX 01", t.number)));
+        }
+
+        let new_file = i == 0 || sheet_tracks[i - 1].file != t.file;
+
+        if new_file {
+            file_start = if i == 0 {
+                // The first track's data always starts at 00:02:00
+                150u32.checked_sub(index1).ok_or_else(|| {
+                    invalid("First track's INDEX 01 is too far into the file".to_string())
+                })?
+            } else {
+                file_end
+            };
+
+            shift = 0;
+        } else if first <= sheet_tracks[i - 1].index1.unwrap() {
+            // The previous track would end before it starts
+            return Err(invalid(format!("Track {} starts before the end of track {}",
+                                       t.number, t.number - 1)));
+        }
+
+        // The first track's 2 second pregap is implicit
+        let pregap = if i == 0 { 0 } else { t.pregap };
+
+        shift += pregap;
+
+        let file_base = file_start + shift;
+        let data_start = file_base + first;
+
+        file_end = file_base + lengths[t.file];
+
+        if file_base + index1 >= file_end {
+            return Err(invalid(format!("Track {} is past the end of its file", t.number)));
+        }
+
+        if let Some(prev) = tracks.last_mut() {
+            if !new_file {
+                prev.end = data_start - pregap;
+            }
+        }
+
+        tracks.push(CueTrack {
+            format: t.format,
+            file: t.file,
+            file_base,
+            pregap_start: data_start - pregap,
+            data_start,
+            end: file_end,
+        });
+    }
+
+    Ok(tracks)
+}
+
+/// Parse a "mm:ss:ff" position into a sector count
+fn parse_msf(s: &str) -> Option<u32> {
+    let mut parts = s.split(':').map(|p| p.parse::<u32>().ok());
+
+    let m = parts.next()??;
+    let s = parts.next()??;
+    let f = parts.next()??;
+
+    if parts.next().is_some() || s >= 60 || f >= 75 {
+        return None;
+    }
+
+    Some((m * 60 + s) * 75 + f)
+}
+
+/// Split a CUE sheet line into tokens, handling double quotes
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut in_token = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_token {
+                    tokens.push(token.clone());
+                    token.clear();
+                    in_token = false;
+                }
+            }
+            c => {
+                token.push(c);
+                in_token = true;
+            }
+        }
+    }
+
+    if in_token {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Build an in-memory BIN file of `sectors` sectors, every byte of
+/// sector `n` is set to `fill + n`
+#[cfg(test)]
+fn test_bin(sectors: u32, fill: u8) -> Box<dyn Storage> {
+    let mut data = Vec::new();
+
+    for n in 0..sectors {
+        data.extend_from_slice(&[fill + n as u8; SECTOR_SIZE]);
+    }
+
+    Box::new(std::io::Cursor::new(data))
+}
+
+#[test]
+fn tokens() {
+    assert_eq!(tokenize("  FILE \"Game (Disc 1).bin\" BINARY"),
+               ["FILE", "Game (Disc 1).bin", "BINARY"]);
+    assert_eq!(tokenize("TITLE \"\""), ["TITLE", ""]);
+    assert!(tokenize("   ").is_empty());
+}
+
+#[test]
+fn multiple_files_and_pregaps() {
+    let sheet = "\
+FILE \"a.bin\" BINARY
+  TRACK 01 MODE2/2352
+    INDEX 01 00:00:00
+FILE \"b.bin\" BINARY
+  TRACK 02 AUDIO
+    INDEX 00 00:00:00
+    INDEX 01 00:00:02
+  TRACK 03 AUDIO
+    PREGAP 00:00:03
+    INDEX 01 00:00:05
+";
+
+    let mut image = CueImage::from_sheet(sheet, |name| match name {
+        "a.bin" => Ok(test_bin(10, 0x00)),
+        "b.bin" => Ok(test_bin(8, 0x80)),
+        _ => panic!("Unexpected file {}", name),
+    }).unwrap();
+
+    let toc = image.toc().clone();
+
+    let track1 = toc.track(1).unwrap();
+    assert_eq!(track1.format, TrackFormat::Mode2);
+    assert_eq!(track1.start, Msf::new(0, 2, 0));
+    assert_eq!(track1.length, 10);
+
+    // Track 2's pregap is stored in b.bin
+    let track2 = toc.track(2).unwrap();
+    assert_eq!(track2.format, TrackFormat::Audio);
+    assert_eq!(track2.start, Msf::from_sector_index(162));
+    assert_eq!(track2.length, 3);
+
+    // Track 3's PREGAP isn't
+    let track3 = toc.track(3).unwrap();
+    assert_eq!(track3.start, Msf::from_sector_index(168));
+    assert_eq!(track3.length, 3);
+    assert_eq!(toc.lead_out(), Msf::from_sector_index(171));
+
+    let mut byte_at = |index| image.read_sector(Msf::from_sector_index(index)).unwrap().raw()[100];
+
+    assert_eq!(byte_at(150), 0x00);
+    assert_eq!(byte_at(159), 0x09);
+    assert_eq!(byte_at(160), 0x80);
+    assert_eq!(byte_at(164), 0x84);
+    // Generated silence
+    assert_eq!(byte_at(165), 0x00);
+    assert_eq!(byte_at(167), 0x00);
+    assert_eq!(byte_at(168), 0x85);
+    assert_eq!(byte_at(170), 0x87);
+
+    assert!(image.read_sector(Msf::from_sector_index(171)).is_err());
+}
+
+#[test]
+fn single_file() {
+    let sheet = "\
+REM Single BIN
+FILE \"game.bin\" BINARY
+  TRACK 01 MODE2/2352
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    INDEX 00 00:00:06
+    INDEX 01 00:00:08
+";
+
+    let mut image = CueImage::from_sheet(sheet, |_| Ok(test_bin(12, 0x10))).unwrap();
+
+    let toc = image.toc().clone();
+
+    assert_eq!(toc.track(1).unwrap().length, 6);
+    assert_eq!(toc.track(2).unwrap().start, Msf::from_sector_index(158));
+    assert_eq!(toc.track(2).unwrap().length, 4);
+
+    let sector = image.read_sector(Msf::from_sector_index(157)).unwrap();
+    assert_eq!(sector.raw()[0], 0x17);
+}
+
+#[test]
+fn invalid_sheets() {
+    let open = |_: &str| Ok(test_bin(4, 0));
+
+    let missing_index = "FILE \"a.bin\" BINARY\nTRACK 01 MODE2/2352\n";
+    assert!(CueImage::from_sheet(missing_index, open).is_err());
+
+    let bad_mode = "FILE \"a.bin\" BINARY\nTRACK 01 MODE2/2336\nINDEX 01 00:00:00\n";
+    assert!(CueImage::from_sheet(bad_mode, open).is_err());
+
+    let no_file = "TRACK 01 AUDIO\nINDEX 01 00:00:00\n";
+    assert!(CueImage::from_sheet(no_file, open).is_err());
+}
+
+#[test]
+fn backwards_indexes() {
+    let open = |_: &str| Ok(test_bin(12, 0));
+
+    let sheet = |index0: &str, index1: &str| format!("\
+FILE \"game.bin\" BINARY
+  TRACK 01 MODE2/2352
+    INDEX 01 00:00:04
+  TRACK 02 AUDIO
+    INDEX 00 {}
+    INDEX 01 {}
+", index0, index1);
+
+    for (index0, index1) in [("00:00:02", "00:00:06"),
+                             ("00:00:04", "00:00:06"),
+                             ("00:00:01", "00:00:02")] {
+        let err = CueImage::from_sheet(&sheet(index0, index1), open).err().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    assert!(CueImage::from_sheet(&sheet("00:00:05", "00:00:06"), open).is_ok());
+}
@@ -0,0 +1,174 @@
+//! CD-ROM error detection (EDC) And correction (ECC) codes
+
+use super::sector::SECTOR_SIZE;
+
+/// Lookup tables used to compute the ECC, multiplication by 2 in the
+/// CD-ROM's Galois field And its inverse
+const ECC_F_LUT: [u8; 256] = ecc_f_lut();
+const ECC_B_LUT: [u8; 256] = ecc_b_lut();
+
+/// Lookup table for the EDC, a CRC32 using the polynomial
+/// 0xd8018001 (reversed)
+const EDC_LUT: [u32; 256] = edc_lut();
+
+const fn ecc_f_lut() -> [u8; 256] {
+    let mut lut = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let j = (i << 1) ^ if i & 0x80 != 0 { 0x11d } else { 0 };
+
+        lut[i] = j as u8;
+        i += 1;
+    }
+
+    lut
+}
+
+const fn ecc_b_lut() -> [u8; 256] {
+    let mut lut = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let j = (i << 1) ^ if i & 0x80 != 0 { 0x11d } else { 0 };
+
+        lut[i ^ j] = i as u8;
+        i += 1;
+    }
+
+    lut
+}
+
+const fn edc_lut() -> [u32; 256] {
+    let mut lut = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut edc = i as u32;
+        let mut k = 0;
+
+        while k < 8 {
+            edc = (edc >> 1) ^ if edc & 1 != 0 { 0xd8018001 } else { 0 };
+            k += 1;
+        }
+
+        lut[i] = edc;
+        i += 1;
+    }
+
+    lut
+}
+
+/// Compute the EDC of `data`
+pub fn edc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |edc, &b| {
+        (edc >> 8) ^ EDC_LUT[((edc ^ b as u32) & 0xff) as usize]
+    })
+}
+
+/// Compute one of the two ECC blocks. `src` starts at the sector
+/// header, the parity bytes are written to `dest`.
+fn ecc_block(src: &[u8],
+             major_count: usize,
+             minor_count: usize,
+             major_mult: usize,
+             minor_inc: usize,
+             dest: &mut [u8]) {
+    let size = major_count * minor_count;
+
+    for major in 0..major_count {
+        let mut index = (major >> 1) * major_mult + (major & 1);
+
+        let mut ecc_a = 0u8;
+        let mut ecc_b = 0u8;
+
+        for _ in 0..minor_count {
+            let b = src[index];
+
+            index += minor_inc;
+
+            if index >= size {
+                index -= size;
+            }
+
+            ecc_a ^= b;
+            ecc_b ^= b;
+            ecc_a = ECC_F_LUT[ecc_a as usize];
+        }
+
+        ecc_a = ECC_B_LUT[(ECC_F_LUT[ecc_a as usize] ^ ecc_b) as usize];
+
+        dest[major] = ecc_a;
+        dest[major + major_count] = ecc_a ^ ecc_b;
+    }
+}
+
+/// Generate the P And Q parity bytes of a Mode 1 or Mode 2 Form 1
+/// sector. Mode 2 sectors compute the ECC with a zeroed header.
+pub fn generate_ecc(raw: &mut [u8; SECTOR_SIZE], zero_address: bool) {
+    let header = [raw[12], raw[13], raw[14], raw[15]];
+
+    if zero_address {
+        raw[12..16].copy_from_slice(&[0; 4]);
+    }
+
+    {
+        // P parity
+        let (src, dest) = raw.split_at_mut(0x81c);
+
+        ecc_block(&src[0xc..], 86, 24, 2, 86, &mut dest[..172]);
+    }
+
+    {
+        // Q parity, covers the P parity bytes
+        let (src, dest) = raw.split_at_mut(0x8c8);
+
+        ecc_block(&src[0xc..], 52, 43, 86, 88, &mut dest[..104]);
+    }
+
+    raw[12..16].copy_from_slice(&header);
+}
+
+#[test]
+fn edc_remainder() {
+    let data: Vec<u8> = (0..2056).map(|i| (i * 7) as u8).collect();
+
+    let mut checked = data.clone();
+
+    checked.extend_from_slice(&edc(&data).to_le_bytes());
+
+    // Appending the EDC yields a null remainder
+    assert_eq!(edc(&checked), 0);
+    assert_ne!(edc(&data), 0);
+}
+
+#[test]
+fn p_parity() {
+    let mut raw = [0; SECTOR_SIZE];
+
+    for (i, b) in raw[12..2076].iter_mut().enumerate() {
+        *b = (i * 13) as u8;
+    }
+
+    generate_ecc(&mut raw, false);
+
+    // Each P codeword is a column of 24 bytes followed by 2 parity
+    // bytes, their sum in GF(2^8) is null
+    for column in 0..86 {
+        let mut sum = raw[0x81c + column] ^ raw[0x81c + 86 + column];
+
+        for row in 0..24 {
+            sum ^= raw[12 + row * 86 + column];
+        }
+
+        assert_eq!(sum, 0);
+    }
+
+    // Changing the data changes the parity
+    let p = raw[0x81c..0x8c8].to_vec();
+
+    raw[100] ^= 1;
+    generate_ecc(&mut raw, false);
+
+    assert_ne!(&raw[0x81c..0x8c8], &p[..]);
+}
@@ -0,0 +1,430 @@
+//! "Error Code Modeler" images (".ecm"). ECM strips the sync
+//! patterns, headers And error correction codes from the sectors of
+//! a BIN file, they're regenerated when the file is read back.
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::Path;
+
+use super::{Image, Storage};
+use super::cue::CueImage;
+use super::ecc;
+use super::sector::{SECTOR_SIZE, SYNC_PATTERN};
+
+/// Kind of data stored in a record
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RecordType {
+    /// Bytes stored as-is
+    Raw,
+    /// Full Mode 1 sector, only the address And user data are stored
+    Mode1,
+    /// Mode 2 Form 1 sector without the sync pattern And header,
+    /// only the subheader And user data are stored
+    Mode2Form1,
+    /// Mode 2 Form 2 sector without the sync pattern And header,
+    /// only the subheader And user data are stored
+    Mode2Form2,
+}
+
+impl RecordType {
+    fn from_code(code: u8) -> RecordType {
+        match code & 3 {
+            0 => RecordType::Raw,
+            1 => RecordType::Mode1,
+            2 => RecordType::Mode2Form1,
+            _ => RecordType::Mode2Form2,
+        }
+    }
+
+    /// Number of bytes of an item in the ECM file
+    fn stored_size(self) -> u64 {
+        match self {
+            RecordType::Raw => 1,
+            RecordType::Mode1 => 3 + 2048,
+            RecordType::Mode2Form1 => 4 + 2048,
+            RecordType::Mode2Form2 => 4 + 2324,
+        }
+    }
+
+    /// Number of bytes of an item once decoded
+    fn decoded_size(self) -> u64 {
+        match self {
+            RecordType::Raw => 1,
+            RecordType::Mode1 => SECTOR_SIZE as u64,
+            // Everything but the sync And header
+            RecordType::Mode2Form1 | RecordType::Mode2Form2 => SECTOR_SIZE as u64 - 16,
+        }
+    }
+}
+
+/// Run of `count` items of the same type
+#[derive(Debug)]
+struct Record {
+    kind: RecordType,
+    count: u64,
+    /// Position of the first item in the ECM file
+    stored_offset: u64,
+    /// Position of the first item in the decoded file
+    decoded_offset: u64,
+}
+
+/// Decoded view of an ECM file. Sectors are rebuilt on the fly so it
+/// can be used in place of the original BIN file.
+pub struct EcmStorage {
+    storage: Box<dyn Storage>,
+    records: Vec<Record>,
+    /// Size of the decoded file
+    len: u64,
+    /// Current position in the decoded file
+    pos: u64,
+    /// Last decoded item: decoded offset And data
+    cache: Option<(u64, Vec<u8>)>,
+}
+
+impl EcmStorage {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<EcmStorage> {
+        let file = File::open(path)?;
+
+        EcmStorage::from_storage(Box::new(io::BufReader::new(file)))
+    }
+
+    /// Index the records of the ECM file
+    pub fn from_storage(mut storage: Box<dyn Storage>) -> Result<EcmStorage> {
+        let mut magic = [0; 4];
+
+        storage.seek(SeekFrom::Start(0))?;
+        storage.read_exact(&mut magic)?;
+
+        if &magic != b"ECM\0" {
+            return Err(invalid("Missing ECM signature"));
+        }
+
+        let mut records = Vec::new();
+        let mut stored_offset = 4;
+        let mut decoded_offset = 0;
+
+        loop {
+            let (code, count, header_len) = read_record_header(storage.as_mut())?;
+
+            stored_offset += header_len;
+
+            let count = match count {
+                Some(c) => c,
+                // End of the records, followed by the EDC of the
+                // whole file
+                None => break,
+            };
+
+            let kind = RecordType::from_code(code);
+
+            records.push(Record {
+                kind,
+                count,
+                stored_offset,
+                decoded_offset,
+            });
+
+            stored_offset += count * kind.stored_size();
+            decoded_offset += count * kind.decoded_size();
+
+            storage.seek(SeekFrom::Start(stored_offset))?;
+        }
+
+        Ok(EcmStorage {
+            storage,
+            records,
+            len: decoded_offset,
+            pos: 0,
+            cache: None,
+        })
+    }
+
+    /// Decode the item containing `pos` in the cache. Returns the
+    /// offset of `pos` in the cached data.
+    fn load_item(&mut self, pos: u64) -> Result<usize> {
+        if let Some((start, ref data)) = self.cache {
+            if pos >= start && pos < start + data.len() as u64 {
+                return Ok((pos - start) as usize);
+            }
+        }
+
+        let index = self.records.partition_point(|r| r.decoded_offset <= pos) - 1;
+        let record = &self.records[index];
+
+        let kind = record.kind;
+        let item = (pos - record.decoded_offset) / kind.decoded_size();
+        let item_start = record.decoded_offset + item * kind.decoded_size();
+
+        self.storage.seek(SeekFrom::Start(record.stored_offset + item * kind.stored_size()))?;
+
+        let data = match kind {
+            RecordType::Raw => {
+                // Read the rest of the run at once
+                let len = (record.count - item).min(0x8000);
+                let mut data = vec![0; len as usize];
+
+                self.storage.read_exact(&mut data)?;
+
+                data
+            }
+            _ => decode_sector(kind, self.storage.as_mut())?,
+        };
+
+        self.cache = Some((item_start, data));
+
+        Ok((pos - item_start) as usize)
+    }
+}
+
+impl Read for EcmStorage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+
+        while read < buf.len() && self.pos < self.len {
+            let offset = self.load_item(self.pos)?;
+
+            let data = &self.cache.as_ref().unwrap().1[offset..];
+            let len = data.len().min(buf.len() - read);
+
+            buf[read..read + len].copy_from_slice(&data[..len]);
+
+            read += len;
+            self.pos += len as u64;
+        }
+
+        Ok(read)
+    }
+}
+
+impl Seek for EcmStorage {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+
+        match new_pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            None => Err(Error::new(ErrorKind::InvalidInput, "Seek before the start of the file")),
+        }
+    }
+}
+
+/// Read a record header: the type is stored in the low 2 bits of the
+/// first byte, followed by the item count minus one as a variable
+/// length integer. Returns the type, the count (None for the end
+/// marker) And the size of the header.
+fn read_record_header(storage: &mut dyn Storage) -> Result<(u8, Option<u64>, u64)> {
+    let mut b = [0];
+
+    storage.read_exact(&mut b)?;
+
+    let code = b[0] & 3;
+    let mut count = ((b[0] >> 2) & 0x1f) as u64;
+    let mut shift = 5;
+    let mut len = 1;
+
+    while b[0] & 0x80 != 0 {
+        if shift > 31 {
+            return Err(invalid("Invalid ECM record header"));
+        }
+
+        storage.read_exact(&mut b)?;
+
+        count |= ((b[0] & 0x7f) as u64) << shift;
+        shift += 7;
+        len += 1;
+    }
+
+    if count == 0xffff_ffff {
+        Ok((code, None, len))
+    } else if count > 0xffff_ffff {
+        Err(invalid("Invalid ECM record header"))
+    } else {
+        Ok((code, Some(count + 1), len))
+    }
+}
+
+/// Read a stored sector And rebuild its sync pattern, header And
+/// error correction codes
+fn decode_sector(kind: RecordType, storage: &mut dyn Storage) -> Result<Vec<u8>> {
+    let mut raw = [0; SECTOR_SIZE];
+
+    match kind {
+        RecordType::Mode1 => {
+            raw[..12].copy_from_slice(&SYNC_PATTERN);
+            storage.read_exact(&mut raw[12..15])?;
+            raw[15] = 1;
+            storage.read_exact(&mut raw[16..2064])?;
+
+            let edc = ecc::edc(&raw[..2064]);
+            raw[2064..2068].copy_from_slice(&edc.to_le_bytes());
+
+            ecc::generate_ecc(&mut raw, false);
+
+            Ok(raw.to_vec())
+        }
+        RecordType::Mode2Form1 => {
+            storage.read_exact(&mut raw[0x14..0x818])?;
+            raw.copy_within(0x14..0x18, 0x10);
+
+            let edc = ecc::edc(&raw[0x10..0x818]);
+            raw[0x818..0x81c].copy_from_slice(&edc.to_le_bytes());
+
+            ecc::generate_ecc(&mut raw, true);
+
+            Ok(raw[16..].to_vec())
+        }
+        RecordType::Mode2Form2 => {
+            storage.read_exact(&mut raw[0x14..0x92c])?;
+            raw.copy_within(0x14..0x18, 0x10);
+
+            let edc = ecc::edc(&raw[0x10..0x92c]);
+            raw[0x92c..0x930].copy_from_slice(&edc.to_le_bytes());
+
+            Ok(raw[16..].to_vec())
+        }
+        RecordType::Raw => unreachable!(),
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Open the ECM file at `path`. If a CUE sheet for the decoded BIN
+/// file exists ("game.bin.ecm" -> "game.cue") it's used, otherwise
+/// the image is treated as a single Mode 2 track.
+pub fn open_image(path: &Path) -> Result<Box<dyn Image>> {
+    let cue = path.with_extension("").with_extension("cue");
+
+    if cue.exists() {
+        return Ok(Box::new(CueImage::new(cue)?));
+    }
+
+    let sheet = "FILE \"image.bin\" BINARY\n  TRACK 01 MODE2/2352\n    INDEX 01 00:00:00\n";
+
+    let image = CueImage::from_sheet(sheet, |_| {
+        Ok(Box::new(EcmStorage::new(path)?))
+    })?;
+
+    Ok(Box::new(image))
+}
+
+/// Encode a record header, used to build test images
+#[cfg(test)]
+fn record_header(code: u8, count: u64) -> Vec<u8> {
+    let mut n = count - 1;
+    let mut header = vec![code | ((n & 0x1f) as u8) << 2];
+
+    n >>= 5;
+
+    while n != 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((n & 0x7f) as u8);
+        n >>= 7;
+    }
+
+    header
+}
+
+#[test]
+fn ecm_storage() {
+    use std::io::Cursor;
+    use super::msf::Msf;
+    use super::sector::Sector;
+
+    // Raw bytes, 2 Mode 1 sectors, a Mode 2 Form 1 sector with its
+    // sync And header stored as raw bytes, a Mode 2 Form 2 sector
+    let mut bin = vec![0x5a; 100];
+    let mut ecm = b"ECM\0".to_vec();
+
+    ecm.extend(record_header(0, 100));
+    ecm.extend_from_slice(&bin);
+
+    ecm.extend(record_header(1, 2));
+
+    for i in 0..2 {
+        let msf = Msf::from_lba(i);
+        let mut sector = Sector::empty(msf, super::trackformat::TrackFormat::Mode1);
+
+        sector.raw_mut()[16..2064].copy_from_slice(&[i as u8 + 1; 2048]);
+
+        let edc = ecc::edc(&sector.raw()[..2064]);
+        sector.raw_mut()[2064..2068].copy_from_slice(&edc.to_le_bytes());
+        ecc::generate_ecc(sector.raw_mut(), false);
+
+        bin.extend_from_slice(sector.raw());
+        ecm.extend_from_slice(&sector.raw()[12..15]);
+        ecm.extend_from_slice(&sector.raw()[16..2064]);
+    }
+
+    let form1 = Sector::from_user_data(Msf::from_lba(2), &[0xa5; 2048]);
+
+    bin.extend_from_slice(form1.raw());
+    ecm.extend(record_header(0, 16));
+    ecm.extend_from_slice(&form1.raw()[..16]);
+    ecm.extend(record_header(2, 1));
+    ecm.extend_from_slice(&form1.raw()[0x14..0x818]);
+
+    let mut form2 = *form1.raw();
+
+    form2[0x12] = 0x28;
+    form2[0x16] = 0x28;
+    form2[0x18..0x92c].copy_from_slice(&[0x3c; 2324]);
+
+    let edc = ecc::edc(&form2[0x10..0x92c]);
+    form2[0x92c..0x930].copy_from_slice(&edc.to_le_bytes());
+
+    bin.extend_from_slice(&form2[..16]);
+    ecm.extend(record_header(0, 16));
+    ecm.extend_from_slice(&form2[..16]);
+    bin.extend_from_slice(&form2[16..]);
+    ecm.extend(record_header(3, 1));
+    ecm.extend_from_slice(&form2[0x14..0x92c]);
+
+    // End marker And (unchecked) EDC
+    ecm.extend(record_header(0, 0x1_0000_0000));
+    ecm.extend_from_slice(&[0; 4]);
+
+    let mut storage = EcmStorage::from_storage(Box::new(Cursor::new(ecm))).unwrap();
+
+    assert_eq!(storage.seek(SeekFrom::End(0)).unwrap(), bin.len() as u64);
+
+    storage.seek(SeekFrom::Start(0)).unwrap();
+
+    let mut decoded = Vec::new();
+
+    storage.read_to_end(&mut decoded).unwrap();
+
+    assert!(decoded == bin);
+
+    // Random access in the middle of a sector
+    let offset = 100 + SECTOR_SIZE + 1000;
+    let mut buf = [0; 3000];
+
+    storage.seek(SeekFrom::Start(offset as u64)).unwrap();
+    storage.read_exact(&mut buf).unwrap();
+
+    assert!(buf[..] == bin[offset..offset + 3000]);
+
+    assert!(EcmStorage::from_storage(Box::new(Cursor::new(b"ECN\0".to_vec()))).is_err());
+}
+
+#[test]
+fn record_headers() {
+    for &count in &[1, 32, 33, 4096, 0xffff_ffff] {
+        let header = record_header(2, count);
+        let mut cursor = io::Cursor::new(header.clone());
+
+        let (code, c, len) = read_record_header(&mut cursor).unwrap();
+
+        assert_eq!(code, 2);
+        assert_eq!(c, Some(count));
+        assert_eq!(len, header.len() as u64);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, SeekFrom};
+use std::path::Path;
+
+use super::{Image, Storage, Toc, Track};
+use super::msf::Msf;
+use super::sector::{Sector, USER_DATA_SIZE};
+use super::trackformat::TrackFormat;
+
+/// Single track image containing only the 2048 byte user data of
+/// each sector (".iso"). The rest of the sector is synthesized as
+/// Mode 2 Form 1.
+pub struct IsoImage {
+    toc: Toc,
+    storage: Box<dyn Storage>,
+}
+
+impl IsoImage {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<IsoImage> {
+        let file = File::open(path)?;
+
+        IsoImage::from_storage(Box::new(file))
+    }
+
+    pub fn from_storage(mut storage: Box<dyn Storage>) -> Result<IsoImage> {
+        let len = storage.seek(SeekFrom::End(0))?;
+
+        if len == 0 || len % USER_DATA_SIZE as u64 != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid ISO image size"));
+        }
+
+        let track = Track {
+            number: 1,
+            format: TrackFormat::Mode2,
+            start: Msf::from_lba(0),
+            length: (len / USER_DATA_SIZE as u64) as u32,
+        };
+
+        Ok(IsoImage {
+            toc: Toc::new(vec![track]),
+            storage,
+        })
+    }
+}
+
+impl Image for IsoImage {
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        let lba = match msf.lba() {
+            Some(lba) => lba,
+            // Pregap, not stored in the image
+            None => return Ok(Sector::empty(msf, TrackFormat::Mode2)),
+        };
+
+        if lba >= self.toc.first_track().length {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Sector {:?} is out of the disc", msf)));
+        }
+
+        self.storage.seek(SeekFrom::Start(lba as u64 * USER_DATA_SIZE as u64))?;
+
+        let mut data = [0; USER_DATA_SIZE];
+
+        self.storage.read_exact(&mut data)?;
+
+        Ok(Sector::from_user_data(msf, &data))
+    }
+}
+
+#[test]
+fn iso_image() {
+    use std::io::Cursor;
+
+    let mut data = vec![0u8; USER_DATA_SIZE * 20];
+
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = (i / USER_DATA_SIZE) as u8;
+    }
+
+    let mut image = IsoImage::from_storage(Box::new(Cursor::new(data))).unwrap();
+
+    assert_eq!(image.toc().first_track().length, 20);
+    assert_eq!(image.toc().lead_out(), Msf::from_lba(20));
+
+    let sector = image.read_sector(Msf::from_lba(16)).unwrap();
+
+    assert!(sector.is_synthesized());
+    assert_eq!(sector.header_msf(), Some(Msf::new(0, 2, 16)));
+    assert!(sector.data_2048().iter().all(|&b| b == 16));
+
+    // Pregap
+    let sector = image.read_sector(Msf::new(0, 1, 0)).unwrap();
+
+    assert_eq!(sector.header_msf(), Some(Msf::new(0, 1, 0)));
+    assert!(sector.data_2048().iter().all(|&b| b == 0));
+
+    assert!(image.read_sector(Msf::from_lba(20)).is_err());
+
+    // Truncated image
+    assert!(IsoImage::from_storage(Box::new(Cursor::new(vec![0; 1000]))).is_err());
+}
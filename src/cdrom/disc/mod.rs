@@ -0,0 +1,261 @@
+use std::io::{Error, ErrorKind, Read, Result, Seek};
+use std::path::Path;
+
+use self::msf::Msf;
+use self::region::Region;
+use self::sector::Sector;
+use self::trackformat::TrackFormat;
+
+pub mod chd;
+pub mod cue;
+pub mod ecc;
+pub mod ecm;
+pub mod iso;
+pub mod msf;
+pub mod pbp;
+pub mod region;
+pub mod sector;
+pub mod trackformat;
+
+/// Interface implemented by the various disc image formats. Images
+/// work with absolute positions: the first track's data starts at
+/// 00:02:00 after the 2 second pregap.
+pub trait Image {
+    /// Table of contents of the disc
+    fn toc(&self) -> &Toc;
+
+    /// Read the raw sector at position `msf`
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector>;
+}
+
+/// Seekable data source backing a disc image. Implemented for files
+/// as well as in-memory buffers.
+pub trait Storage: Read + Seek {}
+
+impl<T: Read + Seek> Storage for T {}
+
+/// A single track of the disc
+#[derive(Copy, Clone, Debug)]
+pub struct Track {
+    /// Track number, starting at 1
+    pub number: u8,
+    pub format: TrackFormat,
+    /// Absolute position of the track's index 01
+    pub start: Msf,
+    /// Length of the track in sectors, starting from `start`
+    pub length: u32,
+}
+
+/// Table of contents
+#[derive(Clone, Debug)]
+pub struct Toc {
+    tracks: Vec<Track>,
+}
+
+impl Toc {
+    /// Build a TOC, `tracks` must be sorted And can't be empty
+    pub fn new(tracks: Vec<Track>) -> Toc {
+        if tracks.is_empty() {
+            panic!("Empty table of contents");
+        }
+
+        Toc { tracks }
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn first_track(&self) -> &Track {
+        &self.tracks[0]
+    }
+
+    pub fn last_track(&self) -> &Track {
+        &self.tracks[self.tracks.len() - 1]
+    }
+
+    /// Return the track with number `number` if it exists
+    pub fn track(&self, number: u8) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.number == number)
+    }
+
+    /// Position of the end of the last track
+    pub fn lead_out(&self) -> Msf {
+        let last = self.last_track();
+
+        Msf::from_sector_index(last.start.sector_index() + last.length)
+    }
+
+    /// Return the track containing `msf`, if any. Pregaps belong to
+    /// the previous track.
+    pub fn track_at(&self, msf: Msf) -> Option<&Track> {
+        if msf >= self.lead_out() {
+            return None;
+        }
+
+        self.tracks.iter().rev().find(|t| t.start <= msf)
+    }
+}
+
+/// Disc loaded in the CD drive
+pub struct Disc {
+    image: Box<dyn Image>,
+    region: Region,
+}
+
+impl Disc {
+    /// Build a disc from an image, the region is read from the
+    /// license string
+    pub fn new(mut image: Box<dyn Image>) -> Result<Disc> {
+        let region = disc_region(image.as_mut())?;
+
+        Ok(Disc {
+            image,
+            region,
+        })
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn toc(&self) -> &Toc {
+        self.image.toc()
+    }
+
+    pub fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        self.image.read_sector(msf)
+    }
+}
+
+/// Open the disc image at `path`, the format is deduced from the
+/// file extension. For multi-disc PBP files this loads the first
+/// disc, use `pbp::PbpImage` to access the others.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Disc> {
+    let path = path.as_ref();
+
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    let image: Box<dyn Image> = match extension.as_deref() {
+        Some("chd") => Box::new(chd::ChdImage::new(path)?),
+        Some("cue") => Box::new(cue::CueImage::new(path)?),
+        Some("ecm") => ecm::open_image(path)?,
+        Some("iso") => Box::new(iso::IsoImage::new(path)?),
+        Some("pbp") => Box::new(pbp::PbpImage::new(path, 0)?),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, "Unsupported disc image format")),
+    };
+
+    Disc::new(image)
+}
+
+/// Look for the license string in sector 00:02:04 to figure out the
+/// region of the disc
+fn disc_region(image: &mut dyn Image) -> Result<Region> {
+    let sector = image.read_sector(Msf::new(0, 2, 4))?;
+
+    let license = sector.data_2048();
+
+    let contains = |pattern: &[u8]| {
+        license.windows(pattern.len()).any(|w| w == pattern)
+    };
+
+    if !contains(b"Sony Computer Entertainment") {
+        return Err(Error::new(ErrorKind::InvalidData, "Missing license string"));
+    }
+
+    // The license string is padded with spaces in the middle of the
+    // words ("Amer  ica", "Euro pe")
+    if contains(b"Inc.") {
+        Ok(Region::Japan)
+    } else if contains(b"Amer") {
+        Ok(Region::NorthAmerica)
+    } else if contains(b"Euro") {
+        Ok(Region::Europe)
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, "Unknown disc region"))
+    }
+}
+
+/// In-memory single track Mode 2 image used by the tests. Each
+/// sector's payload is filled with the low byte of its index.
+#[cfg(test)]
+pub struct TestImage {
+    toc: Toc,
+}
+
+#[cfg(test)]
+impl TestImage {
+    pub fn new(length: u32) -> TestImage {
+        TestImage {
+            toc: Toc::new(vec![Track {
+                number: 1,
+                format: TrackFormat::Mode2,
+                start: Msf::new(0, 2, 0),
+                length,
+            }]),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Image for TestImage {
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        let index = msf.sector_index();
+
+        let mut sector = Sector::new([index as u8; sector::SECTOR_SIZE]);
+
+        sector.set_header(msf, 2);
+
+        if msf == Msf::new(0, 2, 4) {
+            let license = b"Licensed  by  Sony Computer Entertainment Amer  ica ";
+
+            sector.raw_mut()[24..24 + license.len()].copy_from_slice(license);
+        }
+
+        Ok(sector)
+    }
+}
+
+/// North American test disc
+#[cfg(test)]
+pub fn test_disc() -> Disc {
+    Disc::new(Box::new(TestImage::new(1000))).unwrap()
+}
+
+#[test]
+fn region() {
+    let disc = test_disc();
+
+    assert_eq!(disc.region(), Region::NorthAmerica);
+}
+
+#[test]
+fn toc() {
+    let toc = Toc::new(vec![
+        Track {
+            number: 1,
+            format: TrackFormat::Mode2,
+            start: Msf::new(0, 2, 0),
+            length: 1000,
+        },
+        Track {
+            number: 2,
+            format: TrackFormat::Audio,
+            start: Msf::from_sector_index(150 + 1000 + 150),
+            length: 500,
+        },
+    ]);
+
+    assert_eq!(toc.lead_out(), Msf::from_sector_index(150 + 1000 + 150 + 500));
+    assert_eq!(toc.track_at(Msf::new(0, 2, 0)).unwrap().number, 1);
+    // Pregap of track 2
+    assert_eq!(toc.track_at(Msf::from_sector_index(150 + 1000 + 10)).unwrap().number, 1);
+    assert_eq!(toc.track_at(Msf::from_sector_index(150 + 1000 + 150)).unwrap().number, 2);
+    assert!(toc.track_at(toc.lead_out()).is_none());
+}
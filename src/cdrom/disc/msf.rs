@@ -0,0 +1,143 @@
+use std::fmt;
+
+/// CD "minute:second:frame" position. There are 75 frames (sectors)
+/// per second. The values are stored in binary, the CD controller
+/// exchanges them in BCD.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Msf {
+    m: u8,
+    s: u8,
+    f: u8,
+}
+
+impl Msf {
+    pub fn new(m: u8, s: u8, f: u8) -> Msf {
+        if s >= 60 || f >= 75 {
+            panic!("Invalid MSF {}:{}:{}", m, s, f);
+        }
+
+        Msf { m, s, f }
+    }
+
+    pub fn zero() -> Msf {
+        Msf { m: 0, s: 0, f: 0 }
+    }
+
+    /// Build an MSF from its BCD representation, returns `None` if
+    /// one of the values isn't valid BCD or is out of range
+    pub fn from_bcd(m: u8, s: u8, f: u8) -> Option<Msf> {
+        let m = from_bcd(m)?;
+        let s = from_bcd(s)?;
+        let f = from_bcd(f)?;
+
+        if s >= 60 || f >= 75 {
+            return None;
+        }
+
+        Some(Msf { m, s, f })
+    }
+
+    /// Return the BCD encoding of the MSF
+    pub fn to_bcd(self) -> (u8, u8, u8) {
+        (to_bcd(self.m), to_bcd(self.s), to_bcd(self.f))
+    }
+
+    /// Build an MSF from a sector index
+    pub fn from_sector_index(index: u32) -> Msf {
+        let m = index / (60 * 75);
+
+        if m > 99 {
+            panic!("Sector index {} is out of range", index);
+        }
+
+        let index = index % (60 * 75);
+
+        Msf {
+            m: m as u8,
+            s: (index / 75) as u8,
+            f: (index % 75) as u8,
+        }
+    }
+
+    /// Number of sectors between 00:00:00 And this position
+    pub fn sector_index(self) -> u32 {
+        (self.m as u32 * 60 + self.s as u32) * 75 + self.f as u32
+    }
+
+    /// Build an MSF from a logical block address. LBA 0 is the start
+    /// of the first track at 00:02:00.
+    pub fn from_lba(lba: u32) -> Msf {
+        Msf::from_sector_index(lba + 150)
+    }
+
+    /// Logical block address of this position, `None` for the
+    /// positions in the first track's pregap
+    pub fn lba(self) -> Option<u32> {
+        self.sector_index().checked_sub(150)
+    }
+
+    /// Position of the next sector
+    pub fn next(self) -> Msf {
+        Msf::from_sector_index(self.sector_index() + 1)
+    }
+
+    pub fn minute(self) -> u8 {
+        self.m
+    }
+
+    pub fn second(self) -> u8 {
+        self.s
+    }
+
+    pub fn frame(self) -> u8 {
+        self.f
+    }
+}
+
+impl fmt::Debug for Msf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.m, self.s, self.f)
+    }
+}
+
+pub fn from_bcd(b: u8) -> Option<u8> {
+    if b & 0xf > 9 || b >> 4 > 9 {
+        None
+    } else {
+        Some((b >> 4) * 10 + (b & 0xf))
+    }
+}
+
+pub fn to_bcd(v: u8) -> u8 {
+    ((v / 10) << 4) | (v % 10)
+}
+
+#[test]
+fn bcd() {
+    let msf = Msf::from_bcd(0x12, 0x59, 0x74).unwrap();
+
+    assert_eq!(msf, Msf::new(12, 59, 74));
+    assert_eq!(msf.to_bcd(), (0x12, 0x59, 0x74));
+
+    // Invalid BCD And out of range values
+    assert!(Msf::from_bcd(0x0a, 0, 0).is_none());
+    assert!(Msf::from_bcd(0, 0x60, 0).is_none());
+    assert!(Msf::from_bcd(0, 0, 0x75).is_none());
+}
+
+#[test]
+fn sector_index() {
+    let msf = Msf::new(1, 2, 3);
+
+    assert_eq!(msf.sector_index(), (60 + 2) * 75 + 3);
+    assert_eq!(Msf::from_sector_index(msf.sector_index()), msf);
+
+    assert_eq!(Msf::new(0, 59, 74).next(), Msf::new(1, 0, 0));
+}
+
+#[test]
+fn lba() {
+    assert_eq!(Msf::from_lba(0), Msf::new(0, 2, 0));
+    assert_eq!(Msf::from_lba(16).lba(), Some(16));
+    assert_eq!(Msf::new(0, 1, 74).lba(), None);
+}
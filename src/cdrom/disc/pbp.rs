@@ -0,0 +1,394 @@
+//! PSP "eboot" PlayStation images (".pbp"). The disc images are
+//! stored in the DATA.PSAR section of the file, split in blocks of 16
+//! sectors compressed with deflate. A single PBP can contain up to 5
+//! discs.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, SeekFrom};
+use std::path::Path;
+
+use super::{Image, Storage, Toc, Track};
+use super::msf::{self, Msf};
+use super::sector::{Sector, SECTOR_SIZE};
+use super::trackformat::TrackFormat;
+
+/// Number of sectors in a compressed block
+const BLOCK_SECTORS: usize = 16;
+/// Size of a decompressed block
+const BLOCK_SIZE: usize = BLOCK_SECTORS * SECTOR_SIZE;
+
+/// Maximum number of discs in a multi-disc PBP
+const MAX_DISCS: usize = 5;
+
+/// Offsets in a disc's PSISOIMG section
+const TOC_OFFSET: u64 = 0x800;
+const INDEX_OFFSET: u64 = 0x4000;
+const DATA_OFFSET: u64 = 0x100000;
+
+/// Size of a block index entry
+const INDEX_ENTRY_SIZE: usize = 32;
+
+/// One of the discs of a PBP file
+pub struct PbpImage {
+    toc: Toc,
+    storage: Box<dyn Storage>,
+    /// Absolute position of the compressed data
+    data_offset: u64,
+    /// Offset (relative to `data_offset`) And size of each block
+    blocks: Vec<(u64, usize)>,
+    /// Last decompressed block: index And data
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl PbpImage {
+    /// Open disc number `disc` (starting at 0) of the PBP at `path`
+    pub fn new<P: AsRef<Path>>(path: P, disc: usize) -> Result<PbpImage> {
+        let file = File::open(path)?;
+
+        PbpImage::from_storage(Box::new(file), disc)
+    }
+
+    pub fn from_storage(mut storage: Box<dyn Storage>, disc: usize) -> Result<PbpImage> {
+        let discs = disc_offsets(storage.as_mut())?;
+
+        let base = match discs.get(disc) {
+            Some(&o) => o,
+            None => return Err(Error::new(ErrorKind::InvalidInput,
+                                          format!("PBP doesn't contain disc {}", disc + 1))),
+        };
+
+        let toc = read_toc(storage.as_mut(), base + TOC_OFFSET)?;
+
+        // Block index, terminated by an empty entry
+        let mut index = vec![0; (DATA_OFFSET - INDEX_OFFSET) as usize];
+
+        storage.seek(SeekFrom::Start(base + INDEX_OFFSET))?;
+        storage.read_exact(&mut index)?;
+
+        let blocks = index.chunks(INDEX_ENTRY_SIZE)
+            .map(|e| (le32(e) as u64, u16::from_le_bytes([e[4], e[5]]) as usize))
+            .take_while(|&(_, size)| size != 0)
+            .collect::<Vec<_>>();
+
+        let sectors = toc.lead_out().lba().unwrap_or(0) as usize;
+
+        if blocks.len() * BLOCK_SECTORS < sectors {
+            return Err(invalid("PBP block index is too short"));
+        }
+
+        Ok(PbpImage {
+            toc,
+            storage,
+            data_offset: base + DATA_OFFSET,
+            blocks,
+            cache: None,
+        })
+    }
+
+    /// Decompress block `index` in the cache
+    fn load_block(&mut self, index: usize) -> Result<&[u8]> {
+        if self.cache.as_ref().map(|c| c.0) != Some(index) {
+            self.cache = None;
+
+            let (offset, size) = self.blocks[index];
+
+            let mut data = vec![0; size];
+
+            self.storage.seek(SeekFrom::Start(self.data_offset + offset))?;
+            self.storage.read_exact(&mut data)?;
+
+            // Blocks which don't compress are stored as-is
+            if size != BLOCK_SIZE {
+                data = miniz_oxide::inflate::decompress_to_vec_with_limit(&data, BLOCK_SIZE)
+                    .map_err(|e| invalid(&format!("PBP block {}: deflate error {:?}",
+                                                  index, e.status)))?;
+            }
+
+            self.cache = Some((index, data));
+        }
+
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+impl Image for PbpImage {
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> Result<Sector> {
+        let lba = match msf.lba() {
+            Some(lba) => lba as usize,
+            // Pregap of the first track, not stored in the image
+            None => return Ok(Sector::empty(msf, TrackFormat::Mode2)),
+        };
+
+        if msf >= self.toc.lead_out() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Sector {:?} is out of the disc", msf)));
+        }
+
+        let block = self.load_block(lba / BLOCK_SECTORS)?;
+
+        let offset = (lba % BLOCK_SECTORS) * SECTOR_SIZE;
+
+        match block.get(offset..offset + SECTOR_SIZE) {
+            Some(raw) => Ok(Sector::from_slice(raw)),
+            None => Err(invalid(&format!("PBP sector {:?} is truncated", msf))),
+        }
+    }
+}
+
+/// Return the number of discs in the PBP at `path`
+pub fn disc_count<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let mut file = File::open(path)?;
+
+    Ok(disc_offsets(&mut file)?.len())
+}
+
+/// Return the absolute position of the PSISOIMG section of each disc
+fn disc_offsets(storage: &mut dyn Storage) -> Result<Vec<u64>> {
+    let mut header = [0; 0x28];
+
+    storage.seek(SeekFrom::Start(0))?;
+    storage.read_exact(&mut header)?;
+
+    if &header[0..4] != b"\0PBP" {
+        return Err(invalid("Missing PBP signature"));
+    }
+
+    // Offset of the DATA.PSAR section, the last of the 8 sections
+    let psar = le32(&header[0x24..]) as u64;
+
+    let mut signature = [0; 16];
+
+    storage.seek(SeekFrom::Start(psar))?;
+    storage.read_exact(&mut signature)?;
+
+    if &signature[..12] == b"PSISOIMG0000" {
+        return Ok(vec![psar]);
+    }
+
+    if &signature != b"PSTITLEIMG000000" {
+        return Err(invalid("Unsupported PBP format (encrypted image?)"));
+    }
+
+    // Multi-disc image, the offsets of the discs are stored at 0x200
+    let mut offsets = [0; MAX_DISCS * 4];
+
+    storage.seek(SeekFrom::Start(psar + 0x200))?;
+    storage.read_exact(&mut offsets)?;
+
+    let discs: Vec<u64> = offsets.chunks(4)
+        .map(le32)
+        .take_while(|&o| o != 0)
+        .map(|o| psar + o as u64)
+        .collect();
+
+    for &disc in &discs {
+        storage.seek(SeekFrom::Start(disc))?;
+        storage.read_exact(&mut signature[..12])?;
+
+        if &signature[..12] != b"PSISOIMG0000" {
+            return Err(invalid("Invalid PBP disc signature"));
+        }
+    }
+
+    if discs.is_empty() {
+        return Err(invalid("PBP without any disc"));
+    }
+
+    Ok(discs)
+}
+
+/// Parse the disc's TOC, stored like in the lead-in's Q subchannel:
+/// 10 byte entries for points A0 (first track), A1 (last track), A2
+/// (lead-out) followed by the tracks, all in BCD
+fn read_toc(storage: &mut dyn Storage, offset: u64) -> Result<Toc> {
+    let entry_size = 10;
+
+    let mut raw = vec![0; entry_size * (3 + 99)];
+
+    storage.seek(SeekFrom::Start(offset))?;
+    storage.read_exact(&mut raw)?;
+
+    let entries: Vec<&[u8]> = raw.chunks(entry_size).collect();
+
+    let bcd = |b: u8| msf::from_bcd(b).ok_or_else(|| invalid("Invalid BCD in PBP TOC"));
+
+    let position = |e: &[u8]| {
+        Msf::from_bcd(e[7], e[8], e[9]).ok_or_else(|| invalid("Invalid position in PBP TOC"))
+    };
+
+    if entries[0][2] != 0xa0 || entries[1][2] != 0xa1 || entries[2][2] != 0xa2 {
+        return Err(invalid("Invalid PBP TOC"));
+    }
+
+    let last_track = bcd(entries[1][7])? as usize;
+    let lead_out = position(entries[2])?;
+
+    if last_track == 0 {
+        return Err(invalid("PBP TOC without any track"));
+    }
+
+    let mut tracks: Vec<Track> = Vec::with_capacity(last_track);
+
+    for e in &entries[3..3 + last_track] {
+        let start = position(e)?;
+
+        if start >= lead_out || tracks.last().is_some_and(|t| t.start >= start) {
+            return Err(invalid("Invalid track position in PBP TOC"));
+        }
+
+        // Control bit 6 is set for data tracks
+        let format = if e[0] & 0x40 != 0 {
+            TrackFormat::Mode2
+        } else {
+            TrackFormat::Audio
+        };
+
+        tracks.push(Track {
+            number: bcd(e[2])?,
+            format,
+            start,
+            length: 0,
+        });
+    }
+
+    // The tracks end where the next one starts
+    let mut end = lead_out.sector_index();
+
+    for t in tracks.iter_mut().rev() {
+        t.length = end - t.start.sector_index();
+        end = t.start.sector_index();
+    }
+
+    Ok(Toc::new(tracks))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+/// Build a PSISOIMG section for a disc of `sectors` sectors with a
+/// data track And an audio track starting at LBA 30. Every other
+/// block is stored uncompressed.
+#[cfg(test)]
+fn test_psisoimg(sectors: &[Sector]) -> Vec<u8> {
+    let mut img = vec![0; DATA_OFFSET as usize];
+
+    img[..12].copy_from_slice(b"PSISOIMG0000");
+
+    let toc = [
+        [0x41, 0, 0xa0, 0, 0, 0, 0, 0x01, 0x20, 0],
+        [0x01, 0, 0xa1, 0, 0, 0, 0, 0x02, 0, 0],
+        [0x01, 0, 0xa2, 0, 0, 0, 0, 0x00, 0x02, msf::to_bcd(sectors.len() as u8)],
+        [0x41, 0, 0x01, 0, 0, 0, 0, 0x00, 0x02, 0x00],
+        [0x01, 0, 0x02, 0, 0, 0, 0, 0x00, 0x02, 0x30],
+    ];
+
+    for (i, e) in toc.iter().enumerate() {
+        let o = TOC_OFFSET as usize + i * 10;
+
+        img[o..o + 10].copy_from_slice(e);
+    }
+
+    let mut data = Vec::new();
+
+    for (i, block) in sectors.chunks(BLOCK_SECTORS).enumerate() {
+        let raw: Vec<u8> = block.iter().flat_map(|s| s.raw().to_vec()).collect();
+
+        let stored = if i % 2 == 1 && raw.len() == BLOCK_SIZE {
+            raw
+        } else {
+            miniz_oxide::deflate::compress_to_vec(&raw, 6)
+        };
+
+        let o = INDEX_OFFSET as usize + i * INDEX_ENTRY_SIZE;
+
+        img[o..o + 4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        img[o + 4..o + 6].copy_from_slice(&(stored.len() as u16).to_le_bytes());
+
+        data.extend(stored);
+    }
+
+    img.extend(data);
+
+    img
+}
+
+#[test]
+fn multi_disc_pbp() {
+    use std::io::Cursor;
+    use super::{Disc, region::Region};
+
+    let discs: Vec<Vec<Sector>> = (0..2u8)
+        .map(|d| {
+            (0..40u32).map(|i| {
+                let mut data = [d * 100 + i as u8; 2048];
+
+                if i == 4 {
+                    let license = b"Licensed  by  Sony Computer Entertainment Inc.";
+
+                    data[..license.len()].copy_from_slice(license);
+                }
+
+                Sector::from_user_data(Msf::from_lba(i), &data)
+            }).collect()
+        })
+        .collect();
+
+    let psar = 0x1000;
+
+    let mut pbp = vec![0; psar];
+
+    pbp[..4].copy_from_slice(b"\0PBP");
+    pbp[4..8].copy_from_slice(&0x10000u32.to_le_bytes());
+    pbp[0x24..0x28].copy_from_slice(&(psar as u32).to_le_bytes());
+
+    let mut title = vec![0; 0x400];
+
+    title[..16].copy_from_slice(b"PSTITLEIMG000000");
+
+    let disc0 = test_psisoimg(&discs[0]);
+    let disc1 = test_psisoimg(&discs[1]);
+
+    title[0x200..0x204].copy_from_slice(&0x400u32.to_le_bytes());
+    title[0x204..0x208].copy_from_slice(&(0x400 + disc0.len() as u32).to_le_bytes());
+
+    pbp.extend(title);
+    pbp.extend(disc0);
+    pbp.extend(disc1);
+
+    assert_eq!(disc_offsets(&mut Cursor::new(pbp.clone())).unwrap().len(), 2);
+
+    for (d, sectors) in discs.iter().enumerate() {
+        let mut image = PbpImage::from_storage(Box::new(Cursor::new(pbp.clone())), d).unwrap();
+
+        let toc = image.toc().clone();
+
+        assert_eq!(toc.tracks().len(), 2);
+        assert_eq!(toc.first_track().length, 30);
+        assert_eq!(toc.last_track().format, TrackFormat::Audio);
+        assert_eq!(toc.last_track().length, 10);
+        assert_eq!(toc.lead_out(), Msf::from_lba(40));
+
+        for &i in &[20, 0, 39, 16, 15, 31] {
+            let sector = image.read_sector(Msf::from_lba(i)).unwrap();
+
+            assert!(sector.raw()[..] == sectors[i as usize].raw()[..], "disc {} sector {}", d, i);
+        }
+
+        assert!(image.read_sector(Msf::from_lba(40)).is_err());
+
+        let disc = Disc::new(Box::new(image)).unwrap();
+
+        assert_eq!(disc.region(), Region::Japan);
+    }
+
+    assert!(PbpImage::from_storage(Box::new(Cursor::new(pbp)), 2).is_err());
+}
@@ -0,0 +1,21 @@
+/// Disc region, determined from the license string
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    /// Sony Computer Entertainment Inc.
+    Japan,
+    /// Sony Computer Entertainment America
+    NorthAmerica,
+    /// Sony Computer Entertainment Europe
+    Europe,
+}
+
+impl Region {
+    /// Region string returned by the GetID command
+    pub fn scex(self) -> &'static [u8; 4] {
+        match self {
+            Region::Japan => b"SCEI",
+            Region::NorthAmerica => b"SCEA",
+            Region::Europe => b"SCEE",
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use super::ecc;
+use super::msf::Msf;
+use super::trackformat::TrackFormat;
+
+/// Size of a raw CD sector in bytes
+pub const SECTOR_SIZE: usize = 2352;
+
+/// Sync pattern at the start of every data sector
+pub const SYNC_PATTERN: [u8; 12] = [
+    0x00, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
+];
+
+/// Size of the user data of a Mode 2 Form 1 sector
+pub const USER_DATA_SIZE: usize = 2048;
+
+/// Raw 2352 byte CD sector
+#[derive(Clone)]
+pub struct Sector {
+    raw: Box<[u8; SECTOR_SIZE]>,
+    /// True if the sync, header, subheader And error correction
+    /// codes have been generated by the emulator instead of being
+    /// read from the image
+    synthesized: bool,
+}
+
+impl Sector {
+    pub fn new(raw: [u8; SECTOR_SIZE]) -> Sector {
+        Sector {
+            raw: Box::new(raw),
+            synthesized: false,
+        }
+    }
+
+    /// Generate an empty sector for position `msf`: digital silence
+    /// for audio tracks, zeroed user data for data tracks
+    pub fn empty(msf: Msf, format: TrackFormat) -> Sector {
+        match format {
+            TrackFormat::Audio => {
+                let mut sector = Sector::new([0; SECTOR_SIZE]);
+
+                sector.synthesized = true;
+
+                sector
+            }
+            TrackFormat::Mode1 => {
+                let mut sector = Sector::new([0; SECTOR_SIZE]);
+
+                sector.set_header(msf, 1);
+
+                // EDC over the header And the user data
+                let edc = ecc::edc(&sector.raw[..16 + USER_DATA_SIZE]);
+                sector.raw[2064..2068].copy_from_slice(&edc.to_le_bytes());
+
+                ecc::generate_ecc(&mut sector.raw, false);
+
+                sector.synthesized = true;
+
+                sector
+            }
+            TrackFormat::Mode2 => Sector::from_user_data(msf, &[0; USER_DATA_SIZE]),
+        }
+    }
+
+    /// Build a Mode 2 Form 1 sector at position `msf` containing
+    /// `data`. Everything but the user data is synthesized.
+    pub fn from_user_data(msf: Msf, data: &[u8]) -> Sector {
+        if data.len() != USER_DATA_SIZE {
+            panic!("Invalid user data length {}", data.len());
+        }
+
+        let mut sector = Sector::new([0; SECTOR_SIZE]);
+
+        sector.set_header(msf, 2);
+
+        // Subheader, repeated twice: file 0, channel 0, submode "data"
+        // And no coding info
+        let subheader = [0x00, 0x00, 0x08, 0x00];
+
+        sector.raw[16..20].copy_from_slice(&subheader);
+        sector.raw[20..24].copy_from_slice(&subheader);
+
+        sector.raw[24..24 + USER_DATA_SIZE].copy_from_slice(data);
+
+        // EDC over the subheader And the user data
+        let edc = ecc::edc(&sector.raw[16..24 + USER_DATA_SIZE]);
+        sector.raw[2072..2076].copy_from_slice(&edc.to_le_bytes());
+
+        ecc::generate_ecc(&mut sector.raw, true);
+
+        sector.synthesized = true;
+
+        sector
+    }
+
+    /// Return true if the sector's metadata (sync, header,
+    /// subheader, EDC And ECC) has been generated instead of read
+    /// from the image
+    pub fn is_synthesized(&self) -> bool {
+        self.synthesized
+    }
+
+    /// Build a sector from a slice which must be exactly
+    /// `SECTOR_SIZE` long
+    pub fn from_slice(raw: &[u8]) -> Sector {
+        let mut sector = Sector::new([0; SECTOR_SIZE]);
+
+        sector.raw.copy_from_slice(raw);
+
+        sector
+    }
+
+    pub fn raw(&self) -> &[u8; SECTOR_SIZE] {
+        &self.raw
+    }
+
+    pub fn raw_mut(&mut self) -> &mut [u8; SECTOR_SIZE] {
+        &mut self.raw
+    }
+
+    /// Position encoded in the sector header, `None` if it's not
+    /// valid BCD
+    pub fn header_msf(&self) -> Option<Msf> {
+        Msf::from_bcd(self.raw[12], self.raw[13], self.raw[14])
+    }
+
+    /// Sector mode from the header
+    pub fn mode(&self) -> u8 {
+        self.raw[15]
+    }
+
+    /// Mode 2 subheader: file number, channel, submode And coding
+    /// info
+    pub fn subheader(&self) -> &[u8] {
+        &self.raw[16..20]
+    }
+
+    /// Everything after the sync pattern: header, subheader And the
+    /// 2328 byte payload (including the error correction codes for
+    /// form 1)
+    pub fn data_2340(&self) -> &[u8] {
+        &self.raw[12..]
+    }
+
+    /// 2048 byte user data of a Mode 2 Form 1 sector
+    pub fn data_2048(&self) -> &[u8] {
+        &self.raw[24..24 + USER_DATA_SIZE]
+    }
+
+    /// Write the sync pattern And the header for position `msf`
+    pub fn set_header(&mut self, msf: Msf, mode: u8) {
+        let (m, s, f) = msf.to_bcd();
+
+        self.raw[..12].copy_from_slice(&SYNC_PATTERN);
+        self.raw[12] = m;
+        self.raw[13] = s;
+        self.raw[14] = f;
+        self.raw[15] = mode;
+    }
+}
+
+#[test]
+fn header() {
+    let mut sector = Sector::new([0; SECTOR_SIZE]);
+
+    sector.set_header(Msf::new(0, 2, 16), 2);
+
+    assert_eq!(&sector.raw()[..12], &SYNC_PATTERN);
+    assert_eq!(sector.header_msf(), Some(Msf::new(0, 2, 16)));
+    assert_eq!(sector.raw()[14], super::msf::to_bcd(16));
+    assert_eq!(sector.mode(), 2);
+    assert_eq!(sector.data_2340().len(), 2340);
+}
+
+#[test]
+fn synthesized() {
+    let data: Vec<u8> = (0..USER_DATA_SIZE).map(|i| i as u8).collect();
+
+    let sector = Sector::from_user_data(Msf::new(0, 2, 16), &data);
+
+    assert!(sector.is_synthesized());
+    assert!(!Sector::new([0; SECTOR_SIZE]).is_synthesized());
+
+    assert_eq!(sector.header_msf(), Some(Msf::new(0, 2, 16)));
+    assert_eq!(sector.mode(), 2);
+    assert_eq!(sector.subheader(), [0x00, 0x00, 0x08, 0x00]);
+    assert_eq!(sector.data_2048(), &data[..]);
+
+    // The EDC covers the subheader, the user data And itself
+    assert_eq!(ecc::edc(&sector.raw()[16..2076]), 0);
+
+    // The ECC is computed with a zeroed header
+    let mut raw = *sector.raw();
+    ecc::generate_ecc(&mut raw, true);
+
+    assert_eq!(&raw[..], &sector.raw()[..]);
+}
@@ -0,0 +1,16 @@
+/// Format of the sectors of a track
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrackFormat {
+    /// CD-DA audio: 2352 bytes of 16bit stereo samples
+    Audio,
+    /// Mode 1 data: 2048 bytes of user data per sector
+    Mode1,
+    /// Mode 2 data (CD-XA), used by all the PlayStation discs
+    Mode2,
+}
+
+impl TrackFormat {
+    pub fn is_audio(self) -> bool {
+        self == TrackFormat::Audio
+    }
+}
@@ -0,0 +1,96 @@
+/// 16 byte FIFO used for the CD controller's parameters And
+/// responses
+pub struct Fifo {
+    buffer: [u8; 16],
+    /// Write index. The 5th bit is used to tell a full FIFO from an
+    /// empty one.
+    write_idx: u8,
+    /// Read index, same encoding as `write_idx`
+    read_idx: u8,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo {
+            buffer: [0; 16],
+            write_idx: 0,
+            read_idx: 0,
+        }
+    }
+
+    /// Build a FIFO containing `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Fifo {
+        let mut fifo = Fifo::new();
+
+        for &b in bytes {
+            fifo.push(b);
+        }
+
+        fifo
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.write_idx == self.read_idx
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.write_idx == self.read_idx ^ 0x10
+    }
+
+    pub fn clear(&mut self) {
+        self.write_idx = 0;
+        self.read_idx = 0;
+        self.buffer = [0; 16];
+    }
+
+    pub fn len(&self) -> u8 {
+        self.write_idx.wrapping_sub(self.read_idx) & 0x1f
+    }
+
+    /// Push a byte, ignored if the FIFO is full
+    pub fn push(&mut self, val: u8) {
+        if self.is_full() {
+            warn!("CDROM FIFO overflow");
+            return;
+        }
+
+        let idx = (self.write_idx & 0xf) as usize;
+
+        self.buffer[idx] = val;
+
+        self.write_idx = self.write_idx.wrapping_add(1) & 0x1f;
+    }
+
+    /// Pop a byte. Reading from an empty FIFO returns the byte at
+    /// the current position without moving it.
+    pub fn pop(&mut self) -> u8 {
+        let idx = (self.read_idx & 0xf) as usize;
+
+        if !self.is_empty() {
+            self.read_idx = self.read_idx.wrapping_add(1) & 0x1f;
+        }
+
+        self.buffer[idx]
+    }
+}
+
+#[test]
+fn push_pop() {
+    let mut fifo = Fifo::new();
+
+    assert!(fifo.is_empty());
+
+    for i in 0..16 {
+        fifo.push(i);
+    }
+
+    assert!(fifo.is_full());
+    assert_eq!(fifo.len(), 16);
+
+    for i in 0..16 {
+        assert_eq!(fifo.pop(), i);
+    }
+
+    assert!(fifo.is_empty());
+    assert_eq!(fifo.len(), 0);
+}
@@ -0,0 +1,1268 @@
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+
+use self::audio::{CdAudio, VOLUME_LEFT_TO_LEFT, VOLUME_LEFT_TO_RIGHT};
+use self::audio::{VOLUME_RIGHT_TO_LEFT, VOLUME_RIGHT_TO_RIGHT};
+use self::disc::Disc;
+use self::disc::msf::{self, Msf};
+use self::disc::sector::Sector;
+use self::fifo::Fifo;
+use self::xa::XaDecoder;
+
+pub mod audio;
+pub mod disc;
+pub mod fifo;
+pub mod xa;
+
+/// CPU clock frequency in Hz
+const CPU_FREQ: u32 = 33_868_800;
+
+/// Delay between a command write And its first response (INT3) in
+/// CPU cycles
+const FIRST_RESPONSE_DELAY: u32 = 0xc4e1;
+
+/// Init takes longer to acknowledge than the other commands
+const INIT_FIRST_RESPONSE_DELAY: u32 = 0x13cce;
+
+/// Delay between the first And second response of GetID
+const GET_ID_DELAY: u32 = 0x4a00;
+
+/// Delay between the first And second response of Init
+const INIT_DELAY: u32 = 0x4a00;
+
+/// Delay between the first And second response of Pause when the
+/// drive is already paused
+const PAUSE_IDLE_DELAY: u32 = 0x1df2;
+
+/// Delay between the first And second response of Pause while
+/// reading at single speed. Twice as fast at double speed.
+const PAUSE_DELAY: u32 = 0x21181c;
+
+/// Rough duration of a seek. The real duration depends on the
+/// distance And on the position of the sled.
+const SEEK_DELAY: u32 = 0x20000;
+
+/// Interrupt codes
+const INT1_DATA_READY: u8 = 1;
+const INT2_COMPLETE: u8 = 2;
+const INT3_ACKNOWLEDGE: u8 = 3;
+const INT4_DATA_END: u8 = 4;
+const INT5_ERROR: u8 = 5;
+
+/// Error codes returned along with INT5
+const ERROR_INVALID_PARAMETER: u8 = 0x10;
+const ERROR_WRONG_PARAMETER_COUNT: u8 = 0x20;
+const ERROR_INVALID_COMMAND: u8 = 0x40;
+const ERROR_NO_DISC: u8 = 0x80;
+
+/// Mode bits set by Setmode
+const MODE_AUTO_PAUSE: u8 = 0x02;
+const MODE_REPORT: u8 = 0x04;
+const MODE_XA_FILTER: u8 = 0x08;
+const MODE_XA_ADPCM: u8 = 0x40;
+
+/// Second response of the commands which have one
+#[derive(Copy, Clone, Debug)]
+enum AsyncResponse {
+    GetId,
+    Init,
+    Pause,
+    SeekL,
+}
+
+/// CD-ROM controller
+pub struct CdRom {
+    /// Register bank selected through the index register
+    index: u8,
+    /// Command parameters
+    params: Fifo,
+    /// Command responses
+    response: Fifo,
+    /// Interrupt enable
+    irq_mask: u8,
+    /// Interrupt flags. The low 3 bits contain the code of the
+    /// pending interrupt (INT1-INT5).
+    irq_flags: u8,
+    /// Command waiting for its first response
+    command: Option<u8>,
+    /// Remaining cycles before `command` is executed
+    command_delay: u32,
+    /// Pending second response And the remaining cycles before it's
+    /// delivered
+    async_response: Option<(AsyncResponse, u32)>,
+    /// Remaining cycles before the next sector is read, `None` when
+    /// not reading or playing
+    read_delay: Option<u32>,
+    /// True when playing CD-DA instead of reading data
+    playing: bool,
+    /// Track being played, used by the auto pause
+    play_track: u8,
+    /// Value set by the Setmode command
+    mode: u8,
+    /// True if the spindle motor is on
+    motor_on: bool,
+    /// Current position of the read head
+    position: Msf,
+    /// Target of the last Setloc, cleared once the drive has seeked
+    seek_target: Option<Msf>,
+    /// Last sector read from the disc
+    rx_sector: Option<Sector>,
+    /// Contents of the data FIFO
+    data: Vec<u8>,
+    /// Read position in `data`
+    data_index: usize,
+    /// Disc in the drive, if any
+    disc: Option<Disc>,
+    /// File And channel of the XA-ADPCM sectors to play when the
+    /// filter is enabled, set by Setfilter
+    filter: (u8, u8),
+    xa_decoder: XaDecoder,
+    /// CD-DA And XA-ADPCM output
+    audio: CdAudio,
+}
+
+impl CdRom {
+    pub fn new(disc: Option<Disc>) -> CdRom {
+        CdRom {
+            index: 0,
+            params: Fifo::new(),
+            response: Fifo::new(),
+            irq_mask: 0,
+            irq_flags: 0,
+            command: None,
+            command_delay: 0,
+            async_response: None,
+            read_delay: None,
+            playing: false,
+            play_track: 0,
+            mode: 0,
+            motor_on: disc.is_some(),
+            position: Msf::zero(),
+            seek_target: None,
+            rx_sector: None,
+            data: Vec::new(),
+            data_index: 0,
+            disc,
+            filter: (0, 0),
+            xa_decoder: XaDecoder::new(),
+            audio: CdAudio::new(),
+        }
+    }
+
+    /// Advance the controller by `cycles` CPU cycles
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptController) {
+        self.command_delay = self.command_delay.saturating_sub(cycles);
+
+        if let Some((response, delay)) = self.async_response {
+            self.async_response = Some((response, delay.saturating_sub(cycles)));
+        }
+
+        if let Some(delay) = self.read_delay {
+            self.read_delay = Some(delay.saturating_sub(cycles));
+        }
+
+        // The events which are due wait for the previous interrupt
+        // to be acknowledged before they're delivered
+        if self.irq_flags != 0 {
+            return;
+        }
+
+        if let Some(command) = self.command {
+            if self.command_delay == 0 {
+                self.command = None;
+
+                return self.execute(command, irq);
+            }
+        }
+
+        if let Some((response, 0)) = self.async_response {
+            self.async_response = None;
+
+            return self.async_response(response, irq);
+        }
+
+        if let Some(0) = self.read_delay {
+            if self.playing {
+                self.play_sector(irq);
+            } else {
+                self.read_sector(irq);
+            }
+        }
+    }
+
+    /// Number of CPU cycles before the next event, `None` if there's
+    /// nothing to do
+    pub fn cycles_to_next_event(&self) -> Option<u32> {
+        if self.irq_flags != 0 {
+            // Nothing happens until the interrupt is acknowledged
+            return None;
+        }
+
+        let command = self.command.map(|_| self.command_delay);
+        let async_response = self.async_response.map(|(_, delay)| delay);
+
+        [command, async_response, self.read_delay]
+            .iter()
+            .filter_map(|&d| d)
+            .min()
+    }
+
+    /// Register read, `offset` is relative to the start of the
+    /// CDROM range
+    pub fn load(&mut self, offset: u32) -> u8 {
+        match offset {
+            0 => self.status(),
+            1 => self.response.pop(),
+            2 => self.read_data_byte(),
+            3 => match self.index {
+                0 | 2 => 0xe0 | self.irq_mask,
+                _ => 0xe0 | self.irq_flags,
+            },
+            _ => panic!("Unhandled CDROM register {}", offset),
+        }
+    }
+
+    /// Register write, `offset` is relative to the start of the
+    /// CDROM range
+    pub fn store(&mut self, offset: u32, val: u8) {
+        match (offset, self.index) {
+            (0, _) => self.index = val & 3,
+            (1, 0) => self.start_command(val),
+            (2, 0) => self.params.push(val),
+            (2, 1) => self.irq_mask = val & 0x1f,
+            (3, 0) => self.request(val),
+            (3, 1) => self.acknowledge(val),
+            (2, 2) => self.audio.set_pending_volume(VOLUME_LEFT_TO_LEFT, val),
+            (3, 2) => self.audio.set_pending_volume(VOLUME_LEFT_TO_RIGHT, val),
+            (1, 3) => self.audio.set_pending_volume(VOLUME_RIGHT_TO_RIGHT, val),
+            (2, 3) => self.audio.set_pending_volume(VOLUME_RIGHT_TO_LEFT, val),
+            (3, 3) => self.audio.apply(val),
+            (_, index) =>
+                warn!("Unhandled CDROM register {}.{} write: 0x{:02x}", offset, index, val),
+        }
+    }
+
+    /// Read a word from the data FIFO for the DMA
+    pub fn dma_read_word(&mut self) -> u32 {
+        let mut word = 0;
+
+        for i in 0..4 {
+            word |= (self.read_data_byte() as u32) << (i * 8);
+        }
+
+        word
+    }
+
+    /// Return the next 44.1kHz CD audio sample to be mixed by the
+    /// SPU, silence if the drive isn't playing anything
+    pub fn next_audio_sample(&mut self) -> (i16, i16) {
+        self.audio.next_sample()
+    }
+
+    /// Status register
+    fn status(&self) -> u8 {
+        let mut r = self.index;
+
+        // Bit 2 (ADPBUSY): XA-ADPCM being played. CD-DA doesn't
+        // go through the ADPCM FIFO so it doesn't set it.
+        r |= (self.audio.adpcm_busy() as u8) << 2;
+        r |= (self.params.is_empty() as u8) << 3;
+        r |= (!self.params.is_full() as u8) << 4;
+        r |= (!self.response.is_empty() as u8) << 5;
+        r |= ((self.data_index < self.data.len()) as u8) << 6;
+        // Bit 7: busy transmitting a command
+        r |= (self.command.is_some() as u8) << 7;
+
+        r
+    }
+
+    /// Drive status byte returned by most commands
+    fn stat(&self) -> u8 {
+        let mut stat = 0;
+
+        if self.motor_on {
+            stat |= 1 << 1;
+        }
+
+        if self.disc.is_none() {
+            // Shell open
+            stat |= 1 << 4;
+        }
+
+        if self.read_delay.is_some() {
+            if self.playing {
+                stat |= 1 << 7;
+            } else {
+                stat |= 1 << 5;
+            }
+        }
+
+        if let Some((AsyncResponse::SeekL, _)) = self.async_response {
+            stat |= 1 << 6;
+        }
+
+        stat
+    }
+
+    /// Number of CPU cycles needed to read a sector at the current
+    /// speed
+    fn sector_period(&self) -> u32 {
+        if self.double_speed() {
+            CPU_FREQ / 150
+        } else {
+            CPU_FREQ / 75
+        }
+    }
+
+    fn double_speed(&self) -> bool {
+        self.mode & 0x80 != 0
+    }
+
+    /// Write to the request register
+    fn request(&mut self, val: u8) {
+        if val & 0x80 != 0 {
+            // Want data: load the last sector in the data FIFO unless
+            // it still contains something
+            if self.data_index >= self.data.len() {
+                self.load_data_fifo();
+            }
+        } else {
+            self.data.clear();
+            self.data_index = 0;
+        }
+    }
+
+    fn load_data_fifo(&mut self) {
+        let sector = match self.rx_sector {
+            Some(ref s) => s,
+            None => {
+                warn!("CDROM data request without any sector");
+                return;
+            }
+        };
+
+        // Mode bit 5: whole sector (without the sync) or just the
+        // user data
+        let payload = if self.mode & 0x20 != 0 {
+            sector.data_2340()
+        } else {
+            sector.data_2048()
+        };
+
+        self.data.clear();
+        self.data.extend_from_slice(payload);
+        self.data_index = 0;
+    }
+
+    fn read_data_byte(&mut self) -> u8 {
+        match self.data.get(self.data_index) {
+            Some(&b) => {
+                self.data_index += 1;
+                b
+            }
+            None => {
+                warn!("CDROM data FIFO underflow");
+                0
+            }
+        }
+    }
+
+    /// Write to the interrupt flag register
+    fn acknowledge(&mut self, val: u8) {
+        self.irq_flags &= !(val & 0x1f);
+
+        if val & 0x40 != 0 {
+            self.params.clear();
+        }
+    }
+
+    /// Signal interrupt `code` along with `response`
+    fn interrupt(&mut self, code: u8, response: &[u8], irq: &mut InterruptController) {
+        self.response = Fifo::from_bytes(response);
+        self.irq_flags = code;
+
+        if self.irq_flags & self.irq_mask != 0 {
+            irq.assert(Interrupt::CdRom);
+        }
+    }
+
+    fn start_command(&mut self, command: u8) {
+        if let Some(pending) = self.command {
+            warn!("CDROM command 0x{:02x} while 0x{:02x} is pending", command, pending);
+        }
+
+        self.command = Some(command);
+        self.command_delay = match command {
+            0x0a => INIT_FIRST_RESPONSE_DELAY,
+            _ => FIRST_RESPONSE_DELAY,
+        };
+    }
+
+    /// Run `command` And deliver its first response
+    fn execute(&mut self, command: u8, irq: &mut InterruptController) {
+        let mut params = Vec::new();
+
+        while !self.params.is_empty() {
+            params.push(self.params.pop());
+        }
+
+        debug!("CDROM command 0x{:02x} {:?}", command, params);
+
+        let expected_params = match command {
+            0x02 => Some(3),
+            0x0d => Some(2),
+            0x0e | 0x14 => Some(1),
+            // Play And Test take a variable number of parameters
+            0x03 | 0x19 => None,
+            _ => Some(0),
+        };
+
+        let result = match expected_params {
+            Some(n) if params.len() != n => Err(ERROR_WRONG_PARAMETER_COUNT),
+            _ => match command {
+                0x01 => Ok(vec![self.stat()]),
+                0x02 => self.setloc(&params),
+                0x03 => self.play(&params),
+                0x06 | 0x1b => self.read(),
+                0x09 => self.pause(),
+                0x0a => self.init(),
+                0x0b => self.mute(true),
+                0x0c => self.mute(false),
+                0x0d => self.setfilter(&params),
+                0x0e => self.setmode(params[0]),
+                0x11 => self.get_loc_p(),
+                0x13 => self.get_tn(),
+                0x14 => self.get_td(params[0]),
+                0x15 => self.seek_l(),
+                0x19 => self.test(&params),
+                0x1a => self.get_id(),
+                _ => {
+                    warn!("Unhandled CDROM command 0x{:02x}", command);
+                    Err(ERROR_INVALID_COMMAND)
+                }
+            },
+        };
+
+        match result {
+            Ok(response) => self.interrupt(INT3_ACKNOWLEDGE, &response, irq),
+            Err(code) => {
+                let stat = self.stat() | 1;
+
+                self.interrupt(INT5_ERROR, &[stat, code], irq);
+            }
+        }
+    }
+
+    fn async_response(&mut self, response: AsyncResponse, irq: &mut InterruptController) {
+        match response {
+            AsyncResponse::GetId => match self.disc {
+                Some(ref disc) => {
+                    let scex = disc.region().scex();
+
+                    let response = [
+                        self.stat(), 0x00, 0x20, 0x00,
+                        scex[0], scex[1], scex[2], scex[3],
+                    ];
+
+                    self.interrupt(INT2_COMPLETE, &response, irq);
+                }
+                None => {
+                    let response = [0x08, 0x40, 0, 0, 0, 0, 0, 0];
+
+                    self.interrupt(INT5_ERROR, &response, irq);
+                }
+            },
+            AsyncResponse::Init | AsyncResponse::Pause | AsyncResponse::SeekL => {
+                let stat = self.stat();
+
+                self.interrupt(INT2_COMPLETE, &[stat], irq);
+            }
+        }
+    }
+
+    /// Read the sector under the head And signal INT1
+    fn read_sector(&mut self, irq: &mut InterruptController) {
+        let disc = self.disc.as_mut().unwrap();
+
+        match disc.read_sector(self.position) {
+            Ok(sector) => {
+                self.position = self.position.next();
+                self.read_delay = Some(self.sector_period());
+
+                if self.is_adpcm_sector(&sector) {
+                    // Audio sectors go to the ADPCM decoder instead of
+                    // the CPU
+                    if self.filter_matches(&sector) {
+                        let mut samples = Vec::new();
+
+                        self.xa_decoder.decode_sector(&sector, &mut samples);
+                        self.audio.push_adpcm(&samples);
+                    }
+
+                    return;
+                }
+
+                self.rx_sector = Some(sector);
+
+                let stat = self.stat();
+
+                self.interrupt(INT1_DATA_READY, &[stat], irq);
+            }
+            Err(e) => {
+                warn!("CDROM read error at {:?}: {}", self.position, e);
+
+                self.read_delay = None;
+
+                let stat = self.stat() | 1;
+
+                self.interrupt(INT5_ERROR, &[stat, ERROR_INVALID_PARAMETER], irq);
+            }
+        }
+    }
+
+    /// Return true if `sector` must be sent to the XA-ADPCM decoder
+    fn is_adpcm_sector(&self, sector: &Sector) -> bool {
+        self.mode & MODE_XA_ADPCM != 0
+            && sector.mode() == 2
+            && sector.subheader()[2] & xa::SUBMODE_AUDIO != 0
+    }
+
+    /// Return true if the ADPCM `sector` must be played
+    fn filter_matches(&self, sector: &Sector) -> bool {
+        if self.mode & MODE_XA_FILTER == 0 {
+            return true;
+        }
+
+        let subheader = sector.subheader();
+
+        (subheader[0], subheader[1]) == self.filter
+    }
+
+    /// Play the CD-DA sector under the head
+    fn play_sector(&mut self, irq: &mut InterruptController) {
+        let disc = self.disc.as_mut().unwrap();
+
+        let track = disc.toc().track_at(self.position).map(|t| (t.number, t.format));
+
+        let track = match track {
+            // With auto pause the drive stops at the end of the track
+            Some((number, _)) if self.mode & MODE_AUTO_PAUSE != 0
+                && number != self.play_track => None,
+            t => t,
+        };
+
+        let (number, format) = match track {
+            Some(t) => t,
+            None => {
+                self.read_delay = None;
+                self.playing = false;
+
+                let stat = self.stat();
+
+                return self.interrupt(INT4_DATA_END, &[stat], irq);
+            }
+        };
+
+        match disc.read_sector(self.position) {
+            Ok(sector) => {
+                if format.is_audio() {
+                    self.audio.push_cdda(sector.raw());
+                }
+            }
+            Err(e) => warn!("CDROM read error at {:?}: {}", self.position, e),
+        }
+
+        let position = self.position;
+
+        self.position = self.position.next();
+        self.read_delay = Some(self.sector_period());
+
+        // Reports are sent every 10 sectors, alternating between the
+        // absolute And the relative position
+        if self.mode & MODE_REPORT != 0 && position.frame().is_multiple_of(10) {
+            let report = self.report(number, position);
+
+            self.interrupt(INT1_DATA_READY, &report, irq);
+        }
+    }
+
+    /// Build a CD-DA report for `position` in track `track`
+    fn report(&self, track: u8, position: Msf) -> [u8; 8] {
+        let (m, s, f) = if position.frame().is_multiple_of(20) {
+            position.to_bcd()
+        } else {
+            let start = self.disc.as_ref().unwrap().toc().track(track).unwrap().start;
+
+            let (m, s, f) = Msf::from_sector_index(
+                position.sector_index().saturating_sub(start.sector_index())).to_bcd();
+
+            (m, s | 0x80, f)
+        };
+
+        // The last two bytes contain the peak level of the audio
+        [self.stat(), msf::to_bcd(track), 0x01, m, s, f, 0, 0]
+    }
+
+    fn require_disc(&self) -> Result<(), u8> {
+        if self.disc.is_some() {
+            Ok(())
+        } else {
+            Err(ERROR_NO_DISC)
+        }
+    }
+
+    fn setloc(&mut self, params: &[u8]) -> Result<Vec<u8>, u8> {
+        match Msf::from_bcd(params[0], params[1], params[2]) {
+            Some(msf) => {
+                self.seek_target = Some(msf);
+
+                Ok(vec![self.stat()])
+            }
+            None => Err(ERROR_INVALID_PARAMETER),
+        }
+    }
+
+    /// ReadN And ReadS
+    fn read(&mut self) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let stat = self.stat();
+
+        let mut delay = self.sector_period();
+
+        if let Some(target) = self.seek_target.take() {
+            self.position = target;
+            delay += SEEK_DELAY;
+        }
+
+        self.motor_on = true;
+        self.playing = false;
+        self.read_delay = Some(delay);
+        self.xa_decoder.reset();
+
+        Ok(vec![stat])
+    }
+
+    /// Start playing CD-DA, either from the start of the track given
+    /// as parameter or from the current position
+    fn play(&mut self, params: &[u8]) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let track = match params {
+            [] => 0,
+            [t] => msf::from_bcd(*t).ok_or(ERROR_INVALID_PARAMETER)?,
+            _ => return Err(ERROR_WRONG_PARAMETER_COUNT),
+        };
+
+        let stat = self.stat();
+
+        let mut delay = self.sector_period();
+
+        let toc = self.disc.as_ref().unwrap().toc();
+
+        if track != 0 {
+            let start = match toc.track(track) {
+                Some(t) => t.start,
+                None => return Err(ERROR_INVALID_PARAMETER),
+            };
+
+            self.seek_target = None;
+            self.position = start;
+            delay += SEEK_DELAY;
+        } else if let Some(target) = self.seek_target.take() {
+            self.position = target;
+            delay += SEEK_DELAY;
+        }
+
+        self.play_track = toc.track_at(self.position).map(|t| t.number).unwrap_or(0);
+        self.motor_on = true;
+        self.playing = true;
+        self.read_delay = Some(delay);
+
+        Ok(vec![stat])
+    }
+
+    fn mute(&mut self, muted: bool) -> Result<Vec<u8>, u8> {
+        self.audio.set_muted(muted);
+
+        Ok(vec![self.stat()])
+    }
+
+    fn setfilter(&mut self, params: &[u8]) -> Result<Vec<u8>, u8> {
+        self.filter = (params[0], params[1]);
+
+        Ok(vec![self.stat()])
+    }
+
+    /// Position of the head: track, index, position relative to the
+    /// track And absolute position
+    fn get_loc_p(&mut self) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let toc = self.disc.as_ref().unwrap().toc();
+
+        let position = self.position;
+
+        let first = toc.first_track();
+
+        let (track, index, relative) = match toc.track_at(position) {
+            Some(t) => (msf::to_bcd(t.number), 1, position.sector_index() - t.start.sector_index()),
+            // Pregap of the first track, the relative position counts
+            // down to the start of the track
+            None if position < first.start => {
+                (msf::to_bcd(first.number), 0, first.start.sector_index() - position.sector_index())
+            }
+            // Lead-out
+            None => (0xaa, 1, 0),
+        };
+
+        let (m, s, f) = Msf::from_sector_index(relative).to_bcd();
+        let (am, as_, af) = position.to_bcd();
+
+        Ok(vec![track, index, m, s, f, am, as_, af])
+    }
+
+    fn pause(&mut self) -> Result<Vec<u8>, u8> {
+        let stat = self.stat();
+
+        let delay = if self.read_delay.is_some() {
+            if self.double_speed() {
+                PAUSE_DELAY / 2
+            } else {
+                PAUSE_DELAY
+            }
+        } else {
+            PAUSE_IDLE_DELAY
+        };
+
+        self.read_delay = None;
+        self.playing = false;
+        self.async_response = Some((AsyncResponse::Pause, delay));
+
+        Ok(vec![stat])
+    }
+
+    fn init(&mut self) -> Result<Vec<u8>, u8> {
+        let stat = self.stat();
+
+        self.mode = 0x20;
+        self.motor_on = self.disc.is_some();
+        self.read_delay = None;
+        self.playing = false;
+        self.async_response = Some((AsyncResponse::Init, INIT_DELAY));
+
+        Ok(vec![stat])
+    }
+
+    fn setmode(&mut self, mode: u8) -> Result<Vec<u8>, u8> {
+        self.mode = mode;
+
+        Ok(vec![self.stat()])
+    }
+
+    fn get_tn(&mut self) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let toc = self.disc.as_ref().unwrap().toc();
+
+        let first = msf::to_bcd(toc.first_track().number);
+        let last = msf::to_bcd(toc.last_track().number);
+
+        Ok(vec![self.stat(), first, last])
+    }
+
+    fn get_td(&mut self, track: u8) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let toc = self.disc.as_ref().unwrap().toc();
+
+        let track = msf::from_bcd(track).ok_or(ERROR_INVALID_PARAMETER)?;
+
+        // Track 0 is the lead-out
+        let start = if track == 0 {
+            toc.lead_out()
+        } else {
+            toc.track(track).ok_or(ERROR_INVALID_PARAMETER)?.start
+        };
+
+        let (m, s, _) = start.to_bcd();
+
+        Ok(vec![self.stat(), m, s])
+    }
+
+    fn seek_l(&mut self) -> Result<Vec<u8>, u8> {
+        self.require_disc()?;
+
+        let stat = self.stat();
+
+        if let Some(target) = self.seek_target.take() {
+            self.position = target;
+        }
+
+        self.read_delay = None;
+        self.playing = false;
+        self.motor_on = true;
+        self.async_response = Some((AsyncResponse::SeekL, SEEK_DELAY));
+
+        Ok(vec![stat])
+    }
+
+    fn test(&mut self, params: &[u8]) -> Result<Vec<u8>, u8> {
+        let subcommand = match params.first() {
+            Some(&s) => s,
+            None => return Err(ERROR_WRONG_PARAMETER_COUNT),
+        };
+
+        match subcommand {
+            // Start SCEx reading
+            0x04 => Ok(vec![self.stat()]),
+            // Get SCEx counters
+            0x05 => Ok(vec![0, 0]),
+            // Controller version: PU-7, 19 September 1994, version C0
+            0x20 => Ok(vec![0x94, 0x09, 0x19, 0xc0]),
+            // Region string of the controller
+            0x22 => Ok(b"for U/C".to_vec()),
+            _ => {
+                warn!("Unhandled CDROM test subcommand 0x{:02x}", subcommand);
+                Err(ERROR_INVALID_PARAMETER)
+            }
+        }
+    }
+
+    fn get_id(&mut self) -> Result<Vec<u8>, u8> {
+        self.async_response = Some((AsyncResponse::GetId, GET_ID_DELAY));
+
+        Ok(vec![self.stat()])
+    }
+}
+
+/// Send `command` with `params` And wait for the first response.
+/// Returns the interrupt code And the response, the interrupt is
+/// acknowledged.
+#[cfg(test)]
+fn send_command(cdrom: &mut CdRom,
+                irq: &mut InterruptController,
+                command: u8,
+                params: &[u8]) -> (u8, Vec<u8>) {
+    cdrom.store(0, 0);
+
+    for &p in params {
+        cdrom.store(2, p);
+    }
+
+    cdrom.store(1, command);
+
+    next_response(cdrom, irq)
+}
+
+/// Wait for the next interrupt And acknowledge it
+#[cfg(test)]
+fn next_response(cdrom: &mut CdRom, irq: &mut InterruptController) -> (u8, Vec<u8>) {
+    let delay = cdrom.cycles_to_next_event().unwrap();
+
+    cdrom.tick(delay, irq);
+
+    cdrom.store(0, 1);
+    let code = cdrom.load(3) & 7;
+
+    let mut response = Vec::new();
+
+    while cdrom.status() & (1 << 5) != 0 {
+        response.push(cdrom.load(1));
+    }
+
+    cdrom.store(3, 0x1f);
+
+    (code, response)
+}
+
+#[cfg(test)]
+fn test_cdrom() -> CdRom {
+    let mut cdrom = CdRom::new(Some(disc::test_disc()));
+
+    cdrom.store(0, 1);
+    cdrom.store(2, 0x1f);
+
+    cdrom
+}
+
+#[test]
+fn first_response_timing() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // GetStat
+    cdrom.store(0, 0);
+    cdrom.store(1, 0x01);
+
+    // Busy
+    assert_eq!(cdrom.status() & 0x80, 0x80);
+
+    cdrom.tick(FIRST_RESPONSE_DELAY - 1, &mut irq);
+
+    assert_eq!(irq.status(), 0);
+
+    cdrom.tick(1, &mut irq);
+
+    assert_eq!(irq.status(), 1 << (Interrupt::CdRom as usize));
+    assert_eq!(cdrom.status() & 0xa0, 0x20);
+
+    cdrom.store(0, 1);
+    assert_eq!(cdrom.load(3), 0xe0 | INT3_ACKNOWLEDGE);
+    // Motor on
+    assert_eq!(cdrom.load(1), 0x02);
+    assert_eq!(cdrom.status() & 0x20, 0);
+}
+
+#[test]
+fn get_id() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    cdrom.store(0, 0);
+    cdrom.store(1, 0x1a);
+    cdrom.tick(FIRST_RESPONSE_DELAY, &mut irq);
+
+    assert_eq!(cdrom.irq_flags, INT3_ACKNOWLEDGE);
+
+    // The second response waits for the acknowledge
+    cdrom.tick(GET_ID_DELAY * 2, &mut irq);
+
+    assert_eq!(cdrom.irq_flags, INT3_ACKNOWLEDGE);
+    assert_eq!(cdrom.cycles_to_next_event(), None);
+
+    cdrom.store(0, 1);
+    cdrom.store(3, 0x07);
+
+    assert_eq!(cdrom.cycles_to_next_event(), Some(0));
+
+    let (code, response) = next_response(&mut cdrom, &mut irq);
+
+    assert_eq!(code, INT2_COMPLETE);
+    assert_eq!(response, [0x02, 0x00, 0x20, 0x00, b'S', b'C', b'E', b'A']);
+}
+
+#[test]
+fn get_id_no_disc() {
+    let mut cdrom = CdRom::new(None);
+    let mut irq = InterruptController::new();
+
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x1a, &[]).0, INT3_ACKNOWLEDGE);
+
+    let (code, response) = next_response(&mut cdrom, &mut irq);
+
+    assert_eq!(code, INT5_ERROR);
+    assert_eq!(response, [0x08, 0x40, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn read_sectors() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // Setloc 00:02:16
+    let (code, _) = send_command(&mut cdrom, &mut irq, 0x02, &[0x00, 0x02, 0x16]);
+    assert_eq!(code, INT3_ACKNOWLEDGE);
+
+    // Setmode: double speed
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0x80]);
+
+    // ReadN
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x06, &[]), (INT3_ACKNOWLEDGE, vec![0x02]));
+
+    assert_eq!(cdrom.cycles_to_next_event(), Some(SEEK_DELAY + CPU_FREQ / 150));
+
+    // Reading
+    assert_eq!(next_response(&mut cdrom, &mut irq), (INT1_DATA_READY, vec![0x22]));
+
+    cdrom.store(0, 0);
+    cdrom.store(3, 0x80);
+
+    // Data FIFO not empty
+    assert_eq!(cdrom.status() & 0x40, 0x40);
+
+    for _ in 0..2048 / 4 {
+        assert_eq!(cdrom.dma_read_word(), 0xa6a6a6a6);
+    }
+
+    assert_eq!(cdrom.status() & 0x40, 0);
+
+    assert_eq!(cdrom.cycles_to_next_event(), Some(CPU_FREQ / 150));
+
+    // Next sector, whole sector mode
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0xa0]);
+    next_response(&mut cdrom, &mut irq);
+
+    cdrom.store(0, 0);
+    cdrom.store(3, 0x80);
+
+    // Header
+    assert_eq!(cdrom.load(2), 0x00);
+    assert_eq!(cdrom.load(2), 0x02);
+    assert_eq!(cdrom.load(2), 0x17);
+    assert_eq!(cdrom.load(2), 0x02);
+
+    // Pause
+    let (code, response) = send_command(&mut cdrom, &mut irq, 0x09, &[]);
+
+    assert_eq!(code, INT3_ACKNOWLEDGE);
+    assert_eq!(response, [0x22]);
+
+    assert_eq!(next_response(&mut cdrom, &mut irq), (INT2_COMPLETE, vec![0x02]));
+}
+
+#[test]
+fn seek() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    send_command(&mut cdrom, &mut irq, 0x02, &[0x00, 0x05, 0x00]);
+
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x15, &[]).0, INT3_ACKNOWLEDGE);
+
+    // Seeking
+    assert_eq!(cdrom.stat(), 0x42);
+
+    assert_eq!(next_response(&mut cdrom, &mut irq), (INT2_COMPLETE, vec![0x02]));
+    assert_eq!(cdrom.position, Msf::new(0, 5, 0));
+}
+
+#[test]
+fn toc_commands() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // GetTN
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x13, &[]), (INT3_ACKNOWLEDGE, vec![0x02, 0x01, 0x01]));
+
+    // GetTD: track 1 And lead-out (1000 sectors after 00:02:00)
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x14, &[0x01]), (INT3_ACKNOWLEDGE, vec![0x02, 0x00, 0x02]));
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x14, &[0x00]), (INT3_ACKNOWLEDGE, vec![0x02, 0x00, 0x15]));
+
+    // Invalid track
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x14, &[0x02]), (INT5_ERROR, vec![0x03, 0x10]));
+}
+
+#[test]
+fn errors() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // Invalid command
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x5f, &[]), (INT5_ERROR, vec![0x03, 0x40]));
+
+    // Setloc without parameters
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x02, &[]), (INT5_ERROR, vec![0x03, 0x20]));
+
+    // Setloc with an invalid position
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x02, &[0x00, 0x60, 0x00]), (INT5_ERROR, vec![0x03, 0x10]));
+}
+
+#[test]
+fn test_command() {
+    let mut cdrom = test_cdrom();
+    let mut irq = InterruptController::new();
+
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x19, &[0x20]),
+               (INT3_ACKNOWLEDGE, vec![0x94, 0x09, 0x19, 0xc0]));
+
+    // Init resets the mode
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0x80]);
+
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x0a, &[]).0, INT3_ACKNOWLEDGE);
+    assert_eq!(next_response(&mut cdrom, &mut irq), (INT2_COMPLETE, vec![0x02]));
+    assert_eq!(cdrom.mode, 0x20);
+}
+
+/// Disc with a 100 sector data track where the sectors alternate
+/// between XA-ADPCM channels 0 And 1, followed by a 50 sector audio
+/// track
+#[cfg(test)]
+struct AudioTestImage {
+    toc: disc::Toc,
+}
+
+#[cfg(test)]
+impl disc::Image for AudioTestImage {
+    fn toc(&self) -> &disc::Toc {
+        &self.toc
+    }
+
+    fn read_sector(&mut self, msf: Msf) -> std::io::Result<Sector> {
+        let index = msf.sector_index();
+
+        if index >= 250 {
+            // Left: 0x100, right: -0x100
+            let raw: Vec<u8> = [0x00, 0x01, 0x00, 0xff].repeat(588);
+
+            return Ok(Sector::from_slice(&raw));
+        }
+
+        if msf == Msf::new(0, 2, 4) {
+            let mut data = [0; 2048];
+            let license = b"Licensed  by  Sony Computer Entertainment Amer  ica ";
+
+            data[..license.len()].copy_from_slice(license);
+
+            return Ok(Sector::from_user_data(msf, &data));
+        }
+
+        // 4bit mono ADPCM, every sample is 0x1000
+        let mut sector = xa::test_sector(0x00, 0x00, 0x11);
+
+        sector.set_header(msf, 2);
+        sector.raw_mut()[17] = (index & 1) as u8;
+        sector.raw_mut()[21] = (index & 1) as u8;
+
+        Ok(sector)
+    }
+}
+
+#[cfg(test)]
+fn audio_test_cdrom() -> CdRom {
+    use self::disc::Track;
+    use self::disc::trackformat::TrackFormat;
+
+    let image = AudioTestImage {
+        toc: disc::Toc::new(vec![
+            Track {
+                number: 1,
+                format: TrackFormat::Mode2,
+                start: Msf::new(0, 2, 0),
+                length: 100,
+            },
+            Track {
+                number: 2,
+                format: TrackFormat::Audio,
+                start: Msf::from_sector_index(250),
+                length: 50,
+            },
+        ]),
+    };
+
+    let mut cdrom = CdRom::new(Some(Disc::new(Box::new(image)).unwrap()));
+
+    cdrom.store(0, 1);
+    cdrom.store(2, 0x1f);
+
+    cdrom
+}
+
+#[test]
+fn xa_adpcm() {
+    let mut cdrom = audio_test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // Double speed, XA-ADPCM with the filter on file 1 channel 1
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0xc8]);
+    send_command(&mut cdrom, &mut irq, 0x0d, &[0x01, 0x01]);
+    send_command(&mut cdrom, &mut irq, 0x02, &[0x00, 0x02, 0x10]);
+    send_command(&mut cdrom, &mut irq, 0x06, &[]);
+
+    // The audio sectors aren't delivered to the CPU
+    for _ in 0..4 {
+        let delay = cdrom.cycles_to_next_event().unwrap();
+
+        cdrom.tick(delay, &mut irq);
+
+        assert_eq!(cdrom.irq_flags, 0);
+    }
+
+    assert_eq!(cdrom.status() & 4, 4);
+
+    // 2 sectors of 4032 samples at 37.8kHz
+    let mut samples = Vec::new();
+
+    while !cdrom.audio.is_empty() {
+        samples.push(cdrom.next_audio_sample());
+    }
+
+    assert_eq!(samples.len(), 2 * 4032 * 7 / 6);
+    assert_eq!(samples[0], (0, 0));
+    assert!(samples[10..].iter().all(|&s| s == (0x1000, 0x1000)));
+
+    // Without the filter both channels are played
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0xc0]);
+
+    for _ in 0..2 {
+        let delay = cdrom.cycles_to_next_event().unwrap();
+
+        cdrom.tick(delay, &mut irq);
+    }
+
+    let mut count = 0;
+
+    while !cdrom.audio.is_empty() {
+        cdrom.next_audio_sample();
+        count += 1;
+    }
+
+    assert_eq!(count, 2 * 4032 * 7 / 6);
+}
+
+#[test]
+fn cdda_play() {
+    let mut cdrom = audio_test_cdrom();
+    let mut irq = InterruptController::new();
+
+    // Auto pause And report
+    send_command(&mut cdrom, &mut irq, 0x0e, &[0x06]);
+
+    // Play track 2
+    assert_eq!(send_command(&mut cdrom, &mut irq, 0x03, &[0x02]), (INT3_ACKNOWLEDGE, vec![0x02]));
+
+    // GetlocP
+    let (_, response) = send_command(&mut cdrom, &mut irq, 0x11, &[]);
+
+    assert_eq!(response, [0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03, 0x25]);
+
+    let mut reports = Vec::new();
+
+    loop {
+        let delay = cdrom.cycles_to_next_event().unwrap();
+
+        cdrom.tick(delay, &mut irq);
+
+        if cdrom.irq_flags == 0 {
+            continue;
+        }
+
+        if cdrom.irq_flags == INT1_DATA_READY {
+            // CD-DA doesn't set ADPBUSY
+            assert_eq!(cdrom.status() & 4, 0);
+            assert_eq!(cdrom.next_audio_sample(), (0x100, -0x100));
+        }
+
+        cdrom.store(0, 1);
+        let code = cdrom.load(3) & 7;
+
+        let mut response = Vec::new();
+
+        while cdrom.status() & (1 << 5) != 0 {
+            response.push(cdrom.load(1));
+        }
+
+        cdrom.store(3, 0x1f);
+
+        if code == INT4_DATA_END {
+            // Stopped at the end of the track
+            assert_eq!(response, [0x02]);
+            break;
+        }
+
+        assert_eq!(code, INT1_DATA_READY);
+
+        reports.push(response);
+    }
+
+    assert_eq!(reports.len(), 5);
+    // Relative position 00:00:05
+    assert_eq!(reports[0], [0x82, 0x02, 0x01, 0x00, 0x80, 0x05, 0x00, 0x00]);
+    // Absolute position 00:03:40
+    assert_eq!(reports[1], [0x82, 0x02, 0x01, 0x00, 0x03, 0x40, 0x00, 0x00]);
+
+    // Mute
+    send_command(&mut cdrom, &mut irq, 0x0b, &[]);
+
+    assert_eq!(cdrom.next_audio_sample(), (0, 0));
+}
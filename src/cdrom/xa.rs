@@ -0,0 +1,272 @@
+//! XA-ADPCM decoder for the audio sectors of Mode 2 Form 2 streams
+
+use super::disc::sector::Sector;
+
+/// Output sample rate of the CD audio
+pub const AUDIO_FREQ: u32 = 44_100;
+
+/// Submode flag of the sectors containing ADPCM audio
+pub const SUBMODE_AUDIO: u8 = 0x04;
+
+/// Number of 128 byte sound groups in a sector
+const SOUND_GROUPS: usize = 18;
+/// Size of a sound group
+const SOUND_GROUP_SIZE: usize = 128;
+/// Number of samples in a sound unit
+const UNIT_SAMPLES: usize = 28;
+
+/// ADPCM prediction filters
+const POS_TABLE: [i32; 4] = [0, 60, 115, 98];
+const NEG_TABLE: [i32; 4] = [0, 0, -52, -55];
+
+/// Format of an ADPCM sector, decoded from the coding info byte of
+/// the subheader
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Coding {
+    pub stereo: bool,
+    /// Sample rate in Hz: 37800 or 18900
+    pub sample_rate: u32,
+    /// Bits per sample: 4 or 8
+    pub bits: u32,
+}
+
+impl Coding {
+    pub fn from_subheader(coding_info: u8) -> Coding {
+        Coding {
+            stereo: coding_info & 1 != 0,
+            sample_rate: if coding_info & 4 != 0 { 18900 } else { 37800 },
+            bits: if coding_info & 0x10 != 0 { 8 } else { 4 },
+        }
+    }
+}
+
+/// XA-ADPCM decoder, keeps the filter history And resampler state
+/// between sectors
+pub struct XaDecoder {
+    /// Last two decoded samples of each channel
+    history: [(i32, i32); 2],
+    resampler: Resampler,
+}
+
+impl XaDecoder {
+    pub fn new() -> XaDecoder {
+        XaDecoder {
+            history: [(0, 0); 2],
+            resampler: Resampler::new(),
+        }
+    }
+
+    /// Reset the decoder before starting a new stream
+    pub fn reset(&mut self) {
+        self.history = [(0, 0); 2];
+        self.resampler = Resampler::new();
+    }
+
+    /// Decode an ADPCM sector, the samples are resampled to 44.1kHz
+    /// stereo And appended to `out`
+    pub fn decode_sector(&mut self, sector: &Sector, out: &mut Vec<(i16, i16)>) {
+        let coding = Coding::from_subheader(sector.subheader()[3]);
+
+        let samples = self.decode_adpcm(sector, coding);
+
+        for s in samples {
+            self.resampler.push(s, coding.sample_rate, out);
+        }
+    }
+
+    /// Decode the ADPCM data at its original sample rate. Mono
+    /// samples are output on both channels.
+    fn decode_adpcm(&mut self, sector: &Sector, coding: Coding) -> Vec<(i16, i16)> {
+        // The sound groups start right after the subheader
+        let data = &sector.raw()[24..24 + SOUND_GROUPS * SOUND_GROUP_SIZE];
+
+        let units = if coding.bits == 4 { 8 } else { 4 };
+
+        let mut left = Vec::with_capacity(SOUND_GROUPS * units * UNIT_SAMPLES);
+        let mut right = Vec::with_capacity(SOUND_GROUPS * units * UNIT_SAMPLES / 2);
+
+        for group in data.chunks(SOUND_GROUP_SIZE) {
+            for unit in 0..units {
+                // In stereo the even units are for the left channel
+                // And the odd ones for the right one
+                let channel = if coding.stereo { unit & 1 } else { 0 };
+
+                let out = if channel == 0 { &mut left } else { &mut right };
+
+                self.decode_unit(group, unit, coding.bits, channel, out);
+            }
+        }
+
+        if coding.stereo {
+            left.into_iter().zip(right).collect()
+        } else {
+            left.into_iter().map(|s| (s, s)).collect()
+        }
+    }
+
+    /// Decode the 28 samples of sound unit `unit` of `group`
+    fn decode_unit(&mut self,
+                   group: &[u8],
+                   unit: usize,
+                   bits: u32,
+                   channel: usize,
+                   out: &mut Vec<i16>) {
+        let header = group[4 + unit];
+
+        let mut shift = (header & 0xf) as u32;
+        let filter = ((header >> 4) & 3) as usize;
+
+        // Shift values 13 to 15 behave like 9
+        if shift > 12 {
+            shift = 9;
+        }
+
+        let (mut old, mut older) = self.history[channel];
+
+        for i in 0..UNIT_SAMPLES {
+            let sample = if bits == 4 {
+                let b = group[16 + i * 4 + unit / 2];
+                let nibble = (b >> ((unit & 1) * 4)) & 0xf;
+
+                ((nibble as u16) << 12) as i16
+            } else {
+                let b = group[16 + i * 4 + unit];
+
+                ((b as u16) << 8) as i16
+            };
+
+            let mut sample = (sample as i32) >> shift;
+
+            sample += (old * POS_TABLE[filter] + older * NEG_TABLE[filter] + 32) >> 6;
+
+            let sample = sample.clamp(-0x8000, 0x7fff);
+
+            out.push(sample as i16);
+
+            older = old;
+            old = sample;
+        }
+
+        self.history[channel] = (old, older);
+    }
+}
+
+/// Linear interpolation resampler converting the ADPCM streams to
+/// 44.1kHz
+struct Resampler {
+    /// Previous input sample
+    prev: (i16, i16),
+    /// Position of the next output sample between `prev` And the
+    /// next input sample, in units of 1/AUDIO_FREQ input samples
+    phase: u32,
+}
+
+impl Resampler {
+    fn new() -> Resampler {
+        Resampler {
+            prev: (0, 0),
+            phase: 0,
+        }
+    }
+
+    /// Feed one input sample at `rate` Hz
+    fn push(&mut self, sample: (i16, i16), rate: u32, out: &mut Vec<(i16, i16)>) {
+        let lerp = |a: i16, b: i16, phase: u32| {
+            let a = a as i32;
+            let b = b as i32;
+
+            (a + (b - a) * phase as i32 / AUDIO_FREQ as i32) as i16
+        };
+
+        while self.phase < AUDIO_FREQ {
+            out.push((lerp(self.prev.0, sample.0, self.phase),
+                      lerp(self.prev.1, sample.1, self.phase)));
+
+            self.phase += rate;
+        }
+
+        self.phase -= AUDIO_FREQ;
+        self.prev = sample;
+    }
+}
+
+/// Build an ADPCM sector where every sound unit uses `header` And
+/// every data byte is `data`
+#[cfg(test)]
+pub fn test_sector(coding_info: u8, header: u8, data: u8) -> Sector {
+    let mut sector = Sector::new([0; super::disc::sector::SECTOR_SIZE]);
+
+    {
+        let raw = sector.raw_mut();
+
+        raw[16..24].copy_from_slice(&[1, 0, 0x64, coding_info, 1, 0, 0x64, coding_info]);
+
+        for group in raw[24..24 + SOUND_GROUPS * SOUND_GROUP_SIZE].chunks_mut(SOUND_GROUP_SIZE) {
+            group[..16].copy_from_slice(&[header; 16]);
+            group[16..].copy_from_slice(&[data; SOUND_GROUP_SIZE - 16]);
+        }
+    }
+
+    sector
+}
+
+#[test]
+fn adpcm_4bit_mono() {
+    let mut decoder = XaDecoder::new();
+
+    // No shift, no filter: each sample is its nibble << 12. The low
+    // nibble is 1 And the high nibble -2.
+    let sector = test_sector(0x00, 0x00, 0xe1);
+
+    let coding = Coding::from_subheader(0x00);
+    let samples = decoder.decode_adpcm(&sector, coding);
+
+    assert_eq!(samples.len(), SOUND_GROUPS * 8 * UNIT_SAMPLES);
+    assert!(samples[..28].iter().all(|&s| s == (0x1000, 0x1000)));
+    assert!(samples[28..56].iter().all(|&s| s == (-0x2000, -0x2000)));
+
+    // Shift 12 And filter 1: s = nibble + old * 60 / 64
+    let sector = test_sector(0x00, 0x1c, 0x00);
+
+    decoder.reset();
+    decoder.history[0] = (0x1000, 0);
+
+    let samples = decoder.decode_adpcm(&sector, coding);
+
+    assert_eq!(samples[0].0 as i32, (0x1000 * 60 + 32) >> 6);
+    assert_eq!(samples[1].0 as i32, (samples[0].0 as i32 * 60 + 32) >> 6);
+}
+
+#[test]
+fn adpcm_8bit_stereo() {
+    let mut decoder = XaDecoder::new();
+
+    let coding = Coding::from_subheader(0x11);
+
+    assert_eq!(coding, Coding { stereo: true, sample_rate: 37800, bits: 8 });
+
+    // Shift 4: sample = data << 8 >> 4
+    let sector = test_sector(0x11, 0x04, 0x10);
+
+    let samples = decoder.decode_adpcm(&sector, coding);
+
+    assert_eq!(samples.len(), SOUND_GROUPS * 2 * UNIT_SAMPLES);
+    assert!(samples.iter().all(|&s| s == (0x100, 0x100)));
+}
+
+#[test]
+fn resampling() {
+    for &(rate, expected) in &[(37800, 7000), (18900, 14000)] {
+        let mut resampler = Resampler::new();
+        let mut out = Vec::new();
+
+        for _ in 0..6000 {
+            resampler.push((1000, -1000), rate, &mut out);
+        }
+
+        assert_eq!(out.len(), expected);
+        // Ramp up from the initial silence
+        assert!(out[0] == (0, 0));
+        assert!(out[10..].iter().all(|&s| s == (1000, -1000)));
+    }
+}
@@ -0,0 +1,132 @@
+/// Cache control register, mapped at 0xfffe0130
+#[derive(Copy, Clone)]
+pub struct CacheControl(pub u32);
+
+impl CacheControl {
+    /// In tag test mode the writes to the isolated cache invalidate
+    /// the targeted line instead of modifying its contents
+    pub fn tag_test_mode(self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+
+    /// Return true if the instruction cache is enabled
+    pub fn icache_enabled(self) -> bool {
+        self.0 & (1 << 11) != 0
+    }
+}
+
+/// Instruction cache line: 4 words
+#[derive(Copy, Clone)]
+pub struct CacheLine {
+    /// Bits [31:12] of the physical address of the cached words
+    tag: u32,
+
+    /// One valid bit per word
+    valid: u8,
+
+    words: [u32; 4],
+}
+
+impl CacheLine {
+    pub fn new() -> CacheLine {
+        CacheLine {
+            tag: 0,
+            valid: 0,
+            // Garbage
+            words: [0xbadc0de5; 4],
+        }
+    }
+
+    /// Return the cached word for `addr` (a physical address) if it's
+    /// valid
+    pub fn lookup(&self, addr: u32) -> Option<u32> {
+        let index = CacheLine::word_index(addr);
+
+        if self.tag == addr & !0xfff && self.valid & (1 << index) != 0 {
+            Some(self.words[index])
+        } else {
+            None
+        }
+    }
+
+    /// Start a refill for `addr`: the line is retagged And only the
+    /// words from `addr` to the end of the line will be valid
+    pub fn retag(&mut self, addr: u32) {
+        self.tag = addr & !0xfff;
+        self.valid = 0;
+    }
+
+    /// Store a word fetched from memory And mark it valid
+    pub fn fill(&mut self, addr: u32, word: u32) {
+        let index = CacheLine::word_index(addr);
+
+        self.words[index] = word;
+        self.valid |= 1 << index;
+    }
+
+    pub fn invalidate(&mut self) {
+        self.valid = 0;
+    }
+
+    /// Access the data without checking the tag, used when the cache
+    /// is isolated
+    pub fn word(&self, addr: u32) -> u32 {
+        self.words[CacheLine::word_index(addr)]
+    }
+
+    pub fn set_word(&mut self, addr: u32, word: u32) {
+        self.words[CacheLine::word_index(addr)] = word;
+    }
+
+    fn word_index(addr: u32) -> usize {
+        ((addr >> 2) & 3) as usize
+    }
+}
+
+/// 4kB direct mapped instruction cache
+pub struct ICache {
+    lines: [CacheLine; 256],
+}
+
+impl ICache {
+    pub fn new() -> ICache {
+        ICache {
+            lines: [CacheLine::new(); 256],
+        }
+    }
+
+    /// Return the line caching `addr`
+    pub fn line(&self, addr: u32) -> &CacheLine {
+        &self.lines[ICache::line_index(addr)]
+    }
+
+    pub fn line_mut(&mut self, addr: u32) -> &mut CacheLine {
+        &mut self.lines[ICache::line_index(addr)]
+    }
+
+    fn line_index(addr: u32) -> usize {
+        ((addr >> 4) & 0xff) as usize
+    }
+}
+
+#[test]
+fn partial_refill() {
+    let mut line = CacheLine::new();
+
+    assert_eq!(line.lookup(0x1008), None);
+
+    line.retag(0x1008);
+    line.fill(0x1008, 0x1234);
+    line.fill(0x100c, 0x5678);
+
+    assert_eq!(line.lookup(0x1008), Some(0x1234));
+    assert_eq!(line.lookup(0x100c), Some(0x5678));
+    // Words before the refill address aren't fetched
+    assert_eq!(line.lookup(0x1000), None);
+    // Different tag
+    assert_eq!(line.lookup(0x2008), None);
+
+    line.invalidate();
+
+    assert_eq!(line.lookup(0x1008), None);
+}
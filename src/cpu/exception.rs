@@ -8,6 +8,10 @@ pub enum Exception {
     LoadAddressError = 0x4,
     /// Address error on store
     StoreAddressError = 0x5,
+    /// Bus error on instruction fetch
+    InstructionBusError = 0x6,
+    /// Bus error on data load or store
+    DataBusError = 0x7,
     /// System call (caused by the SYSCALL opcode)
     SysCall = 0x8,
     /// Breakpoint (caused by the BREAK opcode)
@@ -26,6 +30,8 @@ impl Display for Exception {
             Exception::Interrupt => "Interrupt",
             Exception::LoadAddressError => "LoadAddressError",
             Exception::StoreAddressError => "StoreAddressError",
+            Exception::InstructionBusError => "InstructionBusError",
+            Exception::DataBusError => "DataBusError",
             Exception::SysCall => "SysCall",
             Exception::Break => "Break",
             Exception::IllegalInstruction => "IllegalInstruction",
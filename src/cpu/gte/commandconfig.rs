@@ -0,0 +1,33 @@
+use crate::cpu::gte::controlvector::ControlVector;
+use crate::cpu::gte::matrix::Matrix;
+
+/// Configuration bits decoded from a GTE command
+#[derive(Copy, Clone)]
+pub struct CommandConfig {
+    /// Right shift applied to the MAC results: 12 if the `sf` bit
+    /// is set, 0 otherwise
+    pub shift: u8,
+    /// When true IR1-3 are saturated to [0, 0x7fff] instead of
+    /// [-0x8000, 0x7fff] (`lm` bit)
+    pub clamp_negative: bool,
+    /// Matrix used by MVMVA
+    pub matrix: Matrix,
+    /// Vector used by MVMVA (3 is the IR vector)
+    pub vector_index: usize,
+    /// Control vector used by MVMVA
+    pub control_vector: ControlVector,
+}
+
+impl CommandConfig {
+    pub fn from_command(command: u32) -> CommandConfig {
+        let shift = if command & (1 << 19) != 0 { 12 } else { 0 };
+
+        CommandConfig {
+            shift,
+            clamp_negative: command & (1 << 10) != 0,
+            matrix: Matrix::from_command(command),
+            vector_index: ((command >> 15) & 3) as usize,
+            control_vector: ControlVector::from_command(command),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/// The GTE control vectors
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ControlVector {
+    /// Translation vector (TR)
+    Translation = 0,
+    /// Background color (BK)
+    BackgroundColor = 1,
+    /// Far color (FC). Buggy when used with MVMVA.
+    FarColor = 2,
+    /// Zero vector
+    Zero = 3,
+}
+
+impl ControlVector {
+    /// Decode the `cv` field of a MVMVA command
+    pub fn from_command(command: u32) -> ControlVector {
+        match (command >> 13) & 3 {
+            0 => ControlVector::Translation,
+            1 => ControlVector::BackgroundColor,
+            2 => ControlVector::FarColor,
+            3 => ControlVector::Zero,
+            _ => unreachable!(),
+        }
+    }
+}
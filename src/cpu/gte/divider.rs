@@ -0,0 +1,81 @@
+//! The GTE perspective division is not a true division, it uses the
+//! Unsigned Newton-Raphson (UNR) algorithm with a small lookup table
+//! to approximate the reciprocal of the divisor. We must reproduce it
+//! exactly to get pixel-perfect projections.
+
+/// Return the projection factor `(numerator * 0x10000) / divisor`
+/// using the UNR algorithm. The result saturates to 0x1ffff.
+///
+/// The caller is responsible for checking that `numerator <
+/// divisor * 2` (otherwise the hardware signals a division overflow
+/// and doesn't call the divider at all).
+pub fn divide(numerator: u16, divisor: u16) -> u32 {
+    let shift = divisor.leading_zeros();
+
+    let n = (numerator as u64) << shift;
+    let d = divisor << shift;
+
+    let reciprocal = reciprocal(d) as u64;
+
+    let res = (n * reciprocal + 0x8000) >> 16;
+
+    if res <= 0x1ffff {
+        res as u32
+    } else {
+        0x1ffff
+    }
+}
+
+/// Approximate `0x2000000 / d` for a normalized (MSB set) divisor
+fn reciprocal(d: u16) -> u32 {
+    let index = (((d & 0x7fff) + 0x40) >> 7) as usize;
+
+    let factor = UNR_TABLE[index] as i32 + 0x101;
+
+    let d = (d | 0x8000) as i32;
+
+    let tmp = ((d * -factor) + 0x80) >> 8;
+
+    let r = ((factor * (0x20000 + tmp)) + 0x80) >> 8;
+
+    r as u32
+}
+
+/// Reciprocal seed table. The Nocash spec documents the formula used
+/// to generate it: `max(0, (0x40000 / (i + 0x100) + 1) / 2 - 0x101)`
+const UNR_TABLE: [u8; 0x101] = build_unr_table();
+
+const fn build_unr_table() -> [u8; 0x101] {
+    let mut table = [0u8; 0x101];
+    let mut i = 0;
+
+    while i < table.len() {
+        let v = (0x40000 / (i as i32 + 0x100) + 1) / 2 - 0x101;
+
+        table[i] = if v > 0 { v as u8 } else { 0 };
+
+        i += 1;
+    }
+
+    table
+}
+
+#[test]
+fn unr_table() {
+    // A few entries checked against the table dumped from the
+    // hardware
+    assert_eq!(UNR_TABLE[0x00], 0xff);
+    assert_eq!(UNR_TABLE[0x01], 0xfd);
+    assert_eq!(UNR_TABLE[0x02], 0xfb);
+    assert_eq!(UNR_TABLE[0x80], 0x54);
+    assert_eq!(UNR_TABLE[0xff], 0x00);
+    assert_eq!(UNR_TABLE[0x100], 0x00);
+}
+
+#[test]
+fn divide_exact() {
+    // h / z in 16.16 fixed point
+    assert_eq!(divide(0x100, 0x200), 0x8000);
+    assert_eq!(divide(0x200, 0x200), 0x10000);
+    assert_eq!(divide(0x300, 0x200), 0x18000);
+}
@@ -0,0 +1,26 @@
+/// The three GTE matrices
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Matrix {
+    /// Rotation matrix (RT)
+    Rotation = 0,
+    /// Light source matrix (LLM)
+    Light = 1,
+    /// Light color matrix (LCM)
+    Color = 2,
+    /// Selecting matrix 3 in MVMVA yields a garbage matrix built from
+    /// bits and pieces of other registers
+    Invalid = 3,
+}
+
+impl Matrix {
+    /// Decode the `mx` field of a MVMVA command
+    pub fn from_command(command: u32) -> Matrix {
+        match (command >> 17) & 3 {
+            0 => Matrix::Rotation,
+            1 => Matrix::Light,
+            2 => Matrix::Color,
+            3 => Matrix::Invalid,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,1466 @@
+//! Geometry Transformation Engine (coprocessor 2)
+//!
+//! The GTE is a fixed point vector unit used by games to do 3D
+//! transformation, perspective projection And lighting. It's accessed
+//! through the MFC2/MTC2/CFC2/CTC2/LWC2/SWC2 instructions And runs
+//! "commands" encoded directly in the COP2 opcodes.
+
+use self::commandconfig::CommandConfig;
+use self::controlvector::ControlVector;
+use self::matrix::Matrix;
+
+pub mod commandconfig;
+pub mod controlvector;
+pub mod divider;
+pub mod matrix;
+
+/// Geometry Transformation Engine state
+pub struct Gte {
+    // Control registers
+    /// Screen offset X: signed 16.16
+    ofx: i32,
+    /// Screen offset Y: signed 16.16
+    ofy: i32,
+    /// Projection plane distance
+    h: u16,
+    /// Depth queuing coefficient
+    dqa: i16,
+    /// Depth queuing offset
+    dqb: i32,
+    /// Scale factor when computing the average of 3 Z values
+    /// (triangle): signed 4.12
+    zsf3: i16,
+    /// Scale factor when computing the average of 4 Z values (quad):
+    /// signed 4.12
+    zsf4: i16,
+    /// Three 3x3 signed 4.12 matrices: rotation, light And color
+    matrices: [[[i16; 3]; 3]; 3],
+    /// Four control vectors: translation, background color, far color
+    /// And the zero vector
+    control_vectors: [[i32; 3]; 4],
+    /// Overflow And saturation flags
+    flags: u32,
+
+    // Data registers
+    /// Vectors 0 to 2. Index 3 is used internally as a copy of IR1-3
+    /// when a command uses it as input.
+    v: [[i16; 3]; 4],
+    /// Accumulators for intermediate results, 4 x signed word
+    mac: [i32; 4],
+    /// Ordering table Z value
+    otz: u16,
+    /// RGB color. The 4th component is the GPU command code passed
+    /// through untouched to the color FIFO.
+    rgb: (u8, u8, u8, u8),
+    /// 16bit accumulators: IR0 And the IR1-3 vector
+    ir: [i16; 4],
+    /// Screen XY coordinate FIFO, 3 entries deep
+    xy_fifo: [(i16, i16); 3],
+    /// Screen Z FIFO, 4 entries deep
+    z_fifo: [u16; 4],
+    /// Color FIFO, 3 entries deep
+    rgb_fifo: [(u8, u8, u8, u8); 3],
+    /// Leading zero/one count source
+    lzcs: u32,
+    /// Leading zero/one count result
+    lzcr: u8,
+    /// Prohibited register 23, readable And writable but otherwise
+    /// unused
+    reg_23: u32,
+}
+
+impl Gte {
+    pub fn new() -> Gte {
+        Gte {
+            ofx: 0,
+            ofy: 0,
+            h: 0,
+            dqa: 0,
+            dqb: 0,
+            zsf3: 0,
+            zsf4: 0,
+            matrices: [[[0; 3]; 3]; 3],
+            control_vectors: [[0; 3]; 4],
+            flags: 0,
+            v: [[0; 3]; 4],
+            mac: [0; 4],
+            otz: 0,
+            rgb: (0, 0, 0, 0),
+            ir: [0; 4],
+            xy_fifo: [(0, 0); 3],
+            z_fifo: [0; 4],
+            rgb_fifo: [(0, 0, 0, 0); 3],
+            lzcs: 0,
+            lzcr: 32,
+            reg_23: 0,
+        }
+    }
+
+    /// Load a control register (CFC2)
+    pub fn control(&self, reg: u32) -> u32 {
+        let matrix_reg = |m: Matrix, reg: u32| -> u32 {
+            let matrix = &self.matrices[m as usize];
+
+            // Each register packs two matrix entries, the last one
+            // contains only the bottom right entry
+            let index = (reg * 2) as usize;
+
+            let lo = matrix[index / 3][index % 3] as u16 as u32;
+
+            if index + 1 < 9 {
+                let hi = matrix[(index + 1) / 3][(index + 1) % 3] as u16 as u32;
+
+                lo | (hi << 16)
+            } else {
+                matrix[2][2] as u32
+            }
+        };
+
+        let vector_reg = |cv: ControlVector, index: u32| -> u32 {
+            self.control_vectors[cv as usize][index as usize] as u32
+        };
+
+        match reg {
+            0..=4 => matrix_reg(Matrix::Rotation, reg),
+            5..=7 => vector_reg(ControlVector::Translation, reg - 5),
+            8..=12 => matrix_reg(Matrix::Light, reg - 8),
+            13..=15 => vector_reg(ControlVector::BackgroundColor, reg - 13),
+            16..=20 => matrix_reg(Matrix::Color, reg - 16),
+            21..=23 => vector_reg(ControlVector::FarColor, reg - 21),
+            24 => self.ofx as u32,
+            25 => self.ofy as u32,
+            // H is unsigned but is sign-extended when read back due
+            // to a hardware bug
+            26 => self.h as i16 as u32,
+            27 => self.dqa as u32,
+            28 => self.dqb as u32,
+            29 => self.zsf3 as u32,
+            30 => self.zsf4 as u32,
+            31 => self.flags,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Store a control register (CTC2)
+    pub fn set_control(&mut self, reg: u32, val: u32) {
+        match reg {
+            0..=4 => self.set_matrix_reg(Matrix::Rotation, reg, val),
+            5..=7 => self.control_vectors[ControlVector::Translation as usize][(reg - 5) as usize] = val as i32,
+            8..=12 => self.set_matrix_reg(Matrix::Light, reg - 8, val),
+            13..=15 => self.control_vectors[ControlVector::BackgroundColor as usize][(reg - 13) as usize] = val as i32,
+            16..=20 => self.set_matrix_reg(Matrix::Color, reg - 16, val),
+            21..=23 => self.control_vectors[ControlVector::FarColor as usize][(reg - 21) as usize] = val as i32,
+            24 => self.ofx = val as i32,
+            25 => self.ofy = val as i32,
+            26 => self.h = val as u16,
+            27 => self.dqa = val as i16,
+            28 => self.dqb = val as i32,
+            29 => self.zsf3 = val as i16,
+            30 => self.zsf4 = val as i16,
+            31 => {
+                self.flags = val & 0x7ffff000;
+                self.update_error_flag();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_matrix_reg(&mut self, m: Matrix, reg: u32, val: u32) {
+        let matrix = &mut self.matrices[m as usize];
+        let index = (reg * 2) as usize;
+
+        matrix[index / 3][index % 3] = val as i16;
+
+        if index + 1 < 9 {
+            matrix[(index + 1) / 3][(index + 1) % 3] = (val >> 16) as i16;
+        }
+    }
+
+    /// Load a data register (MFC2/SWC2)
+    pub fn data(&self, reg: u32) -> u32 {
+        let xy = |(x, y): (i16, i16)| -> u32 {
+            (x as u16 as u32) | ((y as u16 as u32) << 16)
+        };
+
+        let rgbc = |(r, g, b, c): (u8, u8, u8, u8)| -> u32 {
+            (r as u32) | ((g as u32) << 8) | ((b as u32) << 16) | ((c as u32) << 24)
+        };
+
+        match reg {
+            0 | 2 | 4 => {
+                let v = &self.v[(reg / 2) as usize];
+
+                (v[0] as u16 as u32) | ((v[1] as u16 as u32) << 16)
+            }
+            1 | 3 | 5 => self.v[(reg / 2) as usize][2] as u32,
+            6 => rgbc(self.rgb),
+            7 => self.otz as u32,
+            8..=11 => self.ir[(reg - 8) as usize] as u32,
+            12..=14 => xy(self.xy_fifo[(reg - 12) as usize]),
+            // SXYP mirrors SXY2 on read
+            15 => xy(self.xy_fifo[2]),
+            16..=19 => self.z_fifo[(reg - 16) as usize] as u32,
+            20..=22 => rgbc(self.rgb_fifo[(reg - 20) as usize]),
+            23 => self.reg_23,
+            24..=27 => self.mac[(reg - 24) as usize] as u32,
+            28 | 29 => self.packed_ir(),
+            30 => self.lzcs,
+            31 => self.lzcr as u32,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Store a data register (MTC2/LWC2)
+    pub fn set_data(&mut self, reg: u32, val: u32) {
+        let xy = |val: u32| -> (i16, i16) { (val as i16, (val >> 16) as i16) };
+
+        let rgbc = |val: u32| -> (u8, u8, u8, u8) {
+            (val as u8, (val >> 8) as u8, (val >> 16) as u8, (val >> 24) as u8)
+        };
+
+        match reg {
+            0 | 2 | 4 => {
+                let v = &mut self.v[(reg / 2) as usize];
+
+                v[0] = val as i16;
+                v[1] = (val >> 16) as i16;
+            }
+            1 | 3 | 5 => self.v[(reg / 2) as usize][2] = val as i16,
+            6 => self.rgb = rgbc(val),
+            7 => self.otz = val as u16,
+            8..=11 => self.ir[(reg - 8) as usize] = val as i16,
+            12..=14 => self.xy_fifo[(reg - 12) as usize] = xy(val),
+            15 => self.push_xy(xy(val)),
+            16..=19 => self.z_fifo[(reg - 16) as usize] = val as u16,
+            20..=22 => self.rgb_fifo[(reg - 20) as usize] = rgbc(val),
+            23 => self.reg_23 = val,
+            24..=27 => self.mac[(reg - 24) as usize] = val as i32,
+            28 => {
+                // IRGB: 5 bits per component expanded to IR1-3
+                self.ir[1] = ((val & 0x1f) << 7) as i16;
+                self.ir[2] = (((val >> 5) & 0x1f) << 7) as i16;
+                self.ir[3] = (((val >> 10) & 0x1f) << 7) as i16;
+            }
+            // ORGB is read-only
+            29 => (),
+            30 => {
+                self.lzcs = val;
+
+                // Count the leading zeroes for positive values, the
+                // leading ones for negative ones
+                self.lzcr = if (val as i32) < 0 {
+                    val.leading_ones() as u8
+                } else {
+                    val.leading_zeros() as u8
+                };
+            }
+            // LZCR is read-only
+            31 => (),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Execute a GTE command (COP2 opcode with bit 25 set)
+    pub fn command(&mut self, command: u32) {
+        let opcode = command & 0x3f;
+        let config = CommandConfig::from_command(command);
+
+        // Flags are reset at the beginning of each command
+        self.flags = 0;
+
+        match opcode {
+            0x01 => self.cmd_rtps(config),
+            0x06 => self.cmd_nclip(),
+            0x0c => self.cmd_op(config),
+            0x10 => self.cmd_dpcs(config),
+            0x11 => self.cmd_intpl(config),
+            0x12 => self.cmd_mvmva(config),
+            0x13 => self.cmd_ncds(config),
+            0x14 => self.cmd_cdp(config),
+            0x16 => self.cmd_ncdt(config),
+            0x1b => self.cmd_nccs(config),
+            0x1c => self.cmd_cc(config),
+            0x1e => self.cmd_ncs(config),
+            0x20 => self.cmd_nct(config),
+            0x28 => self.cmd_sqr(config),
+            0x29 => self.cmd_dcpl(config),
+            0x2a => self.cmd_dpct(config),
+            0x2d => self.cmd_avsz3(),
+            0x2e => self.cmd_avsz4(),
+            0x30 => self.cmd_rtpt(config),
+            0x3d => self.cmd_gpf(config),
+            0x3e => self.cmd_gpl(config),
+            0x3f => self.cmd_ncct(config),
+            _ => warn!("Unhandled GTE command 0x{:08x}", command),
+        }
+
+        self.update_error_flag();
+    }
+
+    /// RTPS: Perspective transformation, single
+    fn cmd_rtps(&mut self, config: CommandConfig) {
+        let projection_factor = self.do_rtp(config, 0);
+
+        self.depth_queuing(projection_factor);
+    }
+
+    /// RTPT: Perspective transformation, triple
+    fn cmd_rtpt(&mut self, config: CommandConfig) {
+        self.do_rtp(config, 0);
+        self.do_rtp(config, 1);
+
+        let projection_factor = self.do_rtp(config, 2);
+
+        self.depth_queuing(projection_factor);
+    }
+
+    /// NCLIP: Normal clipping. Computes the sign of the winding of the
+    /// triangle in the XY FIFO.
+    fn cmd_nclip(&mut self) {
+        let (x0, y0) = self.xy_fifo[0];
+        let (x1, y1) = self.xy_fifo[1];
+        let (x2, y2) = self.xy_fifo[2];
+
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let (x1, y1) = (x1 as i32, y1 as i32);
+        let (x2, y2) = (x2 as i32, y2 as i32);
+
+        let a = x0 * (y1 - y2);
+        let b = x1 * (y2 - y0);
+        let c = x2 * (y0 - y1);
+
+        // Can't overflow the 64bit accumulator
+        let sum = a as i64 + b as i64 + c as i64;
+
+        self.check_mac0_overflow(sum);
+
+        self.mac[0] = sum as i32;
+    }
+
+    /// OP: Outer product of the rotation matrix diagonal And IR
+    fn cmd_op(&mut self, config: CommandConfig) {
+        let rm = Matrix::Rotation as usize;
+
+        let ir1 = self.ir[1] as i64;
+        let ir2 = self.ir[2] as i64;
+        let ir3 = self.ir[3] as i64;
+
+        let d1 = self.matrices[rm][0][0] as i64;
+        let d2 = self.matrices[rm][1][1] as i64;
+        let d3 = self.matrices[rm][2][2] as i64;
+
+        let products = [ir3 * d2 - ir2 * d3, ir1 * d3 - ir3 * d1, ir2 * d1 - ir1 * d2];
+
+        for (i, &p) in products.iter().enumerate() {
+            let res = self.i64_to_i44(i as u8, p);
+
+            self.mac[i + 1] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+    }
+
+    /// DPCS: Depth cueing, single
+    fn cmd_dpcs(&mut self, config: CommandConfig) {
+        let (r, g, b, _) = self.rgb;
+
+        self.do_dpc(config, (r, g, b));
+    }
+
+    /// DPCT: Depth cueing, triple. Always uses RGB0 which gets shifted
+    /// out of the FIFO after each iteration.
+    fn cmd_dpct(&mut self, config: CommandConfig) {
+        for _ in 0..3 {
+            let (r, g, b, _) = self.rgb_fifo[0];
+
+            self.do_dpc(config, (r, g, b));
+        }
+    }
+
+    /// INTPL: Interpolation of IR1-3 with the far color
+    fn cmd_intpl(&mut self, config: CommandConfig) {
+        let mac = [
+            (self.ir[1] as i64) << 12,
+            (self.ir[2] as i64) << 12,
+            (self.ir[3] as i64) << 12,
+        ];
+
+        self.interpolate_color(config, mac);
+    }
+
+    /// MVMVA: Multiply a vector by a matrix And add a control vector
+    fn cmd_mvmva(&mut self, config: CommandConfig) {
+        self.v[3] = [self.ir[1], self.ir[2], self.ir[3]];
+
+        self.multiply_matrix_by_vector(config, config.matrix, config.vector_index, config.control_vector);
+    }
+
+    /// NCDS: Normal color depth cue, single vector
+    fn cmd_ncds(&mut self, config: CommandConfig) {
+        self.do_ncd(config, 0);
+    }
+
+    /// NCDT: Normal color depth cue, triple vectors
+    fn cmd_ncdt(&mut self, config: CommandConfig) {
+        for v in 0..3 {
+            self.do_ncd(config, v);
+        }
+    }
+
+    /// CDP: Color depth cue
+    fn cmd_cdp(&mut self, config: CommandConfig) {
+        self.light_color(config);
+        self.color_depth_cue(config);
+    }
+
+    /// NCCS: Normal color color, single vector
+    fn cmd_nccs(&mut self, config: CommandConfig) {
+        self.do_ncc(config, 0);
+    }
+
+    /// NCCT: Normal color color, triple vectors
+    fn cmd_ncct(&mut self, config: CommandConfig) {
+        for v in 0..3 {
+            self.do_ncc(config, v);
+        }
+    }
+
+    /// CC: Color color
+    fn cmd_cc(&mut self, config: CommandConfig) {
+        self.light_color(config);
+        self.color_multiply(config);
+    }
+
+    /// NCS: Normal color, single
+    fn cmd_ncs(&mut self, config: CommandConfig) {
+        self.do_nc(config, 0);
+    }
+
+    /// NCT: Normal color, triple
+    fn cmd_nct(&mut self, config: CommandConfig) {
+        for v in 0..3 {
+            self.do_nc(config, v);
+        }
+    }
+
+    /// SQR: Square of the IR vector
+    fn cmd_sqr(&mut self, config: CommandConfig) {
+        for i in 1..4 {
+            let ir = self.ir[i] as i32;
+
+            self.mac[i] = (ir * ir) >> config.shift;
+        }
+
+        self.mac_to_ir(config);
+    }
+
+    /// DCPL: Depth cue color light
+    fn cmd_dcpl(&mut self, config: CommandConfig) {
+        self.color_depth_cue(config);
+    }
+
+    /// AVSZ3: Average of three Z values (for triangles)
+    fn cmd_avsz3(&mut self) {
+        let z1 = self.z_fifo[1] as u32;
+        let z2 = self.z_fifo[2] as u32;
+        let z3 = self.z_fifo[3] as u32;
+
+        let sum = z1 + z2 + z3;
+
+        let average = self.zsf3 as i64 * sum as i64;
+
+        self.set_otz(average);
+    }
+
+    /// AVSZ4: Average of four Z values (for quads)
+    fn cmd_avsz4(&mut self) {
+        let sum: u32 = self.z_fifo.iter().map(|&z| z as u32).sum();
+
+        let average = self.zsf4 as i64 * sum as i64;
+
+        self.set_otz(average);
+    }
+
+    /// GPF: General purpose interpolation
+    fn cmd_gpf(&mut self, config: CommandConfig) {
+        let ir0 = self.ir[0] as i64;
+
+        for i in 1..4 {
+            let ir = self.ir[i] as i64;
+
+            let res = self.i64_to_i44(i as u8 - 1, ir * ir0);
+
+            self.mac[i] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+        self.mac_to_rgb_fifo();
+    }
+
+    /// GPL: General purpose interpolation with base
+    fn cmd_gpl(&mut self, config: CommandConfig) {
+        let ir0 = self.ir[0] as i64;
+
+        for i in 1..4 {
+            let ir = self.ir[i] as i64;
+            let mac = (self.mac[i] as i64) << config.shift;
+
+            let res = self.i64_to_i44(i as u8 - 1, ir * ir0 + mac);
+
+            self.mac[i] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+        self.mac_to_rgb_fifo();
+    }
+
+    /// Rotate, translate And project vector `vector_index`. Returns
+    /// the projection factor used for depth queuing.
+    fn do_rtp(&mut self, config: CommandConfig, vector_index: usize) -> u32 {
+        let rm = Matrix::Rotation as usize;
+        let tr = ControlVector::Translation as usize;
+
+        let mut z_shifted = 0;
+
+        for r in 0..3 {
+            let mut res = (self.control_vectors[tr][r] as i64) << 12;
+
+            for c in 0..3 {
+                let v = self.v[vector_index][c] as i32;
+                let m = self.matrices[rm][r][c] as i32;
+
+                res = self.i64_to_i44(r as u8, res + (v * m) as i64);
+            }
+
+            self.mac[r + 1] = (res >> config.shift) as i32;
+
+            // The Z value is always shifted by 12 regardless of `sf`
+            z_shifted = (res >> 12) as i32;
+        }
+
+        let val = self.mac[1];
+        self.ir[1] = self.i32_to_i16_saturate(config, 0, val);
+        let val = self.mac[2];
+        self.ir[2] = self.i32_to_i16_saturate(config, 1, val);
+
+        // IR3 is saturated normally but the saturation flag is
+        // computed from the unshifted Z value And ignores `lm`
+        if z_shifted > i16::MAX as i32 || z_shifted < i16::MIN as i32 {
+            self.set_flag(22);
+        }
+
+        let min = if config.clamp_negative { 0 } else { i16::MIN as i32 };
+        let val = self.mac[3];
+
+        self.ir[3] = val.clamp(min, i16::MAX as i32) as i16;
+
+        let z_saturated = if z_shifted < 0 {
+            self.set_flag(18);
+            0
+        } else if z_shifted > 0xffff {
+            self.set_flag(18);
+            0xffff
+        } else {
+            z_shifted as u16
+        };
+
+        self.push_z(z_saturated);
+
+        // Perspective division. If the divisor is too small the
+        // result would overflow so the hardware saturates And sets
+        // the divide overflow flag.
+        let projection_factor = if (z_saturated as u32) * 2 > self.h as u32 {
+            divider::divide(self.h, z_saturated)
+        } else {
+            self.set_flag(17);
+            0x1ffff
+        };
+
+        let factor = projection_factor as i64;
+
+        let screen_x = self.ir[1] as i64 * factor + self.ofx as i64;
+        let screen_y = self.ir[2] as i64 * factor + self.ofy as i64;
+
+        self.check_mac0_overflow(screen_x);
+        self.check_mac0_overflow(screen_y);
+
+        let screen_x = (screen_x >> 16) as i32;
+        let screen_y = (screen_y >> 16) as i32;
+
+        let screen_x = self.i32_to_i11_saturate(0, screen_x);
+        let screen_y = self.i32_to_i11_saturate(1, screen_y);
+
+        self.push_xy((screen_x, screen_y));
+
+        projection_factor
+    }
+
+    /// Compute IR0 And MAC0 from the projection factor
+    fn depth_queuing(&mut self, projection_factor: u32) {
+        let factor = projection_factor as i64;
+
+        let depth = self.dqb as i64 + self.dqa as i64 * factor;
+
+        self.check_mac0_overflow(depth);
+
+        self.mac[0] = depth as i32;
+
+        // The result is 20.12 fixed point, IR0 is 4.12
+        let depth = depth >> 12;
+
+        self.ir[0] = if depth < 0 {
+            self.set_flag(12);
+            0
+        } else if depth > 0x1000 {
+            self.set_flag(12);
+            0x1000
+        } else {
+            depth as i16
+        };
+    }
+
+    /// Depth cue the color `rgb`
+    fn do_dpc(&mut self, config: CommandConfig, (r, g, b): (u8, u8, u8)) {
+        let mac = [(r as i64) << 16, (g as i64) << 16, (b as i64) << 16];
+
+        self.interpolate_color(config, mac);
+    }
+
+    /// Normal color: multiply vector `vector_index` by the light
+    /// matrix then the color matrix
+    fn do_nc(&mut self, config: CommandConfig, vector_index: usize) {
+        self.multiply_matrix_by_vector(config, Matrix::Light, vector_index, ControlVector::Zero);
+        self.light_color(config);
+        self.mac_to_rgb_fifo();
+    }
+
+    /// Normal color color
+    fn do_ncc(&mut self, config: CommandConfig, vector_index: usize) {
+        self.multiply_matrix_by_vector(config, Matrix::Light, vector_index, ControlVector::Zero);
+        self.light_color(config);
+        self.color_multiply(config);
+    }
+
+    /// Normal color depth cue
+    fn do_ncd(&mut self, config: CommandConfig, vector_index: usize) {
+        self.multiply_matrix_by_vector(config, Matrix::Light, vector_index, ControlVector::Zero);
+        self.light_color(config);
+        self.color_depth_cue(config);
+    }
+
+    /// Multiply the IR vector by the light color matrix And add the
+    /// background color
+    fn light_color(&mut self, config: CommandConfig) {
+        self.v[3] = [self.ir[1], self.ir[2], self.ir[3]];
+
+        self.multiply_matrix_by_vector(config, Matrix::Color, 3, ControlVector::BackgroundColor);
+    }
+
+    /// Multiply RGB by the IR vector And push the result in the color
+    /// FIFO
+    fn color_multiply(&mut self, config: CommandConfig) {
+        let mac = self.rgb_times_ir();
+
+        for (i, &m) in mac.iter().enumerate() {
+            let res = self.i64_to_i44(i as u8, m);
+
+            self.mac[i + 1] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+        self.mac_to_rgb_fifo();
+    }
+
+    /// Multiply RGB by the IR vector then interpolate with the far
+    /// color
+    fn color_depth_cue(&mut self, config: CommandConfig) {
+        let mac = self.rgb_times_ir();
+
+        self.interpolate_color(config, mac);
+    }
+
+    /// Returns `[R * IR1, G * IR2, B * IR3] << 4`
+    fn rgb_times_ir(&self) -> [i64; 3] {
+        let (r, g, b, _) = self.rgb;
+
+        [
+            ((r as i64) * (self.ir[1] as i64)) << 4,
+            ((g as i64) * (self.ir[2] as i64)) << 4,
+            ((b as i64) * (self.ir[3] as i64)) << 4,
+        ]
+    }
+
+    /// Interpolate between `mac` And the far color using IR0 as the
+    /// factor, then output the result to MAC, IR And the color FIFO
+    fn interpolate_color(&mut self, config: CommandConfig, mac: [i64; 3]) {
+        let fc = ControlVector::FarColor as usize;
+
+        // The intermediate saturation ignores `lm`
+        let no_clamp = CommandConfig { clamp_negative: false, ..config };
+
+        for (i, &m) in mac.iter().enumerate() {
+            let far_color = (self.control_vectors[fc][i] as i64) << 12;
+
+            let sub = self.i64_to_i44(i as u8, far_color - m);
+
+            let ir = self.i32_to_i16_saturate(no_clamp, i as u8, (sub >> config.shift) as i32) as i64;
+
+            let ir0 = self.ir[0] as i64;
+
+            let res = self.i64_to_i44(i as u8, ir * ir0 + m);
+
+            self.mac[i + 1] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+        self.mac_to_rgb_fifo();
+    }
+
+    /// Multiply vector `vector_index` by `matrix`, add
+    /// `control_vector` And store the result in MAC1-3 And IR1-3.
+    fn multiply_matrix_by_vector(&mut self,
+                                 config: CommandConfig,
+                                 matrix: Matrix,
+                                 vector_index: usize,
+                                 control_vector: ControlVector) {
+        let m = self.matrix(matrix);
+        let cv = self.control_vectors[control_vector as usize];
+
+        // When the far color is used as the control vector the
+        // hardware computes the first column with it, checks the
+        // flags And then throws the value away.
+        let far_color_bug = control_vector == ControlVector::FarColor;
+
+        for r in 0..3 {
+            let mut res = (cv[r] as i64) << 12;
+
+            for (c, &coef) in m[r].iter().enumerate() {
+                let v = self.v[vector_index][c] as i32;
+                let product = (v * coef as i32) as i64;
+
+                res = self.i64_to_i44(r as u8, res + product);
+
+                if far_color_bug && c == 0 {
+                    let no_clamp = CommandConfig { clamp_negative: false, ..config };
+
+                    self.i32_to_i16_saturate(no_clamp, r as u8, (res >> config.shift) as i32);
+
+                    res = 0;
+                }
+            }
+
+            self.mac[r + 1] = (res >> config.shift) as i32;
+        }
+
+        self.mac_to_ir(config);
+    }
+
+    /// Return a copy of `matrix`, building the garbage matrix if
+    /// `Matrix::Invalid` is selected
+    fn matrix(&self, matrix: Matrix) -> [[i16; 3]; 3] {
+        match matrix {
+            Matrix::Invalid => {
+                let rm = &self.matrices[Matrix::Rotation as usize];
+                let r = ((self.rgb.0 as u16) << 4) as i16;
+
+                [
+                    [-r, r, self.ir[0]],
+                    [rm[0][2], rm[0][2], rm[0][2]],
+                    [rm[1][1], rm[1][1], rm[1][1]],
+                ]
+            }
+            m => self.matrices[m as usize],
+        }
+    }
+
+    /// Saturate MAC1-3 into IR1-3
+    fn mac_to_ir(&mut self, config: CommandConfig) {
+        for i in 1..4 {
+            let val = self.mac[i];
+
+            self.ir[i] = self.i32_to_i16_saturate(config, i as u8 - 1, val);
+        }
+    }
+
+    /// Convert MAC1-3 into a color And push it in the color FIFO
+    fn mac_to_rgb_fifo(&mut self) {
+        let r = self.mac_to_color(0, self.mac[1]);
+        let g = self.mac_to_color(1, self.mac[2]);
+        let b = self.mac_to_color(2, self.mac[3]);
+
+        self.rgb_fifo[0] = self.rgb_fifo[1];
+        self.rgb_fifo[1] = self.rgb_fifo[2];
+        self.rgb_fifo[2] = (r, g, b, self.rgb.3);
+    }
+
+    fn mac_to_color(&mut self, which: u8, mac: i32) -> u8 {
+        let c = mac >> 4;
+
+        if c < 0 {
+            self.set_flag(21 - which);
+            0
+        } else if c > 0xff {
+            self.set_flag(21 - which);
+            0xff
+        } else {
+            c as u8
+        }
+    }
+
+    /// Saturate MAC0 into OTZ
+    fn set_otz(&mut self, average: i64) {
+        self.check_mac0_overflow(average);
+
+        self.mac[0] = average as i32;
+
+        let average = average >> 12;
+
+        self.otz = if average < 0 {
+            self.set_flag(18);
+            0
+        } else if average > 0xffff {
+            self.set_flag(18);
+            0xffff
+        } else {
+            average as u16
+        };
+    }
+
+    fn push_xy(&mut self, xy: (i16, i16)) {
+        self.xy_fifo[0] = self.xy_fifo[1];
+        self.xy_fifo[1] = self.xy_fifo[2];
+        self.xy_fifo[2] = xy;
+    }
+
+    fn push_z(&mut self, z: u16) {
+        self.z_fifo[0] = self.z_fifo[1];
+        self.z_fifo[1] = self.z_fifo[2];
+        self.z_fifo[2] = self.z_fifo[3];
+        self.z_fifo[3] = z;
+    }
+
+    /// IR1-3 packed as a 15bit color (IRGB/ORGB)
+    fn packed_ir(&self) -> u32 {
+        let saturate = |v: i16| -> u32 { (v >> 7).clamp(0, 0x1f) as u32 };
+
+        saturate(self.ir[1]) | (saturate(self.ir[2]) << 5) | (saturate(self.ir[3]) << 10)
+    }
+
+    fn set_flag(&mut self, bit: u8) {
+        self.flags |= 1 << bit;
+    }
+
+    /// Bit 31 is set if any of the "error" flags is set
+    fn update_error_flag(&mut self) {
+        if self.flags & 0x7f87e000 != 0 {
+            self.flags |= 1 << 31;
+        } else {
+            self.flags &= !(1 << 31);
+        }
+    }
+
+    /// Truncate `val` to 44 bits, setting the MAC1-3 overflow flags
+    /// if it doesn't fit
+    fn i64_to_i44(&mut self, flag: u8, val: i64) -> i64 {
+        if val > 0x7ff_ffff_ffff {
+            self.set_flag(30 - flag);
+        } else if val < -0x800_0000_0000 {
+            self.set_flag(27 - flag);
+        }
+
+        (val << 20) >> 20
+    }
+
+    /// Saturate `val` into IR1-3 range, setting the saturation flag
+    /// if needed
+    fn i32_to_i16_saturate(&mut self, config: CommandConfig, flag: u8, val: i32) -> i16 {
+        let min = if config.clamp_negative { 0 } else { i16::MIN as i32 };
+        let max = i16::MAX as i32;
+
+        if val > max {
+            self.set_flag(24 - flag);
+            max as i16
+        } else if val < min {
+            self.set_flag(24 - flag);
+            min as i16
+        } else {
+            val as i16
+        }
+    }
+
+    /// Saturate a screen coordinate to 11 bits
+    fn i32_to_i11_saturate(&mut self, flag: u8, val: i32) -> i16 {
+        if val < -0x400 {
+            self.set_flag(14 - flag);
+            -0x400
+        } else if val > 0x3ff {
+            self.set_flag(14 - flag);
+            0x3ff
+        } else {
+            val as i16
+        }
+    }
+
+    fn check_mac0_overflow(&mut self, val: i64) {
+        if val < -0x8000_0000 {
+            self.set_flag(15);
+        } else if val > 0x7fff_ffff {
+            self.set_flag(16);
+        }
+    }
+}
+
+/// Build a GTE with the rotation, light And color matrices set to
+/// identity
+#[cfg(test)]
+fn identity_gte() -> Gte {
+    let mut gte = Gte::new();
+
+    for &base in &[0, 8, 16] {
+        gte.set_control(base, 0x1000);
+        gte.set_control(base + 1, 0);
+        gte.set_control(base + 2, 0x1000);
+        gte.set_control(base + 3, 0);
+        gte.set_control(base + 4, 0x1000);
+    }
+
+    gte
+}
+
+#[test]
+fn gte_rtps() {
+    let mut gte = identity_gte();
+
+    // TR
+    gte.set_control(5, 0);
+    gte.set_control(6, 0);
+    gte.set_control(7, 0x100);
+    // OFX, OFY: center of a 320x240 screen
+    gte.set_control(24, 160 << 16);
+    gte.set_control(25, 120 << 16);
+    // H
+    gte.set_control(26, 0x100);
+    // DQA, DQB
+    gte.set_control(27, 0xff00);
+    gte.set_control(28, 0x1400000);
+
+    gte.set_data(0, 0x0020_0010);
+    gte.set_data(1, 0x100);
+
+    gte.command(0x0180001);
+
+    assert_eq!(gte.data(25), 0x10);
+    assert_eq!(gte.data(26), 0x20);
+    assert_eq!(gte.data(27), 0x200);
+    assert_eq!(gte.data(9), 0x10);
+    assert_eq!(gte.data(10), 0x20);
+    assert_eq!(gte.data(11), 0x200);
+    assert_eq!(gte.data(19), 0x200);
+    assert_eq!(gte.data(14), (136 << 16) | 168);
+    assert_eq!(gte.data(15), (136 << 16) | 168);
+    assert_eq!(gte.data(24), 0xc00000);
+    assert_eq!(gte.data(8), 0xc00);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_rtps_saturation() {
+    let mut gte = identity_gte();
+
+    gte.set_control(26, 0x100);
+
+    // Vector on the camera plane: the division overflows And the
+    // projected X coordinate saturates
+    gte.set_data(0, 0x1000);
+    gte.set_data(1, 0);
+
+    gte.command(0x0180001);
+
+    assert_eq!(gte.data(19), 0);
+    assert_eq!(gte.data(14), 0x3ff);
+    assert_eq!(gte.data(8), 0);
+    assert_eq!(gte.control(31), 0x80024000);
+}
+
+#[test]
+fn gte_rtpt() {
+    let mut gte = identity_gte();
+
+    gte.set_control(26, 0x100);
+
+    gte.set_data(0, 0x0020_0010);
+    gte.set_data(1, 0x200);
+    gte.set_data(2, 0x0040_0040);
+    gte.set_data(3, 0x100);
+    gte.set_data(4, 0x0080_ffe0);
+    gte.set_data(5, 0x400);
+
+    gte.command(0x0280030);
+
+    assert_eq!(gte.data(12), 0x0010_0008);
+    assert_eq!(gte.data(13), 0x0040_0040);
+    assert_eq!(gte.data(14), 0x0020_fff8);
+    assert_eq!(gte.data(17), 0x200);
+    assert_eq!(gte.data(18), 0x100);
+    assert_eq!(gte.data(19), 0x400);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_nclip() {
+    let mut gte = Gte::new();
+
+    gte.set_data(12, 0);
+    gte.set_data(13, 10);
+    gte.set_data(14, 10 << 16);
+
+    gte.command(0x1400006);
+
+    assert_eq!(gte.data(24), 100);
+
+    // Reverse winding
+    gte.set_data(13, 10 << 16);
+    gte.set_data(14, 10);
+
+    gte.command(0x1400006);
+
+    assert_eq!(gte.data(24) as i32, -100);
+}
+
+#[test]
+fn gte_op() {
+    let mut gte = Gte::new();
+
+    gte.set_control(0, 0x1000);
+    gte.set_control(2, 0x2000);
+    gte.set_control(4, 0x3000);
+
+    gte.set_data(9, 0x10);
+    gte.set_data(10, 0x20);
+    gte.set_data(11, 0x40);
+
+    gte.command(0x178000c);
+
+    assert_eq!(gte.data(25), 0x20);
+    assert_eq!(gte.data(26) as i32, -0x10);
+    assert_eq!(gte.data(27), 0);
+    assert_eq!(gte.control(31), 0);
+
+    // Without the shift IR1 And IR2 saturate
+    gte.set_data(9, 0x10);
+    gte.set_data(10, 0x20);
+    gte.set_data(11, 0x40);
+
+    gte.command(0x170000c);
+
+    assert_eq!(gte.data(25), 0x20000);
+    assert_eq!(gte.data(9), 0x7fff);
+    assert_eq!(gte.data(10) as i32, -0x8000);
+    assert_eq!(gte.control(31), 0x81800000);
+}
+
+/// Far color And IR0 configuration shared by the depth cueing tests
+#[cfg(test)]
+fn depth_cue_gte(far_color: [u32; 3], ir0: u32) -> Gte {
+    let mut gte = identity_gte();
+
+    gte.set_control(21, far_color[0]);
+    gte.set_control(22, far_color[1]);
+    gte.set_control(23, far_color[2]);
+
+    gte.set_data(8, ir0);
+
+    gte
+}
+
+#[test]
+fn gte_dpcs() {
+    let mut gte = depth_cue_gte([0xff0, 0, 0x800], 0x800);
+
+    gte.set_data(6, 0x5520_4080);
+
+    gte.command(0x0780010);
+
+    assert_eq!(gte.data(25), 0xbf8);
+    assert_eq!(gte.data(26), 0x200);
+    assert_eq!(gte.data(27), 0x500);
+    assert_eq!(gte.data(22), 0x5550_20bf);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_dpct() {
+    let mut gte = depth_cue_gte([0xff0, 0, 0x800], 0x800);
+
+    gte.set_data(6, 0x5500_0000);
+    gte.set_data(20, 0x0020_4080);
+    gte.set_data(21, 0x0020_4080);
+    gte.set_data(22, 0x0020_4080);
+
+    gte.command(0x0f8002a);
+
+    assert_eq!(gte.data(20), 0x5550_20bf);
+    assert_eq!(gte.data(21), 0x5550_20bf);
+    assert_eq!(gte.data(22), 0x5550_20bf);
+}
+
+#[test]
+fn gte_intpl() {
+    let mut gte = depth_cue_gte([0xff0, 0, 0x800], 0x800);
+
+    gte.set_data(6, 0x3000_0000);
+    gte.set_data(9, 0x800);
+    gte.set_data(10, 0x400);
+    gte.set_data(11, 0x200);
+
+    gte.command(0x0980011);
+
+    assert_eq!(gte.data(25), 0xbf8);
+    assert_eq!(gte.data(26), 0x200);
+    assert_eq!(gte.data(27), 0x500);
+    assert_eq!(gte.data(22), 0x3050_20bf);
+}
+
+#[test]
+fn gte_mvmva() {
+    let mut gte = Gte::new();
+
+    gte.set_control(0, 0x1000);
+    gte.set_control(2, 0x800);
+    gte.set_control(4, 0x2000);
+
+    gte.set_control(5, 1);
+    gte.set_control(6, 2);
+    gte.set_control(7, 3);
+
+    gte.set_data(0, 0x0200_0100);
+    gte.set_data(1, 0x300);
+
+    // sf=1, mx=RT, v=V0, cv=TR
+    gte.command(0x0480012);
+
+    assert_eq!(gte.data(25), 0x101);
+    assert_eq!(gte.data(26), 0x102);
+    assert_eq!(gte.data(27), 0x603);
+    assert_eq!(gte.data(11), 0x603);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_mvmva_far_color_bug() {
+    let mut gte = identity_gte();
+
+    gte.set_control(21, 0x10000);
+
+    gte.set_data(9, 0x100);
+    gte.set_data(10, 0x200);
+    gte.set_data(11, 0x300);
+
+    // sf=1, mx=LLM, v=IR, cv=FC
+    gte.command(0x00bc012);
+
+    // The first column (And the far color) is dropped from the
+    // result but still sets the IR1 saturation flag
+    assert_eq!(gte.data(25), 0);
+    assert_eq!(gte.data(26), 0x200);
+    assert_eq!(gte.data(27), 0x300);
+    assert_eq!(gte.control(31), 0x81000000);
+}
+
+/// Lighting setup shared by the normal color tests: identity light
+/// And color matrices, normal (0x800, 0x400, 0x200) in all three
+/// vectors And color (0x80, 0x40, 0x20)
+#[cfg(test)]
+fn lighting_gte() -> Gte {
+    let mut gte = depth_cue_gte([0x100, 0x200, 0x300], 0x400);
+
+    for v in 0..3 {
+        gte.set_data(v * 2, 0x0400_0800);
+        gte.set_data(v * 2 + 1, 0x200);
+    }
+
+    gte.set_data(6, 0x2a20_4080);
+
+    gte
+}
+
+#[test]
+fn gte_ncds() {
+    let mut gte = lighting_gte();
+
+    gte.command(0x0e80413);
+
+    assert_eq!(gte.data(25), 0x340);
+    assert_eq!(gte.data(26), 0x140);
+    assert_eq!(gte.data(27), 0xf0);
+    assert_eq!(gte.data(22), 0x2a0f_1434);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_ncdt() {
+    let mut gte = lighting_gte();
+
+    gte.command(0x0f80416);
+
+    assert_eq!(gte.data(20), 0x2a0f_1434);
+    assert_eq!(gte.data(21), 0x2a0f_1434);
+    assert_eq!(gte.data(22), 0x2a0f_1434);
+}
+
+#[test]
+fn gte_cdp() {
+    let mut gte = lighting_gte();
+
+    gte.set_data(9, 0x800);
+    gte.set_data(10, 0x400);
+    gte.set_data(11, 0x200);
+
+    gte.command(0x1280414);
+
+    assert_eq!(gte.data(22), 0x2a0f_1434);
+}
+
+#[test]
+fn gte_dcpl() {
+    let mut gte = lighting_gte();
+
+    gte.set_data(9, 0x800);
+    gte.set_data(10, 0x400);
+    gte.set_data(11, 0x200);
+
+    gte.command(0x0680029);
+
+    assert_eq!(gte.data(25), 0x340);
+    assert_eq!(gte.data(26), 0x140);
+    assert_eq!(gte.data(27), 0xf0);
+    assert_eq!(gte.data(22), 0x2a0f_1434);
+}
+
+#[test]
+fn gte_nccs() {
+    let mut gte = lighting_gte();
+
+    gte.command(0x108041b);
+
+    assert_eq!(gte.data(25), 0x400);
+    assert_eq!(gte.data(26), 0x100);
+    assert_eq!(gte.data(27), 0x40);
+    assert_eq!(gte.data(22), 0x2a04_1040);
+}
+
+#[test]
+fn gte_ncct() {
+    let mut gte = lighting_gte();
+
+    gte.command(0x118043f);
+
+    assert_eq!(gte.data(20), 0x2a04_1040);
+    assert_eq!(gte.data(21), 0x2a04_1040);
+    assert_eq!(gte.data(22), 0x2a04_1040);
+}
+
+#[test]
+fn gte_cc() {
+    let mut gte = lighting_gte();
+
+    gte.set_data(9, 0x800);
+    gte.set_data(10, 0x400);
+    gte.set_data(11, 0x200);
+
+    gte.command(0x138041c);
+
+    assert_eq!(gte.data(22), 0x2a04_1040);
+}
+
+#[test]
+fn gte_ncs() {
+    let mut gte = lighting_gte();
+
+    // Background color
+    gte.set_control(13, 0x10);
+    gte.set_control(14, 0x20);
+    gte.set_control(15, 0x30);
+
+    gte.command(0x0c8041e);
+
+    assert_eq!(gte.data(25), 0x810);
+    assert_eq!(gte.data(26), 0x420);
+    assert_eq!(gte.data(27), 0x230);
+    assert_eq!(gte.data(22), 0x2a23_4281);
+}
+
+#[test]
+fn gte_nct() {
+    let mut gte = lighting_gte();
+
+    gte.set_control(13, 0x10);
+    gte.set_control(14, 0x20);
+    gte.set_control(15, 0x30);
+
+    gte.command(0x0d80420);
+
+    assert_eq!(gte.data(20), 0x2a23_4281);
+    assert_eq!(gte.data(21), 0x2a23_4281);
+    assert_eq!(gte.data(22), 0x2a23_4281);
+}
+
+#[test]
+fn gte_sqr() {
+    let mut gte = Gte::new();
+
+    gte.set_data(9, 0x10);
+    gte.set_data(10, 0xffe0);
+    gte.set_data(11, 0x300);
+
+    gte.command(0x0a00428);
+
+    assert_eq!(gte.data(25), 0x100);
+    assert_eq!(gte.data(26), 0x400);
+    assert_eq!(gte.data(27), 0x90000);
+    assert_eq!(gte.data(11), 0x7fff);
+    assert_eq!(gte.control(31), 0x00400000);
+}
+
+#[test]
+fn gte_avsz3() {
+    let mut gte = Gte::new();
+
+    gte.set_control(29, 0x555);
+
+    gte.set_data(17, 0x100);
+    gte.set_data(18, 0x200);
+    gte.set_data(19, 0x300);
+
+    gte.command(0x158002d);
+
+    assert_eq!(gte.data(24), 0x1ffe00);
+    assert_eq!(gte.data(7), 0x1ff);
+    assert_eq!(gte.control(31), 0);
+
+    // Negative averages saturate OTZ to 0
+    gte.set_control(29, 0xf000);
+
+    gte.command(0x158002d);
+
+    assert_eq!(gte.data(7), 0);
+    assert_eq!(gte.control(31), 0x80040000);
+}
+
+#[test]
+fn gte_avsz4() {
+    let mut gte = Gte::new();
+
+    gte.set_control(30, 0x400);
+
+    gte.set_data(16, 0x100);
+    gte.set_data(17, 0x200);
+    gte.set_data(18, 0x300);
+    gte.set_data(19, 0x400);
+
+    gte.command(0x168002e);
+
+    assert_eq!(gte.data(24), 0x280000);
+    assert_eq!(gte.data(7), 0x280);
+    assert_eq!(gte.control(31), 0);
+}
+
+#[test]
+fn gte_gpf() {
+    let mut gte = Gte::new();
+
+    gte.set_data(6, 0x1100_0000);
+    gte.set_data(8, 0x800);
+    gte.set_data(9, 0x100);
+    gte.set_data(10, 0x200);
+    gte.set_data(11, 0x300);
+
+    gte.command(0x198003d);
+
+    assert_eq!(gte.data(25), 0x80);
+    assert_eq!(gte.data(26), 0x100);
+    assert_eq!(gte.data(27), 0x180);
+    assert_eq!(gte.data(22), 0x1118_1008);
+}
+
+#[test]
+fn gte_gpl() {
+    let mut gte = Gte::new();
+
+    gte.set_data(6, 0x1100_0000);
+    gte.set_data(8, 0x800);
+    gte.set_data(9, 0x100);
+    gte.set_data(10, 0x200);
+    gte.set_data(11, 0x300);
+    gte.set_data(25, 0x10);
+    gte.set_data(26, 0x20);
+    gte.set_data(27, 0x30);
+
+    gte.command(0x1a8003e);
+
+    assert_eq!(gte.data(25), 0x90);
+    assert_eq!(gte.data(26), 0x120);
+    assert_eq!(gte.data(27), 0x1b0);
+    assert_eq!(gte.data(22), 0x111b_1209);
+}
+
+#[test]
+fn gte_registers() {
+    let mut gte = Gte::new();
+
+    // LZCS/LZCR
+    gte.set_data(30, 0);
+    assert_eq!(gte.data(31), 32);
+    gte.set_data(30, 0xffff_ffff);
+    assert_eq!(gte.data(31), 32);
+    gte.set_data(30, 0x00f0_0000);
+    assert_eq!(gte.data(31), 8);
+    gte.set_data(30, 0xfe00_0000);
+    assert_eq!(gte.data(31), 7);
+
+    // IRGB expands to IR1-3, ORGB packs them back
+    gte.set_data(28, 0x7c1f);
+    assert_eq!(gte.data(9), 0xf80);
+    assert_eq!(gte.data(10), 0);
+    assert_eq!(gte.data(11), 0xf80);
+    assert_eq!(gte.data(29), 0x7c1f);
+
+    // Negative IR values saturate to 0 in ORGB
+    gte.set_data(10, 0xffff_8000);
+    assert_eq!(gte.data(10), 0xffff_8000);
+    assert_eq!(gte.data(29), 0x7c1f);
+
+    // Writing SXYP pushes into the FIFO
+    gte.set_data(12, 1);
+    gte.set_data(13, 2);
+    gte.set_data(14, 3);
+    gte.set_data(15, 4);
+    assert_eq!(gte.data(12), 2);
+    assert_eq!(gte.data(13), 3);
+    assert_eq!(gte.data(14), 4);
+    assert_eq!(gte.data(15), 4);
+
+    // VZ And IR are sign extended, SZ And OTZ are not
+    gte.set_data(1, 0xffff);
+    assert_eq!(gte.data(1), 0xffff_ffff);
+    gte.set_data(16, 0xffff);
+    assert_eq!(gte.data(16), 0xffff);
+    gte.set_data(7, 0xffff);
+    assert_eq!(gte.data(7), 0xffff);
+
+    // RT33 And H are sign extended when read back
+    gte.set_control(4, 0x8000);
+    assert_eq!(gte.control(4), 0xffff_8000);
+    gte.set_control(26, 0x8000);
+    assert_eq!(gte.control(26), 0xffff_8000);
+
+    // Matrix registers pack two entries
+    gte.set_control(1, 0x1234_5678);
+    assert_eq!(gte.control(1), 0x1234_5678);
+
+    // Only bits [30:12] of FLAG are writable, bit 31 is computed
+    gte.set_control(31, 0xffff_ffff);
+    assert_eq!(gte.control(31), 0xffff_f000);
+    gte.set_control(31, 0x0000_1000);
+    assert_eq!(gte.control(31), 0x0000_1000);
+}
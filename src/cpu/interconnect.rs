@@ -1,12 +1,33 @@
+use crate::audio::AudioSink;
 use crate::bios::Bios;
+use crate::cdrom::CdRom;
+use crate::cdrom::disc::Disc;
+use crate::cpu::cache::{CacheControl, ICache};
 use crate::gpu::Gpu;
+use crate::interrupt::InterruptController;
+use crate::mdec::MDec;
 use crate::memory::{Addressable, Word};
 use crate::memory::dma::direction::Direction;
 use crate::memory::dma::Dma;
 use crate::memory::dma::port::Port;
 use crate::memory::dma::step::Step;
 use crate::memory::dma::sync::Sync;
-use crate::memory::ram::Ram;
+use crate::memory::memcontrol::{BusRegion, MemControl};
+use crate::memory::ram::{Ram, ScratchPad};
+use crate::pad_memcard::PadMemCard;
+use crate::scheduler::{Cycles, Scheduler};
+use crate::scheduler::device::Device;
+use crate::serial::Serial;
+use crate::serial::link::SerialLink;
+use crate::spu::Spu;
+use crate::timers::Timers;
+
+/// Duration of a load from main RAM in CPU cycles
+const RAM_LOAD_CYCLES: u32 = 5;
+
+/// Duration of a load from one of the internal I/O ports (DMA,
+/// timers, GPU...) in CPU cycles
+const IO_LOAD_CYCLES: u32 = 3;
 
 /// Global interconnect
 pub struct Interconnect {
@@ -14,32 +35,375 @@ pub struct Interconnect {
     bios: Bios,
     ram: Ram,
     dma: Dma,
-    gpu: Gpu
+    gpu: Gpu,
+    /// Interrupt controller, shared by all the peripherals
+    irq: InterruptController,
+    /// Root counters
+    timers: Timers,
+    /// Event scheduler driving the peripherals
+    scheduler: Scheduler,
+    /// Expansion base addresses And bus timings
+    mem_control: MemControl,
+    /// Data cache used as a fast 1kB RAM
+    scratch_pad: ScratchPad,
+    /// CD-ROM controller
+    cdrom: CdRom,
+    /// Sound Processing Unit
+    spu: Spu,
+    /// Motion decoder
+    mdec: MDec,
+    /// Controller And memory card interface
+    pad_memcard: PadMemCard,
+    /// Serial port
+    serial: Serial,
+    /// Set when an access hits a bus error
+    bus_error: bool,
+    /// Cache control register
+    cache_control: CacheControl,
+    /// Instruction cache
+    icache: ICache,
+    /// Mirror of the "Isolate Cache" bit in the CPU's SR: when set
+    /// the loads And stores target the cache instead of the memory
+    cache_isolated: bool,
 }
 
 impl Interconnect {
-    pub fn new(bios: Bios, ram: Ram, gpu: Gpu) -> Interconnect {
-        Interconnect {
+    pub fn new(bios: Bios, ram: Ram, gpu: Gpu, disc: Option<Disc>) -> Interconnect {
+        let mut interconnect = Interconnect {
             bios,
             ram,
             gpu,
             dma: Dma::new(),
+            irq: InterruptController::new(),
+            timers: Timers::new(),
+            scheduler: Scheduler::new(),
+            mem_control: MemControl::new(),
+            scratch_pad: ScratchPad::new(),
+            cdrom: CdRom::new(disc),
+            spu: Spu::new(),
+            mdec: MDec::new(),
+            pad_memcard: PadMemCard::new(),
+            serial: Serial::new(),
+            bus_error: false,
+            cache_control: CacheControl(0),
+            icache: ICache::new(),
+            cache_isolated: false,
+        };
+
+        interconnect.schedule_video();
+        interconnect.schedule_spu();
+        interconnect.schedule_serial();
+
+        interconnect
+    }
+
+    /// Return true if the CPU's external interrupt line is active
+    pub fn irq_pending(&self) -> bool {
+        self.irq.active()
+    }
+
+    /// Current date in CPU cycles
+    pub fn cycles(&self) -> Cycles {
+        self.scheduler.now()
+    }
+
+    /// Number of frames output by the GPU since reset
+    pub fn frame_count(&self) -> u32 {
+        self.gpu.frame_count()
+    }
+
+    /// Advance the emulated time by `cycles` CPU clock cycles And run
+    /// the device events which are due
+    pub fn tick(&mut self, cycles: u32) {
+        self.scheduler.tick(cycles);
+
+        while let Some(device) = self.scheduler.pop_due() {
+            match device {
+                Device::Gpu | Device::Timers => self.sync_video(),
+                Device::CdRom => self.sync_cdrom(),
+                Device::Spu => self.sync_spu(),
+                Device::PadMemCard => self.sync_pad_memcard(),
+                Device::Serial => self.sync_serial(),
+            }
+        }
+    }
+
+    /// Bring the GPU And the root counters up to date. They're always
+    /// synchronized together since the counters are clocked by the
+    /// video signals.
+    fn sync_video(&mut self) {
+        let elapsed = self.scheduler.elapsed(Device::Gpu) as u32;
+
+        // The light guns pulse the light pen input when the beam
+        // reaches the aimed point
+        self.gpu.set_light_pen(self.pad_memcard.light_pen());
+
+        if elapsed > 0 {
+            let timings = self.gpu.tick(elapsed, &mut self.irq);
+
+            self.timers.tick(elapsed, &timings, &mut self.irq);
+        }
+
+        self.schedule_video();
+    }
+
+    /// Register the next GPU And root counter events
+    fn schedule_video(&mut self) {
+        let gpu_delay = self.gpu.cycles_to_next_event();
+
+        self.scheduler.schedule(Device::Gpu, gpu_delay as Cycles);
+
+        match self.timers.cycles_to_next_event(self.gpu.dotclock_divider()) {
+            Some(delay) => self.scheduler.schedule(Device::Timers, delay as Cycles),
+            None => self.scheduler.cancel(Device::Timers),
+        }
+    }
+
+    /// Bring the CD-ROM controller up to date
+    fn sync_cdrom(&mut self) {
+        let elapsed = self.scheduler.elapsed(Device::CdRom) as u32;
+
+        self.cdrom.tick(elapsed, &mut self.irq);
+
+        self.schedule_cdrom();
+    }
+
+    /// Register the next CD-ROM controller event
+    fn schedule_cdrom(&mut self) {
+        match self.cdrom.cycles_to_next_event() {
+            Some(delay) => self.scheduler.schedule(Device::CdRom, delay as Cycles),
+            None => self.scheduler.cancel(Device::CdRom),
+        }
+    }
+
+    /// Bring the SPU up to date. It pulls the CD audio samples from
+    /// the CD-ROM controller.
+    fn sync_spu(&mut self) {
+        let elapsed = self.scheduler.elapsed(Device::Spu) as u32;
+
+        self.spu.tick(elapsed, &mut self.irq, &mut self.cdrom);
+
+        self.schedule_spu();
+    }
+
+    /// Register the next SPU output sample
+    fn schedule_spu(&mut self) {
+        let delay = self.spu.cycles_to_next_event();
+
+        self.scheduler.schedule(Device::Spu, delay as Cycles);
+    }
+
+    /// Bring the controller And memory card interface up to date
+    fn sync_pad_memcard(&mut self) {
+        let elapsed = self.scheduler.elapsed(Device::PadMemCard) as u32;
+
+        // The light guns need the current position of the picture
+        self.pad_memcard.set_display_area(self.gpu.display_area());
+        self.pad_memcard.tick(elapsed, &mut self.irq);
+
+        self.schedule_pad_memcard();
+    }
+
+    /// Register the next serial transfer event
+    fn schedule_pad_memcard(&mut self) {
+        match self.pad_memcard.cycles_to_next_event() {
+            Some(delay) => self.scheduler.schedule(Device::PadMemCard, delay as Cycles),
+            None => self.scheduler.cancel(Device::PadMemCard),
         }
     }
+
+    /// Bring the serial port up to date
+    fn sync_serial(&mut self) {
+        let elapsed = self.scheduler.elapsed(Device::Serial) as u32;
+
+        self.serial.tick(elapsed, &mut self.irq);
+
+        self.schedule_serial();
+    }
+
+    /// Register the next serial port event. The link is polled
+    /// periodically so there's always one.
+    fn schedule_serial(&mut self) {
+        let delay = self.serial.cycles_to_next_event();
+
+        self.scheduler.schedule(Device::Serial, delay as Cycles);
+    }
+
+    /// Controller And memory card interface, used to plug the
+    /// peripherals
+    pub fn pad_memcard_mut(&mut self) -> &mut PadMemCard {
+        &mut self.pad_memcard
+    }
+
+    /// Replace the sink receiving the SPU output
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.spu.set_audio_sink(sink);
+    }
+
+    /// Plug the other end of the serial cable
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.serial.set_link(link);
+    }
+
+    /// Signal a bus error for an access to `addr`. The CPU raises the
+    /// exception once the instruction is done.
+    fn bus_error(&mut self, addr: u32) {
+        warn!("Bus error at address 0x{:08x}", addr);
+
+        self.bus_error = true;
+    }
+
+    /// Return true if one of the accesses since the last call caused
+    /// a bus error
+    pub fn take_bus_error(&mut self) -> bool {
+        let bus_error = self.bus_error;
+
+        self.bus_error = false;
+
+        bus_error
+    }
+
+    /// Stall the CPU until `date`
+    pub fn stall_until(&mut self, date: Cycles) {
+        let now = self.scheduler.now();
+
+        if date > now {
+            self.tick((date - now) as u32);
+        }
+    }
+
+    /// Duration in CPU cycles of an access to `abs_addr`. The
+    /// cycles are charged as the access takes place, the due device
+    /// events are only run at the end of the instruction.
+    fn access_cycles<A: Addressable>(&self, abs_addr: u32, write: bool) -> u32 {
+        let region = if map::RAM.contains(abs_addr).is_some() {
+            // Writes go through the write queue And don't stall the
+            // CPU
+            return if write { 0 } else { RAM_LOAD_CYCLES };
+        } else if map::SCRATCH_PAD.contains(abs_addr).is_some() {
+            // Same speed as the data cache
+            return 0;
+        } else if map::BIOS.contains(abs_addr).is_some() {
+            BusRegion::Bios
+        } else if map::EXPANSION_1.contains(abs_addr).is_some() {
+            BusRegion::Expansion1
+        } else if map::EXPANSION_2.contains(abs_addr).is_some() {
+            BusRegion::Expansion2
+        } else if map::SPU.contains(abs_addr).is_some() {
+            BusRegion::Spu
+        } else if map::CDROM.contains(abs_addr).is_some() {
+            BusRegion::CdRom
+        } else {
+            // Internal I/O ports
+            return if write { 0 } else { IO_LOAD_CYCLES };
+        };
+
+        self.mem_control.access_time(region, A::size(), write)
+    }
+
+    /// Called by the CPU when the "Isolate Cache" bit of SR changes
+    pub fn set_cache_isolated(&mut self, isolated: bool) {
+        self.cache_isolated = isolated;
+    }
+
+    /// Fetch the instruction at `pc`, going through the instruction
+    /// cache if it's enabled And `pc` is in a cached region
+    pub fn load_instruction(&mut self, pc: u32) -> u32 {
+        // KSEG1 is never cached
+        let cached = pc < 0xa0000000 && self.cache_control.icache_enabled();
+
+        if !cached {
+            return self.load_memory::<Word>(pc);
+        }
+
+        let abs_addr = map::mask_region(pc);
+
+        if let Some(instruction) = self.icache.line(abs_addr).lookup(abs_addr) {
+            // Cache hit, no memory access needed
+            return instruction;
+        }
+
+        // Cache miss: the line is refilled from `pc` up to the end
+        // of the line
+        self.icache.line_mut(abs_addr).retag(abs_addr);
+
+        let mut addr = pc;
+
+        loop {
+            let word = self.load_memory::<Word>(addr);
+
+            self.icache.line_mut(abs_addr).fill(map::mask_region(addr), word);
+
+            addr = addr.wrapping_add(4);
+
+            if addr & 0xf == 0 {
+                break;
+            }
+        }
+
+        self.icache.line(abs_addr).word(abs_addr)
+    }
+
     /// Interconnect: load value at `addr`
     pub fn load<A: Addressable>(&mut self, addr: u32) -> u32 {
+        if self.cache_isolated && map::is_cached(addr) {
+            // The data comes from the cache, the tag is ignored
+            return self.icache.line(addr).word(addr);
+        }
+
+        self.load_memory::<A>(addr)
+    }
+
+    /// Interconnect: store `val` into `addr`
+    pub fn store<A: Addressable>(&mut self, addr: u32, val: u32) {
+        if self.cache_isolated && map::is_cached(addr) {
+            return self.cache_maintenance(addr, val);
+        }
+
+        self.store_memory::<A>(addr, val)
+    }
+
+    /// Store while the cache is isolated. The BIOS uses those to
+    /// flush the instruction cache.
+    fn cache_maintenance(&mut self, addr: u32, val: u32) {
+        if !self.cache_control.icache_enabled() {
+            warn!("Cache maintenance while the instruction cache is disabled");
+            return;
+        }
+
+        let tag_test_mode = self.cache_control.tag_test_mode();
+
+        let line = self.icache.line_mut(addr);
+
+        if tag_test_mode {
+            // In tag test mode the write invalidates the entire line
+            line.invalidate();
+        } else {
+            // Otherwise the write ends up directly in the cache
+            line.set_word(addr, val);
+        }
+    }
+
+    /// Load value at `addr` from the memory bus
+    fn load_memory<A: Addressable>(&mut self, addr: u32) -> u32 {
         let abs_addr = map::mask_region(addr);
 
+        let cycles = self.access_cycles::<A>(abs_addr, false);
+        self.scheduler.tick(cycles);
+
         if let Some(offset) = map::RAM.contains(abs_addr) {
             return self.ram.load::<A>(offset);
         }
 
-        if let Some(_) = map::SCRATCH_PAD.contains(abs_addr) {
-            if addr > 0xa0000000 {
-                panic!("ScratchPad access through uncached memory");
+        if let Some(offset) = map::SCRATCH_PAD.contains(abs_addr) {
+            if addr >= 0xa0000000 {
+                // The ScratchPad is part of the data cache, it can't
+                // be reached through the uncached KSEG1 region
+                self.bus_error(addr);
+                return 0;
             }
 
-            panic!("Unhandled SCRATCH_PAD load at address 0x{:08x}", addr)
+            return self.scratch_pad.load::<A>(offset);
         }
 
         if let Some(offset) = map::BIOS.contains(abs_addr) {
@@ -47,8 +411,14 @@ impl Interconnect {
         }
 
         if let Some(offset) = map::IRQ_CONTROL.contains(abs_addr) {
-            warn!("IRQ control read 0x{:x}", offset);
-            return 0;
+            return match offset {
+                0 => self.irq.status() as u32,
+                4 => self.irq.mask() as u32,
+                _ => {
+                    warn!("Unhandled IRQ control load 0x{:x}", offset);
+                    0
+                }
+            };
         }
 
         if let Some(offset) = map::DMA.contains(abs_addr) {
@@ -68,25 +438,64 @@ impl Interconnect {
         }
 
         if let Some(offset) = map::TIMERS.contains(abs_addr) {
-            warn!("TIMERS control read 0x{:x}", offset);
-            return 0;
+            self.sync_video();
+
+            return self.timers.load(offset);
         }
 
-        if let Some(_) = map::CDROM.contains(abs_addr) {
-            panic!("Unhandled CDROM load at address 0x{:08x}", addr)
+        if let Some(offset) = map::CDROM.contains(abs_addr) {
+            self.sync_cdrom();
+
+            // The controller sits on an 8bit bus, wider accesses are
+            // split into consecutive byte accesses
+            let mut v = 0;
+
+            for i in 0..A::size() as u32 {
+                v |= (self.cdrom.load((offset + i) & 3) as u32) << (i * 8);
+            }
+
+            return v;
         }
 
-        if let Some(_) = map::MDEC.contains(abs_addr) {
-            panic!("Unhandled MDEC load at address 0x{:08x}", addr)
+        if let Some(offset) = map::MDEC.contains(abs_addr) {
+            if A::size() != 4 {
+                warn!("Unhandled MDEC load ({}) at offset {}", A::size(), offset);
+                return 0;
+            }
+
+            return match offset {
+                0 => self.mdec.read_data(),
+                _ => self.mdec.status(),
+            };
         }
 
-        if let Some(_) = map::SPU.contains(abs_addr) {
-            warn!("Unhandled read from SPU register 0x{:08x}", abs_addr);
-            return 0;
+        if let Some(offset) = map::SPU.contains(abs_addr) {
+            self.sync_spu();
+
+            // The registers are 16bit wide, word accesses are split
+            return match A::size() {
+                4 => {
+                    let lo = self.spu.load(offset) as u32;
+                    let hi = self.spu.load(offset + 2) as u32;
+
+                    lo | (hi << 16)
+                }
+                // Byte loads at odd addresses get the high half of
+                // the register
+                _ => (self.spu.load(offset & !1) as u32) >> ((offset & 1) * 8),
+            };
         }
 
-        if let Some(_) = map::PAD_MEMCARD.contains(abs_addr) {
-            panic!("Unhandled PAD_MEMCARD load at address 0x{:08x}", addr)
+        if let Some(offset) = map::PAD_MEMCARD.contains(abs_addr) {
+            self.sync_pad_memcard();
+
+            return self.pad_memcard.load(offset);
+        }
+
+        if let Some(offset) = map::SERIAL.contains(abs_addr) {
+            self.sync_serial();
+
+            return self.serial.load(offset);
         }
 
         if let Some(_) = map::EXPANSION_1.contains(abs_addr) {
@@ -98,12 +507,12 @@ impl Interconnect {
             panic!("Unhandled RAM_SIZE load at address 0x{:08x}", addr)
         }
 
-        if let Some(_) = map::MEM_CONTROL.contains(abs_addr) {
+        if let Some(offset) = map::MEM_CONTROL.contains(abs_addr) {
             if A::size() != 4 {
                 panic!("Unhandled MEM_CONTROL access ({})", A::size());
             }
 
-            panic!("Unhandled MEM_CONTROL load at address 0x{:08x}", addr)
+            return self.mem_control.load(offset);
         }
 
         if let Some(_) = map::CACHE_CONTROL.contains(abs_addr) {
@@ -111,7 +520,7 @@ impl Interconnect {
                 panic!("Unhandled cache control access ({})", A::size());
             }
 
-            panic!("Unhandled CACHE_CONTROL load at address 0x{:08x}", addr)
+            return self.cache_control.0;
         }
 
         if let Some(_) = map::EXPANSION_2.contains(abs_addr) {
@@ -121,25 +530,31 @@ impl Interconnect {
         panic!("unhandled load at address 0x{:08x}", addr);
     }
 
-    /// Interconnect: store `val` into `addr`
-    pub fn store<A: Addressable>(&mut self, addr: u32, val: u32) {
+    /// Store `val` into `addr` on the memory bus
+    fn store_memory<A: Addressable>(&mut self, addr: u32, val: u32) {
         let abs_addr = map::mask_region(addr);
 
+        let cycles = self.access_cycles::<A>(abs_addr, true);
+        self.scheduler.tick(cycles);
+
         if let Some(offset) = map::RAM.contains(abs_addr) {
             return self.ram.store::<A>(offset, val);
         }
 
         if let Some(offset) = map::SCRATCH_PAD.contains(abs_addr) {
-            if addr > 0xa0000000 {
-                panic!("ScratchPad access through uncached memory");
+            if addr >= 0xa0000000 {
+                return self.bus_error(addr);
             }
 
-            panic!("Unhandled write to SCRATCH_PAD 0x{:x}", offset);
+            return self.scratch_pad.store::<A>(offset, val);
         }
 
         if let Some(offset) = map::IRQ_CONTROL.contains(abs_addr) {
-            warn!("Unhandled IRQ control: 0x{:x} <− 0x{:08x}", offset, val);
-            return;
+            return match offset {
+                0 => self.irq.acknowledge(val as u16),
+                4 => self.irq.set_mask(val as u16),
+                _ => warn!("Unhandled IRQ control store 0x{:x}: 0x{:08x}", offset, val),
+            };
         }
 
         if let Some(offset) = map::DMA.contains(abs_addr) {
@@ -147,33 +562,77 @@ impl Interconnect {
         }
 
         if let Some(offset) = map::GPU.contains(abs_addr) {
-            return match offset {
+            // GP1 commands can change the video timings
+            self.sync_video();
+
+            match offset {
                 0 => self.gpu.gp0(val),
                 4 => self.gpu.gp1(val),
                 _ => panic!("GPU write {}: 0x{:08x}", offset, val),
             }
+
+            return self.schedule_video();
         }
 
         if let Some(offset) = map::TIMERS.contains(abs_addr) {
-            warn!("Unhandled TIMERS control: 0x{:x} <− 0x{:08x}", offset, val);
-            return;
+            self.sync_video();
+            self.timers.store(offset, val);
+
+            return self.schedule_video();
         }
 
         if let Some(offset) = map::CDROM.contains(abs_addr) {
-            panic!("Unhandled write to CDROM 0x{:x}", offset);
+            self.sync_cdrom();
+
+            for i in 0..A::size() as u32 {
+                self.cdrom.store((offset + i) & 3, (val >> (i * 8)) as u8);
+            }
+
+            return self.schedule_cdrom();
         }
 
         if let Some(offset) = map::MDEC.contains(abs_addr) {
-            panic!("Unhandled write to MDEC 0x{:x}", offset);
+            if A::size() != 4 {
+                warn!("Unhandled MDEC store ({}) at offset {}: 0x{:08x}",
+                      A::size(), offset, val);
+                return;
+            }
+
+            match offset {
+                0 => self.mdec.command(val),
+                _ => self.mdec.set_control(val),
+            }
+
+            return self.retry_mdec_out();
         }
 
         if let Some(offset) = map::SPU.contains(abs_addr) {
-            warn!("Unhandled write: SPU control: 0x{:x} <− 0x{:08x}", offset, val);
-            return;
+            self.sync_spu();
+
+            match A::size() {
+                4 => {
+                    self.spu.store(offset, val as u16, &mut self.irq);
+                    self.spu.store(offset + 2, (val >> 16) as u16, &mut self.irq);
+                }
+                2 => self.spu.store(offset, val as u16, &mut self.irq),
+                _ => warn!("Unhandled SPU byte store 0x{:x}: 0x{:02x}", offset, val),
+            }
+
+            return self.schedule_spu();
         }
 
         if let Some(offset) = map::PAD_MEMCARD.contains(abs_addr) {
-            panic!("Unhandled write to PAD_MEMCARD 0x{:x}", offset);
+            self.sync_pad_memcard();
+            self.pad_memcard.store(offset, val as u16);
+
+            return self.schedule_pad_memcard();
+        }
+
+        if let Some(offset) = map::SERIAL.contains(abs_addr) {
+            self.sync_serial();
+            self.serial.store(offset, val as u16);
+
+            return self.schedule_serial();
         }
 
         if let Some(_) = map::CACHE_CONTROL.contains(abs_addr) {
@@ -181,6 +640,7 @@ impl Interconnect {
                 panic!("Unhandled cache control access");
             }
 
+            self.cache_control = CacheControl(val);
             return;
         }
 
@@ -189,21 +649,17 @@ impl Interconnect {
                 panic!("Unhandled MEM_CONTROL access ({})", A::size());
             }
 
-            let val = val;
-
             match offset {
-                0 => // Expansion 1 base address
-                    if val != 0x1f000000 {
-                        panic!("Bad expansion 1 base address: 0x{:08x}", val);
-                    },
-                4 => // Expansion 2 base address
-                    if val != 0x1f802000 {
-                        panic!("Bad expansion 2 base address: 0x{:08x}", val);
-                    },
-                _ =>
-                    warn!("Unhandled write to MEM_CONTROL register 0x{:x}: 0x{:08x}", offset, val),
+                // Expansion 1 base address
+                0 if val != 0x1f000000 =>
+                    panic!("Bad expansion 1 base address: 0x{:08x}", val),
+                // Expansion 2 base address
+                4 if val != 0x1f802000 =>
+                    panic!("Bad expansion 2 base address: 0x{:08x}", val),
+                _ => (),
             }
-            return;
+
+            return self.mem_control.store(offset, val);
         }
 
         if let Some(_) = map::RAM_SIZE.contains(abs_addr) {
@@ -245,7 +701,7 @@ impl Interconnect {
                 7 => {
                     match minor {
                         0 => self.dma.set_control(val),
-                        4 => self.dma.set_interrupt(val),
+                        4 => self.dma.set_interrupt(val, &mut self.irq),
                         _ => panic!("Unhandled DMA write 0x{:x}: 0x{:08x}", offset, val)
                     }
                     None
@@ -284,16 +740,46 @@ impl Interconnect {
 
     /// Execute DMA transfer for a port
     fn do_dma(&mut self, port: Port) {
+        // The MDEC only requests the output transfer once the
+        // macroblocks have been decoded, keep the channel pending
+        // until then
+        if port == Port::MDecOut {
+            let size = self.dma.channel(port).transfer_size().unwrap_or(0);
+
+            if (self.mdec.output_len() as u32) < size {
+                return;
+            }
+        }
+
         // DMA transfer has been started, for now let's
         // process everything in one pass (i.e. no chopping or priority handling)
-        match self.dma.channel(port).sync() {
+        let words = match self.dma.channel(port).sync() {
             Sync::LinkedList => self.do_dma_linked_list(port),
             _ => self.do_dma_block(port)
+        };
+
+        // The CPU is stopped while the DMA runs, the transfer takes
+        // roughly one cycle per word
+        self.tick(words);
+
+        self.dma.done(port, &mut self.irq);
+
+        if port == Port::MDecIn {
+            self.retry_mdec_out();
+        }
+    }
+
+    /// Start the pending MDEC output transfer if enough data has been
+    /// decoded
+    fn retry_mdec_out(&mut self) {
+        if self.dma.channel(Port::MDecOut).active() {
+            self.do_dma(Port::MDecOut);
         }
     }
 
     /// Emulate DMA transfer for linked list synchronization mode.
-    fn do_dma_linked_list(&mut self, port: Port) {
+    /// Returns the number of words transferred
+    fn do_dma_linked_list(&mut self, port: Port) -> u32 {
         let channel = self.dma.channel_mut(port);
 
         let mut addr = channel.base() & 0x1ffffc;
@@ -308,6 +794,8 @@ impl Interconnect {
             panic!("Attempted linked list DMA on port {}", port as u8);
         }
 
+        let mut words = 0;
+
         loop {
             // In linked list mode, each entry starts with a
 
@@ -317,6 +805,8 @@ impl Interconnect {
 
             let mut remsz = header >> 24;
 
+            words += remsz + 1;
+
             while remsz > 0 {
                 addr = (addr + 4) & 0x1ffffc;
 
@@ -339,10 +829,12 @@ impl Interconnect {
             addr = header & 0x1ffffc;
         }
 
-        channel.done();
+        words
     }
 
-    fn do_dma_block(&mut self, port: Port) {
+    /// Emulate DMA transfer for block synchronization modes. Returns
+    /// the number of words transferred
+    fn do_dma_block(&mut self, port: Port) -> u32 {
         let channel = self.dma.channel_mut(port);
 
         // Move to channel
@@ -361,6 +853,8 @@ impl Interconnect {
             None => panic!("Couldn't figure out DMA block transfer size")
         };
 
+        let words = remsz;
+
         while remsz > 0 {
             // Not sure what happens if address is
             // bogus... Mednafen just masks addr this way, maybe
@@ -373,12 +867,17 @@ impl Interconnect {
                 Direction::FromRam => {
                     let src_word = self.ram.load::<Word>(cur_addr);
                     match port {
+                        Port::MDecIn => self.mdec.command(src_word),
                         Port::Gpu => self.gpu.gp0(src_word),
+                        Port::Spu => self.spu.dma_write_word(src_word, &mut self.irq),
                         _ => panic!("Unhandled DMA destination port {}", port as u8)
                     }
                 }
                 Direction::ToRam => {
                     let src_word = match port {
+                        Port::MDecOut => self.mdec.read_data(),
+                        Port::CdRom => self.cdrom.dma_read_word(),
+                        Port::Spu => self.spu.dma_read_word(&mut self.irq),
                         Port::Otc => match remsz {
                             // Last entry contains the end
                             // of table marker
@@ -395,8 +894,390 @@ impl Interconnect {
             addr = addr.wrapping_add(increment);
             remsz -= 1;
         }
-        channel.done();
+
+        words
+    }
+}
+
+#[test]
+fn timer_interrupt_deadline() {
+    let mut interconnect = test_interconnect();
+
+    // Timer 2: target 1000, IRQ on target
+    interconnect.store::<Word>(0x1f801128, 1000);
+    interconnect.store::<Word>(0x1f801124, 0x10);
+
+    interconnect.tick(999);
+
+    assert_eq!(interconnect.load::<Word>(0x1f801070) & (1 << 6), 0);
+
+    // The interrupt is raised by the scheduler without any register
+    // access
+    interconnect.tick(1);
+
+    assert_eq!(interconnect.irq.status() & (1 << 6), 1 << 6);
+}
+
+#[test]
+fn irq_control_upper_halves() {
+    use crate::memory::HalfWord;
+
+    let mut interconnect = test_interconnect();
+
+    interconnect.store::<Word>(0x1f801074, 0x5);
+
+    // The upper halves of the registers are unused
+    interconnect.store::<HalfWord>(0x1f801076, 0xffff);
+    interconnect.store::<HalfWord>(0x1f801072, 0xffff);
+
+    assert_eq!(interconnect.load::<HalfWord>(0x1f801076), 0);
+    assert_eq!(interconnect.load::<Word>(0x1f801074), 0x5);
+}
+
+#[test]
+fn vblank_frame_count() {
+    let mut interconnect = test_interconnect();
+
+    // One NTSC frame: 263 lines of 3413 GPU cycles
+    let frame = 263 * 3413 * 7 / 11 + 1;
+
+    for _ in 0..frame / 100 + 1 {
+        interconnect.tick(100);
+    }
+
+    assert_eq!(interconnect.frame_count(), 1);
+    assert_eq!(interconnect.irq.status() & 1, 1);
+}
+
+#[test]
+fn scratch_pad_mirrors() {
+    use crate::memory::{Byte, HalfWord};
+
+    let mut interconnect = test_interconnect();
+
+    interconnect.store::<Word>(0x1f800000, 0x12345678);
+    interconnect.store::<Word>(0x1f8003fc, 0xcafebabe);
+
+    // KUSEG And KSEG0 map the same memory
+    assert_eq!(interconnect.load::<Word>(0x9f800000), 0x12345678);
+    assert_eq!(interconnect.load::<Word>(0x9f8003fc), 0xcafebabe);
+
+    interconnect.store::<Word>(0x9f800004, 0xdeadbeef);
+
+    assert_eq!(interconnect.load::<Word>(0x1f800004), 0xdeadbeef);
+    assert!(!interconnect.take_bus_error());
+
+    // Sub-word accesses at every alignment
+    assert_eq!(interconnect.load::<HalfWord>(0x1f800002), 0x1234);
+    assert_eq!(interconnect.load::<Byte>(0x9f800001), 0x56);
+    assert_eq!(interconnect.load::<Byte>(0x1f8003ff), 0xca);
+
+    interconnect.store::<Byte>(0x1f800003, 0xab);
+    interconnect.store::<HalfWord>(0x9f800000, 0xcdef);
+
+    assert_eq!(interconnect.load::<Word>(0x1f800000), 0xab34cdef);
+}
+
+#[test]
+fn scratch_pad_kseg1() {
+    let mut interconnect = test_interconnect();
+
+    interconnect.store::<Word>(0x1f800000, 0x12345678);
+    assert!(!interconnect.take_bus_error());
+
+    assert_eq!(interconnect.load::<Word>(0xbf800000), 0);
+    assert!(interconnect.take_bus_error());
+    // The flag is cleared once it's been read
+    assert!(!interconnect.take_bus_error());
+
+    // Stores are dropped
+    interconnect.store::<Word>(0xbf800000, 0);
+
+    assert!(interconnect.take_bus_error());
+    assert_eq!(interconnect.load::<Word>(0x1f800000), 0x12345678);
+}
+
+#[test]
+fn cdrom_dma() {
+    use crate::cdrom::disc::test_disc;
+    use crate::memory::Byte;
+
+    let bios = Bios::from_data(vec![0; 512 * 1024]).unwrap();
+    let mut interconnect = Interconnect::new(bios, Ram::new(), Gpu::headless(), Some(test_disc()));
+
+    interconnect.store::<Word>(0x1f801074, 1 << 2);
+
+    // Enable all the CDROM interrupts
+    interconnect.store::<Byte>(0x1f801800, 1);
+    interconnect.store::<Byte>(0x1f801802, 0x1f);
+
+    // Setloc 00:02:16 then ReadN
+    interconnect.store::<Byte>(0x1f801800, 0);
+    interconnect.store::<Byte>(0x1f801802, 0x00);
+    interconnect.store::<Byte>(0x1f801802, 0x02);
+    interconnect.store::<Byte>(0x1f801802, 0x16);
+    interconnect.store::<Byte>(0x1f801801, 0x02);
+
+    for (i, response) in [3, 3, 1].into_iter().enumerate() {
+        // The scheduler delivers the interrupt
+        while interconnect.irq.status() & (1 << 2) == 0 {
+            interconnect.tick(1000);
+        }
+
+        interconnect.store::<Byte>(0x1f801800, 1);
+        assert_eq!(interconnect.load::<Byte>(0x1f801803) & 7, response);
+        interconnect.store::<Byte>(0x1f801803, 0x1f);
+        interconnect.store::<Word>(0x1f801070, 0);
+
+        if i == 0 {
+            // ReadN once Setloc is acknowledged
+            interconnect.store::<Byte>(0x1f801800, 0);
+            interconnect.store::<Byte>(0x1f801801, 0x06);
+        }
     }
+
+    // Request the data And transfer it to RAM at 0x1000
+    interconnect.store::<Byte>(0x1f801800, 0);
+    interconnect.store::<Byte>(0x1f801803, 0x80);
+
+    interconnect.store::<Word>(0x1f8010b0, 0x1000);
+    interconnect.store::<Word>(0x1f8010b4, 0x200);
+    interconnect.store::<Word>(0x1f8010b8, 0x11000000);
+
+    assert_eq!(interconnect.load::<Word>(0x1000), 0xa6a6a6a6);
+    assert_eq!(interconnect.load::<Word>(0x17fc), 0xa6a6a6a6);
+    assert_ne!(interconnect.load::<Word>(0x1800), 0xa6a6a6a6);
+}
+
+#[test]
+fn cdrom_wide_access() {
+    use crate::memory::{Byte, HalfWord};
+
+    let mut interconnect = test_interconnect();
+
+    // Enable all the interrupts with a halfword store covering the
+    // mask And flag registers
+    interconnect.store::<Byte>(0x1f801800, 1);
+    interconnect.store::<HalfWord>(0x1f801802, 0x001f);
+
+    let status = interconnect.load::<Byte>(0x1f801800) & 0xff;
+
+    // Status, response, data FIFO And interrupt flags
+    let word = interconnect.load::<Word>(0x1f801800);
+
+    assert_eq!(word & 0xff, status);
+    assert_eq!(word >> 24, 0xe0);
+
+    // Index 1 reads back the interrupt flags, index 0 the mask
+    interconnect.store::<Byte>(0x1f801800, 0);
+    assert_eq!(interconnect.load::<HalfWord>(0x1f801802) >> 8, 0xff);
+}
+
+#[test]
+fn spu_dma() {
+    use crate::memory::{Byte, HalfWord};
+
+    let mut interconnect = test_interconnect();
+
+    for i in 0..16 {
+        interconnect.store::<Word>(0x2000 + i * 4, 0x1000_0000 + i);
+    }
+
+    // DMA write mode, transfer address 0x100 (byte address 0x800)
+    interconnect.store::<HalfWord>(0x1f801daa, 0x0020);
+    interconnect.store::<HalfWord>(0x1f801da6, 0x100);
+
+    // Channel 4: one block of 16 words from RAM
+    interconnect.store::<Word>(0x1f8010c0, 0x2000);
+    interconnect.store::<Word>(0x1f8010c4, 0x0001_0010);
+    interconnect.store::<Word>(0x1f8010c8, 0x0100_0201);
+
+    // Back to RAM at 0x3000
+    interconnect.store::<HalfWord>(0x1f801daa, 0x0030);
+    interconnect.store::<HalfWord>(0x1f801da6, 0x100);
+
+    interconnect.store::<Word>(0x1f8010c0, 0x3000);
+    interconnect.store::<Word>(0x1f8010c4, 0x0001_0010);
+    interconnect.store::<Word>(0x1f8010c8, 0x0100_0200);
+
+    for i in 0..16 {
+        assert_eq!(interconnect.load::<Word>(0x3000 + i * 4), 0x1000_0000 + i);
+    }
+
+    // Word accesses are split in two 16bit register accesses
+    interconnect.store::<Word>(0x1f801c00, 0x1234_0567);
+    assert_eq!(interconnect.load::<HalfWord>(0x1f801c02), 0x1234);
+    assert_eq!(interconnect.load::<Word>(0x1f801c00), 0x1234_0567);
+
+    // Byte loads return the half of the register they address
+    assert_eq!(interconnect.load::<Byte>(0x1f801c00) & 0xff, 0x67);
+    assert_eq!(interconnect.load::<Byte>(0x1f801c01) & 0xff, 0x05);
+    assert_eq!(interconnect.load::<Byte>(0x1f801c03) & 0xff, 0x12);
+}
+
+#[test]
+fn mdec_narrow_access() {
+    use crate::memory::HalfWord;
+
+    let mut interconnect = test_interconnect();
+
+    let status = interconnect.load::<Word>(0x1f801824);
+
+    // Ignored instead of starting a command or resetting the MDEC
+    interconnect.store::<HalfWord>(0x1f801820, 0x3000);
+    interconnect.store::<HalfWord>(0x1f801824, 0x8000);
+
+    assert_eq!(interconnect.load::<HalfWord>(0x1f801824), 0);
+    assert_eq!(interconnect.load::<Word>(0x1f801824), status);
+}
+
+#[test]
+fn mdec_dma() {
+    let mut interconnect = test_interconnect();
+
+    // Quantization tables: factor 2 everywhere
+    interconnect.store::<Word>(0x1000, 0x4000_0001);
+
+    for i in 0..32 {
+        interconnect.store::<Word>(0x1004 + i * 4, 0x0202_0202);
+    }
+
+    // Standard IDCT scale table
+    let scale = crate::mdec::decoder::STANDARD_SCALE_TABLE;
+
+    interconnect.store::<Word>(0x1084, 0x6000_0000);
+
+    for (i, pair) in scale.chunks(2).enumerate() {
+        let w = (pair[0] as u16 as u32) | ((pair[1] as u16 as u32) << 16);
+
+        interconnect.store::<Word>(0x1088 + i as u32 * 4, w);
+    }
+
+    // 24bpp macroblock: Cr = 40, everything else 0
+    interconnect.store::<Word>(0x1108, 0x3000_0006);
+    interconnect.store::<Word>(0x110c, 0xfe00_0000 | (1 << 10) | 160);
+
+    for i in 0..5 {
+        interconnect.store::<Word>(0x1110 + i * 4, 0xfe00_0000);
+    }
+
+    interconnect.store::<Word>(0x1f801824, 0xe000_0000);
+    interconnect.store::<Word>(0x1f801824, 0x6000_0000);
+
+    // Output channel started first: it has to wait for the decoded
+    // data. 6 blocks of 32 words to RAM at 0x4000.
+    interconnect.store::<Word>(0x1f801090, 0x4000);
+    interconnect.store::<Word>(0x1f801094, 0x0006_0020);
+    interconnect.store::<Word>(0x1f801098, 0x0100_0200);
+
+    assert_eq!(interconnect.load::<Word>(0x1f801098) & (1 << 24), 1 << 24);
+
+    // Input channel: the tables And the macroblock, 73 words
+    interconnect.store::<Word>(0x1f801080, 0x1000);
+    interconnect.store::<Word>(0x1f801084, 0x0001_0049);
+    interconnect.store::<Word>(0x1f801088, 0x0100_0201);
+
+    assert_eq!(interconnect.load::<Word>(0x1f801098) & (1 << 24), 0);
+    assert_eq!(interconnect.load::<Word>(0x1f801824) >> 29, 4);
+
+    let pixel = [128 + 56, 128 - 29, 128];
+
+    for i in 0..256 {
+        for (c, &v) in pixel.iter().enumerate() {
+            use crate::memory::Byte;
+
+            assert_eq!(interconnect.load::<Byte>(0x4000 + i * 3 + c as u32), v as u32);
+        }
+    }
+}
+
+#[test]
+fn instruction_cache() {
+    let mut interconnect = test_interconnect();
+
+    interconnect.store::<Word>(0x1000, 0x11111111);
+    interconnect.store::<Word>(0x1004, 0x22222222);
+
+    // Cache disabled: every fetch goes to memory
+    let now = interconnect.cycles();
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x11111111);
+    assert_eq!(interconnect.cycles(), now + 5);
+
+    interconnect.store::<Word>(0xfffe0130, 0x800);
+
+    // Miss: the line is refilled from 0x1000 to 0x100c
+    interconnect.load_instruction(0x80001000);
+
+    let now = interconnect.cycles();
+
+    // Hits don't access the memory, even for the other words of the
+    // line
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x11111111);
+    assert_eq!(interconnect.load_instruction(0x80001004), 0x22222222);
+    assert_eq!(interconnect.cycles(), now);
+
+    interconnect.store::<Word>(0x1000, 0x33333333);
+
+    // Stale cached value, KSEG1 bypasses the cache
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x11111111);
+    assert_eq!(interconnect.load_instruction(0xa0001000), 0x33333333);
+}
+
+#[test]
+fn cache_isolation() {
+    let mut interconnect = test_interconnect();
+
+    interconnect.store::<Word>(0x1000, 0x11111111);
+    interconnect.store::<Word>(0xfffe0130, 0x800);
+
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x11111111);
+
+    // Isolated stores write the cache, not the memory
+    interconnect.set_cache_isolated(true);
+    interconnect.store::<Word>(0x1000, 0x22222222);
+    assert_eq!(interconnect.load::<Word>(0x1000), 0x22222222);
+    interconnect.set_cache_isolated(false);
+
+    assert_eq!(interconnect.load::<Word>(0x1000), 0x11111111);
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x22222222);
+
+    // BIOS flush sequence: tag test mode, isolated store to each line
+    interconnect.store::<Word>(0xfffe0130, 0x804);
+    interconnect.set_cache_isolated(true);
+
+    for line in 0..256 {
+        interconnect.store::<Word>(line * 16, 0);
+    }
+
+    interconnect.set_cache_isolated(false);
+    interconnect.store::<Word>(0xfffe0130, 0x800);
+
+    // The line has been invalidated And is refilled from memory
+    assert_eq!(interconnect.load_instruction(0x80001000), 0x11111111);
+
+    // Uncached accesses aren't affected by the isolation
+    interconnect.set_cache_isolated(true);
+
+    interconnect.store::<Word>(0xa0001000, 0x44444444);
+    interconnect.store::<Word>(0x1f800000, 0x55555555);
+    interconnect.store::<Word>(0x1f801074, 0x5);
+
+    assert_eq!(interconnect.load::<Word>(0xa0001000), 0x44444444);
+    assert_eq!(interconnect.load::<Word>(0x1f800000), 0x55555555);
+    assert_eq!(interconnect.load::<Word>(0xbf801074), 0x5);
+
+    interconnect.set_cache_isolated(false);
+
+    assert_eq!(interconnect.load::<Word>(0x1000), 0x44444444);
+}
+
+/// Interconnect with a blank BIOS And a headless GPU, used by the
+/// unit tests
+#[cfg(test)]
+pub fn test_interconnect() -> Interconnect {
+    let bios = Bios::from_data(vec![0; 512 * 1024]).unwrap();
+
+    Interconnect::new(bios, Ram::new(), Gpu::headless(), None)
 }
 
 pub mod map {
@@ -435,6 +1316,21 @@ pub mod map {
         addr & REGION_MASK[index]
     }
 
+    /// Return true if an access to `addr` goes through the cache.
+    /// KSEG1 And KSEG2 are never cached, neither are the scratchpad
+    /// And the I/O ports.
+    pub fn is_cached(addr: u32) -> bool {
+        if addr >= 0xa0000000 {
+            return false;
+        }
+
+        let abs_addr = mask_region(addr);
+
+        RAM.contains(abs_addr).is_some() ||
+            EXPANSION_1.contains(abs_addr).is_some() ||
+            BIOS.contains(abs_addr).is_some()
+    }
+
     /// Main RAM: 2MB mirrored four times over the first 8MB (probably
     /// in case they decided to use a bigger RAM later on?)
     pub const RAM: Range = Range(0x00000000, 8 * 1024 * 1024);
@@ -451,7 +1347,10 @@ pub mod map {
     pub const MEM_CONTROL: Range = Range(0x1f801000, 36);
 
     /// Gamepad And memory card controller
-    pub const PAD_MEMCARD: Range = Range(0x1f801040, 32);
+    pub const PAD_MEMCARD: Range = Range(0x1f801040, 16);
+
+    /// Serial port
+    pub const SERIAL: Range = Range(0x1f801050, 16);
 
     /// Register that has something to do with RAM configuration,
     /// configured by the BIOS
@@ -6,6 +6,9 @@ use crate::cpu::exception::Exception;
 use crate::cpu::operations::Operation;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
+#[cfg(test)]
+use crate::instruction::RegisterIndex;
+#[cfg(test)]
 use crate::memory::Word;
 
 use self::interconnect::Interconnect;
@@ -15,6 +18,13 @@ pub mod registers;
 pub mod delay;
 pub mod operations;
 pub mod exception;
+pub mod gte;
+pub mod cache;
+
+/// Every instruction spends at least one cycle in the pipeline. Memory
+/// accesses (including the instruction fetch) And HI/LO stalls are
+/// charged on top of that by the interconnect.
+const CYCLES_PER_INSTRUCTION: u32 = 1;
 
 /// CPU state
 pub struct Cpu {
@@ -41,7 +51,7 @@ impl Cpu {
     pub fn run_next_instruction(&mut self) {
         // TODO - Raise PC alignment exception
         let pc = self.registers.pc();
-        let instruction = Instruction(self.interconnect.load::<Word>(pc));
+        let instruction = Instruction(self.interconnect.load_instruction(pc));
 
         // Save the address of the current instruction to save in
         // 'EPC' in case of an exception.
@@ -62,19 +72,73 @@ impl Cpu {
         self.load.set_delay_slot(self.load.branch());
         self.load.set_branch(false);
 
-        let operation = self.decode(instruction);
+        // Update the external interrupt line in CAUSE bit 10
+        let mut cause = self.registers.cause() & !(1 << 10);
 
-        if log_enabled!(log::Level::Debug) {
-            debug!("0x{:08x}: {}", self.registers.pc(), operation.gnu());
+        if self.interconnect.irq_pending() {
+            cause |= 1 << 10;
         }
 
-        let maybe_exception = operation.perform(&mut self.registers, &mut self.interconnect, &mut self.load);
-
-        if let Err(exception) = maybe_exception {
-            self.enter_exception(exception)
+        self.registers.set_cause(cause);
+
+        if self.interconnect.take_bus_error() {
+            self.enter_exception(Exception::InstructionBusError);
+        } else if self.interrupt_requested() {
+            // GTE commands are executed even if the interrupt is
+            // taken, the BIOS handler knows about that And skips the
+            // instruction at EPC when it returns.
+            if instruction.0 & 0xfe000000 == 0x4a000000 {
+                let operation = self.decode(instruction);
+
+                // If COP2 is disabled the command is dropped, the
+                // interrupt takes precedence over the coprocessor
+                // error.
+                let _ = operation.perform(&mut self.registers, &mut self.interconnect, &mut self.load);
+            }
+
+            self.enter_exception(Exception::Interrupt);
+        } else {
+            let operation = self.decode(instruction);
+
+            if log_enabled!(log::Level::Debug) {
+                debug!("0x{:08x}: {}", self.registers.pc(), operation.gnu());
+            }
+
+            let maybe_exception = operation.perform(&mut self.registers, &mut self.interconnect, &mut self.load);
+
+            if let Err(exception) = maybe_exception {
+                self.enter_exception(exception)
+            } else if self.interconnect.take_bus_error() {
+                // Cancel the load, if any
+                self.load.reset();
+                self.enter_exception(Exception::DataBusError);
+            }
         }
 
         self.registers.swap_registers();
+
+        self.interconnect.tick(CYCLES_PER_INSTRUCTION);
+    }
+
+    /// Run the CPU until the GPU starts outputting a new frame
+    pub fn run_frame(&mut self) {
+        let frame = self.interconnect.frame_count();
+
+        while self.interconnect.frame_count() == frame {
+            self.run_next_instruction();
+        }
+    }
+
+    /// Return true if an interrupt should be taken: interrupts must be
+    /// globally enabled (SR.IEc) And one of the pending interrupts in
+    /// CAUSE.IP must be unmasked in SR.IM
+    fn interrupt_requested(&self) -> bool {
+        let sr = self.registers.sr();
+        let cause = self.registers.cause();
+
+        let pending = (sr & cause) & 0x700 != 0;
+
+        pending && sr & 1 != 0
     }
 
     fn decode(&mut self, instruction: Instruction) -> Operation {
@@ -92,11 +156,12 @@ impl Cpu {
             0b101001 => Operation::Sh(instruction),
             0b000011 => Operation::Jal(instruction),
             0b001100 => Operation::Andi(instruction),
+            0b001110 => Operation::Xori(instruction),
             0b101000 => Operation::Sb(instruction),
             0b100000 => Operation::Lb(instruction),
             0b000100 => Operation::Beq(instruction),
-            0b000111 => Operation::Bqtz(instruction),
-            0b000110 => Operation::Bltz(instruction),
+            0b000111 => Operation::Bgtz(instruction),
+            0b000110 => Operation::Blez(instruction),
             0b100100 => Operation::Lbu(instruction),
             0b000001 => Operation::Bxx(instruction),
             0b001010 => Operation::Slti(instruction),
@@ -107,7 +172,18 @@ impl Cpu {
             0b100110 => Operation::Lwr(instruction),
             0b101010 => Operation::Swl(instruction),
             0b101110 => Operation::Swr(instruction),
-            _ => panic!("Unhandled instruction [0x{:08x}]. Function call was: [{:#08b}]", instruction.0, instruction.function())
+            0b010001 => Operation::Cop1(instruction),
+            0b010010 => self.decode_and_execute_cop2(instruction),
+            0b010011 => Operation::Cop3(instruction),
+            0b110000 => Operation::Lwc0(instruction),
+            0b110001 => Operation::Lwc1(instruction),
+            0b110010 => Operation::Lwc2(instruction),
+            0b110011 => Operation::Lwc3(instruction),
+            0b111000 => Operation::Swc0(instruction),
+            0b111001 => Operation::Swc1(instruction),
+            0b111010 => Operation::Swc2(instruction),
+            0b111011 => Operation::Swc3(instruction),
+            _ => Operation::Illegal(instruction),
         }
     }
 
@@ -124,6 +200,7 @@ impl Cpu {
             0b001001 => Operation::Jalr(instruction),
             0b100011 => Operation::Subu(instruction),
             0b000011 => Operation::Sra(instruction),
+            0b000111 => Operation::Srav(instruction),
             0b011010 => Operation::Div(instruction),
             0b010010 => Operation::Mflo(instruction),
             0b010000 => Operation::Mfhi(instruction),
@@ -131,14 +208,16 @@ impl Cpu {
             0b011011 => Operation::Divu(instruction),
             0b101010 => Operation::Slt(instruction),
             0b001100 => Operation::Syscall(instruction),
+            0b001101 => Operation::Break(instruction),
             0b010011 => Operation::Mtlo(instruction),
             0b010001 => Operation::Mthi(instruction),
             0b000100 => Operation::Sllv(instruction),
             0b100110 => Operation::Xor(instruction),
+            0b011000 => Operation::Mult(instruction),
             0b011001 => Operation::Multu(instruction),
             0b000110 => Operation::Srlv(instruction),
             0b100010 => Operation::Sub(instruction),
-            _ => panic!("Unhandled instruction [0x{:08x}]. Sub function call was: [{:#08b}]", instruction.0, instruction.subfunction())
+            _ => Operation::Illegal(instruction),
         }
     }
 
@@ -148,7 +227,23 @@ impl Cpu {
             0b000100 => Operation::Mtc0(instruction),
             0b000000 => Operation::Mfc0(instruction),
             0b010000 => Operation::Rfe(instruction),
-            _ => panic!("Unhandled cop0 instruction [0x{:08x}]. Cop0 call was: [{:#08b}]", instruction.0, instruction.subfunction())
+            _ => Operation::Illegal(instruction),
+        }
+    }
+
+    /// Coprocessor 2 opcode (GTE)
+    fn decode_and_execute_cop2(&mut self, instruction: Instruction) -> Operation {
+        // Bit 25 set means that the opcode is a GTE command
+        if instruction.cop_opcode() & 0x10 != 0 {
+            return Operation::Cop2(instruction);
+        }
+
+        match instruction.cop_opcode() {
+            0b00000 => Operation::Mfc2(instruction),
+            0b00010 => Operation::Cfc2(instruction),
+            0b00100 => Operation::Mtc2(instruction),
+            0b00110 => Operation::Ctc2(instruction),
+            _ => Operation::Illegal(instruction),
         }
     }
 
@@ -183,6 +278,9 @@ impl Cpu {
         // [6:2])
         let mut register_cause = self.registers.cause();
 
+        // Clear the code And branch delay flag of the previous
+        // exception
+        register_cause &= !0x8000007c;
         register_cause |= (cause as u32) << 2;
 
         self.registers.set_cause(register_cause);
@@ -193,7 +291,7 @@ impl Cpu {
         if self.load.delay_slot() {
             // When an exception occurs in a delay slot `EPC` points
             // to the branch instruction And bit 31 of `CAUSE` is set.
-            self.registers.set_epc(self.registers.pc().wrapping_sub(4));
+            self.registers.set_epc(self.registers.current_pc().wrapping_sub(4));
             let mut cause = self.registers.cause();
             cause |= 1 << 31;
             self.registers.set_cause(cause);
@@ -203,4 +301,288 @@ impl Cpu {
         self.registers.set_pc(handler);
         self.registers.set_next_pc(self.registers.pc().wrapping_add(4));
     }
-}
\ No newline at end of file
+}
+/// Build a CPU with `program` loaded in RAM at 0x80001000 And the PC
+/// pointing at it
+#[cfg(test)]
+fn test_cpu(program: &[u32]) -> Cpu {
+    let mut cpu = Cpu::new(interconnect::test_interconnect());
+
+    for (i, &op) in program.iter().enumerate() {
+        cpu.interconnect.store::<Word>(0x80001000 + (i as u32) * 4, op);
+    }
+
+    cpu.registers.set_pc(0x80001000);
+    cpu.registers.set_next_pc(0x80001004);
+
+    cpu
+}
+
+/// Execute a single instruction And check that it raised `exception`
+#[cfg(test)]
+fn assert_exception(op: u32, exception: Exception) {
+    let mut cpu = test_cpu(&[op]);
+
+    cpu.run_next_instruction();
+
+    let cause = cpu.registers.cause();
+
+    assert_eq!((cause >> 2) & 0x1f, exception as u32, "instruction 0x{:08x}", op);
+    assert_eq!(cause >> 31, 0, "instruction 0x{:08x}", op);
+    assert_eq!(cpu.registers.epc(), 0x80001000, "instruction 0x{:08x}", op);
+    assert_eq!(cpu.registers.pc(), 0x80000080, "instruction 0x{:08x}", op);
+}
+
+#[test]
+fn illegal_primary_opcodes() {
+    let unassigned = [
+        0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+        0x27, 0x2c, 0x2d, 0x2f, 0x34, 0x35, 0x36, 0x37, 0x3c, 0x3d, 0x3e, 0x3f,
+    ];
+
+    for &function in unassigned.iter() {
+        assert_exception(function << 26, Exception::IllegalInstruction);
+    }
+}
+
+#[test]
+fn illegal_special_opcodes() {
+    let unassigned = [
+        0x01, 0x05, 0x0a, 0x0b, 0x0e, 0x0f, 0x14, 0x15, 0x16, 0x17, 0x1c, 0x1d,
+        0x1e, 0x1f, 0x28, 0x29, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33,
+        0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+    ];
+
+    for &subfunction in unassigned.iter() {
+        assert_exception(subfunction, Exception::IllegalInstruction);
+    }
+}
+
+#[test]
+fn illegal_coprocessor_opcodes() {
+    // Unknown COP0 opcode
+    assert_exception(0x40200000, Exception::IllegalInstruction);
+    // RFE encoding with an invalid function
+    assert_exception(0x42000001, Exception::IllegalInstruction);
+
+    // Unknown COP2 opcode with the GTE enabled
+    let mut cpu = test_cpu(&[0x48200000]);
+    cpu.registers.set_sr(1 << 30);
+
+    cpu.run_next_instruction();
+
+    assert_eq!((cpu.registers.cause() >> 2) & 0x1f, Exception::IllegalInstruction as u32);
+    assert_eq!(cpu.registers.epc(), 0x80001000);
+}
+
+#[test]
+fn coprocessor_unusable() {
+    let ops = [
+        // COP1, COP3
+        (0x44000000, 1),
+        (0x4c000000, 3),
+        // LWC0, LWC1, LWC3
+        (0xc0000000, 0),
+        (0xc4000000, 1),
+        (0xcc000000, 3),
+        // SWC0, SWC1, SWC3
+        (0xe0000000, 0),
+        (0xe4000000, 1),
+        (0xec000000, 3),
+        // MTC2 with the GTE disabled
+        (0x48800000, 2),
+    ];
+
+    for &(op, cop) in ops.iter() {
+        assert_exception(op, Exception::CoprocessorError);
+
+        let mut cpu = test_cpu(&[op]);
+        cpu.run_next_instruction();
+
+        assert_eq!((cpu.registers.cause() >> 28) & 3, cop, "instruction 0x{:08x}", op);
+    }
+}
+
+#[test]
+fn exception_in_delay_slot() {
+    // beq $zero, $zero, +4 with an illegal instruction in the delay
+    // slot
+    let mut cpu = test_cpu(&[0x10000004, 0xfc000000]);
+
+    cpu.run_next_instruction();
+    cpu.run_next_instruction();
+
+    let cause = cpu.registers.cause();
+
+    assert_eq!((cause >> 2) & 0x1f, Exception::IllegalInstruction as u32);
+    assert_eq!(cause >> 31, 1);
+    assert_eq!(cpu.registers.epc(), 0x80001000);
+    assert_eq!(cpu.registers.pc(), 0x80000080);
+}
+
+#[test]
+fn exception_vector_bev() {
+    let mut cpu = test_cpu(&[0xfc000000]);
+
+    // Boot exception vectors
+    cpu.registers.set_sr(1 << 22);
+
+    cpu.run_next_instruction();
+
+    assert_eq!(cpu.registers.pc(), 0xbfc00180);
+    // Interrupt enable/user mode stack pushed
+    assert_eq!(cpu.registers.sr() & 0x3f, 0);
+}
+
+#[test]
+fn interrupt() {
+    // addiu $1, $zero, 1
+    let mut cpu = test_cpu(&[0x24010001, 0x24010001]);
+
+    // Interrupts globally enabled And IM bit 2 (external interrupt) set
+    cpu.registers.set_sr(0x401);
+
+    // I_MASK: DMA
+    cpu.interconnect.store::<Word>(0x1f801074, 1 << 3);
+    // DMA: master IRQ enable And force_irq
+    cpu.interconnect.store::<Word>(0x1f8010f4, (1 << 23) | (1 << 15));
+
+    assert_eq!(cpu.interconnect.load::<Word>(0x1f801070), 1 << 3);
+
+    cpu.run_next_instruction();
+
+    let cause = cpu.registers.cause();
+
+    assert_eq!((cause >> 2) & 0x1f, Exception::Interrupt as u32);
+    assert_eq!(cause & (1 << 10), 1 << 10);
+    // The instruction hasn't been executed
+    assert_eq!(cpu.registers.epc(), 0x80001000);
+    assert_eq!(cpu.registers.pc(), 0x80000080);
+    assert_eq!(cpu.registers.reg(RegisterIndex(1)), 0xdeadbeef);
+    // Interrupts are disabled in the handler
+    assert_eq!(cpu.registers.sr() & 1, 0);
+
+    // Acknowledge the interrupt
+    cpu.interconnect.store::<Word>(0x1f801070, 0);
+
+    assert_eq!(cpu.interconnect.load::<Word>(0x1f801070), 0);
+}
+
+#[test]
+fn interrupt_masked() {
+    let mut cpu = test_cpu(&[0x24010001]);
+
+    // IM bit 2 set but interrupts globally disabled
+    cpu.registers.set_sr(0x400);
+
+    cpu.interconnect.store::<Word>(0x1f801074, 1 << 3);
+    cpu.interconnect.store::<Word>(0x1f8010f4, (1 << 23) | (1 << 15));
+
+    cpu.run_next_instruction();
+
+    assert_eq!(cpu.registers.pc(), 0x80001004);
+    assert_eq!(cpu.registers.cause() & (1 << 10), 1 << 10);
+    assert_eq!(cpu.registers.reg(RegisterIndex(1)), 1);
+}
+
+#[test]
+fn software_interrupt() {
+    // mtc0 $1, $13; addiu $1, $zero, 1
+    let mut cpu = test_cpu(&[0x40816800, 0x24010001]);
+
+    cpu.registers.set_sr(0x101);
+    cpu.registers.set_reg(RegisterIndex(1), 1 << 8);
+    cpu.registers.swap_registers();
+
+    cpu.run_next_instruction();
+    cpu.run_next_instruction();
+
+    assert_eq!((cpu.registers.cause() >> 2) & 0x1f, Exception::Interrupt as u32);
+    assert_eq!(cpu.registers.epc(), 0x80001004);
+}
+
+#[test]
+fn instruction_timings() {
+    // addiu $1, $zero, 1; lw $2, 0x100($zero); sw $2, 0x100($zero)
+    let mut cpu = test_cpu(&[0x24010001, 0x8c020100, 0xac020100]);
+
+    // Fetch from RAM + pipeline
+    cpu.run_next_instruction();
+    assert_eq!(cpu.interconnect.cycles(), 6);
+
+    // Fetch + RAM load + pipeline
+    cpu.run_next_instruction();
+    assert_eq!(cpu.interconnect.cycles(), 6 + 11);
+
+    // Stores go through the write queue
+    cpu.run_next_instruction();
+    assert_eq!(cpu.interconnect.cycles(), 6 + 11 + 6);
+}
+
+#[test]
+fn hi_lo_stall() {
+    // div $1, $2; mfhi $3
+    let mut cpu = test_cpu(&[0x0022001a, 0x00001810]);
+
+    cpu.registers.set_reg(RegisterIndex(2), 3);
+    cpu.registers.swap_registers();
+
+    cpu.run_next_instruction();
+    cpu.run_next_instruction();
+
+    // The division result is available 36 cycles after the DIV
+    // executes, the fetch of the MFHI doesn't hide all of it
+    assert_eq!(cpu.interconnect.cycles(), 5 + 36 + 1);
+
+    // mult $1, $2; mflo $3: small operands, the fetch hides the
+    // latency
+    let mut cpu = test_cpu(&[0x00220018, 0x00001812]);
+
+    cpu.registers.set_reg(RegisterIndex(1), 3);
+    cpu.registers.set_reg(RegisterIndex(2), 3);
+    cpu.registers.swap_registers();
+
+    cpu.run_next_instruction();
+    cpu.run_next_instruction();
+
+    assert_eq!(cpu.interconnect.cycles(), 12);
+    assert_eq!(cpu.registers.reg(RegisterIndex(3)), 9);
+}
+
+#[test]
+fn data_bus_error() {
+    // lui $1, 0xbf80; lw $2, 0($1)
+    let mut cpu = test_cpu(&[0x3c01bf80, 0x8c220000]);
+
+    // NOP in the exception handler
+    cpu.interconnect.store::<Word>(0x80000080, 0);
+
+    cpu.registers.set_reg(RegisterIndex(2), 0x1234);
+    cpu.registers.swap_registers();
+
+    cpu.run_next_instruction();
+    cpu.run_next_instruction();
+
+    assert_eq!((cpu.registers.cause() >> 2) & 0x1f, Exception::DataBusError as u32);
+    assert_eq!(cpu.registers.epc(), 0x80001004);
+    assert_eq!(cpu.registers.pc(), 0x80000080);
+
+    cpu.run_next_instruction();
+
+    // The load has been cancelled
+    assert_eq!(cpu.registers.reg(RegisterIndex(2)), 0x1234);
+}
+
+#[test]
+fn instruction_bus_error() {
+    let mut cpu = test_cpu(&[]);
+
+    cpu.registers.set_pc(0xbf800000);
+    cpu.registers.set_next_pc(0xbf800004);
+
+    cpu.run_next_instruction();
+
+    assert_eq!((cpu.registers.cause() >> 2) & 0x1f, Exception::InstructionBusError as u32);
+    assert_eq!(cpu.registers.epc(), 0xbf800000);
+    assert_eq!(cpu.registers.pc(), 0x80000080);
+}
@@ -37,4 +37,26 @@ fn gnu(instruction: Instruction) -> String {
     let d = instruction.d();
 
     format!("ADD {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn add() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xfffffffe);
+    bench.set_reg(2, 3);
+
+    // add $3, $1, $2
+    assert!(bench.run(perform, 0x00221820).is_ok());
+    assert_eq!(bench.reg(3), 1);
+
+    bench.set_reg(1, 0x7fffffff);
+    bench.set_reg(2, 1);
+    bench.set_reg(3, 0);
+
+    // Signed overflow: the target register is left untouched
+    assert!(matches!(bench.run(perform, 0x00221820), Err(Exception::Overflow)));
+    assert_eq!(bench.reg(3), 0);
+}
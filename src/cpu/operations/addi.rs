@@ -31,4 +31,24 @@ fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("ADDI {}, {}, 0x{:04x}", t, s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn addi() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 5);
+
+    // addi $2, $1, -3
+    assert!(bench.run(perform, 0x2022fffd).is_ok());
+    assert_eq!(bench.reg(2), 2);
+
+    bench.set_reg(1, 0x7fffffff);
+    bench.set_reg(2, 0);
+
+    // addi $2, $1, 1
+    assert!(matches!(bench.run(perform, 0x20220001), Err(Exception::Overflow)));
+    assert_eq!(bench.reg(2), 0);
+}
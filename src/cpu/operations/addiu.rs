@@ -24,4 +24,21 @@ fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("ADDIU {}, {}, 0x{:04x}", t, s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn addiu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x7fffffff);
+
+    // addiu $2, $1, 1: never traps
+    assert!(bench.run(perform, 0x24220001).is_ok());
+    assert_eq!(bench.reg(2), 0x80000000);
+
+    // addiu $2, $2, -1
+    assert!(bench.run(perform, 0x2442ffff).is_ok());
+    assert_eq!(bench.reg(2), 0x7fffffff);
+}
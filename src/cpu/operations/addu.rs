@@ -24,4 +24,22 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("ADDU {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn addu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffffffff);
+    bench.set_reg(2, 2);
+
+    // addu $3, $1, $2
+    assert!(bench.run(perform, 0x00221821).is_ok());
+    assert_eq!(bench.reg(3), 1);
+
+    // addu $0, $1, $2: R0 stays 0
+    assert!(bench.run(perform, 0x00220021).is_ok());
+    assert_eq!(bench.reg(0), 0);
+}
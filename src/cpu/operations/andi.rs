@@ -26,4 +26,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("ANDI {}, {}, 0x{:04x}", t, s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn andi() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x12345678);
+
+    // andi $2, $1, 0xff00: the immediate is zero extended
+    assert!(bench.run(perform, 0x3022ff00).is_ok());
+    assert_eq!(bench.reg(2), 0x5600);
+}
@@ -10,7 +10,7 @@ use crate::instruction::Instruction;
 /// beq $15, $zero, +48
 ///
 /// We can reuse the code of BNE by changing the condition:
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_se();
     let s = instruction.s();
     let t = instruction.t();
@@ -18,6 +18,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     if registers.reg(s) == registers.reg(t) {
         registers.branch(i);
     }
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -27,4 +30,29 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("BEQ {}, {}, 0x{:04x}", s, t, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn beq() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 10);
+    bench.set_reg(2, 10);
+
+    // beq $1, $2, +16
+    assert!(bench.run(perform, 0x10220004).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc00010);
+    assert!(bench.delay.branch());
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 10);
+    bench.set_reg(2, 11);
+
+    // Not taken, but the next instruction is still in the delay slot
+    assert!(bench.run(perform, 0x10220004).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc00004);
+    assert!(bench.delay.branch());
+}
@@ -18,7 +18,7 @@ use crate::instruction::Instruction;
 ///
 /// So we have to be careful to cast to a signed integer before the comparison in
 /// our implementation:
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_se();
     let s = instruction.s();
 
@@ -27,6 +27,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     if v > 0 {
         registers.branch(i);
     }
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -35,4 +38,24 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("BGTZ {}, 0x{:04x}", s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn bgtz() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 1);
+
+    // bgtz $1, +8
+    assert!(bench.run(perform, 0x1c200002).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc00008);
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0);
+
+    assert!(bench.run(perform, 0x1c200002).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc00004);
+}
@@ -12,7 +12,7 @@ use crate::instruction::Instruction;
 ///
 /// It's the same thing as BGTZ with the opposite predicate:
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_se();
     let s = instruction.s();
 
@@ -21,6 +21,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     if v <= 0 {
         registers.branch(i);
     }
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -28,5 +31,24 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
     let i = instruction.imm_se();
 
-    format!("BLTZ {}, 0x{:04x}", s, i)
-}
\ No newline at end of file
+    format!("BLEZ {}, 0x{:04x}", s, i)
+}
+
+#[test]
+fn blez() {
+    use crate::cpu::operations::TestBench;
+
+    for &(v, taken) in [(0, true), (0x80000000, true), (1, false)].iter() {
+        let mut bench = TestBench::new();
+
+        bench.set_reg(1, v);
+
+        // blez $1, +8
+        assert!(bench.run(perform, 0x18200002).is_ok());
+
+        let target = if taken { 0xbfc00008 } else { 0xbfc00004 };
+
+        assert_eq!(bench.registers.next_pc(), target);
+        assert!(bench.delay.branch());
+    }
+}
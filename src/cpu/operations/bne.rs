@@ -5,7 +5,7 @@ use crate::cpu::operations::Operation;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_se();
     let s = instruction.s();
     let t = instruction.t();
@@ -13,6 +13,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     if registers.reg(s) != registers.reg(t) {
         registers.branch(i);
     }
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -22,4 +25,26 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("BNE {}, {}, 0x{:04x}", s, t, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn bne() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 10);
+    bench.set_reg(2, 11);
+
+    // bne $1, $2, -4
+    assert!(bench.run(perform, 0x1422ffff).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfbffffc);
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 10);
+    bench.set_reg(2, 10);
+
+    assert!(bench.run(perform, 0x1422ffff).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc00004);
+}
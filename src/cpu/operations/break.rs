@@ -0,0 +1,25 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Break: triggers a breakpoint exception. The 20 bit code in bits
+/// [25:6] is ignored by the CPU, it's up to the handler to fetch it.
+pub fn perform(_: &Instruction, _: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    Err(Exception::Break)
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    format!("BREAK 0x{:05x}", (instruction.0 >> 6) & 0xfffff)
+}
+
+#[test]
+fn break_exception() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    // break 0x1234
+    assert!(matches!(bench.run(perform, 0x00048d0d), Err(Exception::Break)));
+}
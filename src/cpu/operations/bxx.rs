@@ -30,7 +30,7 @@ use crate::instruction::{Instruction, RegisterIndex};
 
 /// Various branch instructions: BGEZ, BLTZ, BGEZAL, BLTZAL.
 /// Bits 16 And 20 are used to figure out which one to use.
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_se();
     let s = instruction.s();
 
@@ -54,15 +54,19 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
 
     // If linking is requested it occurs unconditionally, even if
     // the branch is not taken
-    if test != 0 {
-        if is_link {
-            let ra = registers.next_pc();
+    if is_link {
+        let ra = registers.next_pc();
+
+        // Store return address in R31
+        registers.set_reg(RegisterIndex(31), ra);
+    }
 
-            // Store return address in R31
-            registers.set_reg(RegisterIndex(31), ra);
-        }
+    if test != 0 {
         registers.branch(i);
     }
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -71,4 +75,38 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("BXX {}, 0x{:04x}", s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn bxx() {
+    use crate::cpu::operations::TestBench;
+
+    // (opcode, $1, taken, linked)
+    let tests = [
+        // bltz $1, +8
+        (0x04200002, 0xffffffff, true, false),
+        (0x04200002, 0, false, false),
+        // bgez $1, +8
+        (0x04210002, 0, true, false),
+        (0x04210002, 0xffffffff, false, false),
+        // bltzal $1, +8
+        (0x04300002, 0xffffffff, true, true),
+        // bgezal $1, +8: links even if the branch is not taken
+        (0x04310002, 0xffffffff, false, true),
+    ];
+
+    for &(op, v, taken, linked) in tests.iter() {
+        let mut bench = TestBench::new();
+
+        bench.set_reg(1, v);
+        bench.set_reg(31, 0);
+
+        assert!(bench.run(perform, op).is_ok());
+
+        let target = if taken { 0xbfc00008 } else { 0xbfc00004 };
+        let ra = if linked { 0xbfc00004 } else { 0 };
+
+        assert_eq!(bench.registers.next_pc(), target, "0x{:08x}", op);
+        assert_eq!(bench.reg(31), ra, "0x{:08x}", op);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Move Control From Coprocessor 2: load a GTE control register into
+/// a general purpose register (with load delay).
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    let v = registers.gte().control(cop_r);
+
+    delay.set(cpu_r, v);
+
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    format!("CFC2 {}, cop2r_{}", cpu_r, cop_r + 32)
+}
+
+#[test]
+fn cfc2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.registers.gte_mut().set_control(24, 0x1234_5678);
+
+    // cfc2 $1, $24 (OFX)
+    assert!(bench.run(perform, 0x4841c000).is_ok());
+    assert_eq!(bench.delay.register_index().0, 1);
+    assert_eq!(bench.delay.value(), 0x1234_5678);
+}
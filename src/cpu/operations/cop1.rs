@@ -0,0 +1,16 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Coprocessor 1 opcode. There's no floating point unit on the Playstation.
+pub fn perform(_: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    registers.set_coprocessor_error(1);
+
+    Err(Exception::CoprocessorError)
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    format!("COP1 0x{:07x}", instruction.imm25())
+}
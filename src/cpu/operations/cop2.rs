@@ -0,0 +1,44 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// GTE command. When bit 25 of a COP2 opcode is set the remaining 25
+/// bits encode a command for the Geometry Transformation Engine.
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    registers.gte_mut().command(instruction.imm25());
+
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    format!("COP2 0x{:07x}", instruction.imm25())
+}
+
+#[test]
+fn cop2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.registers.gte_mut().set_data(12, 0);
+    bench.registers.gte_mut().set_data(13, 10);
+    bench.registers.gte_mut().set_data(14, 10 << 16);
+
+    // cop2 0x1400006 (NCLIP)
+    assert!(bench.run(perform, 0x4b400006).is_ok());
+    assert_eq!(bench.registers.gte().data(24), 100);
+
+    // The GTE must be enabled in SR
+    bench.registers.set_sr(0);
+
+    assert!(matches!(bench.run(perform, 0x4b400006), Err(Exception::CoprocessorError)));
+}
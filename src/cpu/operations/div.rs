@@ -1,7 +1,7 @@
 use crate::cpu::delay::Delay;
 use crate::cpu::exception::Exception;
 use crate::cpu::interconnect::Interconnect;
-use crate::cpu::operations::Operation;
+use crate::cpu::operations::DIV_LATENCY;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
@@ -19,7 +19,7 @@ use crate::instruction::Instruction;
 /// due to the memory latency). While a simple ADD or SRA can be executed in a single CPU cycle, DIV
 /// can take as much as 36 cycles to get the result.
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let s = instruction.s();
     let t = instruction.t();
 
@@ -44,6 +44,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
         registers.set_hi((n % d) as u32);
         registers.set_lo((n / d) as u32);
     }
+
+    registers.set_hi_lo_ready(interconnect.cycles() + DIV_LATENCY);
+
     Ok(())
 }
 
@@ -52,4 +55,32 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("DIV {}, {}", s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn div() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    let tests = [
+        // (n, d, quotient, remainder)
+        (7, -2i32 as u32, -3i32 as u32, 1),
+        (-7i32 as u32, 2, -3i32 as u32, -1i32 as u32),
+        // Division by zero
+        (5, 0, 0xffffffff, 5),
+        (-5i32 as u32, 0, 1, -5i32 as u32),
+        // Overflow
+        (0x80000000, 0xffffffff, 0x80000000, 0),
+    ];
+
+    for &(n, d, q, r) in tests.iter() {
+        bench.set_reg(1, n);
+        bench.set_reg(2, d);
+
+        // div $1, $2
+        assert!(bench.run(perform, 0x0022001a).is_ok());
+        assert_eq!(bench.registers.lo(), q);
+        assert_eq!(bench.registers.hi(), r);
+    }
+}
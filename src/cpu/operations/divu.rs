@@ -1,7 +1,7 @@
 use crate::cpu::delay::Delay;
 use crate::cpu::exception::Exception;
 use crate::cpu::interconnect::Interconnect;
-use crate::cpu::operations::Operation;
+use crate::cpu::operations::DIV_LATENCY;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
@@ -12,7 +12,7 @@ use crate::instruction::Instruction;
 /// Since this version uses unsigned operands we only have one special case: the division by zero
 /// (the first line in table 7). Thus the implementation is slightly shorter than DIV:
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let s = instruction.s();
     let t = instruction.t();
 
@@ -27,6 +27,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
         registers.set_hi(n % d);
         registers.set_lo(n / d);
     }
+
+    registers.set_hi_lo_ready(interconnect.cycles() + DIV_LATENCY);
+
     Ok(())
 }
 
@@ -35,4 +38,26 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("DIVU {}, {}", s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn divu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xfffffff0);
+    bench.set_reg(2, 0x10);
+
+    // divu $1, $2
+    assert!(bench.run(perform, 0x0022001b).is_ok());
+    assert_eq!(bench.registers.lo(), 0x0fffffff);
+    assert_eq!(bench.registers.hi(), 0);
+
+    bench.set_reg(2, 0);
+
+    // Division by zero
+    assert!(bench.run(perform, 0x0022001b).is_ok());
+    assert_eq!(bench.registers.lo(), 0xffffffff);
+    assert_eq!(bench.registers.hi(), 0xfffffff0);
+}
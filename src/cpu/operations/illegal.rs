@@ -0,0 +1,15 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Any encoding which doesn't correspond to a valid R3000A instruction
+/// triggers a "reserved instruction" exception
+pub fn perform(_: &Instruction, _: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    Err(Exception::IllegalInstruction)
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    format!("ILLEGAL 0x{:08x}", instruction.0)
+}
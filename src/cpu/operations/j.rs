@@ -6,13 +6,28 @@ use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
 
-pub fn perform(instruction: &Instruction,  registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction,  registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let i = instruction.imm_jump();
 
     registers.set_next_pc((registers.pc() & 0xf0000000) | (i << 2));
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
 pub fn gnu(instruction: &Instruction) -> String {
     format!("J")
-}
\ No newline at end of file
+}
+
+#[test]
+fn j() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    // j 0xbfc01234
+    assert!(bench.run(perform, 0x0bf0048d).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc01234);
+    assert!(bench.delay.branch());
+}
@@ -28,9 +28,25 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
     // Replace with re-used jump instruction
 
     registers.set_reg(RegisterIndex(31), ra);
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
 pub fn gnu(instruction: &Instruction) -> String {
     format!("JAL")
-}
\ No newline at end of file
+}
+
+#[test]
+fn jal() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    // jal 0xbfc01234
+    assert!(bench.run(perform, 0x0ff0048d).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0xbfc01234);
+    assert_eq!(bench.reg(31), 0xbfc00004);
+    assert!(bench.delay.branch());
+}
@@ -12,7 +12,7 @@ use crate::instruction::Instruction;
 /// It's implemented like JR except that it also stores the return address in a general purpose
 /// register. Unlike JAL, JALR can store the return address in any general purpose register, not just
 /// $ra:
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let d = instruction.d();
     let s = instruction.s();
 
@@ -22,6 +22,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     registers.set_reg(d, ra);
 
     registers.set_next_pc(registers.reg(s));
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -31,4 +34,19 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("JALR {} {}", d, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn jalr() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80001000);
+
+    // jalr $2, $1
+    assert!(bench.run(perform, 0x00201009).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0x80001000);
+    assert_eq!(bench.reg(2), 0xbfc00004);
+    assert!(bench.delay.branch());
+}
@@ -12,10 +12,13 @@ use crate::instruction::Instruction;
 ///
 /// Since JAL stores the return address in $31 we can return from a subroutine
 /// by calling jr $ra which is exactly what the BIOS is doing here.
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
     let s = instruction.s();
 
     registers.set_next_pc(registers.reg(s));
+
+    delay.set_branch(true);
+
     Ok(())
 }
 
@@ -23,4 +26,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("JR {}", s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn jr() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(31, 0x80001000);
+
+    // jr $ra
+    assert!(bench.run(perform, 0x03e00008).is_ok());
+    assert_eq!(bench.registers.next_pc(), 0x80001000);
+    assert!(bench.delay.branch());
+}
@@ -32,4 +32,20 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("LBU {}, 0x{:08x}({})", t, i, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn lbu() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x12348056);
+    bench.set_reg(1, 0x80000100);
+
+    // lbu $2, 1($1)
+    assert!(bench.run(perform, 0x90220001).is_ok());
+    assert_eq!(bench.delay.register_index().0, 2);
+    assert_eq!(bench.delay.value(), 0x80);
+}
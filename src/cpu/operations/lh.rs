@@ -18,16 +18,12 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
     let t = instruction.t();
     let s = instruction.s();
 
-    if registers.sr() & 0x10000 != 0 { // Cache is isolated , ignore write
-        warn!("Ignoring load while cache is isolated");
-        return Ok(());
-    }
-
     let addr = registers.reg(s).wrapping_add(i);
 
     if addr % 2 == 0 {
-        let v = interconnect.load::<HalfWord>(addr);
-        load.set(t, v);
+        // Cast as i16 to force sign extension
+        let v = interconnect.load::<HalfWord>(addr) as i16;
+        load.set(t, v as u32);
         Ok(())
     } else {
         Err(Exception::LoadAddressError)
@@ -41,3 +37,22 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("lh {}, 0x{:04x}({})", t, i, s)
 }
+
+#[test]
+fn lh() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x8234_5678);
+    bench.set_reg(1, 0x80000100);
+
+    // lh $2, 2($1): sign extended
+    assert!(bench.run(perform, 0x84220002).is_ok());
+    assert_eq!(bench.delay.register_index().0, 2);
+    assert_eq!(bench.delay.value(), 0xffff8234);
+
+    // lh $2, 1($1): misaligned
+    assert!(matches!(bench.run(perform, 0x84220001), Err(Exception::LoadAddressError)));
+}
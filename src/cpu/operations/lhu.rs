@@ -36,3 +36,21 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("lhu {}, 0x{:08x}({})", t, i, s)
 }
+
+#[test]
+fn lhu() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x8234_5678);
+    bench.set_reg(1, 0x80000100);
+
+    // lhu $2, 2($1)
+    assert!(bench.run(perform, 0x94220002).is_ok());
+    assert_eq!(bench.delay.value(), 0x8234);
+
+    // lhu $2, 3($1): misaligned
+    assert!(matches!(bench.run(perform, 0x94220003), Err(Exception::LoadAddressError)));
+}
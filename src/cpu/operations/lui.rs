@@ -22,3 +22,14 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("LUI {}, 0x{:04x}", t, i)
 }
+
+#[test]
+fn lui() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    // lui $1, 0x1234
+    assert!(bench.run(perform, 0x3c011234).is_ok());
+    assert_eq!(bench.reg(1), 0x12340000);
+}
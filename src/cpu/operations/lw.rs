@@ -17,11 +17,6 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
     let t = instruction.t();
     let s = instruction.s();
 
-    if registers.sr() & 0x10000 != 0 { // Cache is isolated , ignore write
-        warn!("Ignoring load while cache is isolated");
-        return Ok(());
-    }
-
     let addr = registers.reg(s).wrapping_add(i);
 
     if addr % 4 == 0 {
@@ -40,3 +35,25 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("LW {}, 0x{:04x}({})", t, i, s)
 }
+
+#[test]
+fn lw() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0xcafebabe);
+    bench.set_reg(1, 0x80000104);
+    bench.set_reg(2, 0);
+
+    // lw $2, -4($1)
+    assert!(bench.run(perform, 0x8c22fffc).is_ok());
+    assert_eq!(bench.delay.register_index().0, 2);
+    assert_eq!(bench.delay.value(), 0xcafebabe);
+    // The target register isn't modified until the load delay slot
+    // is over
+    assert_eq!(bench.reg(2), 0);
+
+    // lw $2, 2($1): misaligned
+    assert!(matches!(bench.run(perform, 0x8c220002), Err(Exception::LoadAddressError)));
+}
@@ -0,0 +1,20 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Load Word to Coprocessor 1. There's no coprocessor 1 on the Playstation.
+pub fn perform(_: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    registers.set_coprocessor_error(1);
+
+    Err(Exception::CoprocessorError)
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let i = instruction.imm_se();
+    let cop_r = instruction.t().0;
+    let s = instruction.s();
+
+    format!("LWC1 cop1r_{}, 0x{:04x}({})", cop_r, i, s)
+}
@@ -0,0 +1,54 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+use crate::memory::Word;
+
+/// Load Word to Coprocessor 2: load a word from memory straight into
+/// a GTE data register
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    let i = instruction.imm_se();
+    let cop_r = instruction.t().0;
+    let s = instruction.s();
+
+    let addr = registers.reg(s).wrapping_add(i);
+
+    if addr.is_multiple_of(4) {
+        let v = interconnect.load::<Word>(addr);
+
+        registers.gte_mut().set_data(cop_r, v);
+        Ok(())
+    } else {
+        Err(Exception::LoadAddressError)
+    }
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let i = instruction.imm_se();
+    let cop_r = instruction.t().0;
+    let s = instruction.s();
+
+    format!("LWC2 cop2r_{}, 0x{:04x}({})", cop_r, i, s)
+}
+
+#[test]
+fn lwc2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.interconnect.store::<Word>(0x100, 0x0040_0020);
+    bench.set_reg(1, 0x80000100);
+
+    // lwc2 $12 (SXY0), 0($1)
+    assert!(bench.run(perform, 0xc82c0000).is_ok());
+    assert_eq!(bench.registers.gte().data(12), 0x0040_0020);
+}
@@ -46,4 +46,24 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("lwl {}, 0x{:04x}({})", t, i, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn lwl() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x44332211);
+    bench.set_reg(1, 0x80000100);
+
+    let expected = [0x11bbccdd, 0x2211ccdd, 0x332211dd, 0x44332211];
+
+    for (offset, &v) in expected.iter().enumerate() {
+        bench.set_reg(2, 0xaabbccdd);
+
+        // lwl $2, offset($1)
+        assert!(bench.run(perform, 0x88220000 + offset as u32).is_ok());
+        assert_eq!(bench.delay.value(), v);
+    }
+}
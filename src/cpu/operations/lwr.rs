@@ -47,3 +47,23 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("lwr {}, 0x{:04x}({})", t, i, s)
 }
+
+#[test]
+fn lwr() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x44332211);
+    bench.set_reg(1, 0x80000100);
+
+    let expected = [0x44332211, 0xaa443322, 0xaabb4433, 0xaabbcc44];
+
+    for (offset, &v) in expected.iter().enumerate() {
+        bench.set_reg(2, 0xaabbccdd);
+
+        // lwr $2, offset($1)
+        assert!(bench.run(perform, 0x98220000 + offset as u32).is_ok());
+        assert_eq!(bench.delay.value(), v);
+    }
+}
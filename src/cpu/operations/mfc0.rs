@@ -35,4 +35,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let cop_r = instruction.d().0;
 
     format!("MFC0 {}, cop0r_{}", cpu_r, cop_r)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mfc0() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(0x10000);
+
+    // mfc0 $2, $12
+    assert!(bench.run(perform, 0x40026000).is_ok());
+    assert_eq!(bench.delay.register_index().0, 2);
+    assert_eq!(bench.delay.value(), 0x10000);
+}
@@ -0,0 +1,47 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Move From Coprocessor 2: load a GTE data register into a general
+/// purpose register. Like MFC0 the value goes through the load delay
+/// slot.
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, delay: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    let v = registers.gte().data(cop_r);
+
+    delay.set(cpu_r, v);
+
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    format!("MFC2 {}, cop2r_{}", cpu_r, cop_r)
+}
+
+#[test]
+fn mfc2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.registers.gte_mut().set_data(30, 0x00f00000);
+
+    // mfc2 $1, $31 (LZCR)
+    assert!(bench.run(perform, 0x4801f800).is_ok());
+    assert_eq!(bench.delay.register_index().0, 1);
+    assert_eq!(bench.delay.value(), 8);
+}
@@ -9,11 +9,14 @@ use crate::instruction::Instruction;
 ///
 /// mfhi $25
 ///
-/// Like MFLO it should be able to stall if the operation has not yet finished
-/// but we'll implement that later:
+/// Like MFLO it stalls if the operation has not yet finished:
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let d = instruction.d();
+
+    // Stall until the multiplication or division is done
+    interconnect.stall_until(registers.hi_lo_ready());
+
     let hi = registers.hi();
 
     registers.set_reg(d, hi);
@@ -24,4 +27,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("MFHI {}", d)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mfhi() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_hi(0x1234);
+
+    // mfhi $3
+    assert!(bench.run(perform, 0x00001810).is_ok());
+    assert_eq!(bench.reg(3), 0x1234);
+}
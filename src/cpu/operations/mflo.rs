@@ -12,10 +12,14 @@ use crate::instruction::Instruction;
 /// mflo $3
 ///
 /// This instruction simply moves the contents of LO in a general purpose register. This instruction
-/// would also stall if the division was not yet done but we'll implement that later:
+/// also stalls if the division is not yet done:
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let d = instruction.d();
+
+    // Stall until the multiplication or division is done
+    interconnect.stall_until(registers.hi_lo_ready());
+
     let lo = registers.lo();
 
     registers.set_reg(d, lo);
@@ -26,4 +30,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("MFLO {}", d)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mflo() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_lo(0x5678);
+
+    // mflo $3
+    assert!(bench.run(perform, 0x00001812).is_ok());
+    assert_eq!(bench.reg(3), 0x5678);
+}
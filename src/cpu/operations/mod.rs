@@ -3,6 +3,7 @@ use crate::cpu::exception::Exception;
 use crate::cpu::interconnect::Interconnect;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
+use crate::scheduler::Cycles;
 
 mod addi;
 mod lw;
@@ -28,7 +29,7 @@ mod mfc0;
 mod and;
 mod add;
 mod bgtz;
-mod bltz;
+mod blez;
 mod lbu;
 mod jalr;
 mod bxx;
@@ -59,6 +60,25 @@ mod lwl;
 mod lwr;
 mod swl;
 mod swr;
+mod mfc2;
+mod cfc2;
+mod mtc2;
+mod ctc2;
+mod lwc2;
+mod swc2;
+mod cop2;
+mod illegal;
+mod cop1;
+mod cop3;
+mod lwc0;
+mod lwc1;
+mod lwc3;
+mod swc0;
+mod swc1;
+mod swc3;
+mod mult;
+mod r#break;
+mod xori;
 
 pub enum Operation {
     Add(Instruction),
@@ -68,12 +88,19 @@ pub enum Operation {
     And(Instruction),
     Andi(Instruction),
     Beq(Instruction),
-    Bqtz(Instruction),
-    Bltz(Instruction),
+    Bgtz(Instruction),
+    Blez(Instruction),
     Bne(Instruction),
+    Break(Instruction),
     Bxx(Instruction),
+    Cfc2(Instruction),
+    Cop1(Instruction),
+    Cop2(Instruction),
+    Cop3(Instruction),
+    Ctc2(Instruction),
     Div(Instruction),
     Divu(Instruction),
+    Illegal(Instruction),
     J(Instruction),
     Jal(Instruction),
     Jalr(Instruction),
@@ -84,14 +111,21 @@ pub enum Operation {
     Lhu(Instruction),
     Lui(Instruction),
     Lw(Instruction),
+    Lwc0(Instruction),
+    Lwc1(Instruction),
+    Lwc2(Instruction),
+    Lwc3(Instruction),
     Lwl(Instruction),
     Lwr(Instruction),
     Mfc0(Instruction),
+    Mfc2(Instruction),
     Mfhi(Instruction),
     Mflo(Instruction),
     Mtc0(Instruction),
+    Mtc2(Instruction),
     Mthi(Instruction),
     Mtlo(Instruction),
+    Mult(Instruction),
     Multu(Instruction),
     Nor(Instruction),
     Or(Instruction),
@@ -112,10 +146,15 @@ pub enum Operation {
     Sub(Instruction),
     Subu(Instruction),
     Sw(Instruction),
+    Swc0(Instruction),
+    Swc1(Instruction),
+    Swc2(Instruction),
+    Swc3(Instruction),
     Swl(Instruction),
     Swr(Instruction),
     Syscall(Instruction),
     Xor(Instruction),
+    Xori(Instruction),
 }
 
 impl Operation {
@@ -134,12 +173,19 @@ impl Operation {
             Operation::And(instruction) => and::perform(instruction, registers, interconnect, delay),
             Operation::Andi(instruction) => andi::perform(instruction, registers, interconnect, delay),
             Operation::Beq(instruction) => beq::perform(instruction, registers, interconnect, delay),
-            Operation::Bqtz(instruction) => bgtz::perform(instruction, registers, interconnect, delay),
-            Operation::Bltz(instruction) => bltz::perform(instruction, registers, interconnect, delay),
+            Operation::Bgtz(instruction) => bgtz::perform(instruction, registers, interconnect, delay),
+            Operation::Blez(instruction) => blez::perform(instruction, registers, interconnect, delay),
             Operation::Bne(instruction) => bne::perform(instruction, registers, interconnect, delay),
+            Operation::Break(instruction) => r#break::perform(instruction, registers, interconnect, delay),
             Operation::Bxx(instruction) => bxx::perform(instruction, registers, interconnect, delay),
+            Operation::Cfc2(instruction) => cfc2::perform(instruction, registers, interconnect, delay),
+            Operation::Cop1(instruction) => cop1::perform(instruction, registers, interconnect, delay),
+            Operation::Cop2(instruction) => cop2::perform(instruction, registers, interconnect, delay),
+            Operation::Cop3(instruction) => cop3::perform(instruction, registers, interconnect, delay),
+            Operation::Ctc2(instruction) => ctc2::perform(instruction, registers, interconnect, delay),
             Operation::Div(instruction) => div::perform(instruction, registers, interconnect, delay),
             Operation::Divu(instruction) => divu::perform(instruction, registers, interconnect, delay),
+            Operation::Illegal(instruction) => illegal::perform(instruction, registers, interconnect, delay),
             Operation::J(instruction) => j::perform(instruction, registers, interconnect, delay),
             Operation::Jal(instruction) => jal::perform(instruction, registers, interconnect, delay),
             Operation::Jalr(instruction) => jalr::perform(instruction, registers, interconnect, delay),
@@ -150,14 +196,21 @@ impl Operation {
             Operation::Lhu(instruction) => lhu::perform(instruction, registers, interconnect, delay),
             Operation::Lui(instruction) => lui::perform(instruction, registers, interconnect, delay),
             Operation::Lw(instruction) => lw::perform(instruction, registers, interconnect, delay),
+            Operation::Lwc0(instruction) => lwc0::perform(instruction, registers, interconnect, delay),
+            Operation::Lwc1(instruction) => lwc1::perform(instruction, registers, interconnect, delay),
+            Operation::Lwc2(instruction) => lwc2::perform(instruction, registers, interconnect, delay),
+            Operation::Lwc3(instruction) => lwc3::perform(instruction, registers, interconnect, delay),
             Operation::Lwl(instruction) => lwl::perform(instruction, registers, interconnect, delay),
             Operation::Lwr(instruction) => lwr::perform(instruction, registers, interconnect, delay),
             Operation::Mfc0(instruction) => mfc0::perform(instruction, registers, interconnect, delay),
+            Operation::Mfc2(instruction) => mfc2::perform(instruction, registers, interconnect, delay),
             Operation::Mfhi(instruction) => mfhi::perform(instruction, registers, interconnect, delay),
             Operation::Mflo(instruction) => mflo::perform(instruction, registers, interconnect, delay),
             Operation::Mtc0(instruction) => mtc0::perform(instruction, registers, interconnect, delay),
+            Operation::Mtc2(instruction) => mtc2::perform(instruction, registers, interconnect, delay),
             Operation::Mthi(instruction) => mthi::perform(instruction, registers, interconnect, delay),
             Operation::Mtlo(instruction) => mtlo::perform(instruction, registers, interconnect, delay),
+            Operation::Mult(instruction) => mult::perform(instruction, registers, interconnect, delay),
             Operation::Multu(instruction) => multu::perform(instruction, registers, interconnect, delay),
             Operation::Nor(instruction) => nor::perform(instruction, registers, interconnect, delay),
             Operation::Or(instruction) => or::perform(instruction, registers, interconnect, delay),
@@ -178,10 +231,74 @@ impl Operation {
             Operation::Sub(instruction) => sub::perform(instruction, registers, interconnect, delay),
             Operation::Subu(instruction) => subu::perform(instruction, registers, interconnect, delay),
             Operation::Sw(instruction) => sw::perform(instruction, registers, interconnect, delay),
+            Operation::Swc0(instruction) => swc0::perform(instruction, registers, interconnect, delay),
+            Operation::Swc1(instruction) => swc1::perform(instruction, registers, interconnect, delay),
+            Operation::Swc2(instruction) => swc2::perform(instruction, registers, interconnect, delay),
+            Operation::Swc3(instruction) => swc3::perform(instruction, registers, interconnect, delay),
             Operation::Swl(instruction) => swl::perform(instruction, registers, interconnect, delay),
             Operation::Swr(instruction) => swr::perform(instruction, registers, interconnect, delay),
             Operation::Syscall(instruction) => syscall::perform(instruction, registers, interconnect, delay),
             Operation::Xor(instruction) => xor::perform(instruction, registers, interconnect, delay),
+            Operation::Xori(instruction) => xori::perform(instruction, registers, interconnect, delay),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Number of cycles before the result of a division is available in
+/// HI And LO
+pub const DIV_LATENCY: Cycles = 36;
+
+/// Number of cycles before the result of a multiplication is
+/// available in HI And LO. The multiplier terminates early when the
+/// magnitude of the first operand is small.
+pub fn mult_latency(magnitude: u32) -> Cycles {
+    if magnitude < 0x800 {
+        6
+    } else if magnitude < 0x100000 {
+        9
+    } else {
+        13
+    }
+}
+
+/// Execution environment for the instruction unit tests
+#[cfg(test)]
+pub struct TestBench {
+    pub registers: Registers,
+    pub interconnect: Interconnect,
+    pub delay: Delay,
+}
+
+#[cfg(test)]
+impl TestBench {
+    pub fn new() -> TestBench {
+        TestBench {
+            registers: Registers::new(),
+            interconnect: crate::cpu::interconnect::test_interconnect(),
+            delay: Delay::new(),
+        }
+    }
+
+    /// Set general purpose register `index` as seen by the next
+    /// instruction
+    pub fn set_reg(&mut self, index: u32, val: u32) {
+        self.registers.set_reg(crate::instruction::RegisterIndex(index), val);
+        self.registers.swap_registers();
+    }
+
+    pub fn reg(&self, index: u32) -> u32 {
+        self.registers.reg(crate::instruction::RegisterIndex(index))
+    }
+
+    /// Execute `op` through the `perform` function of an operation
+    /// module And commit the output registers
+    pub fn run(&mut self,
+               perform: fn(&Instruction, &mut Registers, &mut Interconnect, &mut Delay) -> Result<(), Exception>,
+               op: u32) -> Result<(), Exception> {
+        let res = perform(&Instruction(op), &mut self.registers, &mut self.interconnect, &mut self.delay);
+
+        self.registers.swap_registers();
+
+        res
+    }
+}
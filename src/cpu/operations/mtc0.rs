@@ -6,7 +6,7 @@ use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
 /// Coprocessor 0 opcode
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let cpu_r = instruction.t();
     let cop_r = instruction.d().0;
 
@@ -17,11 +17,17 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
             if v != 0 {
                 panic!("Unhandled write to cop0r{}: 0x{:08x}", cop_r, v)
             },
-        12 => registers.set_sr(v),
-        13 => // Cause register
-            if v != 0 {
-                panic!("Unhandled write to CAUSE register.")
-            },
+        12 => {
+            registers.set_sr(v);
+
+            // Bit 16: Isolate Cache
+            interconnect.set_cache_isolated(v & 0x10000 != 0);
+        }
+        13 => { // Cause register: only the software interrupt bits are writable
+            let cause = registers.cause() & !0x300;
+
+            registers.set_cause(cause | (v & 0x300));
+        }
         _ => panic!("Unhandled cop0 register {}", cop_r),
     }
     Ok(())
@@ -32,4 +38,28 @@ pub fn gnu(instruction: &Instruction) -> String {
     let cop_r = instruction.d().0;
 
     format!("MTC0 {}, cop0r_{}", cpu_r, cop_r)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mtc0() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    let ram = bench.interconnect.load::<Word>(0x100);
+
+    bench.set_reg(2, 0x10000);
+
+    // mtc0 $2, $12
+    assert!(bench.run(perform, 0x40826000).is_ok());
+    assert_eq!(bench.registers.sr(), 0x10000);
+
+    // The cache is isolated, the store doesn't reach the RAM
+    bench.interconnect.store::<Word>(0x100, 0x1234);
+
+    bench.set_reg(2, 0);
+
+    assert!(bench.run(perform, 0x40826000).is_ok());
+    assert_eq!(bench.interconnect.load::<Word>(0x100), ram);
+}
@@ -0,0 +1,45 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Move To Coprocessor 2: store a general purpose register into a GTE
+/// data register
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    let v = registers.reg(cpu_r);
+
+    registers.gte_mut().set_data(cop_r, v);
+
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let cpu_r = instruction.t();
+    let cop_r = instruction.d().0;
+
+    format!("MTC2 {}, cop2r_{}", cpu_r, cop_r)
+}
+
+#[test]
+fn mtc2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.set_reg(1, 0x1234);
+
+    // mtc2 $1, $9 (IR1)
+    assert!(bench.run(perform, 0x48814800).is_ok());
+    assert_eq!(bench.registers.gte().data(9), 0x1234);
+}
@@ -13,7 +13,7 @@ use crate::instruction::Instruction;
 pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let s = instruction.s();
 
-    registers.set_lo(registers.reg(s));
+    registers.set_hi(registers.reg(s));
     Ok(())
 }
 
@@ -21,4 +21,19 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("mtlo {}", s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mthi() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x1234);
+    bench.registers.set_lo(0);
+
+    // mthi $1
+    assert!(bench.run(perform, 0x00200011).is_ok());
+    assert_eq!(bench.registers.hi(), 0x1234);
+    assert_eq!(bench.registers.lo(), 0);
+}
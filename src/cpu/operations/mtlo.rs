@@ -23,4 +23,19 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("mtlo {}", s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn mtlo() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x5678);
+    bench.registers.set_hi(0);
+
+    // mtlo $1
+    assert!(bench.run(perform, 0x00200013).is_ok());
+    assert_eq!(bench.registers.lo(), 0x5678);
+    assert_eq!(bench.registers.hi(), 0);
+}
@@ -0,0 +1,62 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::operations::mult_latency;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Signed version of MULTU: "multiply" (MULT)
+///
+/// mult $4, $5
+///
+/// The operands are sign extended to 64bits before the
+/// multiplication, the result is stored across HI And LO:
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    let s = instruction.s();
+    let t = instruction.t();
+
+    let a = (registers.reg(s) as i32) as i64;
+    let b = (registers.reg(t) as i32) as i64;
+
+    let v = (a * b) as u64;
+
+    registers.set_hi((v >> 32) as u32);
+    registers.set_lo(v as u32);
+
+    // Negative operands are handled like their one's complement
+    let s = registers.reg(s);
+    let magnitude = if (s as i32) < 0 { !s } else { s };
+
+    registers.set_hi_lo_ready(interconnect.cycles() + mult_latency(magnitude));
+
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let s = instruction.s();
+    let t = instruction.t();
+
+    format!("MULT {}, {}", s, t)
+}
+
+#[test]
+fn mult() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffffffff);
+    bench.set_reg(2, 2);
+
+    // mult $1, $2: -1 * 2
+    assert!(bench.run(perform, 0x00220018).is_ok());
+    assert_eq!(bench.registers.hi(), 0xffffffff);
+    assert_eq!(bench.registers.lo(), 0xfffffffe);
+
+    bench.set_reg(1, 0x80000000);
+    bench.set_reg(2, 0x80000000);
+
+    assert!(bench.run(perform, 0x00220018).is_ok());
+    assert_eq!(bench.registers.hi(), 0x40000000);
+    assert_eq!(bench.registers.lo(), 0);
+}
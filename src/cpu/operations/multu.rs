@@ -1,7 +1,7 @@
 use crate::cpu::delay::Delay;
 use crate::cpu::exception::Exception;
 use crate::cpu::interconnect::Interconnect;
-use crate::cpu::operations::Operation;
+use crate::cpu::operations::mult_latency;
 use crate::cpu::registers::Registers;
 use crate::instruction::Instruction;
 
@@ -12,7 +12,7 @@ use crate::instruction::Instruction;
 /// It’s our first multiplication opcode. The CPU does the multiplication using
 /// 64bit arithmetics And store the result across the HI And LO registers:
 
-pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
     let s = instruction.s();
     let t = instruction.t();
 
@@ -23,6 +23,9 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
 
     registers.set_hi((v >> 32) as u32);
     registers.set_lo(v as u32);
+
+    registers.set_hi_lo_ready(interconnect.cycles() + mult_latency(a as u32));
+
     Ok(())
 }
 
@@ -31,4 +34,19 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("multut {}, {}", s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn multu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffffffff);
+    bench.set_reg(2, 2);
+
+    // multu $1, $2
+    assert!(bench.run(perform, 0x00220019).is_ok());
+    assert_eq!(bench.registers.hi(), 1);
+    assert_eq!(bench.registers.lo(), 0xfffffffe);
+}
@@ -27,4 +27,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("nor {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn nor() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xf0f0f0f0);
+    bench.set_reg(2, 0x0000ffff);
+
+    // nor $3, $1, $2
+    assert!(bench.run(perform, 0x00221827).is_ok());
+    assert_eq!(bench.reg(3), 0x0f0f0000);
+}
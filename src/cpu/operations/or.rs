@@ -23,4 +23,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("OR {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn or() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xf0f00000);
+    bench.set_reg(2, 0x0000ffff);
+
+    // or $3, $1, $2
+    assert!(bench.run(perform, 0x00221825).is_ok());
+    assert_eq!(bench.reg(3), 0xf0f0ffff);
+}
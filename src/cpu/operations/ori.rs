@@ -23,3 +23,16 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("ORI {}, {}, 0x{:04x}", t, s, i)
 }
+
+#[test]
+fn ori() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x12340000);
+
+    // ori $2, $1, 0x8000: the immediate is zero extended
+    assert!(bench.run(perform, 0x34228000).is_ok());
+    assert_eq!(bench.reg(2), 0x12348000);
+}
@@ -24,13 +24,13 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     // implement them. Still, let's make sure we're not running
     // buggy code .
     if instruction.0 & 0x3f != 0b010000 {
-        panic!("Invalid cop0 instruction: {}", instruction.0);
+        return Err(Exception::IllegalInstruction);
     }
 
     let mode = registers.sr() & 0x3f;
     let mut sr = registers.sr();
 
-    sr &= !0x3f;
+    sr &= !0xf;
     sr |= mode >> 2;
 
     registers.set_sr(sr);
@@ -38,6 +38,19 @@ pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Int
     Ok(())
 }
 
-pub fn gnu(instruction: &Instruction) -> String {
+pub fn gnu(_: &Instruction) -> String {
     format!("rfe")
-}
\ No newline at end of file
+}
+
+#[test]
+fn rfe() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(0x123456c);
+
+    // rfe: pops the interrupt enable/user mode stack
+    assert!(bench.run(perform, 0x42000010).is_ok());
+    assert_eq!(bench.registers.sr(), 0x123456b);
+}
@@ -9,12 +9,6 @@ use crate::memory::Byte;
 /// Store Word
 
 pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
-    if registers.sr() & 0x10000 != 0 {
-        // Cache is isolated , ignore write
-        warn!("ignoring store while cache is isolated");
-        return Ok(());
-    }
-
     let i = instruction.imm_se();
     let t = instruction.t();
     let s = instruction.s();
@@ -33,4 +27,20 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("SB {}, 0x{:04x}({})", t, i, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sb() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x44332211);
+    bench.set_reg(1, 0x80000100);
+    bench.set_reg(2, 0xaabbccdd);
+
+    // sb $2, 2($1)
+    assert!(bench.run(perform, 0xa0220002).is_ok());
+    assert_eq!(bench.interconnect.load::<Word>(0x100), 0x44dd2211);
+}
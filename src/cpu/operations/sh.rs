@@ -17,12 +17,6 @@ use crate::memory::HalfWord;
 /// except we truncate the register to 16bits And we'll have to
 /// implement a new store16 method on our interconnect12:
 pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
-    if registers.sr() & 0x10000 != 0 {
-        // Cache is isolated , ignore write
-        warn!("Ignoring store while cache is isolated");
-        return Ok(());
-    }
-
     let i = instruction.imm_se();
     let t = instruction.t();
     let s = instruction.s();
@@ -46,3 +40,22 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("SH {}, 0x{:04x}({})", t, i, s)
 }
+
+#[test]
+fn sh() {
+    use crate::cpu::operations::TestBench;
+    use crate::memory::Word;
+
+    let mut bench = TestBench::new();
+
+    bench.interconnect.store::<Word>(0x100, 0x44332211);
+    bench.set_reg(1, 0x80000100);
+    bench.set_reg(2, 0xaabbccdd);
+
+    // sh $2, 2($1)
+    assert!(bench.run(perform, 0xa4220002).is_ok());
+    assert_eq!(bench.interconnect.load::<Word>(0x100), 0xccdd2211);
+
+    // sh $2, 1($1): misaligned
+    assert!(matches!(bench.run(perform, 0xa4220001), Err(Exception::StoreAddressError)));
+}
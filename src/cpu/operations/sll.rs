@@ -24,4 +24,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("SLL {}, {}, {}", d, t, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sll() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000001);
+
+    // sll $2, $1, 4
+    assert!(bench.run(perform, 0x00011100).is_ok());
+    assert_eq!(bench.reg(2), 0x00000010);
+}
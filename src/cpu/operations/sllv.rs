@@ -31,4 +31,19 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("sllv {}, {}, {}", d, t, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sllv() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000001);
+    // Only the 5 LSBs of the shift amount are used
+    bench.set_reg(2, 0x24);
+
+    // sllv $3, $1, $2
+    assert!(bench.run(perform, 0x00411804).is_ok());
+    assert_eq!(bench.reg(3), 0x00000010);
+}
@@ -31,4 +31,22 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("SLT {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn slt() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffffffff);
+    bench.set_reg(2, 1);
+
+    // slt $3, $1, $2: -1 < 1
+    assert!(bench.run(perform, 0x0022182a).is_ok());
+    assert_eq!(bench.reg(3), 1);
+
+    // slt $3, $2, $1
+    assert!(bench.run(perform, 0x0041182a).is_ok());
+    assert_eq!(bench.reg(3), 0);
+}
@@ -30,4 +30,21 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("SLTI {}, {}, 0x{:04x}", t, s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn slti() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xfffffffe);
+
+    // slti $2, $1, -1
+    assert!(bench.run(perform, 0x2822ffff).is_ok());
+    assert_eq!(bench.reg(2), 1);
+
+    // slti $2, $1, -2
+    assert!(bench.run(perform, 0x2822fffe).is_ok());
+    assert_eq!(bench.reg(2), 0);
+}
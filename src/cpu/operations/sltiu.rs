@@ -29,4 +29,22 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("SLTIU {}, {}, 0x{:04x}", t, s, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sltiu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xfffffffe);
+
+    // sltiu $2, $1, -1: the immediate is sign extended then compared
+    // unsigned
+    assert!(bench.run(perform, 0x2c22ffff).is_ok());
+    assert_eq!(bench.reg(2), 1);
+
+    // sltiu $2, $1, 1
+    assert!(bench.run(perform, 0x2c220001).is_ok());
+    assert_eq!(bench.reg(2), 0);
+}
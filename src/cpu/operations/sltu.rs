@@ -32,4 +32,22 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("SLTU {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sltu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffffffff);
+    bench.set_reg(2, 1);
+
+    // sltu $3, $1, $2
+    assert!(bench.run(perform, 0x0022182b).is_ok());
+    assert_eq!(bench.reg(3), 0);
+
+    // sltu $3, $2, $1
+    assert!(bench.run(perform, 0x0041182b).is_ok());
+    assert_eq!(bench.reg(3), 1);
+}
@@ -33,4 +33,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("SRA {}, {}, {}", d, t, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sra() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000010);
+
+    // sra $2, $1, 4
+    assert!(bench.run(perform, 0x00011103).is_ok());
+    assert_eq!(bench.reg(2), 0xf8000001);
+}
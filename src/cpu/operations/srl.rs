@@ -32,4 +32,17 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("SRL {}, {}, {}", d, t, i)
-}
\ No newline at end of file
+}
+
+#[test]
+fn srl() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000010);
+
+    // srl $2, $1, 4
+    assert!(bench.run(perform, 0x00011102).is_ok());
+    assert_eq!(bench.reg(2), 0x08000001);
+}
@@ -29,4 +29,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("srlv {}, {}, {}", d, t, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn srlv() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000010);
+    bench.set_reg(2, 0x24);
+
+    // srlv $3, $1, $2
+    assert!(bench.run(perform, 0x00411806).is_ok());
+    assert_eq!(bench.reg(3), 0x08000001);
+}
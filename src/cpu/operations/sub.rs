@@ -34,4 +34,25 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("sub {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sub() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 1);
+    bench.set_reg(2, 3);
+
+    // sub $3, $1, $2
+    assert!(bench.run(perform, 0x00221822).is_ok());
+    assert_eq!(bench.reg(3), 0xfffffffe);
+
+    bench.set_reg(1, 0x80000000);
+    bench.set_reg(2, 1);
+    bench.set_reg(3, 0);
+
+    assert!(matches!(bench.run(perform, 0x00221822), Err(Exception::Overflow)));
+    assert_eq!(bench.reg(3), 0);
+}
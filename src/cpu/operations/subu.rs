@@ -29,4 +29,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let d = instruction.d();
 
     format!("SUBU {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn subu() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000000);
+    bench.set_reg(2, 1);
+
+    // subu $3, $1, $2: never traps
+    assert!(bench.run(perform, 0x00221823).is_ok());
+    assert_eq!(bench.reg(3), 0x7fffffff);
+}
@@ -12,12 +12,6 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
     let t = instruction.t();
     let s = instruction.s();
 
-    if registers.sr() & 0x10000 != 0 {
-        // Cache is isolated , ignore write
-        warn!("ignoring store while cache is isolated");
-        return Ok(());
-    }
-
     let addr = registers.reg(s).wrapping_add(i);
 
     if addr % 4 == 0 {
@@ -36,4 +30,29 @@ pub fn gnu(instruction: &Instruction) -> String {
     let s = instruction.s();
 
     format!("SW {}, 0x{:04x}({})", t, i, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn sw() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000100);
+    bench.set_reg(2, 0xaabbccdd);
+
+    // sw $2, 4($1)
+    assert!(bench.run(perform, 0xac220004).is_ok());
+    assert_eq!(bench.interconnect.load::<Word>(0x104), 0xaabbccdd);
+
+    // sw $2, 1($1): misaligned
+    assert!(matches!(bench.run(perform, 0xac220001), Err(Exception::StoreAddressError)));
+
+    // Stores don't reach the memory while the cache is isolated
+    bench.interconnect.set_cache_isolated(true);
+    bench.set_reg(2, 0);
+
+    assert!(bench.run(perform, 0xac220004).is_ok());
+    bench.interconnect.set_cache_isolated(false);
+    assert_eq!(bench.interconnect.load::<Word>(0x104), 0xaabbccdd);
+}
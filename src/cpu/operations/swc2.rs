@@ -0,0 +1,53 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+use crate::memory::Word;
+
+/// Store Word from Coprocessor 2: store a GTE data register to memory
+pub fn perform(instruction: &Instruction, registers: &mut Registers, interconnect: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    if registers.sr() & (1 << 30) == 0 {
+        registers.set_coprocessor_error(2);
+
+        return Err(Exception::CoprocessorError);
+    }
+
+    let i = instruction.imm_se();
+    let cop_r = instruction.t().0;
+    let s = instruction.s();
+
+    let addr = registers.reg(s).wrapping_add(i);
+
+    if addr.is_multiple_of(4) {
+        let v = registers.gte().data(cop_r);
+
+        interconnect.store::<Word>(addr, v);
+        Ok(())
+    } else {
+        Err(Exception::StoreAddressError)
+    }
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let i = instruction.imm_se();
+    let cop_r = instruction.t().0;
+    let s = instruction.s();
+
+    format!("SWC2 cop2r_{}, 0x{:04x}({})", cop_r, i, s)
+}
+
+#[test]
+fn swc2() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.registers.set_sr(1 << 30);
+    bench.registers.gte_mut().set_data(7, 0xbeef);
+    bench.set_reg(1, 0x80000100);
+
+    // swc2 $7 (OTZ), 4($1)
+    assert!(bench.run(perform, 0xe8270004).is_ok());
+    assert_eq!(bench.interconnect.load::<Word>(0x104), 0xbeef);
+}
@@ -34,7 +34,7 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
         _ => unreachable!(),
     };
 
-    interconnect.store::<Word>(addr, mem);
+    interconnect.store::<Word>(aligned_addr, mem);
 
     Ok(())
 }
@@ -46,3 +46,23 @@ pub fn gnu(instruction: &Instruction) -> String {
 
     format!("swl {}, 0x{:04x}({})", t, i, s)
 }
+
+#[test]
+fn swl() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000100);
+    bench.set_reg(2, 0xaabbccdd);
+
+    let expected = [0x443322aa, 0x4433aabb, 0x44aabbcc, 0xaabbccdd];
+
+    for (offset, &v) in expected.iter().enumerate() {
+        bench.interconnect.store::<Word>(0x100, 0x44332211);
+
+        // swl $2, offset($1)
+        assert!(bench.run(perform, 0xa8220000 + offset as u32).is_ok());
+        assert_eq!(bench.interconnect.load::<Word>(0x100), v);
+    }
+}
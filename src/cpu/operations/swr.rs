@@ -32,7 +32,7 @@ pub fn perform(instruction: &Instruction,  registers: &mut Registers, interconne
         _ => unreachable!(),
     };
 
-    interconnect.store::<Word>(addr, mem);
+    interconnect.store::<Word>(aligned_addr, mem);
 
     Ok(())
 }
@@ -43,4 +43,24 @@ pub fn gnu(instruction: &Instruction) -> String {
     let i = instruction.imm_se();
 
     format!("swr {}, 0x{:04x}({})", t, i, s)
-}
\ No newline at end of file
+}
+
+#[test]
+fn swr() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0x80000100);
+    bench.set_reg(2, 0xaabbccdd);
+
+    let expected = [0xaabbccdd, 0xbbccdd11, 0xccdd2211, 0xdd332211];
+
+    for (offset, &v) in expected.iter().enumerate() {
+        bench.interconnect.store::<Word>(0x100, 0x44332211);
+
+        // swr $2, offset($1)
+        assert!(bench.run(perform, 0xb8220000 + offset as u32).is_ok());
+        assert_eq!(bench.interconnect.load::<Word>(0x100), v);
+    }
+}
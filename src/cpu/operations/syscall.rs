@@ -13,4 +13,14 @@ pub fn perform(instruction: &Instruction,  _: &mut Registers, _: &mut Interconne
 
 pub fn gnu(instruction: &Instruction) -> String {
     format!("SYSCALL")
-}
\ No newline at end of file
+}
+
+#[test]
+fn syscall_exception() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    // syscall
+    assert!(matches!(bench.run(perform, 0x0000000c), Err(Exception::SysCall)));
+}
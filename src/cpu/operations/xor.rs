@@ -28,4 +28,18 @@ pub fn gnu(instruction: &Instruction) -> String {
     let t = instruction.t();
 
     format!("xor {}, {}, {}", d, s, t)
-}
\ No newline at end of file
+}
+
+#[test]
+fn xor() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xff00ff00);
+    bench.set_reg(2, 0x0ff00ff0);
+
+    // xor $3, $1, $2
+    assert!(bench.run(perform, 0x00221826).is_ok());
+    assert_eq!(bench.reg(3), 0xf0f0f0f0);
+}
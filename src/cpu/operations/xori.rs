@@ -0,0 +1,42 @@
+use crate::cpu::delay::Delay;
+use crate::cpu::exception::Exception;
+use crate::cpu::interconnect::Interconnect;
+use crate::cpu::registers::Registers;
+use crate::instruction::Instruction;
+
+/// Bitwise "exclusive or immediate" (XORI):
+///
+/// xori $1, $2, 0xff
+///
+/// Like ANDI And ORI the immediate value is zero extended:
+pub fn perform(instruction: &Instruction, registers: &mut Registers, _: &mut Interconnect, _: &mut Delay) -> Result<(), Exception> {
+    let i = instruction.imm();
+    let t = instruction.t();
+    let s = instruction.s();
+
+    let v = registers.reg(s) ^ i;
+
+    registers.set_reg(t, v);
+    Ok(())
+}
+
+pub fn gnu(instruction: &Instruction) -> String {
+    let i = instruction.imm();
+    let t = instruction.t();
+    let s = instruction.s();
+
+    format!("XORI {}, {}, 0x{:04x}", t, s, i)
+}
+
+#[test]
+fn xori() {
+    use crate::cpu::operations::TestBench;
+
+    let mut bench = TestBench::new();
+
+    bench.set_reg(1, 0xffff00ff);
+
+    // xori $2, $1, 0xffff: the immediate is zero extended
+    assert!(bench.run(perform, 0x3822ffff).is_ok());
+    assert_eq!(bench.reg(2), 0xffffff00);
+}
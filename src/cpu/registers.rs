@@ -1,4 +1,6 @@
+use crate::cpu::gte::Gte;
 use crate::instruction::RegisterIndex;
+use crate::scheduler::Cycles;
 
 pub struct Registers {
     /// The program counter register
@@ -35,6 +37,13 @@ pub struct Registers {
 
     /// For a division LO will contain the quotient
     lo: u32,
+
+    /// Date at which the result of the last multiplication or
+    /// division will be available in HI And LO
+    hi_lo_ready: Cycles,
+
+    /// Coprocessor 2: Geometry Transformation Engine
+    gte: Gte,
 }
 
 impl Registers {
@@ -56,6 +65,8 @@ impl Registers {
             epc: 0,
             hi: 0xdeadbeef,
             lo: 0xdeadbeef,
+            hi_lo_ready: 0,
+            gte: Gte::new(),
         }
     }
 
@@ -115,6 +126,14 @@ impl Registers {
         self.lo = lo
     }
 
+    pub fn hi_lo_ready(&self) -> Cycles {
+        self.hi_lo_ready
+    }
+
+    pub fn set_hi_lo_ready(&mut self, date: Cycles) {
+        self.hi_lo_ready = date
+    }
+
     pub fn cause(&self) -> u32 {
         self.cause
     }
@@ -123,6 +142,13 @@ impl Registers {
         self.cause = cause
     }
 
+    /// Store the number of the coprocessor responsible for a
+    /// `CoprocessorError` in CAUSE bits [29:28]
+    pub fn set_coprocessor_error(&mut self, cop: u32) {
+        self.cause &= !(3 << 28);
+        self.cause |= (cop & 3) << 28;
+    }
+
     pub fn epc(&self) -> u32 {
         self.epc
     }
@@ -131,6 +157,14 @@ impl Registers {
         self.epc = epc
     }
 
+    pub fn gte(&self) -> &Gte {
+        &self.gte
+    }
+
+    pub fn gte_mut(&mut self) -> &mut Gte {
+        &mut self.gte
+    }
+
     pub fn out_regs(&self) -> [u32; 32] {
         self.out_regs
     }
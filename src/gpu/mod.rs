@@ -3,12 +3,15 @@ use std::fmt;
 use crate::gpu::commandbuffer::CommandBuffer;
 use crate::gpu::opengl::{Color, Renderer, Vertex};
 use crate::gpu::opengl::Position;
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
 
 use self::displaydepth::DisplayDepth;
 use self::dmadirection::DmaDirection;
 use self::field::Field;
 use self::resolution::{HorizontalRes, VerticalRes};
 use self::texturedepth::TextureDepth;
+use self::timings::{DisplayArea, Timings};
 use self::vmode::VMode;
 
 pub mod opengl;
@@ -20,6 +23,7 @@ pub mod vmode;
 pub mod displaydepth;
 pub mod dmadirection;
 pub mod commandbuffer;
+pub mod timings;
 
 pub struct Gpu {
     /// Texture page base X coordinate (4 bits , 64 byte increment )
@@ -131,6 +135,28 @@ pub struct Gpu {
     /// Display output last line relative to VSYNC
     display_line_end: u16,
 
+    /// Line currently being output, relative to VSYNC
+    display_line: u16,
+
+    /// Position within the current line, in GPU clock cycles
+    /// multiplied by 7 (the GPU clock is 11/7 times the CPU clock, the
+    /// extra factor keeps the conversion exact)
+    display_line_tick: u32,
+
+    /// True while the output is in the vertical blanking
+    vblank: bool,
+
+    /// Number of frames output since reset
+    frame_count: u32,
+
+    /// Beam position at which the light pen input is pulsed: X in GPU
+    /// clock cycles since HSYNC, line since VSYNC
+    light_pen: Option<(u16, u16)>,
+
+    /// Position within the current dot clock period, in GPU clock
+    /// cycles multiplied by 7
+    dotclock_tick: u32,
+
     /// Buffer containing the current GP0 command
     gp0_command: CommandBuffer,
     /// Remaining words for the current GP0 command
@@ -141,12 +167,21 @@ pub struct Gpu {
     /// Current mode of the GP0 register
     gp0_mode: Gp0Mode,
 
-    /// OpenGL renderer
-    renderer: Renderer,
+    /// OpenGL renderer. `None` when running headless (e.g. in tests)
+    renderer: Option<Renderer>,
 }
 
 impl Gpu {
     pub fn new(renderer: Renderer) -> Gpu {
+        Gpu::with_renderer(Some(renderer))
+    }
+
+    /// Instantiate a GPU which doesn't draw anything
+    pub fn headless() -> Gpu {
+        Gpu::with_renderer(None)
+    }
+
+    fn with_renderer(renderer: Option<Renderer>) -> Gpu {
         Gpu {
             page_base_x: 0,
             page_base_y: 0,
@@ -180,10 +215,17 @@ impl Gpu {
             drawing_y_offset: 0,
             display_vram_x_start: 0,
             display_vram_y_start: 0,
-            display_horiz_start: 0,
-            display_horiz_end: 0,
-            display_line_start: 0,
-            display_line_end: 0,
+            // Same values as the ones set by GP1(0x00)
+            display_horiz_start: 0x200,
+            display_horiz_end: 0xc00,
+            display_line_start: 0x10,
+            display_line_end: 0x100,
+            display_line: 0,
+            display_line_tick: 0,
+            vblank: true,
+            frame_count: 0,
+            light_pen: None,
+            dotclock_tick: 0,
             gp0_command: CommandBuffer::new(),
             gp0_words_remaining: 0,
             gp0_command_method: Gpu::gp0_nop,
@@ -192,6 +234,149 @@ impl Gpu {
         }
     }
 
+    /// Advance the video timings by `cycles` CPU clock cycles, raising
+    /// the VBlank interrupt when the output enters the vertical
+    /// blanking. Returns the timing signals generated in the meantime.
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptController) -> Timings {
+        let mut timings = Timings::new();
+
+        // The GPU clock is 11/7 times the CPU clock. All the counters
+        // below are in GPU cycles multiplied by 7
+        let ticks = cycles * 11;
+
+        let dot_len = self.hres.dotclock_divider() * 7;
+
+        self.dotclock_tick += ticks;
+
+        timings.dotclocks = self.dotclock_tick / dot_len;
+        self.dotclock_tick %= dot_len;
+
+        let line_len = self.vmode.cycles_per_line() * 7;
+        // The horizontal blanking starts at the end of the
+        // horizontal display range
+        let hblank_start = (self.display_horiz_end as u32 * 7).min(line_len - 1);
+
+        let prev_tick = self.display_line_tick;
+
+        self.display_line_tick += ticks;
+
+        if prev_tick < hblank_start && self.display_line_tick >= hblank_start {
+            timings.hblanks += 1;
+        }
+
+        let light_pen = self.light_pen.map(|(x, line)| (x as u32 * 7, line));
+
+        if let Some((pen_tick, line)) = light_pen {
+            if line == self.display_line && prev_tick < pen_tick && self.display_line_tick >= pen_tick {
+                irq.assert(Interrupt::Lightpen);
+            }
+        }
+
+        while self.display_line_tick >= line_len {
+            self.display_line_tick -= line_len;
+
+            self.display_line += 1;
+
+            if self.display_line >= self.vmode.lines_per_frame() {
+                self.display_line = 0;
+
+                if self.interlaced {
+                    self.field = match self.field {
+                        Field::Top => Field::Bottom,
+                        Field::Bottom => Field::Top,
+                    };
+                }
+            }
+
+            if self.display_line_tick >= hblank_start {
+                timings.hblanks += 1;
+            }
+
+            if let Some((pen_tick, line)) = light_pen {
+                if line == self.display_line && self.display_line_tick >= pen_tick {
+                    irq.assert(Interrupt::Lightpen);
+                }
+            }
+
+            let vblank = self.in_vblank();
+
+            if vblank && !self.vblank {
+                irq.assert(Interrupt::VBlank);
+                self.frame_count = self.frame_count.wrapping_add(1);
+                timings.vblanks += 1;
+            }
+
+            self.vblank = vblank;
+        }
+
+        timings.in_hblank = self.display_line_tick < self.display_horiz_start as u32 * 7 ||
+            self.display_line_tick >= hblank_start;
+        timings.in_vblank = self.vblank;
+
+        timings
+    }
+
+    /// Number of CPU cycles until the next change in the video
+    /// signals (start or end of the horizontal blanking, new line,
+    /// light pen pulse)
+    pub fn cycles_to_next_event(&self) -> u32 {
+        let line_len = self.vmode.cycles_per_line() * 7;
+        let hblank_start = (self.display_horiz_end as u32 * 7).min(line_len - 1);
+        let hblank_end = self.display_horiz_start as u32 * 7;
+
+        let light_pen = match self.light_pen {
+            Some((x, line)) if line == self.display_line => x as u32 * 7,
+            _ => line_len,
+        };
+
+        let pos = self.display_line_tick;
+
+        let next = [hblank_end, hblank_start, line_len, light_pen]
+            .iter()
+            .cloned()
+            .filter(|&t| t > pos)
+            .min()
+            .unwrap_or(line_len);
+
+        // Round up, we need to reach the event
+        (next - pos).div_ceil(11)
+    }
+
+    /// Number of GPU clock cycles per dot for the current horizontal
+    /// resolution
+    pub fn dotclock_divider(&self) -> u32 {
+        self.hres.dotclock_divider()
+    }
+
+    /// Current position of the picture in the video signal
+    pub fn display_area(&self) -> DisplayArea {
+        DisplayArea {
+            horiz_start: self.display_horiz_start,
+            horiz_end: self.display_horiz_end,
+            line_start: self.display_line_start,
+            line_end: self.display_line_end,
+        }
+    }
+
+    /// Set the beam position at which the light pen input is pulsed
+    /// every frame, `None` to stop the pulses
+    pub fn set_light_pen(&mut self, light_pen: Option<(u16, u16)>) {
+        self.light_pen = light_pen;
+    }
+
+    /// Number of frames output since reset, incremented at the start
+    /// of the vertical blanking
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Return true if the current line is outside of the vertical
+    /// display range set by GP1(0x07)
+    fn in_vblank(&self) -> bool {
+        self.display_line < self.display_line_start ||
+            self.display_line >= self.display_line_end
+    }
+
     /// Handle writes to the GP0 command register
     pub fn gp0(&mut self, val: u32) {
         if self.gp0_words_remaining == 0 {
@@ -281,7 +466,9 @@ impl Gpu {
             Vertex::new(Position::from_packed(self.gp0_command[7]), Color::from_packed(self.gp0_command[6])),
         ];
 
-        self.renderer.push_quad(&vertices);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.push_quad(&vertices);
+        }
     }
 
     /// GP0(0x30) : Shaded Opaque Triangle
@@ -294,7 +481,9 @@ impl Gpu {
             Vertex::new(Position::from_packed(self.gp0_command[5]), Color::from_packed(self.gp0_command[4])),
         ];
 
-        self.renderer.push_triangle(&vertices);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.push_triangle(&vertices);
+        }
     }
 
     /// GP0(0x2c): Textured Opaque Quadrilateral
@@ -311,7 +500,9 @@ impl Gpu {
             Vertex::new(Position::from_packed(self.gp0_command[7]), color),
         ];
 
-        self.renderer.push_quad(&vertices);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.push_quad(&vertices);
+        }
     }
 
     /// GP0(0XA0): Image Load
@@ -375,10 +566,12 @@ impl Gpu {
 
     // Called when the drawing area changes to notify the renderer
     fn update_drawing_area(&mut self) {
-        self.renderer.set_drawing_area(self.drawing_area_left,
-                                       self.drawing_area_top,
-                                       self.drawing_area_right,
-                                       self.drawing_area_bottom);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.set_drawing_area(self.drawing_area_left,
+                                      self.drawing_area_top,
+                                      self.drawing_area_right,
+                                      self.drawing_area_bottom);
+        }
     }
 
     /// GP0(0xE5): Set Drawing Offset
@@ -399,9 +592,11 @@ impl Gpu {
         self.drawing_y_offset = y;
 
 
-        self.renderer.set_draw_offset(x, y);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.set_draw_offset(x, y);
 
-        self.renderer.display();
+            renderer.display();
+        }
     }
 
     /// GP0(0xE6): Set Mask Bit Setting
@@ -425,7 +620,9 @@ impl Gpu {
             Vertex::new(Position::from_packed(self.gp0_command[4]), color),
         ];
 
-        self.renderer.push_quad(&vertices);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.push_quad(&vertices);
+        }
     }
 
     /// GP1(0x01): Reset Command Buffer
@@ -622,7 +819,9 @@ impl Gpu {
         self.display_line_end = 0x100;
         self.display_depth = DisplayDepth::D15Bits;
 
-        self.renderer.set_draw_offset(0, 0);
+        if let Some(renderer) = self.renderer.as_mut() {
+            renderer.set_draw_offset(0, 0);
+        }
     }
 
     /// Parse a position as written in the GP0 register and return it as
@@ -659,4 +858,129 @@ impl fmt::Display for Gp0Mode {
         };
         write!(f, "{:?}", name)
     }
-}
\ No newline at end of file
+}
+#[test]
+fn vblank_interrupt() {
+    let mut gpu = Gpu::headless();
+    let mut irq = InterruptController::new();
+
+    irq.set_mask(1 << (Interrupt::VBlank as usize));
+
+    // One NTSC line lasts 3413 * 7 / 11 CPU cycles
+    let line = 3413 * 7 / 11 + 1;
+
+    // Display range is [0x10, 0x100[ after reset, we start at line 0
+    // in the blanking
+    for _ in 0..0x100 - 1 {
+        gpu.tick(line, &mut irq);
+    }
+
+    assert!(!irq.active());
+
+    gpu.tick(line, &mut irq);
+
+    assert!(irq.active());
+
+    // Acknowledge And run up to the next frame
+    irq.acknowledge(0);
+
+    for _ in 0..263 {
+        gpu.tick(line, &mut irq);
+    }
+
+    assert!(irq.active());
+}
+
+#[test]
+fn light_pen_interrupt() {
+    let mut gpu = Gpu::headless();
+    let mut irq = InterruptController::new();
+
+    let pen: u16 = 1 << (Interrupt::Lightpen as usize);
+
+    gpu.set_light_pen(Some((0x700, 0x88)));
+
+    // Follow the scheduled events, the reset values put line 0x88
+    // before the first VBlank
+    let mut pulses = 0;
+
+    while gpu.frame_count() < 2 {
+        gpu.tick(gpu.cycles_to_next_event(), &mut irq);
+
+        if irq.status() & pen != 0 {
+            // The event is scheduled right at the pulse
+            assert_eq!(gpu.display_line, 0x88);
+            assert!(gpu.display_line_tick >= 0x700 * 7);
+            assert!(gpu.display_line_tick < 0x700 * 7 + 11);
+
+            pulses += 1;
+            irq.acknowledge(!pen);
+        }
+    }
+
+    // One pulse per frame
+    assert_eq!(pulses, 2);
+
+    gpu.set_light_pen(None);
+
+    for _ in 0..263 * 3 {
+        gpu.tick(3413 * 7 / 11 / 3, &mut irq);
+    }
+
+    assert_eq!(irq.status() & pen, 0);
+}
+
+#[test]
+fn dotclock_and_hblank() {
+    let mut gpu = Gpu::headless();
+    let mut irq = InterruptController::new();
+
+    // 256 pixels: 10 GPU cycles per dot, i.e. 70 CPU cycles for 11
+    // dots
+    let timings = gpu.tick(70, &mut irq);
+
+    assert_eq!(timings.dotclocks, 11);
+    assert_eq!(timings.hblanks, 0);
+    // Display range starts at 0x200 GPU cycles
+    assert!(timings.in_hblank);
+
+    // Move past the start of the horizontal display range
+    let timings = gpu.tick(350, &mut irq);
+
+    assert!(!timings.in_hblank);
+
+    // The horizontal display range ends at 0xc00 GPU cycles
+    let timings = gpu.tick(0xc00 * 7 / 11 - 420 + 1, &mut irq);
+
+    assert_eq!(timings.hblanks, 1);
+    assert!(timings.in_hblank);
+
+    // 10 full lines
+    let timings = gpu.tick(3413 * 7 * 10 / 11, &mut irq);
+
+    assert_eq!(timings.hblanks, 10);
+}
+
+#[test]
+fn next_event() {
+    let mut gpu = Gpu::headless();
+    let mut irq = InterruptController::new();
+
+    // The first event is the end of the horizontal blanking at 0x200
+    // GPU cycles
+    let delay = gpu.cycles_to_next_event();
+
+    assert_eq!(delay, (0x200 * 7 + 10) / 11);
+
+    let timings = gpu.tick(delay - 1, &mut irq);
+    assert!(timings.in_hblank);
+
+    let timings = gpu.tick(1, &mut irq);
+    assert!(!timings.in_hblank);
+
+    // Then the start of the horizontal blanking
+    let delay = gpu.cycles_to_next_event();
+
+    let timings = gpu.tick(delay, &mut irq);
+    assert_eq!(timings.hblanks, 1);
+}
@@ -24,4 +24,23 @@ pub enum VerticalRes {
     Y240Lines = 0,
     /// 480 lines (only available for interlaced output)
     Y480Lines = 1,
-}
\ No newline at end of file
+}
+impl HorizontalRes {
+    /// Number of GPU clock cycles per dot for this resolution
+    pub fn dotclock_divider(self) -> u32 {
+        let HorizontalRes(hr) = self;
+
+        // hr2 set means 368 pixels, regardless of hr1
+        if hr & 1 != 0 {
+            7
+        } else {
+            match hr >> 1 {
+                0 => 10, // 256 pixels
+                1 => 8,  // 320 pixels
+                2 => 5,  // 512 pixels
+                3 => 4,  // 640 pixels
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// Video timing signals generated by the GPU during a call to
+/// `Gpu::tick`, used to clock And synchronize the root counters
+pub struct Timings {
+    /// Number of dot clock periods elapsed
+    pub dotclocks: u32,
+    /// Number of horizontal blanking periods started
+    pub hblanks: u32,
+    /// True if the output is currently in the horizontal blanking
+    pub in_hblank: bool,
+    /// Number of vertical blanking periods started
+    pub vblanks: u32,
+    /// True if the output is currently in the vertical blanking
+    pub in_vblank: bool,
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings {
+            dotclocks: 0,
+            hblanks: 0,
+            in_hblank: false,
+            vblanks: 0,
+            in_vblank: false,
+        }
+    }
+}
+
+/// Position of the visible picture within the video signal, set by
+/// GP1(0x06) And GP1(0x07)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DisplayArea {
+    /// Horizontal range in GPU clock cycles relative to HSYNC
+    pub horiz_start: u16,
+    pub horiz_end: u16,
+    /// Vertical range in lines relative to VSYNC
+    pub line_start: u16,
+    pub line_end: u16,
+}
+
+impl DisplayArea {
+    /// Reset values of the GPU registers
+    pub fn new() -> DisplayArea {
+        DisplayArea {
+            horiz_start: 0x200,
+            horiz_end: 0xc00,
+            line_start: 0x10,
+            line_end: 0x100,
+        }
+    }
+
+    /// Beam position when it reaches `(x, y)`, normalized within the
+    /// picture: X in GPU clock cycles since HSYNC, Y in lines since
+    /// VSYNC
+    pub fn beam_position(&self, (x, y): (f32, f32)) -> (u16, u16) {
+        let width = self.horiz_end.saturating_sub(self.horiz_start) as f32;
+        let height = self.line_end.saturating_sub(self.line_start) as f32;
+
+        (self.horiz_start + (x * width) as u16, self.line_start + (y * height) as u16)
+    }
+}
@@ -5,4 +5,22 @@ pub enum VMode {
     Ntsc = 0,
     /// PAL: 576i50Hz
     Pal = 1,
-}
\ No newline at end of file
+}
+impl VMode {
+    /// Total number of lines in a frame, including the vertical
+    /// blanking
+    pub fn lines_per_frame(self) -> u16 {
+        match self {
+            VMode::Ntsc => 263,
+            VMode::Pal => 314,
+        }
+    }
+
+    /// Duration of a line in GPU clock cycles
+    pub fn cycles_per_line(self) -> u32 {
+        match self {
+            VMode::Ntsc => 3413,
+            VMode::Pal => 3406,
+        }
+    }
+}
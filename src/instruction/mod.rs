@@ -106,4 +106,11 @@ impl Instruction {
 
         op & 0x3ffffff
     }
+
+    /// Coprocessor command stored in bits [24:0]
+    pub fn imm25(&self) -> u32 {
+        let Instruction(op) = self;
+
+        op & 0x1ffffff
+    }
 }
\ No newline at end of file
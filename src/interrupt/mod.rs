@@ -0,0 +1,99 @@
+use self::source::Interrupt;
+
+pub mod source;
+
+/// Interrupt controller. Each peripheral asserts its IRQ line by
+/// setting the matching bit in I_STAT, the CPU's external interrupt
+/// line (CAUSE bit 10) is active as long as one of those bits is also
+/// set in I_MASK.
+pub struct InterruptController {
+    /// I_STAT: interrupt request flags, they remain set until they're
+    /// acknowledged by the software
+    status: u16,
+
+    /// I_MASK: enabled interrupts
+    mask: u16,
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        InterruptController {
+            status: 0,
+            mask: 0,
+        }
+    }
+
+    /// Return true if at least one unmasked interrupt is pending
+    pub fn active(&self) -> bool {
+        (self.status & self.mask) != 0
+    }
+
+    /// Retrieve the value of I_STAT
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Write to I_STAT: a bit set to 0 acknowledges the matching
+    /// interrupt, a 1 leaves it untouched. The software can't raise
+    /// an interrupt this way.
+    pub fn acknowledge(&mut self, ack: u16) {
+        self.status &= ack;
+    }
+
+    /// Retrieve the value of I_MASK
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u16) {
+        // Only bits [10:0] are implemented
+        self.mask = mask & 0x7ff;
+    }
+
+    /// Signal the interrupt `which`. IRQs are edge triggered so the
+    /// peripherals should only call this on a rising edge of their
+    /// interrupt line.
+    pub fn assert(&mut self, which: Interrupt) {
+        self.status |= 1 << (which as usize);
+    }
+}
+
+#[test]
+fn acknowledge() {
+    let mut irq = InterruptController::new();
+
+    irq.assert(Interrupt::VBlank);
+    irq.assert(Interrupt::Dma);
+
+    assert_eq!(irq.status(), 0b1001);
+
+    // Writing 0 acknowledges, 1 leaves the flag untouched
+    irq.acknowledge(!0b1000);
+
+    assert_eq!(irq.status(), 0b0001);
+
+    // Writing 1 doesn't raise anything
+    irq.acknowledge(0xffff);
+
+    assert_eq!(irq.status(), 0b0001);
+}
+
+#[test]
+fn mask() {
+    let mut irq = InterruptController::new();
+
+    irq.assert(Interrupt::Timer2);
+
+    // Flags are latched even if the interrupt is masked
+    assert_eq!(irq.status(), 1 << 6);
+    assert!(!irq.active());
+
+    irq.set_mask(0xffff);
+
+    assert_eq!(irq.mask(), 0x7ff);
+    assert!(irq.active());
+
+    irq.acknowledge(0);
+
+    assert!(!irq.active());
+}
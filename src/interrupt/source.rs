@@ -0,0 +1,27 @@
+/// Interrupt sources, the value is the bit number in the I_STAT And
+/// I_MASK registers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Display in vertical blanking
+    VBlank = 0,
+    /// GPU IRQ requested with GP0(0x1f)
+    Gpu = 1,
+    /// CDROM controller
+    CdRom = 2,
+    /// DMA transfer done
+    Dma = 3,
+    /// Timer 0 (dot clock or system clock)
+    Timer0 = 4,
+    /// Timer 1 (hblank or system clock)
+    Timer1 = 5,
+    /// Timer 2 (system clock or system clock / 8)
+    Timer2 = 6,
+    /// Gamepad And memory card controller (SIO0)
+    PadMemCard = 7,
+    /// Serial port (SIO1)
+    Sio = 8,
+    /// Sound Processing Unit
+    Spu = 9,
+    /// Lightgun connected on the controller port
+    Lightpen = 10,
+}
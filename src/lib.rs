@@ -9,4 +9,15 @@ pub mod cpu;
 pub mod bios;
 pub mod debugger;
 pub mod memory;
-pub mod gpu;
\ No newline at end of file
+pub mod gpu;
+pub mod interrupt;
+pub mod timers;
+pub mod scheduler;
+pub mod cdrom;
+pub mod spu;
+pub mod mdec;
+pub mod memcard;
+pub mod pad_memcard;
+pub mod serial;
+pub mod sio;
+pub mod audio;
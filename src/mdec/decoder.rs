@@ -0,0 +1,242 @@
+//! Macroblock decoding: run-length decoding, dequantization, inverse
+//! DCT And YUV to RGB conversion
+
+/// End of block marker, also used as padding between macroblocks
+const END_OF_BLOCK: u16 = 0xfe00;
+
+/// Position in the 8x8 block of the Nth coefficient of the RLE
+/// stream
+const ZIGZAG: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// 8x8 block of coefficients or pixels
+pub type Block = [i16; 64];
+
+/// Sign extend the 10bit value in the low bits of `v`
+fn signed10(v: u16) -> i32 {
+    ((v as i32) << 22) >> 22
+}
+
+/// Decode one block from the RLE stream starting at `input[pos]`.
+/// Returns the coefficients And the position after the block, or
+/// `None` if the stream ends before the block is complete.
+pub fn decode_rle(input: &[u16], mut pos: usize, qt: &[u8; 64]) -> Option<(Block, usize)> {
+    let mut block = [0; 64];
+
+    // Skip the padding
+    let mut n = loop {
+        let n = *input.get(pos)?;
+
+        pos += 1;
+
+        if n != END_OF_BLOCK {
+            break n;
+        }
+    };
+
+    let q_scale = ((n >> 10) & 0x3f) as i32;
+
+    let mut k = 0;
+    let mut val = signed10(n) * qt[0] as i32;
+
+    loop {
+        if q_scale == 0 {
+            val = signed10(n) * 2;
+        }
+
+        let val_clamped = val.clamp(-0x400, 0x3ff) as i16;
+
+        // Without scaling the coefficients aren't in zigzag order
+        if q_scale > 0 {
+            block[ZIGZAG[k]] = val_clamped;
+        } else {
+            block[k] = val_clamped;
+        }
+
+        n = *input.get(pos)?;
+        pos += 1;
+
+        k += ((n >> 10) & 0x3f) as usize + 1;
+
+        if k > 63 {
+            break;
+        }
+
+        val = (signed10(n) * qt[k] as i32 * q_scale + 4) / 8;
+    }
+
+    Some((block, pos))
+}
+
+/// Two pass inverse DCT using the uploaded scale table. The output is
+/// clamped to signed 8 bits.
+pub fn idct(block: &mut Block, scale: &[i16; 64]) {
+    let mut tmp = [0i64; 64];
+
+    for x in 0..8 {
+        for y in 0..8 {
+            let mut sum = 0;
+
+            for u in 0..8 {
+                sum += block[u * 8 + x] as i64 * scale[u * 8 + y] as i64;
+            }
+
+            tmp[x + y * 8] = sum;
+        }
+    }
+
+    for x in 0..8 {
+        for y in 0..8 {
+            let mut sum = 0;
+
+            for u in 0..8 {
+                sum += tmp[u + y * 8] * scale[u * 8 + x] as i64;
+            }
+
+            // Round, then sign extend from 9 bits
+            let v = ((sum >> 32) + ((sum >> 31) & 1)) as i32;
+            let v = (v << 23) >> 23;
+
+            block[x + y * 8] = v.clamp(-128, 127) as i16;
+        }
+    }
+}
+
+/// Convert a 16x16 macroblock to RGB. Each chroma sample covers 2x2
+/// luma samples. The output is signed, from -128 to 127.
+pub fn yuv_to_rgb(cr: &Block, cb: &Block, y: &[Block; 4]) -> [[i16; 3]; 256] {
+    let mut out = [[0; 3]; 256];
+
+    for py in 0..16 {
+        for px in 0..16 {
+            let c = (py / 2) * 8 + px / 2;
+
+            let cr = cr[c] as i32;
+            let cb = cb[c] as i32;
+
+            let r = (359 * cr + 0x80) >> 8;
+            let g = (-88 * cb - 183 * cr + 0x80) >> 8;
+            let b = (454 * cb + 0x80) >> 8;
+
+            // Y1 Y2 on top, Y3 Y4 below
+            let block = &y[(py / 8) * 2 + px / 8];
+            let l = block[(py % 8) * 8 + px % 8] as i32;
+
+            out[py * 16 + px] = [(l + r).clamp(-128, 127) as i16,
+                                 (l + g).clamp(-128, 127) as i16,
+                                 (l + b).clamp(-128, 127) as i16];
+        }
+    }
+
+    out
+}
+
+/// Standard IDCT scale table, the one uploaded by the BIOS And the
+/// libraries
+#[cfg(test)]
+pub const STANDARD_SCALE_TABLE: [i16; 64] = [
+    0x5a82, 0x5a82, 0x5a82, 0x5a82, 0x5a82, 0x5a82, 0x5a82, 0x5a82,
+    0x7d8a, 0x6a6d, 0x471c, 0x18f8, -0x18f9, -0x471d, -0x6a6e, -0x7d8b,
+    0x7641, 0x30fb, -0x30fc, -0x7642, -0x7642, -0x30fc, 0x30fb, 0x7641,
+    0x6a6d, -0x18f9, -0x7d8b, -0x471d, 0x471c, 0x7d8a, 0x18f8, -0x6a6e,
+    0x5a82, -0x5a83, -0x5a83, 0x5a82, 0x5a82, -0x5a83, -0x5a83, 0x5a82,
+    0x471c, -0x7d8b, 0x18f8, 0x6a6d, -0x6a6e, -0x18f9, 0x7d8a, -0x471d,
+    0x30fb, -0x7642, 0x7641, -0x30fc, -0x30fc, 0x7641, -0x7642, 0x30fb,
+    0x18f8, -0x471d, 0x6a6d, -0x7d8b, 0x7d8a, -0x6a6e, 0x471c, -0x18f9,
+];
+
+/// Standard quantization table used by most games, in zigzag order
+#[cfg(test)]
+pub const STANDARD_QUANT_TABLE: [u8; 64] = [
+     2, 16, 16, 19, 16, 19, 22, 22,
+    22, 22, 22, 22, 26, 24, 26, 27,
+    27, 27, 26, 26, 26, 26, 27, 27,
+    27, 29, 29, 29, 34, 34, 34, 29,
+    29, 29, 27, 27, 29, 29, 32, 32,
+    34, 34, 37, 38, 37, 35, 35, 34,
+    35, 38, 38, 40, 40, 40, 48, 48,
+    46, 46, 56, 56, 58, 69, 69, 83,
+];
+
+#[test]
+fn rle_decode() {
+    let mut qt = [1; 64];
+
+    qt[0] = 2;
+    qt[2] = 4;
+
+    // Padding, DC 10 with scale 8, then a coefficient after a run of
+    // 1 zero
+    let input = [END_OF_BLOCK, END_OF_BLOCK, (8 << 10) | 10, (1 << 10) | 0x3ff, END_OF_BLOCK, 7];
+
+    let (block, pos) = decode_rle(&input, 0, &qt).unwrap();
+
+    assert_eq!(pos, 5);
+    assert_eq!(block[0], 20);
+    // Third coefficient in zigzag order: (-1 * 4 * 8 + 4) / 8
+    assert_eq!(block[ZIGZAG[2]], -3);
+    assert_eq!(block.iter().filter(|&&c| c != 0).count(), 2);
+
+    // Incomplete block
+    assert!(decode_rle(&input[..4], 0, &qt).is_none());
+
+    // No scaling: linear order, coefficients * 2
+    let input = [0x3ff, 0x001, END_OF_BLOCK];
+
+    let (block, _) = decode_rle(&input, 0, &qt).unwrap();
+
+    assert_eq!(block[0], -2);
+    assert_eq!(block[1], 2);
+}
+
+#[test]
+fn idct_reference() {
+    let scale = STANDARD_SCALE_TABLE;
+
+    assert_eq!(scale[0], 0x5a82);
+    assert_eq!(scale[8] as u16, 0x7d8a);
+    assert_eq!(scale[63] as u16, 0xe707);
+
+    let mut block = [0; 64];
+
+    block[0] = 320;
+    block[1] = -100;
+    block[9] = 60;
+    block[17] = 33;
+
+    let coefs = block;
+
+    idct(&mut block, &scale);
+
+    // Floating point reference
+    for y in 0..8 {
+        for x in 0..8 {
+            let mut sum = 0.;
+
+            for v in 0..8 {
+                for u in 0..8 {
+                    let cu = if u == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1. };
+                    let cv = if v == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1. };
+
+                    let cos_x = (((2 * x + 1) * u) as f64 * std::f64::consts::PI / 16.).cos();
+                    let cos_y = (((2 * y + 1) * v) as f64 * std::f64::consts::PI / 16.).cos();
+
+                    sum += cu * cv * coefs[v * 8 + u] as f64 * cos_x * cos_y;
+                }
+            }
+
+            let expected = (sum / 4.).round() as i32;
+            let got = block[y * 8 + x] as i32;
+
+            assert!((got - expected).abs() <= 1, "({}, {}): {} {}", x, y, got, expected);
+        }
+    }
+}
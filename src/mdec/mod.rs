@@ -0,0 +1,528 @@
+use std::collections::VecDeque;
+
+use self::decoder::Block;
+
+pub mod decoder;
+
+/// Output pixel format of the decode command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Depth {
+    /// Monochrome, 4 bits per pixel
+    Mono4 = 0,
+    /// Monochrome, 8 bits per pixel
+    Mono8 = 1,
+    Rgb24 = 2,
+    Rgb15 = 3,
+}
+
+impl Depth {
+    fn from_field(field: u32) -> Depth {
+        match field & 3 {
+            0 => Depth::Mono4,
+            1 => Depth::Mono8,
+            2 => Depth::Rgb24,
+            _ => Depth::Rgb15,
+        }
+    }
+}
+
+/// Command currently receiving its parameters
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Command {
+    Idle,
+    /// Decode macroblocks from the RLE stream
+    Decode,
+    /// Upload the quantization tables, `true` if the chroma table is
+    /// included
+    QuantTables(bool),
+    /// Upload the IDCT scale table
+    ScaleTable,
+}
+
+/// Motion decoder, decompresses the macroblocks of the FMVs
+pub struct MDec {
+    command: Command,
+    /// Number of parameter words left for the current command
+    remaining: u32,
+    /// Bits [28:25] of the last command word, reported in the status
+    /// bits [26:23]
+    command_bits: u32,
+    depth: Depth,
+    /// Output signed pixels
+    signed: bool,
+    /// Value of bit 15 in the 15bpp output
+    bit15: bool,
+    /// Parameters of the current command
+    params: Vec<u32>,
+    /// Quantization tables for the luma And chroma blocks
+    luma_qt: [u8; 64],
+    chroma_qt: [u8; 64],
+    scale_table: [i16; 64],
+    /// RLE halfwords not decoded yet
+    input: Vec<u16>,
+    /// Decoded data waiting to be read
+    output: VecDeque<u32>,
+    /// DMA request enables set through the control register
+    data_in_enabled: bool,
+    data_out_enabled: bool,
+}
+
+impl MDec {
+    pub fn new() -> MDec {
+        MDec {
+            command: Command::Idle,
+            remaining: 0,
+            command_bits: 0,
+            depth: Depth::Mono4,
+            signed: false,
+            bit15: false,
+            params: Vec::new(),
+            luma_qt: [0; 64],
+            chroma_qt: [0; 64],
+            scale_table: [0; 64],
+            input: Vec::new(),
+            output: VecDeque::new(),
+            data_in_enabled: false,
+            data_out_enabled: false,
+        }
+    }
+
+    /// Write to the command/parameter register
+    pub fn command(&mut self, val: u32) {
+        if self.command != Command::Idle {
+            return self.parameter(val);
+        }
+
+        self.command_bits = (val >> 25) & 0xf;
+
+        let (command, words) = match val >> 29 {
+            1 => {
+                self.depth = Depth::from_field(val >> 27);
+                self.signed = val & (1 << 26) != 0;
+                self.bit15 = val & (1 << 25) != 0;
+
+                (Command::Decode, val & 0xffff)
+            }
+            2 => {
+                let color = val & 1 != 0;
+
+                (Command::QuantTables(color), if color { 32 } else { 16 })
+            }
+            3 => (Command::ScaleTable, 32),
+            n => {
+                warn!("Unhandled MDEC command {}: 0x{:08x}", n, val);
+                (Command::Idle, 0)
+            }
+        };
+
+        if words > 0 {
+            self.command = command;
+            self.remaining = words;
+        }
+    }
+
+    fn parameter(&mut self, val: u32) {
+        self.remaining -= 1;
+
+        match self.command {
+            Command::Decode => {
+                self.input.push(val as u16);
+                self.input.push((val >> 16) as u16);
+
+                self.decode_macroblocks();
+
+                if self.remaining == 0 {
+                    // Drop the padding at the end of the stream
+                    self.input.clear();
+                }
+            }
+            _ => {
+                self.params.push(val);
+
+                if self.remaining == 0 {
+                    self.upload_table();
+                }
+            }
+        }
+
+        if self.remaining == 0 {
+            self.command = Command::Idle;
+        }
+    }
+
+    fn upload_table(&mut self) {
+        let bytes: Vec<u8> = self.params.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        match self.command {
+            Command::QuantTables(color) => {
+                self.luma_qt.copy_from_slice(&bytes[..64]);
+
+                if color {
+                    self.chroma_qt.copy_from_slice(&bytes[64..]);
+                }
+            }
+            Command::ScaleTable => {
+                for (entry, b) in self.scale_table.iter_mut().zip(bytes.chunks(2)) {
+                    *entry = i16::from_le_bytes([b[0], b[1]]);
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        self.params.clear();
+    }
+
+    /// Decode all the complete macroblocks in the input buffer
+    fn decode_macroblocks(&mut self) {
+        let mut pos = 0;
+
+        loop {
+            let next = match self.depth {
+                Depth::Mono4 | Depth::Mono8 => self.decode_mono(pos),
+                Depth::Rgb24 | Depth::Rgb15 => self.decode_color(pos),
+            };
+
+            match next {
+                Some(p) => pos = p,
+                None => break,
+            }
+        }
+
+        self.input.drain(..pos);
+    }
+
+    /// Decode a single 8x8 monochrome block at `pos`, returns the
+    /// position of the next one
+    fn decode_mono(&mut self, pos: usize) -> Option<usize> {
+        let (mut block, pos) = decoder::decode_rle(&self.input, pos, &self.luma_qt)?;
+
+        decoder::idct(&mut block, &self.scale_table);
+
+        let pixels: Vec<u8> = block.iter().map(|&y| self.to_unsigned(y)).collect();
+
+        match self.depth {
+            Depth::Mono4 => {
+                let nibbles: Vec<u8> = pixels
+                    .chunks(2)
+                    .map(|p| (p[0] >> 4) | (p[1] & 0xf0))
+                    .collect();
+
+                self.push_bytes(&nibbles);
+            }
+            _ => self.push_bytes(&pixels),
+        }
+
+        Some(pos)
+    }
+
+    /// Decode a 16x16 color macroblock at `pos`: Cr, Cb then the 4
+    /// luma blocks. Returns the position of the next macroblock.
+    fn decode_color(&mut self, pos: usize) -> Option<usize> {
+        let (cr, pos) = self.decode_block(pos, true)?;
+        let (cb, mut pos) = self.decode_block(pos, true)?;
+
+        let mut y = [[0; 64]; 4];
+
+        for block in y.iter_mut() {
+            let (b, next) = self.decode_block(pos, false)?;
+
+            *block = b;
+            pos = next;
+        }
+
+        let pixels = decoder::yuv_to_rgb(&cr, &cb, &y);
+
+        if self.depth == Depth::Rgb24 {
+            let bytes: Vec<u8> = pixels
+                .iter()
+                .flat_map(|p| p.iter().map(|&c| self.to_unsigned(c)))
+                .collect();
+
+            self.push_bytes(&bytes);
+        } else {
+            let bytes: Vec<u8> = pixels
+                .iter()
+                .flat_map(|p| {
+                    let c = |i: usize| (self.to_unsigned(p[i]) >> 3) as u16;
+
+                    let v = c(0) | (c(1) << 5) | (c(2) << 10) | ((self.bit15 as u16) << 15);
+
+                    v.to_le_bytes()
+                })
+                .collect();
+
+            self.push_bytes(&bytes);
+        }
+
+        Some(pos)
+    }
+
+    fn decode_block(&self, pos: usize, chroma: bool) -> Option<(Block, usize)> {
+        let qt = if chroma { &self.chroma_qt } else { &self.luma_qt };
+
+        let (mut block, pos) = decoder::decode_rle(&self.input, pos, qt)?;
+
+        decoder::idct(&mut block, &self.scale_table);
+
+        Some((block, pos))
+    }
+
+    /// Convert a decoded value to the output format
+    fn to_unsigned(&self, v: i16) -> u8 {
+        if self.signed {
+            v as u8
+        } else {
+            (v + 128) as u8
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for w in bytes.chunks(4) {
+            self.output.push_back(u32::from_le_bytes([w[0], w[1], w[2], w[3]]));
+        }
+    }
+
+    /// Read from the data register
+    pub fn read_data(&mut self) -> u32 {
+        match self.output.pop_front() {
+            Some(w) => w,
+            None => {
+                warn!("MDEC data read while the output FIFO is empty");
+                0
+            }
+        }
+    }
+
+    /// Number of output words waiting to be read
+    pub fn output_len(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Write to the control register
+    pub fn set_control(&mut self, val: u32) {
+        if val & (1 << 31) != 0 {
+            // Reset: abort the current command
+            self.command = Command::Idle;
+            self.remaining = 0;
+            self.command_bits = 0;
+            self.params.clear();
+            self.input.clear();
+            self.output.clear();
+        }
+
+        self.data_in_enabled = val & (1 << 30) != 0;
+        self.data_out_enabled = val & (1 << 29) != 0;
+    }
+
+    /// Status register
+    pub fn status(&self) -> u32 {
+        let busy = self.command != Command::Idle;
+
+        // Number of parameter words left - 1
+        let mut r = self.remaining.wrapping_sub(1) & 0xffff;
+
+        // Current block, not tracked: always report Y4/mono
+        r |= 4 << 16;
+        r |= self.command_bits << 23;
+        r |= ((self.data_out_enabled && !self.output.is_empty()) as u32) << 27;
+        r |= ((self.data_in_enabled && busy) as u32) << 28;
+        r |= (busy as u32) << 29;
+        r |= (self.output.is_empty() as u32) << 31;
+
+        r
+    }
+}
+
+#[cfg(test)]
+fn upload_tables(mdec: &mut MDec, qt: &[u8; 64]) {
+    // Same table for luma And chroma
+    mdec.command(0x4000_0001);
+
+    for _ in 0..2 {
+        for w in qt.chunks(4) {
+            mdec.command(u32::from_le_bytes([w[0], w[1], w[2], w[3]]));
+        }
+    }
+
+    mdec.command(0x6000_0000);
+
+    for pair in decoder::STANDARD_SCALE_TABLE.chunks(2) {
+        mdec.command((pair[0] as u16 as u32) | ((pair[1] as u16 as u32) << 16));
+    }
+}
+
+#[test]
+fn reset_status() {
+    let mut mdec = MDec::new();
+
+    mdec.set_control(0x8000_0000);
+
+    assert_eq!(mdec.status(), 0x8004_ffff);
+
+    // Decode command with 2 words, 8bpp signed
+    mdec.set_control(0x6000_0000);
+    mdec.command(0x2c00_0002);
+
+    assert_eq!(mdec.status(), 0xb304_0001);
+}
+
+#[test]
+fn color_macroblock() {
+    let mut mdec = MDec::new();
+
+    // Quantization factor 2 everywhere
+    upload_tables(&mut mdec, &[2; 64]);
+
+    // 24bpp unsigned, 6 blocks of 2 halfwords plus one padding word
+    mdec.command(0x3000_0007);
+
+    // Cr: DC 160 * 2 = 320 -> 40 after the IDCT
+    mdec.command(0xfe00_0000 | (1 << 10) | 160);
+    // Cb And the 4 Y blocks are all 0
+    for _ in 0..5 {
+        mdec.command(0xfe00_0000);
+    }
+
+    assert_eq!(mdec.output_len(), 16 * 16 * 3 / 4);
+    assert_eq!(mdec.status() >> 29, 1);
+
+    mdec.command(0xfe00_fe00);
+    assert_eq!(mdec.status() >> 29, 0);
+
+    // R = 40 * 359 / 256, G = -40 * 183 / 256, B = 0
+    let pixel = [128 + 56, 128 - 29, 128];
+
+    let bytes: Vec<u8> = (0..192).flat_map(|_| mdec.read_data().to_le_bytes()).collect();
+
+    for p in bytes.chunks(3) {
+        assert_eq!(p, &pixel);
+    }
+
+    assert_eq!(mdec.status() >> 31, 1);
+}
+
+#[test]
+fn mono_and_15bpp() {
+    let mut mdec = MDec::new();
+
+    // Quantization factor 2 everywhere
+    upload_tables(&mut mdec, &[2; 64]);
+
+    // 4bpp unsigned: one block with DC -64 * 2 -> -16
+    mdec.command(0x2000_0001);
+    mdec.command(0xfe00_0000 | (1 << 10) | (-64i32 as u32 & 0x3ff));
+
+    assert_eq!(mdec.output_len(), 8);
+    assert!((0..8).all(|_| mdec.read_data() == 0x7777_7777));
+
+    // 15bpp with bit 15 set: grey macroblock
+    mdec.command(0x3a00_0006);
+
+    for _ in 0..6 {
+        mdec.command(0xfe00_0000);
+    }
+
+    assert_eq!(mdec.output_len(), 128);
+    assert!((0..128).all(|_| mdec.read_data() == 0xc210_c210));
+}
+
+#[test]
+fn reference_frame() {
+    // Four 8bpp blocks: padding, various quantization scales,
+    // unscaled coefficients And saturated outputs. The negative
+    // coefficients only appear in the DC And unscaled values.
+    const STREAM: [u32; 14] = [
+        0x0464_fe00, 0x082d_001e, 0x0007_140c, 0xfe00_0c03, 0x0014_23c4, 0x0c0f_0019,
+        0x0405_2808, 0xfe00_fe00, 0x0200_01ff, 0x1338_052c, 0xfe00_5064, 0x01ff_ff88,
+        0x005a_1990, 0xfe00_3040,
+    ];
+
+    const UNSIGNED: [u8; 256] = [
+        0xc2, 0xba, 0xb2, 0xa6, 0x95, 0x88, 0x80, 0x78,
+        0xb2, 0xab, 0xa5, 0x9c, 0x8e, 0x85, 0x7f, 0x78,
+        0xa8, 0xa2, 0x9f, 0x9a, 0x90, 0x8a, 0x87, 0x82,
+        0xaa, 0xa6, 0xa4, 0xa1, 0x99, 0x96, 0x95, 0x90,
+        0xa7, 0xa3, 0xa2, 0xa0, 0x9a, 0x98, 0x97, 0x93,
+        0x99, 0x95, 0x97, 0x97, 0x93, 0x93, 0x94, 0x91,
+        0x8f, 0x8d, 0x91, 0x95, 0x95, 0x99, 0x9d, 0x9b,
+        0x8f, 0x8f, 0x95, 0x9c, 0x9f, 0xa5, 0xac, 0xab,
+        0xff, 0xe3, 0x78, 0x8a, 0xe3, 0xf4, 0x89, 0x0e,
+        0xff, 0xb8, 0x71, 0x86, 0xd1, 0xe7, 0x9f, 0x49,
+        0xd9, 0xad, 0x86, 0x8a, 0xa7, 0xab, 0x84, 0x58,
+        0xd4, 0xbf, 0xa3, 0x8a, 0x74, 0x5b, 0x3e, 0x2a,
+        0xb8, 0xa4, 0x87, 0x6e, 0x58, 0x3f, 0x23, 0x0e,
+        0x8a, 0x5e, 0x37, 0x3b, 0x58, 0x5c, 0x35, 0x09,
+        0x99, 0x43, 0x00, 0x11, 0x5c, 0x71, 0x2a, 0x00,
+        0xd4, 0x59, 0x00, 0x00, 0x58, 0x6a, 0x00, 0x00,
+        0x78, 0x00, 0x00, 0x7f, 0xf6, 0xff, 0x00, 0xfd,
+        0x66, 0x23, 0x00, 0x5f, 0xff, 0x00, 0xff, 0xff,
+        0x64, 0x5d, 0x02, 0x51, 0xff, 0x00, 0xff, 0xff,
+        0x89, 0x62, 0x20, 0x7d, 0xff, 0x00, 0x00, 0xff,
+        0xc4, 0x47, 0x46, 0xc6, 0xff, 0x00, 0x00, 0xff,
+        0xe9, 0x4c, 0x64, 0xf2, 0xff, 0x00, 0x02, 0xff,
+        0xe7, 0x86, 0x70, 0xe4, 0x00, 0x05, 0x00, 0x00,
+        0xd5, 0xc3, 0x70, 0xc4, 0x00, 0x1a, 0x00, 0x00,
+        0xff, 0xc9, 0x2f, 0xff, 0x8b, 0x00, 0xff, 0xd4,
+        0x5a, 0x56, 0x4e, 0x44, 0x39, 0x2f, 0x28, 0x23,
+        0x33, 0x1f, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff,
+        0x98, 0x80, 0x55, 0x1c, 0x00, 0x00, 0xff, 0xff,
+        0x00, 0x00, 0x32, 0x92, 0xfb, 0xff, 0x00, 0x00,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf8, 0xf3,
+        0x8d, 0x3e, 0x00, 0xed, 0x1e, 0xff, 0xcd, 0x7e,
+        0xd9, 0xb0, 0x64, 0x01, 0x00, 0xff, 0xe7, 0xbe,
+    ];
+
+    const SIGNED: [u8; 256] = [
+        0x42, 0x3a, 0x32, 0x26, 0x15, 0x08, 0x00, 0xf8,
+        0x32, 0x2b, 0x25, 0x1c, 0x0e, 0x05, 0xff, 0xf8,
+        0x28, 0x22, 0x1f, 0x1a, 0x10, 0x0a, 0x07, 0x02,
+        0x2a, 0x26, 0x24, 0x21, 0x19, 0x16, 0x15, 0x10,
+        0x27, 0x23, 0x22, 0x20, 0x1a, 0x18, 0x17, 0x13,
+        0x19, 0x15, 0x17, 0x17, 0x13, 0x13, 0x14, 0x11,
+        0x0f, 0x0d, 0x11, 0x15, 0x15, 0x19, 0x1d, 0x1b,
+        0x0f, 0x0f, 0x15, 0x1c, 0x1f, 0x25, 0x2c, 0x2b,
+        0x7f, 0x63, 0xf8, 0x0a, 0x63, 0x74, 0x09, 0x8e,
+        0x7f, 0x38, 0xf1, 0x06, 0x51, 0x67, 0x1f, 0xc9,
+        0x59, 0x2d, 0x06, 0x0a, 0x27, 0x2b, 0x04, 0xd8,
+        0x54, 0x3f, 0x23, 0x0a, 0xf4, 0xdb, 0xbe, 0xaa,
+        0x38, 0x24, 0x07, 0xee, 0xd8, 0xbf, 0xa3, 0x8e,
+        0x0a, 0xde, 0xb7, 0xbb, 0xd8, 0xdc, 0xb5, 0x89,
+        0x19, 0xc3, 0x80, 0x91, 0xdc, 0xf1, 0xaa, 0x80,
+        0x54, 0xd9, 0x80, 0x80, 0xd8, 0xea, 0x80, 0x80,
+        0xf8, 0x80, 0x80, 0xff, 0x76, 0x7f, 0x80, 0x7d,
+        0xe6, 0xa3, 0x80, 0xdf, 0x7f, 0x80, 0x7f, 0x7f,
+        0xe4, 0xdd, 0x82, 0xd1, 0x7f, 0x80, 0x7f, 0x7f,
+        0x09, 0xe2, 0xa0, 0xfd, 0x7f, 0x80, 0x80, 0x7f,
+        0x44, 0xc7, 0xc6, 0x46, 0x7f, 0x80, 0x80, 0x7f,
+        0x69, 0xcc, 0xe4, 0x72, 0x7f, 0x80, 0x82, 0x7f,
+        0x67, 0x06, 0xf0, 0x64, 0x80, 0x85, 0x80, 0x80,
+        0x55, 0x43, 0xf0, 0x44, 0x80, 0x9a, 0x80, 0x80,
+        0x7f, 0x49, 0xaf, 0x7f, 0x0b, 0x80, 0x7f, 0x54,
+        0xda, 0xd6, 0xce, 0xc4, 0xb9, 0xaf, 0xa8, 0xa3,
+        0xb3, 0x9f, 0x80, 0x80, 0x80, 0x7f, 0x7f, 0x7f,
+        0x18, 0x00, 0xd5, 0x9c, 0x80, 0x80, 0x7f, 0x7f,
+        0x80, 0x80, 0xb2, 0x12, 0x7b, 0x7f, 0x80, 0x80,
+        0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x78, 0x73,
+        0x0d, 0xbe, 0x80, 0x6d, 0x9e, 0x7f, 0x4d, 0xfe,
+        0x59, 0x30, 0xe4, 0x81, 0x80, 0x7f, 0x67, 0x3e,
+    ];
+
+    // Output of the decoder of trapezoid-core 0.3.0 for the same
+    // stream with the standard tables
+    let mut mdec = MDec::new();
+
+    upload_tables(&mut mdec, &decoder::STANDARD_QUANT_TABLE);
+
+    for (command, expected) in [(0x2800_0000, &UNSIGNED), (0x2c00_0000, &SIGNED)] {
+        mdec.command(command | STREAM.len() as u32);
+
+        for &w in STREAM.iter() {
+            mdec.command(w);
+        }
+
+        let bytes: Vec<u8> = (0..64).flat_map(|_| mdec.read_data().to_le_bytes()).collect();
+
+        assert_eq!(&bytes[..], &expected[..]);
+        assert_eq!(mdec.output_len(), 0);
+    }
+}
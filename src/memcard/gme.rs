@@ -0,0 +1,48 @@
+//! DexDrive card images: a header with the per-save comments followed
+//! by the raw card
+
+use std::io::{Error, ErrorKind, Result};
+
+use super::{BLOCK_COUNT, CARD_SIZE, FRAME_SIZE};
+
+const MAGIC: &[u8] = b"123-456-STD";
+
+/// Size of the header preceding the card data. It ends with a 256
+/// byte comment for each block, starting at 0x40.
+const HEADER_SIZE: usize = 0xf40;
+
+pub fn is_gme(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Extract the raw card image
+pub fn parse(bytes: &[u8]) -> Result<Vec<u8>> {
+    if !is_gme(bytes) || bytes.len() != HEADER_SIZE + CARD_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "Invalid GME file"));
+    }
+
+    Ok(bytes[HEADER_SIZE..].to_vec())
+}
+
+/// Build a GME file for the raw card image `card`. The comments are
+/// left empty.
+pub fn build(card: &[u8]) -> Vec<u8> {
+    let mut gme = vec![0; HEADER_SIZE];
+
+    gme[..MAGIC.len()].copy_from_slice(MAGIC);
+    gme[0x12] = 0x01;
+    gme[0x14] = 0x01;
+    gme[0x15] = b'M';
+
+    // Copy of the state And next block of each directory entry
+    for block in 1..BLOCK_COUNT {
+        let frame = &card[block * FRAME_SIZE..];
+
+        gme[0x15 + block] = frame[0];
+        gme[0x25 + block] = frame[8];
+    }
+
+    gme.extend_from_slice(card);
+
+    gme
+}
@@ -0,0 +1,94 @@
+/// Icon width And height in pixels
+pub const ICON_SIZE: usize = 16;
+
+/// Animated 16x16 icon of a save, up to 3 frames of 4bpp paletted
+/// pixels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Icon {
+    /// 16 colors in the console's 15bit BGR format
+    palette: [u16; 16],
+    /// Palette index of each pixel, one array per frame
+    frames: Vec<[u8; ICON_SIZE * ICON_SIZE]>,
+}
+
+impl Icon {
+    /// Decode the icon from the title frame (the first 128 bytes of
+    /// `save`) And the following bitmap frames
+    pub fn from_save(save: &[u8]) -> Icon {
+        let frame_count = match save[2] {
+            0x12 => 2,
+            0x13 => 3,
+            _ => 1,
+        };
+
+        let mut palette = [0; 16];
+
+        for (i, color) in palette.iter_mut().enumerate() {
+            *color = u16::from_le_bytes([save[0x60 + i * 2], save[0x61 + i * 2]]);
+        }
+
+        let frames = (0..frame_count).map(|f| {
+            let bitmap = &save[0x80 * (f + 1)..0x80 * (f + 2)];
+            let mut pixels = [0; ICON_SIZE * ICON_SIZE];
+
+            // 4bpp, low nibble first
+            for (i, &b) in bitmap.iter().enumerate() {
+                pixels[i * 2] = b & 0xf;
+                pixels[i * 2 + 1] = b >> 4;
+            }
+
+            pixels
+        }).collect();
+
+        Icon { palette, frames }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Convert `frame` to 8bit RGBA, row by row. Black (color 0) is
+    /// transparent.
+    pub fn rgba(&self, frame: usize) -> Vec<u8> {
+        self.frames[frame].iter().flat_map(|&index| {
+            let color = self.palette[index as usize];
+
+            // Expand 5 bits to 8
+            let c = |shift: u16| {
+                let v = ((color >> shift) & 0x1f) as u8;
+
+                (v << 3) | (v >> 2)
+            };
+
+            let alpha = if color == 0 { 0 } else { 0xff };
+
+            [c(0), c(5), c(10), alpha]
+        }).collect()
+    }
+}
+
+#[test]
+fn decode_icon() {
+    let mut save = vec![0; 0x200];
+
+    save[2] = 0x12;
+    // Color 1: pure red, color 2: white
+    save[0x62] = 0x1f;
+    save[0x64] = 0xff;
+    save[0x65] = 0x7f;
+
+    save[0x80] = 0x21;
+    save[0x100 + 127] = 0x10;
+
+    let icon = Icon::from_save(&save);
+
+    assert_eq!(icon.frame_count(), 2);
+
+    let rgba = icon.rgba(0);
+
+    assert_eq!(rgba.len(), 16 * 16 * 4);
+    assert_eq!(&rgba[0..8], [0xff, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(&rgba[8..12], [0, 0, 0, 0]);
+
+    assert_eq!(&icon.rgba(1)[255 * 4..], [0xff, 0, 0, 0xff]);
+}
@@ -0,0 +1,390 @@
+//! Memory card filesystem: directory parsing And save management on
+//! raw card images
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use self::icon::Icon;
+use self::save::{extension, read_filename, SaveFile};
+
+pub mod gme;
+pub mod icon;
+pub mod save;
+
+/// Size of a card image in bytes
+pub const CARD_SIZE: usize = 128 * 1024;
+
+/// Size of a frame, the unit of the card accesses
+pub const FRAME_SIZE: usize = 128;
+
+/// Size of a block, the allocation unit of the filesystem
+pub const BLOCK_SIZE: usize = 8 * 1024;
+
+/// Number of blocks, the first one holds the directory
+pub const BLOCK_COUNT: usize = CARD_SIZE / BLOCK_SIZE;
+
+/// Maximum length of a filename
+pub const FILENAME_LEN: usize = 20;
+
+/// Directory entry states
+pub const STATE_FIRST: u8 = 0x51;
+pub const STATE_MIDDLE: u8 = 0x52;
+pub const STATE_LAST: u8 = 0x53;
+pub const STATE_FREE: u8 = 0xa0;
+
+/// Deleting a save only moves its entries to the 0xa1-0xa3 states
+const STATE_DELETED: u8 = 0x50;
+
+/// Save found in the directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    /// Block holding the start of the save (1 to 15)
+    pub slot: usize,
+    pub filename: String,
+    /// Blocks used by the save, in order
+    pub blocks: Vec<usize>,
+    pub title: String,
+    pub icon: Icon,
+}
+
+/// Raw memory card image
+pub struct Card {
+    data: Vec<u8>,
+}
+
+impl Card {
+    /// Freshly formatted card
+    pub fn new() -> Card {
+        let mut data = vec![0; CARD_SIZE];
+
+        format(&mut data);
+
+        Card { data }
+    }
+
+    pub fn from_data(data: Vec<u8>) -> Result<Card> {
+        if data.len() != CARD_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("Invalid memory card size: {} bytes",
+                                          data.len())));
+        }
+
+        if &data[0..2] != b"MC" {
+            return Err(Error::new(ErrorKind::InvalidData, "Memory card isn't formatted"));
+        }
+
+        Ok(Card { data })
+    }
+
+    /// Load a raw (.mcr/.mcd...) or DexDrive (.gme) card image
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Card> {
+        let bytes = fs::read(path)?;
+
+        if gme::is_gme(&bytes) {
+            Card::from_data(gme::parse(&bytes)?)
+        } else {
+            Card::from_data(bytes)
+        }
+    }
+
+    /// Write the card image, in the DexDrive format if the extension
+    /// is .gme, raw otherwise
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        if extension(path) == "gme" {
+            fs::write(path, gme::build(&self.data))
+        } else {
+            fs::write(path, &self.data)
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn format(&mut self) {
+        format(&mut self.data);
+    }
+
+    fn directory(&self, block: usize) -> &[u8] {
+        &self.data[block * FRAME_SIZE..(block + 1) * FRAME_SIZE]
+    }
+
+    fn block(&self, block: usize) -> &[u8] {
+        &self.data[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+    }
+
+    /// List the saves on the card
+    pub fn entries(&self) -> Vec<DirectoryEntry> {
+        (1..BLOCK_COUNT)
+            .filter(|&b| self.directory(b)[0] == STATE_FIRST)
+            .filter_map(|slot| self.entry(slot))
+            .collect()
+    }
+
+    fn entry(&self, slot: usize) -> Option<DirectoryEntry> {
+        let frame = self.directory(slot);
+
+        if frame[0] != STATE_FIRST {
+            return None;
+        }
+
+        let mut blocks = vec![slot];
+
+        loop {
+            let frame = self.directory(*blocks.last().unwrap());
+            let next = u16::from_le_bytes([frame[8], frame[9]]) as usize;
+
+            if next == 0xffff {
+                break;
+            }
+
+            let next = next + 1;
+
+            // Corrupted chain
+            if next >= BLOCK_COUNT || blocks.contains(&next) {
+                warn!("Invalid memory card block chain for slot {}", slot);
+                return None;
+            }
+
+            blocks.push(next);
+        }
+
+        let first = self.block(slot);
+
+        Some(DirectoryEntry {
+            slot,
+            filename: read_filename(&frame[0x0a..0x0a + FILENAME_LEN]),
+            blocks,
+            title: save::decode_title(&first[4..0x44]),
+            icon: Icon::from_save(first),
+        })
+    }
+
+    /// Blocks available for new saves
+    pub fn free_blocks(&self) -> Vec<usize> {
+        (1..BLOCK_COUNT)
+            .filter(|&b| self.directory(b)[0] & 0xf0 == STATE_FREE)
+            .collect()
+    }
+
+    /// Extract the save starting at block `slot`
+    pub fn export(&self, slot: usize) -> Result<SaveFile> {
+        check_slot(slot)?;
+
+        let entry = self.entry(slot)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No save in slot {}", slot)))?;
+
+        let data = entry.blocks.iter().flat_map(|&b| self.block(b).iter().copied()).collect();
+
+        SaveFile::new(&entry.filename, data)
+    }
+
+    /// Copy `save` to the free blocks of the card. Returns its slot.
+    pub fn import(&mut self, save: &SaveFile) -> Result<usize> {
+        if self.entries().iter().any(|e| e.filename == save.filename()) {
+            return Err(Error::new(ErrorKind::AlreadyExists,
+                                  format!("{} is already on the card", save.filename())));
+        }
+
+        let free = self.free_blocks();
+
+        if free.len() < save.blocks() {
+            return Err(Error::other(format!("Not enough free blocks: {} needed, {} available",
+                                            save.blocks(), free.len())));
+        }
+
+        let blocks = &free[..save.blocks()];
+
+        for (i, &block) in blocks.iter().enumerate() {
+            let last = i + 1 == blocks.len();
+
+            let state = match i {
+                0 => STATE_FIRST,
+                _ if last => STATE_LAST,
+                _ => STATE_MIDDLE,
+            };
+
+            let frame = if i == 0 {
+                directory_frame(state, save.data().len() as u32, blocks.get(1).copied(), save.filename())
+            } else {
+                directory_frame(state, 0, blocks.get(i + 1).copied(), "")
+            };
+
+            self.data[block * FRAME_SIZE..(block + 1) * FRAME_SIZE].copy_from_slice(&frame);
+
+            let data = &save.data()[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+
+            self.data[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE].copy_from_slice(data);
+        }
+
+        Ok(blocks[0])
+    }
+
+    /// Delete the save starting at block `slot`. Like the BIOS only
+    /// the directory entries are marked as deleted.
+    pub fn delete(&mut self, slot: usize) -> Result<()> {
+        check_slot(slot)?;
+
+        let entry = self.entry(slot)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No save in slot {}", slot)))?;
+
+        for block in entry.blocks {
+            let frame = &mut self.data[block * FRAME_SIZE..(block + 1) * FRAME_SIZE];
+
+            frame[0] += STATE_DELETED;
+            frame[FRAME_SIZE - 1] = checksum(&frame[..FRAME_SIZE - 1]);
+        }
+
+        Ok(())
+    }
+}
+
+/// Make sure `slot` is one of the save blocks. Block 0 holds the
+/// directory itself.
+fn check_slot(slot: usize) -> Result<()> {
+    if slot == 0 || slot >= BLOCK_COUNT {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("Invalid slot {}, must be between 1 and {}",
+                                      slot, BLOCK_COUNT - 1)));
+    }
+
+    Ok(())
+}
+
+/// Build a directory frame. `next` is the next block of the save, if
+/// any.
+pub fn directory_frame(state: u8, size: u32, next: Option<usize>, filename: &str) -> [u8; FRAME_SIZE] {
+    let mut frame = [0; FRAME_SIZE];
+
+    frame[0] = state;
+    frame[4..8].copy_from_slice(&size.to_le_bytes());
+
+    let next = next.map(|b| b as u16 - 1).unwrap_or(0xffff);
+
+    frame[8..10].copy_from_slice(&next.to_le_bytes());
+    frame[0x0a..0x0a + filename.len()].copy_from_slice(filename.as_bytes());
+    frame[FRAME_SIZE - 1] = checksum(&frame[..FRAME_SIZE - 1]);
+
+    frame
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |c, &b| c ^ b)
+}
+
+/// Write an empty filesystem to the card image `data`
+pub fn format(data: &mut [u8]) {
+    data.fill(0);
+
+    // Header frame
+    data[0] = b'M';
+    data[1] = b'C';
+    data[FRAME_SIZE - 1] = checksum(b"MC");
+
+    for frame in 1..64 {
+        let f = &mut data[frame * FRAME_SIZE..(frame + 1) * FRAME_SIZE];
+
+        match frame {
+            // Directory entries
+            1..=15 => f.copy_from_slice(&directory_frame(STATE_FREE, 0, None, "")),
+            // Broken sector list: no broken sector
+            16..=35 => {
+                f[0..4].fill(0xff);
+                f[8] = 0xff;
+                f[9] = 0xff;
+            }
+            _ => (),
+        }
+    }
+
+    // Write test frame, a copy of the header
+    data.copy_within(0..FRAME_SIZE, 63 * FRAME_SIZE);
+}
+
+#[test]
+fn import_export() {
+    use self::save::test_save;
+
+    let mut card = Card::new();
+
+    assert_eq!(card.data()[0x7f], b'M' ^ b'C');
+    assert_eq!(card.data()[0x80], STATE_FREE);
+    assert_eq!(card.data()[0xff], STATE_FREE);
+    assert_eq!(card.free_blocks().len(), 15);
+
+    let small = test_save("BESLES-00000SMALL");
+
+    assert_eq!(card.import(&small).unwrap(), 1);
+
+    // 3 block save
+    let mut data = small.data().to_vec();
+
+    data.resize(BLOCK_SIZE * 3, 0x42);
+
+    let big = SaveFile::new("BASLUS-00000BIG", data).unwrap();
+
+    assert_eq!(card.import(&big).unwrap(), 2);
+    assert_eq!(card.import(&big).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    let entries = card.entries();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title, "TEST セーブ");
+    assert_eq!(entries[1].filename, "BASLUS-00000BIG");
+    assert_eq!(entries[1].blocks, [2, 3, 4]);
+
+    assert_eq!(card.directory(3)[0], STATE_MIDDLE);
+    assert_eq!(card.directory(4)[0], STATE_LAST);
+    assert_eq!(checksum(card.directory(2)), 0);
+
+    assert_eq!(card.export(2).unwrap(), big);
+
+    card.delete(1).unwrap();
+
+    assert_eq!(card.directory(1)[0], 0xa1);
+    assert_eq!(card.entries().len(), 1);
+    assert!(card.export(1).is_err());
+
+    // The deleted block is reused
+    assert_eq!(card.import(&small).unwrap(), 1);
+
+    let mut data = small.data().to_vec();
+
+    data.resize(BLOCK_SIZE * 12, 0);
+
+    let huge = SaveFile::new("BASLUS-00000HUGE", data).unwrap();
+
+    assert!(card.import(&huge).is_err());
+}
+
+#[test]
+fn invalid_slots() {
+    let mut card = Card::new();
+
+    for slot in [0, BLOCK_COUNT, 1000] {
+        assert_eq!(card.export(slot).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(card.delete(slot).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    assert_eq!(card.delete(15).unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn gme_round_trip() {
+    let mut card = Card::new();
+
+    card.import(&save::test_save("BESLES-00000TEST")).unwrap();
+
+    let gme = gme::build(card.data());
+
+    assert!(gme::is_gme(&gme));
+    assert_eq!(gme[0x16], STATE_FIRST);
+    assert_eq!(gme[0x26], 0xff);
+
+    let card2 = Card::from_data(gme::parse(&gme).unwrap()).unwrap();
+
+    assert_eq!(card2.data(), card.data());
+}
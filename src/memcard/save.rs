@@ -0,0 +1,245 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use encoding_rs::SHIFT_JIS;
+
+use super::icon::Icon;
+use super::{directory_frame, BLOCK_SIZE, FILENAME_LEN, FRAME_SIZE, STATE_FIRST};
+
+/// Magic of the PS3 virtual memory card exports
+const PSV_MAGIC: &[u8; 4] = b"\0VSP";
+
+/// Offset of the save data in a PSV file
+const PSV_DATA_OFFSET: usize = 0x84;
+
+/// A single save, as stored in the blocks of the card
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveFile {
+    /// Name of the file in the card directory, for instance
+    /// "BASLUS-00571SAVE01"
+    filename: String,
+    /// Contents of the blocks, starting with the title frame
+    data: Vec<u8>,
+}
+
+impl SaveFile {
+    pub fn new(filename: &str, data: Vec<u8>) -> Result<SaveFile> {
+        if filename.is_empty() || filename.len() > FILENAME_LEN || !filename.is_ascii() {
+            return Err(invalid(format!("Invalid save filename {:?}", filename)));
+        }
+
+        if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) || data.len() > BLOCK_SIZE * 15 {
+            return Err(invalid(format!("Invalid save size: {} bytes", data.len())));
+        }
+
+        if &data[0..2] != b"SC" {
+            return Err(invalid("Missing save title frame".to_string()));
+        }
+
+        Ok(SaveFile {
+            filename: filename.to_string(),
+            data,
+        })
+    }
+
+    /// Load a save file, the format is deduced from the extension:
+    /// `.mcs` or `.psv`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SaveFile> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+
+        match extension(path).as_str() {
+            "mcs" => SaveFile::from_mcs(&bytes),
+            "psv" => SaveFile::from_psv(&bytes),
+            ext => Err(invalid(format!("Unsupported save format {:?}", ext))),
+        }
+    }
+
+    /// Write the save to `path` in the format matching the extension
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let bytes = match extension(path).as_str() {
+            "mcs" => self.to_mcs(),
+            "psv" => self.to_psv(),
+            ext => return Err(invalid(format!("Unsupported save format {:?}", ext))),
+        };
+
+        fs::write(path, bytes)
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Number of 8KB blocks used by the save
+    pub fn blocks(&self) -> usize {
+        self.data.len() / BLOCK_SIZE
+    }
+
+    /// Title displayed by the BIOS
+    pub fn title(&self) -> String {
+        decode_title(&self.data[4..0x44])
+    }
+
+    pub fn icon(&self) -> Icon {
+        Icon::from_save(&self.data)
+    }
+
+    /// Parse a single save in the MCS format: the directory frame
+    /// followed by the blocks
+    pub fn from_mcs(bytes: &[u8]) -> Result<SaveFile> {
+        if bytes.len() < FRAME_SIZE || bytes[0] != STATE_FIRST {
+            return Err(invalid("Invalid MCS header".to_string()));
+        }
+
+        let filename = read_filename(&bytes[0x0a..0x0a + FILENAME_LEN]);
+
+        SaveFile::new(&filename, bytes[FRAME_SIZE..].to_vec())
+    }
+
+    pub fn to_mcs(&self) -> Vec<u8> {
+        let size = self.data.len() as u32;
+        let mut mcs = directory_frame(STATE_FIRST, size, None, &self.filename).to_vec();
+
+        mcs.extend_from_slice(&self.data);
+
+        mcs
+    }
+
+    /// Parse a PS3 virtual memory card export
+    pub fn from_psv(bytes: &[u8]) -> Result<SaveFile> {
+        if bytes.len() < PSV_DATA_OFFSET || &bytes[0..4] != PSV_MAGIC {
+            return Err(invalid("Invalid PSV header".to_string()));
+        }
+
+        let u32_at = |o: usize| u32::from_le_bytes([bytes[o], bytes[o + 1], bytes[o + 2], bytes[o + 3]]);
+
+        let size = u32_at(0x40) as usize;
+        let offset = u32_at(0x44) as usize;
+
+        let data = bytes.get(offset..offset + size)
+            .ok_or_else(|| invalid("Truncated PSV file".to_string()))?;
+
+        let filename = read_filename(&bytes[0x64..0x64 + FILENAME_LEN]);
+
+        SaveFile::new(&filename, data.to_vec())
+    }
+
+    /// Export in the PSV layout. The signature isn't computed so the
+    /// file must be resigned before a PS3 accepts it.
+    pub fn to_psv(&self) -> Vec<u8> {
+        let mut psv = vec![0; PSV_DATA_OFFSET];
+
+        let mut put_u32 = |o: usize, v: u32| psv[o..o + 4].copy_from_slice(&v.to_le_bytes());
+
+        // PS1 save
+        put_u32(0x38, 0x14);
+        put_u32(0x3c, 1);
+        put_u32(0x40, self.data.len() as u32);
+        put_u32(0x44, PSV_DATA_OFFSET as u32);
+        put_u32(0x48, 0x200);
+        put_u32(0x5c, 0x9000);
+        put_u32(0x60, 3);
+
+        psv[0..4].copy_from_slice(PSV_MAGIC);
+        psv[0x64..0x64 + self.filename.len()].copy_from_slice(self.filename.as_bytes());
+
+        psv.extend_from_slice(&self.data);
+
+        psv
+    }
+}
+
+/// Decode a Shift-JIS save title. The full-width latin characters
+/// used by most games are converted to ASCII.
+pub fn decode_title(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    let (title, _) = SHIFT_JIS.decode_without_bom_handling(&bytes[..end]);
+
+    title.chars()
+        .map(|c| match c {
+            '\u{3000}' => ' ',
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap(),
+            _ => c,
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Read a NUL terminated filename
+pub fn read_filename(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+/// Lowercase extension of `path`
+pub fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// One block save with the title "ＴＥＳＴ　セーブ"
+#[cfg(test)]
+pub fn test_save(filename: &str) -> SaveFile {
+    let mut data = vec![0; BLOCK_SIZE];
+
+    data[0..2].copy_from_slice(b"SC");
+    data[2] = 0x11;
+    data[3] = 1;
+
+    let title = [0x82, 0x73, 0x82, 0x64, 0x82, 0x72, 0x82, 0x73, 0x81, 0x40,
+                 0x83, 0x5a, 0x81, 0x5b, 0x83, 0x75];
+
+    data[4..4 + title.len()].copy_from_slice(&title);
+
+    SaveFile::new(filename, data).unwrap()
+}
+
+#[test]
+fn title() {
+    let save = test_save("BESLES-00000TEST");
+
+    assert_eq!(save.title(), "TEST セーブ");
+    assert_eq!(save.blocks(), 1);
+    assert_eq!(save.icon().frame_count(), 1);
+
+    assert!(SaveFile::new("", vec![0; BLOCK_SIZE]).is_err());
+    assert!(SaveFile::new("X", vec![0; BLOCK_SIZE]).is_err());
+}
+
+#[test]
+fn mcs_and_psv() {
+    let save = test_save("BESLES-00000TEST");
+
+    let mcs = save.to_mcs();
+
+    assert_eq!(mcs.len(), FRAME_SIZE + BLOCK_SIZE);
+    assert_eq!(&mcs[0..5], [0x51, 0, 0, 0, 0]);
+    assert_eq!(&mcs[4..8], &(BLOCK_SIZE as u32).to_le_bytes());
+    assert_eq!(&mcs[8..10], [0xff, 0xff]);
+    assert_eq!(mcs[..FRAME_SIZE].iter().fold(0, |c, &b| c ^ b), 0);
+    assert_eq!(SaveFile::from_mcs(&mcs).unwrap(), save);
+
+    let psv = save.to_psv();
+
+    assert_eq!(&psv[0..4], b"\0VSP");
+    assert_eq!(psv.len(), 0x84 + BLOCK_SIZE);
+    assert_eq!(SaveFile::from_psv(&psv).unwrap(), save);
+
+    assert!(SaveFile::from_psv(&psv[..0x100]).is_err());
+    assert!(SaveFile::from_mcs(&psv).is_err());
+}
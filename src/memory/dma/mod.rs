@@ -1,3 +1,5 @@
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
 use crate::memory::dma::channel::Channel;
 use crate::memory::dma::port::Port;
 
@@ -65,7 +67,7 @@ impl Dma {
     }
 
     /// Return the status of the DMA interrupt
-    fn irq(&self) -> bool {
+    pub fn irq(&self) -> bool {
         let channel_irq = self.channel_irq_flags & self.channel_irq_en;
         self.force_irq || (self.irq_en && channel_irq != 0)
     }
@@ -81,15 +83,42 @@ impl Dma {
         r
     }
     /// Set the value of the interrupt register
-    pub fn set_interrupt(&mut self, val: u32) { // Unknown what bits [5:0] do
+    pub fn set_interrupt(&mut self, val: u32, irq: &mut InterruptController) {
+        let prev_irq = self.irq();
+
+        // Unknown what bits [5:0] do
         self.irq_dummy = (val & 0x3f) as u8;
         self.force_irq = (val >> 15) & 1 != 0;
         self.channel_irq_en = ((val >> 16) & 0x7f) as u8;
         self.irq_en = (val >> 23) & 1 != 0;
 
         // Writing 1 to a flag resets it
-        let ack = ((val >> 24) & 0x3f) as u8;
+        let ack = ((val >> 24) & 0x7f) as u8;
         self.channel_irq_flags &= !ack;
+
+        if !prev_irq && self.irq() {
+            // Rising edge of the DMA interrupt (e.g. 'force_irq' has just been set)
+            irq.assert(Interrupt::Dma);
+        }
+    }
+
+    /// Called when the transfer on `port` is complete. Sets the
+    /// channel's IRQ flag if it's enabled And signals the interrupt
+    /// controller on a rising edge of the DMA IRQ.
+    pub fn done(&mut self, port: Port, irq: &mut InterruptController) {
+        let prev_irq = self.irq();
+
+        self.channel_mut(port).done();
+
+        let mask = 1 << (port as u8);
+
+        if self.channel_irq_en & mask != 0 {
+            self.channel_irq_flags |= mask;
+        }
+
+        if !prev_irq && self.irq() {
+            irq.assert(Interrupt::Dma);
+        }
     }
 
 
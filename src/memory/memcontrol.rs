@@ -0,0 +1,150 @@
+/// Memory regions whose bus timings are configured through a
+/// MEM_CONTROL delay/size register. The value is the index of the
+/// register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusRegion {
+    Expansion1 = 2,
+    Expansion3 = 3,
+    Bios = 4,
+    Spu = 5,
+    CdRom = 6,
+    Expansion2 = 7,
+}
+
+/// Memory control registers: expansion base addresses And the bus
+/// timings of the external devices
+pub struct MemControl {
+    /// The 9 registers, in the order they're mapped:
+    ///  * 0: Expansion 1 base address
+    ///  * 1: Expansion 2 base address
+    ///  * 2..=7: delay/size for each `BusRegion`
+    ///  * 8: COM_DELAY, common delays referenced by the delay/size
+    ///    registers
+    regs: [u32; 9],
+}
+
+impl MemControl {
+    pub fn new() -> MemControl {
+        MemControl {
+            // Values set by the BIOS during boot
+            regs: [
+                0x1f000000,
+                0x1f802000,
+                0x0013243f,
+                0x00003022,
+                0x0013243f,
+                0x200931e1,
+                0x00020843,
+                0x00070777,
+                0x00031125,
+            ],
+        }
+    }
+
+    /// Register read, `offset` is relative to the start of the
+    /// MEM_CONTROL range
+    pub fn load(&self, offset: u32) -> u32 {
+        self.regs[(offset >> 2) as usize]
+    }
+
+    /// Register write, `offset` is relative to the start of the
+    /// MEM_CONTROL range
+    pub fn store(&mut self, offset: u32, val: u32) {
+        self.regs[(offset >> 2) as usize] = val;
+    }
+
+    /// Duration in CPU cycles of an access of `size` bytes to
+    /// `region`. Uses the formula from the Nocash PSX spec: the first
+    /// access of a burst is a bit slower than the following
+    /// sequential ones And accesses wider than the bus are split.
+    pub fn access_time(&self, region: BusRegion, size: u8, write: bool) -> u32 {
+        let delay_size = self.regs[region as usize];
+        let com_delay = self.regs[8];
+
+        let com = |n: u32| (com_delay >> (n * 4)) & 0xf;
+
+        let delay = match write {
+            false => (delay_size >> 4) & 0xf,
+            true => delay_size & 0xf,
+        };
+
+        let mut first = 0;
+        let mut seq = 0;
+        let mut min = 0;
+
+        if delay_size & (1 << 8) != 0 {
+            // COM0 is guest-controlled And may be 0
+            first += com(0).saturating_sub(1);
+            seq += com(0).saturating_sub(1);
+        }
+
+        if delay_size & (1 << 10) != 0 {
+            first += com(2);
+            seq += com(2);
+        }
+
+        if delay_size & (1 << 11) != 0 {
+            min = com(3);
+        }
+
+        if first < 6 {
+            first += 1;
+        }
+
+        first += delay + 2;
+        seq += delay + 2;
+
+        first = first.max(min + 6);
+        seq = seq.max(min + 2);
+
+        // Number of bus cycles needed for the access
+        let accesses = match delay_size & (1 << 12) != 0 {
+            // 16bit bus
+            true => ((size as u32) / 2).max(1),
+            // 8bit bus
+            false => size as u32,
+        };
+
+        first + (accesses - 1) * seq
+    }
+}
+
+#[test]
+fn bios_timings() {
+    let mem_control = MemControl::new();
+
+    // 8bit bus, 3 cycles read delay, uses COM2
+    assert_eq!(mem_control.access_time(BusRegion::Bios, 1, false), 7);
+    assert_eq!(mem_control.access_time(BusRegion::Bios, 2, false), 13);
+    assert_eq!(mem_control.access_time(BusRegion::Bios, 4, false), 25);
+}
+
+#[test]
+fn sixteen_bit_bus() {
+    let mut mem_control = MemControl::new();
+
+    // 16bit bus, 1 cycle read delay, no common delay
+    mem_control.store(0x14, 0x1010);
+
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 1, false), 6);
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 2, false), 6);
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 4, false), 6 + 3);
+
+    // COM3 sets the minimum access time
+    mem_control.store(0x14, 0x1810);
+    mem_control.store(0x20, 0x5000);
+
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 4, false), 11 + 7);
+}
+
+#[test]
+fn zero_com0() {
+    let mut mem_control = MemControl::new();
+
+    // 16bit bus, 1 cycle read delay, uses COM0 which is 0
+    mem_control.store(0x14, 0x1110);
+    mem_control.store(0x20, 0);
+
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 2, false), 6);
+    assert_eq!(mem_control.access_time(BusRegion::Spu, 4, false), 6 + 3);
+}
@@ -1,5 +1,6 @@
 pub mod ram;
 pub mod dma;
+pub mod memcontrol;
 
 /// Trait representing the attributes of a memory access
 pub trait Addressable {
@@ -0,0 +1,499 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::device::Peripheral;
+
+#[cfg(test)]
+use super::device::transfer;
+
+/// Controller buttons, the value is the bit number in the button
+/// state returned by the pad
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+    Select = 0,
+    /// Left stick press (DualShock only)
+    L3 = 1,
+    /// Right stick press (DualShock only)
+    R3 = 2,
+    Start = 3,
+    DUp = 4,
+    DRight = 5,
+    DDown = 6,
+    DLeft = 7,
+    L2 = 8,
+    R2 = 9,
+    L1 = 10,
+    R1 = 11,
+    Triangle = 12,
+    Circle = 13,
+    Cross = 14,
+    Square = 15,
+}
+
+/// State of the buttons And sticks as set by the frontend
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputState {
+    /// Pressed buttons, one bit per `Button`
+    buttons: u16,
+    /// Left stick position (x, y), 0x80 is the center
+    left_stick: (u8, u8),
+    right_stick: (u8, u8),
+}
+
+impl InputState {
+    /// No button pressed, sticks centered
+    pub fn new() -> InputState {
+        InputState {
+            buttons: 0,
+            left_stick: (0x80, 0x80),
+            right_stick: (0x80, 0x80),
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let mask = 1 << (button as u16);
+
+        if pressed {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        self.buttons & (1 << (button as u16)) != 0
+    }
+
+    /// Set the left stick position, from (0, 0) for up-left to
+    /// (0xff, 0xff) for down-right
+    pub fn set_left_stick(&mut self, x: u8, y: u8) {
+        self.left_stick = (x, y);
+    }
+
+    pub fn set_right_stick(&mut self, x: u8, y: u8) {
+        self.right_stick = (x, y);
+    }
+}
+
+/// State of the DualShock vibration motors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rumble {
+    /// The small motor is either on or off
+    pub small: bool,
+    /// Speed of the large motor
+    pub large: u8,
+}
+
+/// Maximum number of motor state changes kept for the frontend. The
+/// oldest ones are dropped if it doesn't retrieve them.
+const RUMBLE_EVENTS_MAX: usize = 64;
+
+/// State shared between the controller And its handle
+struct Shared {
+    input: InputState,
+    /// Set when the Analog button has been pressed since the last
+    /// transfer
+    analog_toggle: bool,
+    rumble: Rumble,
+    /// Motor state changes not retrieved by the frontend yet
+    rumble_events: VecDeque<Rumble>,
+}
+
+/// Handle used by the frontend to drive a controller once it's been
+/// plugged
+#[derive(Clone)]
+pub struct ControllerHandle(Arc<Mutex<Shared>>);
+
+impl ControllerHandle {
+    pub fn input(&self) -> InputState {
+        self.0.lock().unwrap().input
+    }
+
+    pub fn set_input(&self, input: InputState) {
+        self.0.lock().unwrap().input = input;
+    }
+
+    /// Press the Analog button, ignored by digital pads And while the
+    /// software locks the mode
+    pub fn toggle_analog(&self) {
+        self.0.lock().unwrap().analog_toggle = true;
+    }
+
+    /// Current state of the motors
+    pub fn rumble(&self) -> Rumble {
+        self.0.lock().unwrap().rumble
+    }
+
+    /// Return the motor state changes since the last call, at most
+    /// the `RUMBLE_EVENTS_MAX` most recent ones
+    pub fn take_rumble_events(&self) -> Vec<Rumble> {
+        self.0.lock().unwrap().rumble_events.drain(..).collect()
+    }
+}
+
+/// Controller model
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Model {
+    /// SCPH-1080 digital pad
+    Digital,
+    /// SCPH-1200 DualShock, supports the analog mode And the
+    /// configuration commands
+    DualShock,
+}
+
+/// Controller ID in digital mode
+const ID_DIGITAL: u8 = 0x41;
+/// Controller ID in analog mode
+const ID_ANALOG: u8 = 0x73;
+/// Controller ID in configuration mode
+const ID_CONFIG: u8 = 0xf3;
+
+/// Value of a rumble mapping entry for the small And the large motor.
+/// Any other value leaves the byte unused.
+const RUMBLE_SMALL: u8 = 0x00;
+const RUMBLE_LARGE: u8 = 0x01;
+
+/// Digital pad or DualShock
+pub struct Controller {
+    model: Model,
+    shared: Arc<Mutex<Shared>>,
+    /// Analog mode (red LED on)
+    analog: bool,
+    /// The software prevents the Analog button from switching modes
+    analog_locked: bool,
+    config_mode: bool,
+    /// Assignment of the bytes of the read command to the motors
+    rumble_map: [u8; 6],
+    /// Position in the current transfer
+    seq: usize,
+    /// Current command
+    command: u8,
+    /// Data bytes returned by the current command
+    response: [u8; 6],
+    /// Number of data bytes of the current command
+    response_len: usize,
+    /// First data byte received, some responses depend on it
+    param: u8,
+    /// Motor state being built during a read command
+    rumble: Rumble,
+}
+
+impl Controller {
+    pub fn digital() -> Controller {
+        Controller::new(Model::Digital)
+    }
+
+    /// DualShock, starting in digital mode like the real one
+    pub fn dual_shock() -> Controller {
+        Controller::new(Model::DualShock)
+    }
+
+    fn new(model: Model) -> Controller {
+        let shared = Shared {
+            input: InputState::new(),
+            analog_toggle: false,
+            rumble: Rumble { small: false, large: 0 },
+            rumble_events: VecDeque::new(),
+        };
+
+        Controller {
+            model,
+            shared: Arc::new(Mutex::new(shared)),
+            analog: false,
+            analog_locked: false,
+            config_mode: false,
+            rumble_map: [0xff; 6],
+            seq: 0,
+            command: 0,
+            response: [0; 6],
+            response_len: 0,
+            param: 0,
+            rumble: Rumble { small: false, large: 0 },
+        }
+    }
+
+    pub fn handle(&self) -> ControllerHandle {
+        ControllerHandle(self.shared.clone())
+    }
+
+    fn id(&self) -> u8 {
+        if self.config_mode {
+            ID_CONFIG
+        } else if self.analog {
+            ID_ANALOG
+        } else {
+            ID_DIGITAL
+        }
+    }
+
+    /// Handle the command byte. Returns false if the command isn't
+    /// supported.
+    fn start_command(&mut self, command: u8) -> bool {
+        let dual_shock = self.model == Model::DualShock;
+
+        self.command = command;
+        self.response = [0; 6];
+        self.response_len = 6;
+
+        match command {
+            0x42 => self.read_pad(),
+            0x43 if dual_shock => {
+                if !self.config_mode {
+                    self.read_pad();
+                }
+            }
+            0x44..=0x4d if self.config_mode => (),
+            _ => return false,
+        }
+
+        if command == 0x42 {
+            self.rumble = self.shared.lock().unwrap().rumble;
+        }
+
+        true
+    }
+
+    fn read_pad(&mut self) {
+        let input = self.shared.lock().unwrap().input;
+
+        // The buttons are active low
+        let buttons = !input.buttons;
+
+        self.response[0] = buttons as u8;
+        self.response[1] = (buttons >> 8) as u8;
+
+        if self.analog {
+            self.response[2] = input.right_stick.0;
+            self.response[3] = input.right_stick.1;
+            self.response[4] = input.left_stick.0;
+            self.response[5] = input.left_stick.1;
+        } else {
+            self.response_len = 2;
+        }
+    }
+
+    /// Exchange the data byte `index` of the current command
+    fn data(&mut self, index: usize, val: u8) -> u8 {
+        if index == 0 {
+            self.param = val;
+        }
+
+        let response = match self.command {
+            0x46 => match (index, self.param) {
+                (2, _) => 0x01,
+                (3, 0) => 0x02,
+                (3, _) => 0x01,
+                (4, 0) => 0x00,
+                (4, _) => 0x01,
+                (5, 0) => 0x0a,
+                (5, _) => 0x14,
+                _ => 0x00,
+            },
+            0x47 => [0x00, 0x00, 0x02, 0x00, 0x01, 0x00][index],
+            0x4c => match (index, self.param) {
+                (3, 0) => 0x04,
+                (3, 1) => 0x07,
+                _ => 0x00,
+            },
+            0x4d => {
+                let old = self.rumble_map[index];
+
+                self.rumble_map[index] = val;
+
+                old
+            }
+            0x45 => [0x01, 0x02, self.analog as u8, 0x02, 0x01, 0x00][index],
+            _ => self.response[index],
+        };
+
+        match (self.command, index) {
+            (0x42, _) => match self.rumble_map[index] {
+                RUMBLE_SMALL => self.rumble.small = val & 1 != 0,
+                RUMBLE_LARGE => self.rumble.large = val,
+                _ => (),
+            },
+            (0x43, 0) => self.config_mode = val == 1,
+            (0x44, 0) => self.analog = val == 1,
+            (0x44, 1) => self.analog_locked = val == 3,
+            _ => (),
+        }
+
+        response
+    }
+
+    /// Called after the last byte of a command
+    fn end_command(&mut self) {
+        if self.command != 0x42 {
+            return;
+        }
+
+        let mut shared = self.shared.lock().unwrap();
+
+        if shared.rumble != self.rumble {
+            shared.rumble = self.rumble;
+
+            if shared.rumble_events.len() == RUMBLE_EVENTS_MAX {
+                shared.rumble_events.pop_front();
+            }
+
+            shared.rumble_events.push_back(self.rumble);
+        }
+    }
+}
+
+impl Peripheral for Controller {
+    fn select(&mut self) {
+        self.seq = 0;
+
+        let mut shared = self.shared.lock().unwrap();
+
+        if shared.analog_toggle {
+            shared.analog_toggle = false;
+
+            if self.model == Model::DualShock && !self.analog_locked && !self.config_mode {
+                self.analog = !self.analog;
+            }
+        }
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        let seq = self.seq;
+
+        self.seq += 1;
+
+        match seq {
+            // Address byte, routed to us by the port
+            0 => (0xff, true),
+            1 => {
+                // The ID is the one before the command is executed
+                let id = self.id();
+
+                if self.start_command(cmd) {
+                    (id, true)
+                } else {
+                    (0xff, false)
+                }
+            }
+            2 => (0x5a, true),
+            n => {
+                let index = n - 3;
+
+                if index >= self.response_len {
+                    return (0xff, false);
+                }
+
+                let response = self.data(index, cmd);
+                let last = index + 1 == self.response_len;
+
+                if last {
+                    self.end_command();
+                }
+
+                (response, !last)
+            }
+        }
+    }
+}
+
+#[test]
+fn digital_pad() {
+    let mut pad = Controller::digital();
+    let handle = pad.handle();
+
+    let mut input = InputState::new();
+
+    input.set_button(Button::Cross, true);
+    input.set_button(Button::Start, true);
+    handle.set_input(input);
+
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42, 0x00, 0x00, 0x00]),
+               [0xff, 0x41, 0x5a, 0xf7, 0xbf]);
+
+    // No configuration mode on the digital pad
+    assert_eq!(transfer(&mut pad, &[0x01, 0x43, 0x00, 0x01]), [0xff, 0xff]);
+
+    // Neither analog mode
+    handle.toggle_analog();
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42])[1], 0x41);
+}
+
+#[test]
+fn dual_shock_modes() {
+    let mut pad = Controller::dual_shock();
+    let handle = pad.handle();
+
+    let mut input = InputState::new();
+
+    input.set_left_stick(0x00, 0xff);
+    handle.set_input(input);
+
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42, 0x00, 0x00, 0x00]).len(), 5);
+
+    handle.toggle_analog();
+
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42, 0, 0, 0, 0, 0, 0, 0]),
+               [0xff, 0x73, 0x5a, 0xff, 0xff, 0x80, 0x80, 0x00, 0xff]);
+
+    // Enter the configuration mode
+    assert_eq!(transfer(&mut pad, &[0x01, 0x43, 0x00, 0x01, 0, 0, 0, 0, 0])[1], 0x73);
+
+    assert_eq!(transfer(&mut pad, &[0x01, 0x45, 0x00, 0, 0, 0, 0, 0, 0]),
+               [0xff, 0xf3, 0x5a, 0x01, 0x02, 0x01, 0x02, 0x01, 0x00]);
+
+    assert_eq!(&transfer(&mut pad, &[0x01, 0x46, 0x00, 0x01, 0, 0, 0, 0, 0])[3..],
+               [0x00, 0x00, 0x01, 0x01, 0x01, 0x14]);
+
+    assert_eq!(&transfer(&mut pad, &[0x01, 0x4c, 0x00, 0x00, 0, 0, 0, 0, 0])[3..],
+               [0x00, 0x00, 0x00, 0x04, 0x00, 0x00]);
+
+    // Digital mode, locked
+    transfer(&mut pad, &[0x01, 0x44, 0x00, 0x00, 0x03, 0, 0, 0, 0]);
+
+    // Exit the configuration mode
+    assert_eq!(transfer(&mut pad, &[0x01, 0x43, 0x00, 0x00, 0, 0, 0, 0, 0])[1], 0xf3);
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42, 0x00, 0x00, 0x00]).len(), 5);
+
+    // The Analog button is ignored
+    handle.toggle_analog();
+    assert_eq!(transfer(&mut pad, &[0x01, 0x42])[1], 0x41);
+}
+
+#[test]
+fn rumble() {
+    let mut pad = Controller::dual_shock();
+    let handle = pad.handle();
+
+    handle.toggle_analog();
+
+    // Map the small motor to byte 0 And the large one to byte 1
+    transfer(&mut pad, &[0x01, 0x43, 0x00, 0x01, 0, 0, 0, 0, 0]);
+
+    assert_eq!(&transfer(&mut pad, &[0x01, 0x4d, 0x00, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff])[3..],
+               [0xff; 6]);
+
+    transfer(&mut pad, &[0x01, 0x43, 0x00, 0x00, 0, 0, 0, 0, 0]);
+
+    assert!(handle.take_rumble_events().is_empty());
+
+    transfer(&mut pad, &[0x01, 0x42, 0x00, 0x01, 0xc0, 0, 0, 0, 0]);
+    // Same state, no new event
+    transfer(&mut pad, &[0x01, 0x42, 0x00, 0x01, 0xc0, 0, 0, 0, 0]);
+
+    assert_eq!(handle.take_rumble_events(), [Rumble { small: true, large: 0xc0 }]);
+
+    transfer(&mut pad, &[0x01, 0x42, 0x00, 0x00, 0x00, 0, 0, 0, 0]);
+
+    assert_eq!(handle.rumble(), Rumble { small: false, large: 0 });
+    assert_eq!(handle.take_rumble_events().len(), 1);
+
+    // Only the most recent changes are kept if the frontend doesn't
+    // retrieve them
+    for i in 0..RUMBLE_EVENTS_MAX + 10 {
+        transfer(&mut pad, &[0x01, 0x42, 0x00, 0x00, i as u8 + 1, 0, 0, 0, 0]);
+    }
+
+    let events = handle.take_rumble_events();
+
+    assert_eq!(events.len(), RUMBLE_EVENTS_MAX);
+    assert_eq!(events.last().unwrap().large, (RUMBLE_EVENTS_MAX + 10) as u8);
+}
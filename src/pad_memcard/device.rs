@@ -0,0 +1,76 @@
+use crate::gpu::timings::DisplayArea;
+
+/// Interface of the peripherals connected to one of the controller
+/// ports. The pads And the memory cards share the same serial bus, the
+/// first byte of each transfer tells which one is addressed.
+pub trait Peripheral {
+    /// Called when the /SELECT line of the port is asserted, before
+    /// the first byte of a new transfer
+    fn select(&mut self) {}
+
+    /// Exchange one byte with the peripheral. Returns the response
+    /// And whether the peripheral pulses /ACK to request the next
+    /// byte. The sequence ends once /ACK isn't asserted anymore.
+    fn exchange(&mut self, cmd: u8) -> (u8, bool);
+
+    /// Number of CPU cycles between the end of the byte And the /ACK
+    /// pulse
+    fn ack_delay(&self) -> u32 {
+        DEFAULT_ACK_DELAY
+    }
+
+    /// Called when the position of the picture in the video signal
+    /// may have changed, used by the light guns to convert the aimed
+    /// point to beam coordinates
+    fn set_display_area(&mut self, _area: DisplayArea) {}
+
+    /// Beam position at which the peripheral pulses the light pen
+    /// input (IRQ10), if any: X in GPU clock cycles since HSYNC, Y in
+    /// lines since VSYNC
+    fn light_pen(&self) -> Option<(u16, u16)> {
+        None
+    }
+}
+
+/// Delay before the /ACK pulse of a typical digital pad
+pub const DEFAULT_ACK_DELAY: u32 = 450;
+
+/// Empty slot: nothing drives the data line so it reads back as 0xff
+/// And the transfer is never acknowledged
+pub struct Disconnected;
+
+impl Peripheral for Disconnected {
+    fn exchange(&mut self, _: u8) -> (u8, bool) {
+        (0xff, false)
+    }
+}
+
+/// Run a full transfer, returns the response bytes up to the first
+/// one which isn't acknowledged
+#[cfg(test)]
+pub fn transfer(dev: &mut dyn Peripheral, bytes: &[u8]) -> Vec<u8> {
+    dev.select();
+
+    let mut response = Vec::new();
+
+    for &b in bytes {
+        let (r, ack) = dev.exchange(b);
+
+        response.push(r);
+
+        if !ack {
+            break;
+        }
+    }
+
+    response
+}
+
+/// Send all the `bytes` regardless of /ACK, returns the response
+/// bytes
+#[cfg(test)]
+pub fn transfer_all(dev: &mut dyn Peripheral, bytes: &[u8]) -> Vec<u8> {
+    dev.select();
+
+    bytes.iter().map(|&b| dev.exchange(b).0).collect()
+}
@@ -0,0 +1,143 @@
+use crate::gpu::timings::DisplayArea;
+
+use super::device::Peripheral;
+use super::light_gun::{GunButton, LightGun, LightGunHandle};
+
+#[cfg(test)]
+use super::device::transfer_all;
+
+/// GunCon ID
+const ID_GUNCON: u8 = 0x63;
+
+/// Button bits in the (active low) button state
+const BUTTON_A: u16 = 1 << 3;
+const BUTTON_TRIGGER: u16 = 1 << 13;
+const BUTTON_B: u16 = 1 << 14;
+
+/// Mapping of the light gun buttons
+const BUTTONS: [(GunButton, u16); 3] = [
+    (GunButton::Trigger, BUTTON_TRIGGER),
+    (GunButton::A, BUTTON_A),
+    (GunButton::B, BUTTON_B),
+];
+
+/// Frequency of the GPU video clock in Hz
+const GPU_CLOCK: u64 = 53_222_400;
+
+/// The GunCon counts the horizontal position with its own 8MHz clock
+const GUNCON_CLOCK: u64 = 8_000_000;
+
+/// Coordinates reported when the gun doesn't see the beam
+const OFFSCREEN_X: u16 = 0x0001;
+const OFFSCREEN_Y: u16 = 0x000a;
+
+/// Namco GunCon (NPC-103) light gun
+pub struct GunCon {
+    gun: LightGun,
+    /// Position in the current transfer
+    seq: usize,
+    /// Data bytes of the current read
+    response: [u8; 6],
+}
+
+impl GunCon {
+    pub fn new() -> GunCon {
+        GunCon {
+            gun: LightGun::new(),
+            seq: 0,
+            response: [0; 6],
+        }
+    }
+
+    pub fn handle(&self) -> LightGunHandle {
+        self.gun.handle()
+    }
+
+    fn read(&mut self) {
+        let buttons = self.gun.button_state(&BUTTONS);
+
+        // X is counted in 8MHz clock cycles since HSYNC
+        let (x, y) = match self.gun.beam_position() {
+            Some((gpu_x, line)) => ((gpu_x as u64 * GUNCON_CLOCK / GPU_CLOCK) as u16, line),
+            None => (OFFSCREEN_X, OFFSCREEN_Y),
+        };
+
+        self.response = [buttons as u8,
+                         (buttons >> 8) as u8,
+                         x as u8,
+                         (x >> 8) as u8,
+                         y as u8,
+                         (y >> 8) as u8];
+    }
+}
+
+impl Peripheral for GunCon {
+    fn select(&mut self) {
+        self.seq = 0;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        let seq = self.seq;
+
+        self.seq += 1;
+
+        match seq {
+            // Address byte, routed to us by the port
+            0 => (0xff, true),
+            1 => {
+                if cmd != 0x42 {
+                    return (0xff, false);
+                }
+
+                self.read();
+
+                (ID_GUNCON, true)
+            }
+            2 => (0x5a, true),
+            n if n < 3 + self.response.len() => {
+                let index = n - 3;
+
+                (self.response[index], index + 1 < self.response.len())
+            }
+            _ => (0xff, false),
+        }
+    }
+
+    fn set_display_area(&mut self, area: DisplayArea) {
+        self.gun.set_display_area(area);
+    }
+}
+
+#[test]
+fn guncon_read() {
+    let mut gun = GunCon::new();
+    let handle = gun.handle();
+
+    let read = [0x01, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    // Off-screen
+    handle.set_button(GunButton::Trigger, true);
+
+    assert_eq!(transfer_all(&mut gun, &read),
+               [0xff, 0x63, 0x5a, 0xff, 0xdf, 0x01, 0x00, 0x0a, 0x00]);
+
+    // Typical NTSC 320x240 picture
+    gun.set_display_area(DisplayArea {
+        horiz_start: 0x260,
+        horiz_end: 0xc60,
+        line_start: 0x10,
+        line_end: 0x100,
+    });
+
+    handle.set_button(GunButton::Trigger, false);
+    handle.set_button(GunButton::A, true);
+    handle.aim(Some((0.5, 0.5)));
+
+    // X: (0x260 + 0x500) * 8MHz / 53.2224MHz = 283, Y: 0x10 + 0x78
+    assert_eq!(transfer_all(&mut gun, &read),
+               [0xff, 0x63, 0x5a, 0xf7, 0xff, 0x1b, 0x01, 0x88, 0x00]);
+
+    handle.aim(Some((1.5, 0.5)));
+
+    assert_eq!(&transfer_all(&mut gun, &read)[5..], [0x01, 0x00, 0x0a, 0x00]);
+}
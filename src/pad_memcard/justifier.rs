@@ -0,0 +1,126 @@
+use crate::gpu::timings::DisplayArea;
+
+use super::device::Peripheral;
+use super::light_gun::{GunButton, LightGun, LightGunHandle};
+
+#[cfg(test)]
+use super::device::transfer_all;
+
+/// Konami light gun ID
+const ID_JUSTIFIER: u8 = 0x31;
+
+/// Button bits in the (active low) button state
+const BUTTON_START: u16 = 1 << 3;
+const BUTTON_BACK: u16 = 1 << 14;
+const BUTTON_TRIGGER: u16 = 1 << 15;
+
+/// Mapping of the light gun buttons
+const BUTTONS: [(GunButton, u16); 3] = [
+    (GunButton::Trigger, BUTTON_TRIGGER),
+    (GunButton::A, BUTTON_START),
+    (GunButton::B, BUTTON_BACK),
+];
+
+/// Konami Justifier (Hyper Blaster) light gun. Unlike the GunCon it
+/// doesn't report any coordinates: its sensor pulses the light pen
+/// input when it sees the beam And the game reads the root counters
+/// from the IRQ10 handler.
+pub struct Justifier {
+    gun: LightGun,
+    /// The light pen pulses are enabled by the game through the
+    /// third byte of the read command
+    irq_enabled: bool,
+    /// Position in the current transfer
+    seq: usize,
+    /// Button state of the current read
+    buttons: u16,
+}
+
+impl Justifier {
+    pub fn new() -> Justifier {
+        Justifier {
+            gun: LightGun::new(),
+            irq_enabled: false,
+            seq: 0,
+            buttons: 0xffff,
+        }
+    }
+
+    pub fn handle(&self) -> LightGunHandle {
+        self.gun.handle()
+    }
+}
+
+impl Peripheral for Justifier {
+    fn select(&mut self) {
+        self.seq = 0;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        let seq = self.seq;
+
+        self.seq += 1;
+
+        match seq {
+            // Address byte, routed to us by the port
+            0 => (0xff, true),
+            1 => {
+                if cmd != 0x42 {
+                    return (0xff, false);
+                }
+
+                self.buttons = self.gun.button_state(&BUTTONS);
+
+                (ID_JUSTIFIER, true)
+            }
+            2 => {
+                self.irq_enabled = cmd & 0x10 != 0;
+
+                (0x5a, true)
+            }
+            3 => (self.buttons as u8, true),
+            4 => ((self.buttons >> 8) as u8, false),
+            _ => (0xff, false),
+        }
+    }
+
+    fn set_display_area(&mut self, area: DisplayArea) {
+        self.gun.set_display_area(area);
+    }
+
+    fn light_pen(&self) -> Option<(u16, u16)> {
+        if !self.irq_enabled {
+            return None;
+        }
+
+        self.gun.beam_position()
+    }
+}
+
+#[test]
+fn justifier_read() {
+    let mut gun = Justifier::new();
+    let handle = gun.handle();
+
+    handle.set_button(GunButton::Trigger, true);
+    handle.aim(Some((0.5, 0.5)));
+
+    assert_eq!(transfer_all(&mut gun, &[0x01, 0x42, 0x00, 0x00, 0x00]),
+               [0xff, 0x31, 0x5a, 0xff, 0x7f]);
+
+    // The pulses are disabled
+    assert_eq!(gun.light_pen(), None);
+
+    handle.set_button(GunButton::Trigger, false);
+    handle.set_button(GunButton::A, true);
+
+    assert_eq!(transfer_all(&mut gun, &[0x01, 0x42, 0x10, 0x00, 0x00]),
+               [0xff, 0x31, 0x5a, 0xf7, 0xff]);
+
+    // Center of the picture with the GPU reset values
+    assert_eq!(gun.light_pen(), Some((0x200 + 0x500, 0x10 + 0x78)));
+
+    handle.aim(None);
+
+    assert_eq!(gun.light_pen(), None);
+}
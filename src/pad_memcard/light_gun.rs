@@ -0,0 +1,111 @@
+use std::sync::{Arc, Mutex};
+
+use crate::gpu::timings::DisplayArea;
+
+/// Light gun buttons, the value is the index in the shared state
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GunButton {
+    Trigger = 0,
+    /// A on the GunCon, Start on the Justifier
+    A = 1,
+    /// B on the GunCon, Back on the Justifier
+    B = 2,
+}
+
+/// State shared between the gun And its handle
+struct Shared {
+    /// Aimed point, normalized within the displayed picture
+    aim: Option<(f32, f32)>,
+    buttons: [bool; 3],
+}
+
+/// Handle used by the frontend to drive a light gun
+#[derive(Clone)]
+pub struct LightGunHandle(Arc<Mutex<Shared>>);
+
+impl LightGunHandle {
+    /// Point the gun at `(x, y)` in the displayed picture, `(0, 0)`
+    /// being the top-left corner And `(1, 1)` the bottom-right one.
+    /// `None` or a point outside of the picture aims off-screen.
+    pub fn aim(&self, aim: Option<(f32, f32)>) {
+        let aim = aim.filter(|&(x, y)| (0. ..=1.).contains(&x) && (0. ..=1.).contains(&y));
+
+        self.0.lock().unwrap().aim = aim;
+    }
+
+    pub fn set_button(&self, button: GunButton, pressed: bool) {
+        self.0.lock().unwrap().buttons[button as usize] = pressed;
+    }
+}
+
+/// Aim And buttons of a light gun, along with the position of the
+/// picture used to convert the aimed point into beam coordinates
+pub struct LightGun {
+    shared: Arc<Mutex<Shared>>,
+    area: DisplayArea,
+}
+
+impl LightGun {
+    pub fn new() -> LightGun {
+        let shared = Shared {
+            aim: None,
+            buttons: [false; 3],
+        };
+
+        LightGun {
+            shared: Arc::new(Mutex::new(shared)),
+            area: DisplayArea::new(),
+        }
+    }
+
+    pub fn handle(&self) -> LightGunHandle {
+        LightGunHandle(self.shared.clone())
+    }
+
+    pub fn set_display_area(&mut self, area: DisplayArea) {
+        self.area = area;
+    }
+
+    /// Beam position when it reaches the aimed point: X in GPU clock
+    /// cycles since HSYNC, Y in lines since VSYNC. `None` if the gun
+    /// aims off-screen.
+    pub fn beam_position(&self) -> Option<(u16, u16)> {
+        self.shared.lock().unwrap().aim.map(|aim| self.area.beam_position(aim))
+    }
+
+    /// Active low button state, `bits` gives the mask of each button
+    pub fn button_state(&self, bits: &[(GunButton, u16)]) -> u16 {
+        let shared = self.shared.lock().unwrap();
+
+        let mut state = 0xffff;
+
+        for &(button, mask) in bits {
+            if shared.buttons[button as usize] {
+                state &= !mask;
+            }
+        }
+
+        state
+    }
+}
+
+#[test]
+fn aim_and_buttons() {
+    let gun = LightGun::new();
+    let handle = gun.handle();
+
+    let bits = [(GunButton::Trigger, 1 << 13), (GunButton::A, 1 << 3)];
+
+    handle.set_button(GunButton::A, true);
+    // Not mapped
+    handle.set_button(GunButton::B, true);
+
+    assert_eq!(gun.button_state(&bits), 0xfff7);
+
+    // Center of the picture with the GPU reset values
+    handle.aim(Some((0.5, 0.5)));
+    assert_eq!(gun.beam_position(), Some((0x200 + 0x500, 0x10 + 0x78)));
+
+    handle.aim(Some((0.5, -0.1)));
+    assert_eq!(gun.beam_position(), None);
+}
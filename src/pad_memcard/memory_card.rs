@@ -0,0 +1,455 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::memcard::{format, CARD_SIZE, FRAME_SIZE};
+
+use super::device::Peripheral;
+
+#[cfg(test)]
+use super::device::{transfer, transfer_all};
+
+/// Size of a sector (a "frame") in bytes
+pub const SECTOR_SIZE: usize = FRAME_SIZE;
+
+/// Number of sectors on the card
+const SECTOR_COUNT: u16 = (CARD_SIZE / SECTOR_SIZE) as u16;
+
+/// FLAG bit set until the first write command after the card has
+/// been inserted. The BIOS uses it to detect card changes.
+const FLAG_NO_WRITE_YET: u8 = 0x08;
+
+/// FLAG bit set when the last write command failed
+const FLAG_WRITE_ERROR: u8 = 0x04;
+
+/// End byte of a successful command
+const STATUS_GOOD: u8 = 0x47;
+/// End byte of a write with a bad checksum
+const STATUS_BAD_CHECKSUM: u8 = 0x4e;
+/// End byte of an access to an invalid sector
+const STATUS_BAD_SECTOR: u8 = 0xff;
+
+/// Command being executed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Command {
+    /// Read a sector ("R")
+    Read,
+    /// Write a sector ("W")
+    Write,
+    /// Get the card's ID ("S")
+    GetId,
+}
+
+/// 128KB memory card, optionally backed by a raw image file
+/// (.mcr/.mcd)
+pub struct MemoryCard {
+    data: Vec<u8>,
+    /// Image file, updated after each write
+    file: Option<File>,
+    flag: u8,
+    /// Position in the current transfer
+    seq: usize,
+    /// Set once the card stopped acknowledging, the following bytes
+    /// of the transfer are ignored
+    done: bool,
+    command: Command,
+    /// Previous byte received, echoed by some steps of the sequences
+    previous: u8,
+    sector: u16,
+    checksum: u8,
+    /// Data received by the write command
+    buffer: [u8; SECTOR_SIZE],
+    /// End byte of the write command
+    write_status: u8,
+}
+
+impl MemoryCard {
+    /// Freshly formatted card, not backed by a file
+    pub fn new() -> MemoryCard {
+        let mut data = vec![0; CARD_SIZE];
+
+        format(&mut data);
+
+        MemoryCard::from_data(data, None)
+    }
+
+    /// Load the memory card image at `path`. A formatted image is
+    /// created if the file doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MemoryCard> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            let mut data = vec![0; CARD_SIZE];
+
+            format(&mut data);
+
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+
+            file.write_all(&data)?;
+            file.flush()?;
+
+            return Ok(MemoryCard::from_data(data, Some(file)));
+        }
+
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut data = Vec::new();
+
+        file.read_to_end(&mut data)?;
+
+        if data.len() != CARD_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("Invalid memory card size: {} bytes",
+                                          data.len())));
+        }
+
+        Ok(MemoryCard::from_data(data, Some(file)))
+    }
+
+    fn from_data(data: Vec<u8>, file: Option<File>) -> MemoryCard {
+        MemoryCard {
+            data,
+            file,
+            flag: FLAG_NO_WRITE_YET,
+            seq: 0,
+            done: false,
+            command: Command::Read,
+            previous: 0,
+            sector: 0,
+            checksum: 0,
+            buffer: [0; SECTOR_SIZE],
+            write_status: STATUS_GOOD,
+        }
+    }
+
+    /// Raw image of the card
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn sector_valid(&self) -> bool {
+        self.sector < SECTOR_COUNT
+    }
+
+    fn sector_data(&self) -> &[u8] {
+        let offset = self.sector as usize * SECTOR_SIZE;
+
+        &self.data[offset..offset + SECTOR_SIZE]
+    }
+
+    fn read(&mut self, seq: usize, cmd: u8) -> (u8, bool) {
+        match seq {
+            4 => {
+                self.sector = (cmd as u16) << 8;
+                (0x00, true)
+            }
+            5 => {
+                self.sector |= cmd as u16;
+                (self.previous, true)
+            }
+            6 => (0x5c, true),
+            7 => (0x5d, true),
+            8 => {
+                if !self.sector_valid() {
+                    return (0xff, true);
+                }
+
+                let msb = (self.sector >> 8) as u8;
+
+                self.checksum = msb;
+
+                (msb, true)
+            }
+            9 => {
+                if !self.sector_valid() {
+                    // The transfer is aborted
+                    return (0xff, false);
+                }
+
+                let lsb = self.sector as u8;
+
+                self.checksum ^= lsb;
+
+                (lsb, true)
+            }
+            10..=137 => {
+                let b = self.sector_data()[seq - 10];
+
+                self.checksum ^= b;
+
+                (b, true)
+            }
+            138 => (self.checksum, true),
+            _ => (STATUS_GOOD, false),
+        }
+    }
+
+    fn write(&mut self, seq: usize, cmd: u8) -> (u8, bool) {
+        let response = match seq {
+            4 => {
+                self.sector = (cmd as u16) << 8;
+                self.checksum = cmd;
+                0x00
+            }
+            5 => {
+                self.sector |= cmd as u16;
+                self.checksum ^= cmd;
+                self.previous
+            }
+            6..=133 => {
+                self.buffer[seq - 6] = cmd;
+                self.checksum ^= cmd;
+                self.previous
+            }
+            134 => {
+                self.write_status = if !self.sector_valid() {
+                    STATUS_BAD_SECTOR
+                } else if cmd != self.checksum {
+                    STATUS_BAD_CHECKSUM
+                } else {
+                    self.commit_write();
+                    STATUS_GOOD
+                };
+
+                self.previous
+            }
+            135 => 0x5c,
+            136 => 0x5d,
+            _ => {
+                self.flag &= !FLAG_NO_WRITE_YET;
+
+                if self.write_status == STATUS_GOOD {
+                    self.flag &= !FLAG_WRITE_ERROR;
+                } else {
+                    self.flag |= FLAG_WRITE_ERROR;
+                }
+
+                return (self.write_status, false);
+            }
+        };
+
+        (response, true)
+    }
+
+    /// Store the received sector And flush it to the image file
+    fn commit_write(&mut self) {
+        let offset = self.sector as usize * SECTOR_SIZE;
+
+        self.data[offset..offset + SECTOR_SIZE].copy_from_slice(&self.buffer);
+
+        if let Some(file) = self.file.as_mut() {
+            let res = file.seek(SeekFrom::Start(offset as u64))
+                .and_then(|_| file.write_all(&self.buffer))
+                .and_then(|_| file.flush());
+
+            if let Err(e) = res {
+                warn!("Couldn't write memory card sector {}: {}", self.sector, e);
+            }
+        }
+    }
+}
+
+impl Peripheral for MemoryCard {
+    fn select(&mut self) {
+        self.seq = 0;
+        self.done = false;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        if self.done {
+            return (0xff, false);
+        }
+
+        let seq = self.seq;
+
+        self.seq += 1;
+
+        let response = match seq {
+            // Address byte, routed to us by the port
+            0 => (0xff, true),
+            1 => {
+                self.command = match cmd {
+                    0x52 => Command::Read,
+                    0x57 => Command::Write,
+                    0x53 => Command::GetId,
+                    _ => {
+                        warn!("Unhandled memory card command 0x{:02x}", cmd);
+                        return (self.flag, false);
+                    }
+                };
+
+                (self.flag, true)
+            }
+            2 => (0x5a, true),
+            3 => (0x5d, true),
+            _ => match self.command {
+                Command::Read => self.read(seq, cmd),
+                Command::Write => self.write(seq, cmd),
+                Command::GetId => {
+                    let id = [0x5c, 0x5d, 0x04, 0x00, 0x00, 0x80];
+                    let index = seq - 4;
+
+                    (id[index], index + 1 < id.len())
+                }
+            },
+        };
+
+        self.previous = cmd;
+        self.done = !response.1;
+
+        response
+    }
+}
+
+#[cfg(test)]
+fn write_command(sector: u16, data: &[u8; SECTOR_SIZE], checksum_error: u8) -> Vec<u8> {
+    let msb = (sector >> 8) as u8;
+    let lsb = sector as u8;
+
+    let checksum = data.iter().fold(msb ^ lsb, |c, &b| c ^ b) ^ checksum_error;
+
+    let mut cmd = vec![0x81, 0x57, 0x00, 0x00, msb, lsb];
+
+    cmd.extend_from_slice(data);
+    cmd.extend_from_slice(&[checksum, 0x00, 0x00, 0x00]);
+
+    cmd
+}
+
+#[test]
+fn read_write() {
+    let mut card = MemoryCard::new();
+
+    assert_eq!(&card.data()[0..2], b"MC");
+    assert_eq!(card.data()[0x7f], b'M' ^ b'C');
+    assert_eq!(card.data()[0x80], 0xa0);
+    assert_eq!(card.data()[0xff], 0xa0);
+
+    // Read the first directory frame
+    let mut cmd = vec![0x81, 0x52, 0x00, 0x00, 0x00, 0x01];
+
+    cmd.resize(cmd.len() + 4 + 128 + 2, 0);
+
+    let r = transfer(&mut card, &cmd);
+
+    assert_eq!(r.len(), 140);
+    assert_eq!(&r[1..10], [0x08, 0x5a, 0x5d, 0x00, 0x00, 0x5c, 0x5d, 0x00, 0x01]);
+    assert_eq!(&r[10..138], &card.data()[0x80..0x100]);
+    // The frame's own checksum cancels out, only the address remains
+    assert_eq!(r[138], 0x01);
+    assert_eq!(r[139], STATUS_GOOD);
+
+    let data = [0x33; SECTOR_SIZE];
+
+    // Bad checksum
+    let r = transfer(&mut card, &write_command(0x3ff, &data, 1));
+
+    assert_eq!(r.len(), 138);
+    assert_eq!(r[137], STATUS_BAD_CHECKSUM);
+    assert_eq!(card.data()[0x3ff * 128], 0);
+
+    assert_eq!(transfer(&mut card, &[0x81, 0x53])[1], FLAG_WRITE_ERROR);
+
+    let r = transfer(&mut card, &write_command(0x3ff, &data, 0));
+
+    // The bytes are echoed one byte late
+    assert_eq!(r[6], 0xff);
+    assert_eq!(r[7], 0x33);
+    assert_eq!(r[137], STATUS_GOOD);
+    assert_eq!(&card.data()[0x3ff * 128..], &data);
+
+    // Invalid sector
+    let mut cmd = vec![0x81, 0x52, 0x00, 0x00, 0x04, 0x00, 0, 0, 0, 0];
+
+    cmd.resize(140, 0);
+
+    let r = transfer(&mut card, &cmd);
+
+    assert_eq!(r.len(), 10);
+    assert_eq!(&r[8..], [0xff, 0xff]);
+}
+
+#[test]
+fn get_id() {
+    let mut card = MemoryCard::new();
+
+    assert_eq!(transfer(&mut card, &[0x81, 0x53, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+               [0xff, 0x08, 0x5a, 0x5d, 0x5c, 0x5d, 0x04, 0x00, 0x00, 0x80]);
+
+    // Unknown command
+    assert_eq!(transfer(&mut card, &[0x81, 0x00, 0x00]), [0xff, 0x08]);
+}
+
+#[test]
+fn past_the_end() {
+    let mut card = MemoryCard::new();
+
+    let r = transfer_all(&mut card, &[0x81, 0x53, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert_eq!(r[9], 0x80);
+    assert_eq!(&r[10..], [0xff, 0xff]);
+
+    // Read of an invalid sector, aborted after the address
+    let mut cmd = vec![0x81, 0x52, 0x00, 0x00, 0x04, 0x00];
+
+    cmd.resize(200, 0);
+
+    let r = transfer_all(&mut card, &cmd);
+
+    assert!(r[10..].iter().all(|&b| b == 0xff));
+
+    // Full read followed by extra bytes
+    let mut cmd = vec![0x81, 0x52, 0x00, 0x00, 0x00, 0x01];
+
+    cmd.resize(200, 0);
+
+    let r = transfer_all(&mut card, &cmd);
+
+    assert_eq!(r[139], STATUS_GOOD);
+    assert!(r[140..].iter().all(|&b| b == 0xff));
+
+    // Full write followed by extra bytes
+    let mut cmd = write_command(0x10, &[0x11; SECTOR_SIZE], 0);
+
+    cmd.resize(200, 0);
+
+    let r = transfer_all(&mut card, &cmd);
+
+    assert_eq!(r[137], STATUS_GOOD);
+    assert!(r[138..].iter().all(|&b| b == 0xff));
+    assert_eq!(transfer(&mut card, &[0x81, 0x53])[1], 0x00);
+}
+
+#[test]
+fn image_file() {
+    let path = std::env::temp_dir().join(format!("memcard-{}.mcr", std::process::id()));
+
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let mut card = MemoryCard::open(&path).unwrap();
+
+        let data = [0x5a; SECTOR_SIZE];
+
+        transfer(&mut card, &write_command(0x40, &data, 0));
+    }
+
+    let image = std::fs::read(&path).unwrap();
+
+    assert_eq!(image.len(), CARD_SIZE);
+    assert_eq!(&image[0..2], b"MC");
+    assert!(image[0x40 * 128..0x41 * 128].iter().all(|&b| b == 0x5a));
+
+    let card = MemoryCard::open(&path).unwrap();
+
+    assert_eq!(card.data(), &image[..]);
+
+    std::fs::write(&path, [0; 1024]).unwrap();
+    assert!(MemoryCard::open(&path).is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
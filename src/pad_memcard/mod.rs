@@ -0,0 +1,416 @@
+use crate::gpu::timings::DisplayArea;
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+use crate::sio::{self, Framing, Interface, Sio};
+
+use self::device::{Disconnected, Peripheral};
+use self::multitap::Multitap;
+
+pub mod controller;
+pub mod device;
+pub mod guncon;
+pub mod justifier;
+pub mod light_gun;
+pub mod memory_card;
+pub mod mouse;
+pub mod multitap;
+
+/// Duration of the /ACK pulse in CPU cycles
+const ACK_LENGTH: u32 = 100;
+
+/// Controller And memory card serial interface (SIO0)
+pub struct PadMemCard {
+    sio: Sio,
+    /// State of the /JOYn output (selects the peripheral)
+    select: bool,
+    /// Port targeted by /JOYn (0 for port 1, 1 for port 2)
+    target: usize,
+    /// Interrupt on /ACK
+    ack_irq_enable: bool,
+    /// JOY_CTRL bits which are stored as-is
+    control: u16,
+    /// Cycles before the /ACK pulse of the last transfer
+    ack_delay: Option<u32>,
+    /// Cycles before the end of the current /ACK pulse
+    ack_pulse: Option<u32>,
+    ports: [Port; 2],
+}
+
+impl PadMemCard {
+    pub fn new() -> PadMemCard {
+        PadMemCard {
+            sio: Sio::new("SIO0", Framing::Synchronous, Interrupt::PadMemCard),
+            select: false,
+            target: 0,
+            ack_irq_enable: false,
+            control: 0,
+            ack_delay: None,
+            ack_pulse: None,
+            ports: [Port::new(), Port::new()],
+        }
+    }
+
+    /// Plug a controller in `port` (0 or 1)
+    pub fn connect_pad(&mut self, port: usize, pad: Box<dyn Peripheral>) {
+        self.ports[port].pad = pad;
+    }
+
+    /// Plug a memory card in `port` (0 or 1)
+    pub fn connect_memory_card(&mut self, port: usize, card: Box<dyn Peripheral>) {
+        self.ports[port].memory_card = card;
+    }
+
+    /// Plug a multitap in `port` (0 or 1). The pad And memory card
+    /// connected directly to the port are hidden while it's plugged.
+    pub fn connect_multitap(&mut self, port: usize, multitap: Multitap) {
+        self.ports[port].multitap = Some(Box::new(multitap));
+    }
+
+    /// Forward the position of the picture to the peripherals
+    pub fn set_display_area(&mut self, area: DisplayArea) {
+        for port in self.ports.iter_mut() {
+            port.set_display_area(area);
+        }
+    }
+
+    /// Beam position at which one of the peripherals pulses the
+    /// light pen input, if any
+    pub fn light_pen(&self) -> Option<(u16, u16)> {
+        self.ports.iter().find_map(|port| port.light_pen())
+    }
+
+    /// Advance the state machine by `cycles` CPU cycles
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptController) {
+        sio::tick(self, cycles, irq)
+    }
+
+    /// Number of cycles before the next event, if any
+    pub fn cycles_to_next_event(&self) -> Option<u32> {
+        sio::cycles_to_next_event(self)
+    }
+
+    pub fn load(&mut self, offset: u32) -> u32 {
+        match offset {
+            0 => self.sio.read() as u32,
+            4 => self.status(),
+            8 => self.sio.mode() as u32,
+            0xa => self.control() as u32,
+            0xe => self.sio.baud() as u32,
+            _ => {
+                warn!("Unhandled SIO load at offset 0x{:x}", offset);
+                0
+            }
+        }
+    }
+
+    pub fn store(&mut self, offset: u32, val: u16) {
+        match offset {
+            0 => self.sio.send(val as u8),
+            8 => self.sio.set_mode(val),
+            0xa => self.set_control(val),
+            0xe => self.sio.set_baud(val),
+            _ => warn!("Unhandled SIO store at offset 0x{:x}: 0x{:04x}", offset, val),
+        }
+    }
+
+    fn status(&self) -> u32 {
+        let mut r = self.sio.status();
+
+        // /ACK input level, inverted
+        r |= (self.ack_pulse.is_some() as u32) << 7;
+
+        r
+    }
+
+    fn control(&self) -> u16 {
+        let mut r = self.control;
+
+        r |= self.sio.tx_enabled() as u16;
+        r |= (self.select as u16) << 1;
+        r |= (self.ack_irq_enable as u16) << 12;
+        r |= (self.target as u16) << 13;
+
+        r
+    }
+
+    fn set_control(&mut self, val: u16) {
+        if val & 0x40 != 0 {
+            // Reset
+            self.sio.reset();
+            self.ack_delay = None;
+            self.ack_pulse = None;
+            self.set_control(0);
+            return;
+        }
+
+        if val & 0x10 != 0 {
+            // Acknowledge the interrupt
+            self.sio.acknowledge_irq();
+        }
+
+        let select = val & 2 != 0;
+        let target = ((val >> 13) & 1) as usize;
+
+        if !select {
+            // The pending /ACK is lost when the peripheral is
+            // deselected
+            self.ack_delay = None;
+        } else if !self.select || target != self.target {
+            self.ports[target].select();
+        }
+
+        self.select = select;
+        self.target = target;
+        self.ack_irq_enable = val & 0x1000 != 0;
+        // RX enable, RX interrupt mode And enable, TX interrupt
+        // enable. Bits 4 And 6 are write-only.
+        self.control = val & 0x0f2c;
+
+        self.sio.set_tx_enable(val & 1 != 0);
+    }
+
+    /// The peripheral pulls /ACK low
+    fn acknowledge(&mut self, irq: &mut InterruptController) {
+        self.ack_pulse = Some(ACK_LENGTH);
+
+        if self.ack_irq_enable {
+            self.sio.raise_irq(irq);
+        }
+    }
+}
+
+impl Interface for PadMemCard {
+    fn sio(&self) -> &Sio {
+        &self.sio
+    }
+
+    fn sio_mut(&mut self) -> &mut Sio {
+        &mut self.sio
+    }
+
+    fn next_port_event(&self) -> Option<u32> {
+        [self.ack_delay, self.ack_pulse]
+            .iter()
+            .filter_map(|&d| d)
+            .min()
+    }
+
+    fn advance_port(&mut self, cycles: u32, irq: &mut InterruptController) {
+        if let Some(delay) = self.ack_pulse {
+            let delay = delay - cycles;
+
+            self.ack_pulse = if delay > 0 { Some(delay) } else { None };
+        }
+
+        if let Some(delay) = self.ack_delay {
+            let delay = delay - cycles;
+
+            if delay > 0 {
+                self.ack_delay = Some(delay);
+            } else {
+                self.ack_delay = None;
+                self.acknowledge(irq);
+            }
+        }
+    }
+
+    fn end_transfer(&mut self, byte: u8, _: &mut InterruptController) {
+        let (response, ack_delay) = if self.select {
+            self.ports[self.target].exchange(byte)
+        } else {
+            (0xff, None)
+        };
+
+        // The response is lost if the RX FIFO is full
+        self.sio.receive(response);
+
+        self.ack_delay = ack_delay.map(|d| d.max(1));
+    }
+}
+
+/// Peripheral addressed by the current transfer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Target {
+    Pad,
+    MemoryCard,
+}
+
+/// One of the two controller ports with its memory card slot. Also
+/// used for the slots of the multitap.
+struct Port {
+    pad: Box<dyn Peripheral>,
+    memory_card: Box<dyn Peripheral>,
+    /// Multitap plugged in the port, it receives all the traffic
+    multitap: Option<Box<Multitap>>,
+    /// Peripheral which answered the first byte of the transfer
+    active: Option<Target>,
+}
+
+impl Port {
+    fn new() -> Port {
+        Port {
+            pad: Box::new(Disconnected),
+            memory_card: Box::new(Disconnected),
+            multitap: None,
+            active: None,
+        }
+    }
+
+    fn select(&mut self) {
+        if let Some(multitap) = self.multitap.as_mut() {
+            return multitap.select();
+        }
+
+        self.active = None;
+        self.pad.select();
+        self.memory_card.select();
+    }
+
+    fn set_display_area(&mut self, area: DisplayArea) {
+        if let Some(multitap) = self.multitap.as_mut() {
+            multitap.set_display_area(area);
+        }
+
+        self.pad.set_display_area(area);
+        self.memory_card.set_display_area(area);
+    }
+
+    fn light_pen(&self) -> Option<(u16, u16)> {
+        match self.multitap.as_ref() {
+            Some(multitap) => multitap.light_pen(),
+            None => self.pad.light_pen(),
+        }
+    }
+
+    /// Exchange a byte with the addressed peripheral. Returns the
+    /// response And the /ACK delay if the peripheral acknowledged it.
+    fn exchange(&mut self, cmd: u8) -> (u8, Option<u32>) {
+        if let Some(multitap) = self.multitap.as_mut() {
+            let (response, ack) = multitap.exchange(cmd);
+
+            return (response, if ack { Some(multitap.ack_delay()) } else { None });
+        }
+
+        let target = match self.active {
+            Some(t) => t,
+            None => match cmd {
+                0x01 => Target::Pad,
+                0x81 => Target::MemoryCard,
+                _ => return (0xff, None),
+            },
+        };
+
+        self.active = Some(target);
+
+        let device = match target {
+            Target::Pad => &mut self.pad,
+            Target::MemoryCard => &mut self.memory_card,
+        };
+
+        let (response, ack) = device.exchange(cmd);
+
+        if ack {
+            (response, Some(device.ack_delay()))
+        } else {
+            (response, None)
+        }
+    }
+}
+
+/// Peripheral returning the complement of each byte, for 3 bytes
+#[cfg(test)]
+struct TestDevice(u8);
+
+#[cfg(test)]
+impl Peripheral for TestDevice {
+    fn select(&mut self) {
+        self.0 = 0;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        self.0 += 1;
+
+        (!cmd, self.0 < 3)
+    }
+}
+
+#[test]
+fn transfer_and_ack() {
+    let mut irq = InterruptController::new();
+    let mut sio = PadMemCard::new();
+
+    sio.connect_pad(1, Box::new(TestDevice(0)));
+
+    sio.store(0xe, 0x88);
+    sio.store(8, 0x0d);
+    // Port 2, TX enable, /JOY2, /ACK interrupt
+    sio.store(0xa, 0x3003);
+
+    assert_eq!(sio.load(4) & 5, 5);
+
+    sio.store(0, 0x01);
+
+    assert_eq!(sio.load(4) & 5, 1);
+    assert_eq!(sio.cycles_to_next_event(), Some(0x88 * 8));
+
+    sio.tick(0x88 * 8, &mut irq);
+
+    assert_eq!(sio.load(4) & 7, 7);
+    assert_eq!(sio.load(0), 0xfe);
+    assert_eq!(sio.load(4) & 0x282, 0);
+
+    // /ACK pulse
+    sio.tick(device::DEFAULT_ACK_DELAY, &mut irq);
+
+    assert_eq!(sio.load(4) & 0x280, 0x280);
+    assert_eq!(irq.status(), 1 << 7);
+
+    sio.tick(ACK_LENGTH, &mut irq);
+    assert_eq!(sio.load(4) & 0x280, 0x200);
+
+    sio.store(0xa, 0x3013);
+    assert_eq!(sio.load(4) & 0x200, 0);
+
+    // Back to back bytes
+    sio.store(0, 0x42);
+    sio.store(0, 0x00);
+    assert_eq!(sio.load(4) & 5, 0);
+
+    sio.tick(0x88 * 16 + device::DEFAULT_ACK_DELAY + ACK_LENGTH, &mut irq);
+
+    assert_eq!(sio.load(0), 0xbd);
+    assert_eq!(sio.load(0), 0xff);
+    assert_eq!(sio.load(4) & 0x282, 0x200);
+
+    // The last byte isn't acknowledged
+    sio.store(0xa, 0x3013);
+    sio.tick(10_000, &mut irq);
+    assert_eq!(sio.load(4) & 0x200, 0);
+}
+
+#[test]
+fn unselected_port() {
+    let mut irq = InterruptController::new();
+    let mut sio = PadMemCard::new();
+
+    sio.connect_pad(1, Box::new(TestDevice(0)));
+
+    sio.store(0xe, 0x88);
+    // Port 1 is empty
+    sio.store(0xa, 0x1003);
+    sio.store(0, 0x01);
+
+    sio.tick(10_000, &mut irq);
+
+    assert_eq!(sio.load(0), 0xff);
+    assert_eq!(irq.status(), 0);
+
+    // Memory card access on port 2: no card plugged
+    sio.store(0xa, 0x3003);
+    sio.store(0, 0x81);
+
+    sio.tick(10_000, &mut irq);
+
+    assert_eq!(sio.load(0), 0xff);
+    assert_eq!(irq.status(), 0);
+}
@@ -0,0 +1,149 @@
+use std::sync::{Arc, Mutex};
+
+use super::device::Peripheral;
+
+#[cfg(test)]
+use super::device::transfer_all;
+
+/// Mouse ID
+const ID_MOUSE: u8 = 0x12;
+
+/// Button bits in the (active low) button state
+const BUTTON_RIGHT: u16 = 1 << 10;
+const BUTTON_LEFT: u16 = 1 << 11;
+
+/// State shared between the mouse And its handle
+struct Shared {
+    /// Motion not reported to the console yet
+    dx: i32,
+    dy: i32,
+    left: bool,
+    right: bool,
+}
+
+/// Handle used by the frontend to drive the mouse
+#[derive(Clone)]
+pub struct MouseHandle(Arc<Mutex<Shared>>);
+
+impl MouseHandle {
+    /// Accumulate a relative motion, positive `dy` goes down
+    pub fn move_by(&self, dx: i32, dy: i32) {
+        let mut shared = self.0.lock().unwrap();
+
+        shared.dx += dx;
+        shared.dy += dy;
+    }
+
+    pub fn set_buttons(&self, left: bool, right: bool) {
+        let mut shared = self.0.lock().unwrap();
+
+        shared.left = left;
+        shared.right = right;
+    }
+}
+
+/// SCPH-1090 PlayStation Mouse
+pub struct Mouse {
+    shared: Arc<Mutex<Shared>>,
+    /// Position in the current transfer
+    seq: usize,
+    /// Data bytes of the current read
+    response: [u8; 4],
+}
+
+impl Mouse {
+    pub fn new() -> Mouse {
+        let shared = Shared {
+            dx: 0,
+            dy: 0,
+            left: false,
+            right: false,
+        };
+
+        Mouse {
+            shared: Arc::new(Mutex::new(shared)),
+            seq: 0,
+            response: [0; 4],
+        }
+    }
+
+    pub fn handle(&self) -> MouseHandle {
+        MouseHandle(self.shared.clone())
+    }
+
+    fn read(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+
+        let mut buttons = 0xffff;
+
+        if shared.left {
+            buttons &= !BUTTON_LEFT;
+        }
+
+        if shared.right {
+            buttons &= !BUTTON_RIGHT;
+        }
+
+        // The motion is reported 8 bits at a time, the rest is kept
+        // for the next read
+        let dx = shared.dx.clamp(-128, 127);
+        let dy = shared.dy.clamp(-128, 127);
+
+        shared.dx -= dx;
+        shared.dy -= dy;
+
+        self.response = [buttons as u8, (buttons >> 8) as u8, dx as u8, dy as u8];
+    }
+}
+
+impl Peripheral for Mouse {
+    fn select(&mut self) {
+        self.seq = 0;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        let seq = self.seq;
+
+        self.seq += 1;
+
+        match seq {
+            // Address byte, routed to us by the port
+            0 => (0xff, true),
+            1 => {
+                if cmd != 0x42 {
+                    return (0xff, false);
+                }
+
+                self.read();
+
+                (ID_MOUSE, true)
+            }
+            2 => (0x5a, true),
+            n if n < 3 + self.response.len() => {
+                let index = n - 3;
+
+                (self.response[index], index + 1 < self.response.len())
+            }
+            _ => (0xff, false),
+        }
+    }
+}
+
+#[test]
+fn mouse_read() {
+    let mut mouse = Mouse::new();
+    let handle = mouse.handle();
+
+    let read = [0x01, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    handle.move_by(200, -5);
+    handle.set_buttons(true, false);
+
+    assert_eq!(transfer_all(&mut mouse, &read), [0xff, 0x12, 0x5a, 0xff, 0xf7, 127, (-5i8) as u8]);
+
+    // The rest of the motion
+    handle.set_buttons(false, true);
+
+    assert_eq!(transfer_all(&mut mouse, &read), [0xff, 0x12, 0x5a, 0xff, 0xfb, 73, 0]);
+    assert_eq!(&transfer_all(&mut mouse, &read)[5..], [0, 0]);
+}
@@ -0,0 +1,277 @@
+use crate::gpu::timings::DisplayArea;
+
+use super::device::{Peripheral, DEFAULT_ACK_DELAY};
+use super::Port;
+
+#[cfg(test)]
+use super::device::transfer;
+
+/// Number of slots
+const SLOT_COUNT: usize = 4;
+
+/// Bytes returned for each slot in a multitap read: ID, 0x5a And 6
+/// data bytes
+const SLOT_BYTES: usize = 8;
+
+/// Multitap ID, returned instead of the controller ID in a multitap
+/// read
+const ID_MULTITAP: u8 = 0x80;
+
+/// Routing of the current transfer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Waiting for the address byte
+    Idle,
+    /// Transfer forwarded to the pad or memory card of a slot
+    Forward(usize),
+    /// Read of the four controllers at once
+    ReadAll,
+    /// Nobody answers
+    Ignore,
+}
+
+/// SCPH-1070 Multitap, four controller And memory card slots on one
+/// port
+pub struct Multitap {
+    slots: [Port; SLOT_COUNT],
+    mode: Mode,
+    /// Position in the current transfer
+    seq: usize,
+    /// Command of the current transfer
+    command: u8,
+    /// Set when the software requested a multitap read for the next
+    /// transfer
+    read_all_next: bool,
+    /// Multitap read requested for the current transfer
+    read_all: bool,
+    /// The current slot still acknowledges in multitap read
+    slot_active: bool,
+    /// /ACK delay of the last byte
+    ack_delay: u32,
+}
+
+impl Multitap {
+    pub fn new() -> Multitap {
+        Multitap {
+            slots: [Port::new(), Port::new(), Port::new(), Port::new()],
+            mode: Mode::Idle,
+            seq: 0,
+            command: 0,
+            read_all_next: false,
+            read_all: false,
+            slot_active: false,
+            ack_delay: DEFAULT_ACK_DELAY,
+        }
+    }
+
+    /// Plug a controller in `slot` (0 to 3 for A to D)
+    pub fn connect_pad(&mut self, slot: usize, pad: Box<dyn Peripheral>) {
+        self.slots[slot].pad = pad;
+    }
+
+    /// Plug a memory card in `slot` (0 to 3 for A to D)
+    pub fn connect_memory_card(&mut self, slot: usize, card: Box<dyn Peripheral>) {
+        self.slots[slot].memory_card = card;
+    }
+
+    /// Convert the result of a slot exchange
+    fn forward_result(&mut self, (response, ack): (u8, Option<u32>)) -> (u8, bool) {
+        match ack {
+            Some(delay) => {
+                self.ack_delay = delay;
+                (response, true)
+            }
+            None => (response, false),
+        }
+    }
+
+    /// Address byte: 0x01-0x04 for the controllers in slots A to D,
+    /// 0x81-0x84 for the memory cards
+    fn address(&mut self, cmd: u8) -> (u8, bool) {
+        if self.read_all && cmd == 0x01 {
+            self.mode = Mode::ReadAll;
+            return (0xff, true);
+        }
+
+        let slot = (cmd & 0x7f) as usize;
+
+        if !(1..=SLOT_COUNT).contains(&slot) {
+            self.mode = Mode::Ignore;
+            return (0xff, false);
+        }
+
+        let slot = slot - 1;
+
+        self.mode = Mode::Forward(slot);
+        self.slots[slot].select();
+
+        let res = self.slots[slot].exchange((cmd & 0x80) | 0x01);
+
+        self.forward_result(res)
+    }
+
+    fn forward(&mut self, slot: usize, seq: usize, cmd: u8) -> (u8, bool) {
+        match seq {
+            1 => self.command = cmd,
+            // The "tap" byte of a read command selects the mode of
+            // the next transfer
+            2 if self.command == 0x42 => self.read_all_next = cmd == 0x01,
+            _ => (),
+        }
+
+        let res = self.slots[slot].exchange(cmd);
+
+        self.forward_result(res)
+    }
+
+    fn read_all(&mut self, seq: usize, cmd: u8) -> (u8, bool) {
+        match seq {
+            1 => {
+                self.command = cmd;
+
+                if cmd != 0x42 {
+                    return (0xff, false);
+                }
+
+                (ID_MULTITAP, true)
+            }
+            2 => {
+                self.read_all_next = cmd == 0x01;
+                (0x5a, true)
+            }
+            _ => {
+                let n = seq - 3;
+
+                if n >= SLOT_COUNT * SLOT_BYTES {
+                    return (0xff, false);
+                }
+
+                let slot = n / SLOT_BYTES;
+                let port = &mut self.slots[slot];
+
+                if n.is_multiple_of(SLOT_BYTES) {
+                    // Address the controller, then send the command
+                    port.select();
+                    self.slot_active = port.exchange(0x01).1.is_some();
+                }
+
+                let response = if self.slot_active {
+                    let (r, ack) = port.exchange(cmd);
+
+                    self.slot_active = ack.is_some();
+
+                    r
+                } else {
+                    0xff
+                };
+
+                (response, n + 1 < SLOT_COUNT * SLOT_BYTES)
+            }
+        }
+    }
+}
+
+impl Peripheral for Multitap {
+    fn select(&mut self) {
+        self.seq = 0;
+        self.mode = Mode::Idle;
+        self.read_all = self.read_all_next;
+    }
+
+    fn exchange(&mut self, cmd: u8) -> (u8, bool) {
+        let seq = self.seq;
+
+        self.seq += 1;
+        self.ack_delay = DEFAULT_ACK_DELAY;
+
+        match self.mode {
+            Mode::Idle => self.address(cmd),
+            Mode::Forward(slot) => self.forward(slot, seq, cmd),
+            Mode::ReadAll => self.read_all(seq, cmd),
+            Mode::Ignore => (0xff, false),
+        }
+    }
+
+    fn ack_delay(&self) -> u32 {
+        self.ack_delay
+    }
+
+    fn set_display_area(&mut self, area: DisplayArea) {
+        for slot in self.slots.iter_mut() {
+            slot.set_display_area(area);
+        }
+    }
+
+    fn light_pen(&self) -> Option<(u16, u16)> {
+        self.slots.iter().find_map(|slot| slot.light_pen())
+    }
+}
+
+#[test]
+fn slot_access() {
+    use super::controller::{Button, Controller, InputState};
+    use super::memory_card::MemoryCard;
+
+    let mut tap = Multitap::new();
+
+    let pad = Controller::digital();
+    let mut input = InputState::new();
+
+    input.set_button(Button::Circle, true);
+    pad.handle().set_input(input);
+
+    tap.connect_pad(1, Box::new(pad));
+    tap.connect_memory_card(3, Box::new(MemoryCard::new()));
+
+    assert_eq!(transfer(&mut tap, &[0x02, 0x42, 0x00, 0x00, 0x00]),
+               [0xff, 0x41, 0x5a, 0xff, 0xdf]);
+
+    // Empty slot
+    assert_eq!(transfer(&mut tap, &[0x01, 0x42, 0x00]), [0xff]);
+
+    // Memory card in slot D
+    assert_eq!(transfer(&mut tap, &[0x84, 0x53, 0x00, 0x00])[1..], [0x08, 0x5a, 0x5d]);
+    assert_eq!(transfer(&mut tap, &[0x81, 0x53]), [0xff]);
+}
+
+#[test]
+fn read_all() {
+    use super::controller::{Button, Controller, InputState};
+
+    let mut tap = Multitap::new();
+
+    let pad_a = Controller::digital();
+    let pad_c = Controller::dual_shock();
+    let mut input = InputState::new();
+
+    input.set_button(Button::Select, true);
+    pad_a.handle().set_input(input);
+    pad_c.handle().toggle_analog();
+
+    tap.connect_pad(0, Box::new(pad_a));
+    tap.connect_pad(2, Box::new(pad_c));
+
+    // Request the multitap mode
+    assert_eq!(transfer(&mut tap, &[0x01, 0x42, 0x01, 0x00, 0x00]).len(), 5);
+
+    let mut cmd = vec![0x01, 0x42, 0x01];
+
+    for _ in 0..4 {
+        cmd.extend_from_slice(&[0x42, 0x00, 0, 0, 0, 0, 0, 0]);
+    }
+
+    let r = transfer(&mut tap, &cmd);
+
+    assert_eq!(r.len(), 35);
+    assert_eq!(&r[0..3], [0xff, 0x80, 0x5a]);
+    assert_eq!(&r[3..11], [0x41, 0x5a, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(&r[11..19], [0xff; 8]);
+    assert_eq!(&r[19..27], [0x73, 0x5a, 0xff, 0xff, 0x80, 0x80, 0x80, 0x80]);
+    assert_eq!(&r[27..35], [0xff; 8]);
+
+    // Back to the normal mode after the next transfer
+    cmd[2] = 0x00;
+
+    assert_eq!(transfer(&mut tap, &cmd)[1], 0x80);
+    assert_eq!(transfer(&mut tap, &cmd)[1], 0x41);
+}
@@ -0,0 +1,33 @@
+/// Devices which can register a deadline with the scheduler
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Device {
+    /// Video timings (hblank, vblank And dot clock)
+    Gpu = 0,
+    /// Root counters
+    Timers = 1,
+    /// CD-ROM controller
+    CdRom = 2,
+    /// Sound Processing Unit
+    Spu = 3,
+    /// Controller And memory card interface
+    PadMemCard = 4,
+    /// Serial port (SIO1)
+    Serial = 5,
+}
+
+impl Device {
+    /// Number of devices
+    pub const COUNT: usize = 6;
+
+    pub fn from_index(index: usize) -> Device {
+        match index {
+            0 => Device::Gpu,
+            1 => Device::Timers,
+            2 => Device::CdRom,
+            3 => Device::Spu,
+            4 => Device::PadMemCard,
+            5 => Device::Serial,
+            n => panic!("Invalid device {}", n),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use self::device::Device;
+
+pub mod device;
+
+/// Type used to count CPU clock cycles
+pub type Cycles = u64;
+
+/// Central event scheduler. The CPU advances the global cycle counter
+/// as it runs And the devices register the date of their next event
+/// (interrupt, video signal...). Devices are only synchronized when
+/// one of those deadlines is reached or when the CPU accesses them,
+/// this way the emulated time drives all the peripherals
+/// deterministically.
+pub struct Scheduler {
+    /// Global cycle counter
+    now: Cycles,
+
+    /// Date of the next event for each device, `Cycles::MAX` if none
+    deadlines: [Cycles; Device::COUNT],
+
+    /// Date of the last synchronization for each device
+    last_sync: [Cycles; Device::COUNT],
+
+    /// Closest deadline
+    next_event: Cycles,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            deadlines: [Cycles::MAX; Device::COUNT],
+            last_sync: [0; Device::COUNT],
+            next_event: Cycles::MAX,
+        }
+    }
+
+    /// Current date
+    pub fn now(&self) -> Cycles {
+        self.now
+    }
+
+    /// Advance the global cycle counter
+    pub fn tick(&mut self, cycles: u32) {
+        self.now += cycles as Cycles;
+    }
+
+    /// Return true if at least one deadline has been reached
+    pub fn is_due(&self) -> bool {
+        self.now >= self.next_event
+    }
+
+    /// Register an event for `device` in `delay` cycles, replacing
+    /// the previous one
+    pub fn schedule(&mut self, device: Device, delay: Cycles) {
+        self.deadlines[device as usize] = self.now.saturating_add(delay);
+        self.update_next_event();
+    }
+
+    /// Remove the pending event for `device`
+    pub fn cancel(&mut self, device: Device) {
+        self.deadlines[device as usize] = Cycles::MAX;
+        self.update_next_event();
+    }
+
+    /// Date of the next event for `device`
+    pub fn deadline(&self, device: Device) -> Cycles {
+        self.deadlines[device as usize]
+    }
+
+    /// Return a device whose deadline has been reached And clear its
+    /// deadline. Returns `None` once all the due events have been
+    /// handled.
+    pub fn pop_due(&mut self) -> Option<Device> {
+        if !self.is_due() {
+            return None;
+        }
+
+        let index = (0..Device::COUNT)
+            .min_by_key(|&i| self.deadlines[i])
+            .unwrap();
+
+        self.deadlines[index] = Cycles::MAX;
+        self.update_next_event();
+
+        Some(Device::from_index(index))
+    }
+
+    /// Return the number of cycles elapsed since the last call for
+    /// `device`
+    pub fn elapsed(&mut self, device: Device) -> Cycles {
+        let elapsed = self.now - self.last_sync[device as usize];
+
+        self.last_sync[device as usize] = self.now;
+
+        elapsed
+    }
+
+    fn update_next_event(&mut self) {
+        self.next_event = *self.deadlines.iter().min().unwrap();
+    }
+}
+
+#[test]
+fn deadlines() {
+    let mut scheduler = Scheduler::new();
+
+    scheduler.schedule(Device::Timers, 100);
+    scheduler.schedule(Device::Gpu, 50);
+
+    scheduler.tick(49);
+
+    assert!(!scheduler.is_due());
+    assert_eq!(scheduler.pop_due(), None);
+
+    scheduler.tick(60);
+
+    assert!(scheduler.is_due());
+    // Events are returned in chronological order
+    assert_eq!(scheduler.pop_due(), Some(Device::Gpu));
+    assert_eq!(scheduler.pop_due(), Some(Device::Timers));
+    assert_eq!(scheduler.pop_due(), None);
+
+    // Rescheduling replaces the previous deadline
+    scheduler.schedule(Device::Gpu, 10);
+    scheduler.schedule(Device::Gpu, 20);
+
+    assert_eq!(scheduler.deadline(Device::Gpu), 129);
+
+    scheduler.cancel(Device::Gpu);
+    scheduler.tick(1000);
+
+    assert_eq!(scheduler.pop_due(), None);
+}
+
+#[test]
+fn elapsed() {
+    let mut scheduler = Scheduler::new();
+
+    scheduler.tick(10);
+
+    assert_eq!(scheduler.elapsed(Device::Gpu), 10);
+
+    scheduler.tick(5);
+
+    assert_eq!(scheduler.elapsed(Device::Gpu), 5);
+    assert_eq!(scheduler.elapsed(Device::Timers), 15);
+    assert_eq!(scheduler.elapsed(Device::Gpu), 0);
+}
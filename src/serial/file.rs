@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use super::link::{ControlLines, SerialLink};
+
+/// Link writing the transmitted bytes to a file, used to debug serial
+/// protocols. The received bytes can come from another file or a
+/// named pipe.
+pub struct FileLink {
+    output: File,
+    /// Bytes read from the input file. The file is read by a separate
+    /// thread since a pipe may block until its writer shows up.
+    input: Option<Receiver<u8>>,
+}
+
+impl FileLink {
+    /// Create (or truncate) `output`. If `input` is given its
+    /// contents are fed to the console as they become available.
+    pub fn open<P: AsRef<Path>>(output: P, input: Option<P>) -> Result<FileLink> {
+        let output = File::create(output)?;
+
+        let input = match input {
+            Some(path) => {
+                let path = path.as_ref().to_path_buf();
+
+                // Report missing files right away, the actual open
+                // happens in the thread since it blocks on a pipe
+                // until the other end is opened
+                path.metadata()?;
+
+                let (tx, rx) = channel();
+
+                thread::spawn(move || {
+                    let mut file = match File::open(&path) {
+                        Ok(f) => f,
+                        Err(e) => {
+                            warn!("Couldn't open serial link input {}: {}", path.display(), e);
+                            return;
+                        }
+                    };
+
+                    let mut buf = [0; 256];
+
+                    loop {
+                        let n = match file.read(&mut buf) {
+                            Ok(0) => break,
+                            Ok(n) => n,
+                            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                            Err(e) => {
+                                warn!("Serial link input error: {}", e);
+                                break;
+                            }
+                        };
+
+                        // Stop once the link has been dropped
+                        if buf[..n].iter().any(|&b| tx.send(b).is_err()) {
+                            break;
+                        }
+                    }
+                });
+
+                Some(rx)
+            }
+            None => None,
+        };
+
+        Ok(FileLink {
+            output,
+            input,
+        })
+    }
+}
+
+impl SerialLink for FileLink {
+    fn send(&mut self, byte: u8) {
+        // Unbuffered so that the dump is up to date if the emulator
+        // crashes
+        if let Err(e) = self.output.write_all(&[byte]) {
+            warn!("Serial link output error: {}", e);
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.input.as_ref().and_then(|rx| rx.try_recv().ok())
+    }
+
+    fn set_control_lines(&mut self, _: ControlLines) {
+        // Only the data is dumped
+    }
+
+    fn remote_control_lines(&mut self) -> ControlLines {
+        // The file is always ready
+        ControlLines {
+            dtr: true,
+            rts: true,
+        }
+    }
+}
+
+#[test]
+fn file_link() {
+    let dir = std::env::temp_dir();
+    let output = dir.join(format!("rpsx_file_link_out-{}.bin", std::process::id()));
+    let input = dir.join(format!("rpsx_file_link_in-{}.bin", std::process::id()));
+
+    std::fs::write(&input, [0x12, 0x34]).unwrap();
+
+    let mut link = FileLink::open(&output, Some(&input)).unwrap();
+
+    link.send(0xaa);
+    link.send(0x55);
+
+    assert_eq!(std::fs::read(&output).unwrap(), [0xaa, 0x55]);
+
+    let mut received = Vec::new();
+
+    // Wait for the reader thread
+    for _ in 0..1000 {
+        if let Some(b) = link.receive() {
+            received.push(b);
+        } else if received.len() == 2 {
+            break;
+        } else {
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    assert_eq!(received, [0x12, 0x34]);
+
+    std::fs::remove_file(output).unwrap();
+    std::fs::remove_file(input).unwrap();
+}
@@ -0,0 +1,97 @@
+use std::io::{Error, ErrorKind, Result};
+
+use super::file::FileLink;
+use super::tcp::TcpLink;
+
+/// Levels of the DTR And RTS outputs of one end of the cable. The
+/// cable is crossed: they drive the DSR And CTS inputs of the other
+/// end.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ControlLines {
+    pub dtr: bool,
+    pub rts: bool,
+}
+
+/// Device at the other end of the serial cable. The serial port
+/// only calls `receive` And `remote_control_lines` when it polls the
+/// link, about once per scanline, so the implementations can do their
+/// I/O there.
+pub trait SerialLink {
+    /// Send a byte to the remote end
+    fn send(&mut self, byte: u8);
+
+    /// Return the next byte received from the remote end, if any.
+    /// Must not block.
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Update the DTR And RTS outputs of the console
+    fn set_control_lines(&mut self, lines: ControlLines);
+
+    /// DTR And RTS outputs of the remote end, seen by the console
+    /// through its DSR And CTS inputs. Both are low if nothing's
+    /// connected.
+    fn remote_control_lines(&mut self) -> ControlLines;
+}
+
+/// Nothing plugged in the serial port
+pub struct Disconnected;
+
+impl SerialLink for Disconnected {
+    fn send(&mut self, _: u8) {
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn set_control_lines(&mut self, _: ControlLines) {
+    }
+
+    fn remote_control_lines(&mut self) -> ControlLines {
+        ControlLines::default()
+    }
+}
+
+/// Open the link described by `spec`: "none", "tcp:<address>" to
+/// connect to another instance, "tcp-listen:<address>" to wait for
+/// one or "file:<output>[,<input>]" to dump the transmitted bytes And
+/// optionally receive the contents of a file or named pipe
+pub fn open(spec: &str) -> Result<Box<dyn SerialLink>> {
+    if let Some(addr) = spec.strip_prefix("tcp:") {
+        return Ok(Box::new(TcpLink::connect(addr)?));
+    }
+
+    if let Some(addr) = spec.strip_prefix("tcp-listen:") {
+        return Ok(Box::new(TcpLink::listen(addr)?));
+    }
+
+    if let Some(paths) = spec.strip_prefix("file:") {
+        let (output, input) = match paths.split_once(',') {
+            Some((output, input)) => (output, Some(input)),
+            None => (paths, None),
+        };
+
+        return Ok(Box::new(FileLink::open(output, input)?));
+    }
+
+    match spec {
+        "none" => Ok(Box::new(Disconnected)),
+        _ => Err(Error::new(ErrorKind::InvalidInput,
+                            format!("Unknown serial link '{}'", spec))),
+    }
+}
+
+#[test]
+fn open_spec() {
+    assert!(open("none").is_ok());
+    assert!(open("file").is_err());
+    assert!(open("modem").is_err());
+
+    let name = format!("rpsx_serial_open_spec-{}.bin", std::process::id());
+    let path = std::env::temp_dir().join(name);
+
+    assert!(open(&format!("file:{}", path.display())).is_ok());
+    assert!(path.exists());
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,435 @@
+//! Serial port (SIO1), used by the link cable. The other end of the
+//! cable is a `SerialLink` selected at startup.
+
+use std::collections::VecDeque;
+
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+use crate::sio::{self, Framing, Interface, Sio};
+
+use self::link::{ControlLines, Disconnected, SerialLink};
+
+pub mod file;
+pub mod link;
+pub mod tcp;
+
+/// Delay between two polls of the link in CPU cycles, about one NTSC
+/// scanline. Polling the host for every frame would be way too slow
+/// at the higher baudrates.
+pub const LINK_POLL_CYCLES: u32 = 2172;
+
+/// Asynchronous serial port (SIO1)
+pub struct Serial {
+    sio: Sio,
+    /// SIO_CTRL bits which are stored as-is
+    control: u16,
+    /// A byte was received while the RX FIFO was full
+    rx_overrun: bool,
+    /// Bytes fetched from the link, entering the RX FIFO one per
+    /// frame
+    rx_pending: VecDeque<u8>,
+    /// Cycles before the next pending byte is received
+    rx_delay: u32,
+    /// Cycles before the next link poll
+    poll_delay: u32,
+    /// DTR And RTS outputs of the remote end, connected to our DSR
+    /// And CTS inputs, the last time they were checked
+    remote_lines: ControlLines,
+    link: Box<dyn SerialLink>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            sio: Sio::new("SIO1", Framing::Asynchronous, Interrupt::Sio),
+            control: 0,
+            rx_overrun: false,
+            rx_pending: VecDeque::new(),
+            rx_delay: 0,
+            poll_delay: LINK_POLL_CYCLES,
+            remote_lines: ControlLines::default(),
+            link: Box::new(Disconnected),
+        }
+    }
+
+    /// Plug the other end of the cable
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = link;
+    }
+
+    /// Advance the state machine by `cycles` CPU cycles
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptController) {
+        sio::tick(self, cycles, irq)
+    }
+
+    /// Number of cycles before the next event
+    pub fn cycles_to_next_event(&self) -> u32 {
+        // The link is always polled
+        sio::cycles_to_next_event(self).unwrap()
+    }
+
+    pub fn load(&mut self, offset: u32) -> u32 {
+        match offset {
+            0 => self.sio.read() as u32,
+            4 => self.status(),
+            8 => self.sio.mode() as u32,
+            0xa => self.control as u32,
+            0xe => self.sio.baud() as u32,
+            _ => {
+                warn!("Unhandled SIO1 load at offset 0x{:x}", offset);
+                0
+            }
+        }
+    }
+
+    pub fn store(&mut self, offset: u32, val: u16) {
+        match offset {
+            0 => self.sio.send(val as u8),
+            8 => self.sio.set_mode(val),
+            0xa => self.set_control(val),
+            0xe => self.sio.set_baud(val),
+            _ => warn!("Unhandled SIO1 store at offset 0x{:x}: 0x{:04x}", offset, val),
+        }
+    }
+
+    fn status(&self) -> u32 {
+        let mut r = self.sio.status();
+
+        // The control lines are only checked when the link is polled
+        r |= (self.rx_overrun as u32) << 4;
+        r |= (self.remote_lines.dtr as u32) << 7;
+        r |= (self.remote_lines.rts as u32) << 8;
+
+        r
+    }
+
+    fn set_control(&mut self, val: u16) {
+        let lines = self.control_lines();
+
+        if val & 0x40 != 0 {
+            // Reset
+            self.sio.reset();
+            self.sio.set_baud(0);
+            self.rx_overrun = false;
+            self.rx_pending.clear();
+            self.control = 0;
+            self.update_control_lines(lines);
+            return;
+        }
+
+        if val & 0x10 != 0 {
+            // Acknowledge the interrupt And the error flags
+            self.sio.acknowledge_irq();
+            self.rx_overrun = false;
+        }
+
+        // TX enable, DTR, RX enable, TX level, RTS, RX interrupt mode
+        // And the interrupt enables. Bits 4 And 6 are write-only.
+        self.control = val & 0x1f2f;
+
+        self.update_control_lines(lines);
+        self.sio.set_tx_enable(val & 1 != 0);
+    }
+
+    /// Levels of the DTR And RTS outputs
+    fn control_lines(&self) -> ControlLines {
+        ControlLines {
+            dtr: self.control & 2 != 0,
+            rts: self.control & 0x20 != 0,
+        }
+    }
+
+    /// Tell the remote end if the control lines changed from `old`
+    fn update_control_lines(&mut self, old: ControlLines) {
+        let lines = self.control_lines();
+
+        if lines != old {
+            self.link.set_control_lines(lines);
+        }
+    }
+
+    fn rx_enabled(&self) -> bool {
+        self.control & 4 != 0
+    }
+
+    /// Number of bytes in the RX FIFO which trigger an interrupt
+    fn rx_irq_threshold(&self) -> usize {
+        1 << ((self.control >> 8) & 3)
+    }
+
+    /// Move the next pending byte to the RX FIFO
+    fn receive(&mut self, irq: &mut InterruptController) {
+        let byte = match self.rx_pending.pop_front() {
+            Some(b) => b,
+            None => return,
+        };
+
+        if !self.sio.receive(byte) {
+            self.rx_overrun = true;
+        }
+
+        if self.control & 0x800 != 0 && self.sio.rx_len() >= self.rx_irq_threshold() {
+            self.sio.raise_irq(irq);
+        }
+    }
+
+    /// Fetch the bytes the console can receive before the next poll
+    /// from the link And check the DSR And CTS inputs
+    fn poll_link(&mut self, irq: &mut InterruptController) {
+        if self.rx_enabled() {
+            let frame_cycles = self.sio.frame_cycles();
+            // Rounded up, plus the byte still in flight at the next
+            // poll
+            let budget = (LINK_POLL_CYCLES / frame_cycles) as usize + 2;
+
+            while self.rx_pending.len() < budget {
+                match self.link.receive() {
+                    Some(byte) => {
+                        if self.rx_pending.is_empty() {
+                            self.rx_delay = frame_cycles;
+                        }
+
+                        self.rx_pending.push_back(byte);
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        let lines = self.link.remote_control_lines();
+
+        // The remote DTR drives our DSR input
+        if lines.dtr && !self.remote_lines.dtr && self.control & 0x1000 != 0 {
+            self.sio.raise_irq(irq);
+        }
+
+        self.remote_lines = lines;
+    }
+}
+
+impl Interface for Serial {
+    fn sio(&self) -> &Sio {
+        &self.sio
+    }
+
+    fn sio_mut(&mut self) -> &mut Sio {
+        &mut self.sio
+    }
+
+    fn next_port_event(&self) -> Option<u32> {
+        let rx = match self.rx_enabled() && !self.rx_pending.is_empty() {
+            true => Some(self.rx_delay.max(1)),
+            false => None,
+        };
+        let poll = Some(self.poll_delay.max(1));
+
+        [rx, poll].iter().filter_map(|&d| d).min()
+    }
+
+    fn advance_port(&mut self, cycles: u32, irq: &mut InterruptController) {
+        if self.rx_enabled() && !self.rx_pending.is_empty() {
+            if self.rx_delay > cycles {
+                self.rx_delay -= cycles;
+            } else {
+                self.rx_delay = self.sio.frame_cycles();
+                self.receive(irq);
+            }
+        }
+
+        if self.poll_delay > cycles {
+            self.poll_delay -= cycles;
+        } else {
+            self.poll_delay = LINK_POLL_CYCLES;
+            self.poll_link(irq);
+        }
+    }
+
+    fn end_transfer(&mut self, byte: u8, irq: &mut InterruptController) {
+        self.link.send(byte);
+
+        if self.control & 0x400 != 0 {
+            self.sio.raise_irq(irq);
+        }
+    }
+}
+
+/// Link shared with the test so that the traffic can be inspected
+/// after the link has been handed over to the serial port
+#[cfg(test)]
+#[derive(Clone)]
+struct TestLink(std::sync::Arc<std::sync::Mutex<(Vec<u8>, VecDeque<u8>)>>);
+
+#[cfg(test)]
+impl SerialLink for TestLink {
+    fn send(&mut self, byte: u8) {
+        self.0.lock().unwrap().0.push(byte);
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.0.lock().unwrap().1.pop_front()
+    }
+
+    fn set_control_lines(&mut self, _: ControlLines) {
+    }
+
+    fn remote_control_lines(&mut self) -> ControlLines {
+        ControlLines {
+            dtr: true,
+            rts: true,
+        }
+    }
+}
+
+#[test]
+fn transmit() {
+    let mut irq = InterruptController::new();
+    let mut sio = Serial::new();
+    let link = TestLink(Default::default());
+
+    sio.set_link(Box::new(link.clone()));
+
+    // MUL16, 8 bits, no parity, 1 stop bit
+    sio.store(8, 0x4e);
+    sio.store(0xe, 0x12);
+    // TX enable, TX interrupt
+    sio.store(0xa, 0x401);
+
+    // DSR And CTS are sampled by the link poll
+    assert_eq!(sio.load(4) & 0x385, 0x005);
+
+    sio.tick(LINK_POLL_CYCLES, &mut irq);
+
+    assert_eq!(sio.load(4) & 0x385, 0x185);
+
+    sio.store(0, 0x55);
+    sio.store(0, 0xaa);
+
+    assert_eq!(sio.load(4) & 5, 0);
+
+    sio.tick(0x12 * 16 * 10 - 1, &mut irq);
+
+    assert!(link.0.lock().unwrap().0.is_empty());
+
+    sio.tick(1, &mut irq);
+
+    assert_eq!(link.0.lock().unwrap().0, [0x55]);
+    assert_eq!(sio.load(4) & 0x205, 0x201);
+    assert_eq!(irq.status(), 1 << 8);
+
+    // Acknowledge
+    sio.store(0xa, 0x411);
+    assert_eq!(sio.load(4) & 0x200, 0);
+
+    sio.tick(0x12 * 16 * 10, &mut irq);
+
+    assert_eq!(link.0.lock().unwrap().0, [0x55, 0xaa]);
+    assert_eq!(sio.load(4) & 0x205, 0x205);
+    // Only the link poll is left
+    assert!(sio.cycles_to_next_event() <= LINK_POLL_CYCLES);
+}
+
+#[test]
+fn receive() {
+    let mut irq = InterruptController::new();
+    let mut sio = Serial::new();
+    let link = TestLink(Default::default());
+
+    link.0.lock().unwrap().1.extend(&[0x12, 0x34, 0x56]);
+
+    sio.set_link(Box::new(link.clone()));
+
+    // MUL1, 7 bits, parity, 2 stop bits
+    sio.store(8, 0xd9);
+    sio.store(0xe, 0x100);
+    // RX enable, RX interrupt after 2 bytes
+    sio.store(0xa, 0x904);
+
+    let frame = 0x100 * 11;
+
+    // The bytes are fetched by the next poll, then received one per
+    // frame
+    sio.tick(LINK_POLL_CYCLES, &mut irq);
+
+    assert_eq!(sio.load(4) & 2, 0);
+    // Only the bytes which can be received before the next poll are
+    // fetched
+    assert_eq!(link.0.lock().unwrap().1.len(), 1);
+
+    sio.tick(frame, &mut irq);
+
+    assert_eq!(sio.load(4) & 0x202, 0x002);
+    assert_eq!(irq.status(), 0);
+
+    sio.tick(frame, &mut irq);
+
+    assert_eq!(sio.load(4) & 0x202, 0x202);
+    assert_eq!(irq.status(), 1 << 8);
+
+    assert_eq!(sio.load(0), 0x12);
+    assert_eq!(sio.load(0), 0x34);
+    assert_eq!(sio.load(4) & 2, 0);
+
+    // Fill the FIFO
+    link.0.lock().unwrap().1.extend(&[0; 8]);
+
+    sio.tick(frame * 12, &mut irq);
+
+    assert_eq!(sio.load(4) & 0x12, 0x12);
+    assert_eq!(sio.load(0), 0x56);
+
+    sio.store(0xa, 0x914);
+    assert_eq!(sio.load(4) & 0x210, 0);
+}
+
+#[test]
+fn handshake() {
+    use self::tcp::TcpLink;
+
+    let mut irq = InterruptController::new();
+    let mut a = Serial::new();
+    let mut b = Serial::new();
+
+    let server = TcpLink::listen("127.0.0.1:0").unwrap();
+    let client = TcpLink::connect(server.local_addr().unwrap()).unwrap();
+
+    a.set_link(Box::new(server));
+    b.set_link(Box::new(client));
+
+    // Run both ends until `b`'s status matches `expected` under
+    // `mask`
+    let wait_for = |a: &mut Serial, b: &mut Serial, irq: &mut InterruptController, mask: u32, expected: u32| {
+        for _ in 0..1000 {
+            a.tick(LINK_POLL_CYCLES, irq);
+            b.tick(LINK_POLL_CYCLES, irq);
+
+            if b.load(4) & mask == expected {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert_eq!(b.load(4) & mask, expected);
+    };
+
+    // RX enable And DSR interrupt on B
+    b.store(0xa, 0x1004);
+
+    wait_for(&mut a, &mut b, &mut irq, 0x180, 0);
+
+    // DTR on A reaches B's DSR
+    a.store(0xa, 0x0002);
+
+    wait_for(&mut a, &mut b, &mut irq, 0x380, 0x280);
+    assert_eq!(irq.status(), 1 << 8);
+
+    // RTS on B reaches A's CTS
+    b.store(0xa, 0x1024);
+
+    wait_for(&mut b, &mut a, &mut irq, 0x180, 0x100);
+
+    // Dropping DTR
+    a.store(0xa, 0);
+
+    wait_for(&mut a, &mut b, &mut irq, 0x80, 0);
+}
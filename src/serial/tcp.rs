@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use super::link::{ControlLines, SerialLink};
+
+/// Escape byte of the link protocol. It's followed either by another
+/// `ESCAPE` for a data byte of that value or by the new state of the
+/// sender's control lines: DTR in bit 0 And RTS in bit 1.
+const ESCAPE: u8 = 0xff;
+
+/// Maximum number of bytes waiting to be written to the socket
+const TX_BUFFER_MAX: usize = 4096;
+
+/// Link cable emulated over a TCP connection, typically between two
+/// instances of the emulator running on the same machine. One side
+/// listens, the other connects. The data bytes are sent as-is except
+/// for `ESCAPE`, which also introduces the control line updates.
+pub struct TcpLink {
+    /// Set on the listening side, a new peer is accepted whenever the
+    /// connection is lost
+    listener: Option<TcpListener>,
+    stream: Option<TcpStream>,
+    /// Bytes received from the socket but not read by the console yet
+    rx: VecDeque<u8>,
+    /// Encoded bytes not written to the socket yet
+    tx: Vec<u8>,
+    /// True if the last byte received was an `ESCAPE`
+    escape: bool,
+    /// Our control lines, sent again to every new peer
+    lines: ControlLines,
+    /// Control lines of the peer
+    remote_lines: ControlLines,
+}
+
+impl TcpLink {
+    /// Wait for the other instance on `addr`
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> Result<TcpLink> {
+        let listener = TcpListener::bind(addr)?;
+
+        listener.set_nonblocking(true)?;
+
+        Ok(TcpLink::new(Some(listener)))
+    }
+
+    /// Connect to an instance listening on `addr`
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpLink> {
+        let stream = TcpStream::connect(addr)?;
+
+        TcpLink::setup(&stream)?;
+
+        let mut link = TcpLink::new(None);
+
+        link.set_stream(stream);
+
+        Ok(link)
+    }
+
+    fn new(listener: Option<TcpListener>) -> TcpLink {
+        TcpLink {
+            listener,
+            stream: None,
+            rx: VecDeque::new(),
+            tx: Vec::new(),
+            escape: false,
+            lines: ControlLines::default(),
+            remote_lines: ControlLines::default(),
+        }
+    }
+
+    /// Address the link is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match self.listener.as_ref() {
+            Some(listener) => listener.local_addr(),
+            None => self.stream.as_ref().unwrap().local_addr(),
+        }
+    }
+
+    fn setup(stream: &TcpStream) -> Result<()> {
+        stream.set_nonblocking(true)?;
+        // The bytes are exchanged one by one, don't wait to fill
+        // packets
+        stream.set_nodelay(true)
+    }
+
+    /// Start talking to a new peer. It doesn't know the state of our
+    /// control lines yet.
+    fn set_stream(&mut self, stream: TcpStream) {
+        self.stream = Some(stream);
+        self.push_lines();
+        self.flush();
+    }
+
+    /// Accept the peer if we're still waiting for it, send the
+    /// pending bytes And fetch the incoming ones
+    fn poll(&mut self) {
+        if self.stream.is_none() {
+            if let Some(listener) = self.listener.as_ref() {
+                match listener.accept() {
+                    Ok((stream, peer)) => match TcpLink::setup(&stream) {
+                        Ok(()) => {
+                            info!("Serial link connected to {}", peer);
+                            self.set_stream(stream);
+                        }
+                        Err(e) => warn!("Serial link setup failed: {}", e),
+                    },
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                    Err(e) => warn!("Serial link accept failed: {}", e),
+                }
+            }
+        }
+
+        self.flush();
+
+        let mut buf = [0; 256];
+
+        loop {
+            let res = match self.stream.as_mut() {
+                Some(s) => s.read(&mut buf),
+                None => return,
+            };
+
+            match res {
+                Ok(0) => return self.disconnect(),
+                Ok(n) => {
+                    for &b in &buf[..n] {
+                        self.decode(b);
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    warn!("Serial link read error: {}", e);
+                    return self.disconnect();
+                }
+            }
+        }
+    }
+
+    /// Handle a byte received from the socket. Control line updates
+    /// take effect right away, even if some data received before them
+    /// hasn't been read by the console yet.
+    fn decode(&mut self, byte: u8) {
+        if !self.escape {
+            match byte {
+                ESCAPE => self.escape = true,
+                _ => self.rx.push_back(byte),
+            }
+
+            return;
+        }
+
+        self.escape = false;
+
+        match byte {
+            ESCAPE => self.rx.push_back(ESCAPE),
+            b if b & !3 == 0 => {
+                self.remote_lines = ControlLines {
+                    dtr: b & 1 != 0,
+                    rts: b & 2 != 0,
+                }
+            }
+            b => warn!("Serial link: invalid escape sequence 0x{:02x}", b),
+        }
+    }
+
+    /// Queue the state of our control lines
+    fn push_lines(&mut self) {
+        let bits = (self.lines.dtr as u8) | ((self.lines.rts as u8) << 1);
+
+        self.tx.extend_from_slice(&[ESCAPE, bits]);
+    }
+
+    /// Write as many pending bytes as the socket accepts
+    fn flush(&mut self) {
+        while !self.tx.is_empty() {
+            let res = match self.stream.as_mut() {
+                Some(s) => s.write(&self.tx),
+                None => return self.tx.clear(),
+            };
+
+            match res {
+                Ok(0) => return self.disconnect(),
+                Ok(n) => {
+                    self.tx.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => {
+                    warn!("Serial link write error: {}", e);
+                    return self.disconnect();
+                }
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        info!("Serial link disconnected");
+        self.stream = None;
+        self.tx.clear();
+        self.escape = false;
+        self.remote_lines = ControlLines::default();
+    }
+}
+
+impl SerialLink for TcpLink {
+    fn send(&mut self, byte: u8) {
+        if self.stream.is_none() {
+            // Nobody on the other end, the byte is lost
+            return;
+        }
+
+        if self.tx.len() >= TX_BUFFER_MAX {
+            warn!("Serial link congested, dropping 0x{:02x}", byte);
+            return;
+        }
+
+        match byte {
+            ESCAPE => self.tx.extend_from_slice(&[ESCAPE, ESCAPE]),
+            _ => self.tx.push(byte),
+        }
+
+        // Written by the next poll
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        if self.rx.is_empty() {
+            self.poll();
+        }
+
+        self.rx.pop_front()
+    }
+
+    fn set_control_lines(&mut self, lines: ControlLines) {
+        self.lines = lines;
+
+        if self.stream.is_some() {
+            self.push_lines();
+        }
+    }
+
+    fn remote_control_lines(&mut self) -> ControlLines {
+        self.poll();
+
+        self.remote_lines
+    }
+}
+
+/// Poll `link` And check if it has a peer
+#[cfg(test)]
+fn is_connected(link: &mut TcpLink) -> bool {
+    link.poll();
+
+    link.stream.is_some()
+}
+
+#[test]
+fn loopback() {
+    let mut server = TcpLink::listen("127.0.0.1:0").unwrap();
+
+    assert!(!is_connected(&mut server));
+
+    let mut client = TcpLink::connect(server.local_addr().unwrap()).unwrap();
+
+    assert!(is_connected(&mut client));
+
+    // Poll `sender` until `link` receives `count` bytes
+    let receive = |link: &mut TcpLink, sender: &mut TcpLink, count: usize| -> Vec<u8> {
+        let mut received = Vec::new();
+
+        for _ in 0..1000 {
+            sender.poll();
+
+            match link.receive() {
+                Some(b) => received.push(b),
+                None if received.len() >= count => break,
+                None => std::thread::sleep(std::time::Duration::from_millis(1)),
+            }
+        }
+
+        received
+    };
+
+    client.send(0x01);
+    client.send(0x02);
+
+    assert_eq!(receive(&mut server, &mut client, 2), [0x01, 0x02]);
+    assert!(is_connected(&mut server));
+
+    // The escape byte goes through as data
+    server.send(0xfe);
+    server.send(0xff);
+    server.send(0x00);
+
+    assert_eq!(receive(&mut client, &mut server, 3), [0xfe, 0xff, 0x00]);
+
+    // The server waits for a new peer once the client is gone
+    drop(client);
+
+    let mut connected = true;
+
+    for _ in 0..1000 {
+        connected = is_connected(&mut server);
+
+        if !connected {
+            break;
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    assert!(!connected);
+}
+
+#[test]
+fn control_lines() {
+    let mut server = TcpLink::listen("127.0.0.1:0").unwrap();
+
+    // Set before the peer shows up
+    server.set_control_lines(ControlLines { dtr: true, rts: false });
+
+    let mut client = TcpLink::connect(server.local_addr().unwrap()).unwrap();
+
+    // Poll both ends until `link` sees `lines`
+    let wait_for = |link: &mut TcpLink, other: &mut TcpLink, lines: ControlLines| {
+        for _ in 0..1000 {
+            other.poll();
+
+            if link.remote_control_lines() == lines {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert_eq!(link.remote_control_lines(), lines);
+    };
+
+    wait_for(&mut client, &mut server, ControlLines { dtr: true, rts: false });
+
+    client.set_control_lines(ControlLines { dtr: false, rts: true });
+    client.send(0x42);
+
+    wait_for(&mut server, &mut client, ControlLines { dtr: false, rts: true });
+    assert_eq!(server.receive(), Some(0x42));
+
+    // The lines drop with the connection
+    drop(client);
+
+    for _ in 0..1000 {
+        if server.remote_control_lines() == ControlLines::default() {
+            break;
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    assert_eq!(server.remote_control_lines(), ControlLines::default());
+}
@@ -0,0 +1,289 @@
+//! Serial shift register shared by the controller port (SIO0) And
+//! the serial port (SIO1). Both interfaces use the same data, mode
+//! And baudrate registers, only the control lines And what sits on
+//! the other end of the wire differ.
+
+use std::collections::VecDeque;
+
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+
+/// Depth of the RX FIFO
+const RX_FIFO_DEPTH: usize = 8;
+
+/// Format of the bytes on the wire
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// 8 data bits clocked by the console
+    Synchronous,
+    /// Start bit, data bits, parity And stop bits as configured in
+    /// the mode register
+    Asynchronous,
+}
+
+/// Transmitter, RX FIFO And interrupt flag of a serial interface
+pub struct Sio {
+    /// Name used in the warnings
+    name: &'static str,
+    framing: Framing,
+    /// Interrupt asserted by `raise_irq`
+    interrupt: Interrupt,
+    /// Baudrate reload value
+    baud: u16,
+    /// Mode register: baudrate factor And frame format
+    mode: u16,
+    /// Transmission enabled
+    tx_enable: bool,
+    /// Interrupt flag (status bit 9)
+    irq: bool,
+    /// Byte written to the data register waiting to be sent
+    tx_pending: Option<u8>,
+    /// Byte currently being shifted out And number of cycles before
+    /// the end of the transfer
+    transfer: Option<(u8, u32)>,
+    /// Bytes received from the other end
+    rx_fifo: VecDeque<u8>,
+}
+
+impl Sio {
+    pub fn new(name: &'static str, framing: Framing, interrupt: Interrupt) -> Sio {
+        Sio {
+            name,
+            framing,
+            interrupt,
+            baud: 0,
+            mode: 0,
+            tx_enable: false,
+            irq: false,
+            tx_pending: None,
+            transfer: None,
+            rx_fifo: VecDeque::new(),
+        }
+    }
+
+    /// Reset triggered through the control register. The baudrate
+    /// reload value is left untouched.
+    pub fn reset(&mut self) {
+        self.mode = 0;
+        self.tx_enable = false;
+        self.irq = false;
+        self.tx_pending = None;
+        self.transfer = None;
+        self.rx_fifo.clear();
+    }
+
+    pub fn baud(&self) -> u16 {
+        self.baud
+    }
+
+    pub fn set_baud(&mut self, baud: u16) {
+        self.baud = baud;
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: u16) {
+        self.mode = mode;
+    }
+
+    pub fn tx_enabled(&self) -> bool {
+        self.tx_enable
+    }
+
+    pub fn set_tx_enable(&mut self, enable: bool) {
+        self.tx_enable = enable;
+
+        self.start_transfer();
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    /// Acknowledge the interrupt
+    pub fn acknowledge_irq(&mut self) {
+        self.irq = false;
+    }
+
+    pub fn raise_irq(&mut self, irq: &mut InterruptController) {
+        if !self.irq {
+            self.irq = true;
+            irq.assert(self.interrupt);
+        }
+    }
+
+    /// Status bits common to both interfaces: TX ready, RX FIFO not
+    /// empty, TX done And the interrupt flag
+    pub fn status(&self) -> u32 {
+        let mut r = 0;
+
+        r |= self.tx_pending.is_none() as u32;
+        r |= (!self.rx_fifo.is_empty() as u32) << 1;
+        r |= ((self.tx_pending.is_none() && self.transfer.is_none()) as u32) << 2;
+        r |= (self.irq as u32) << 9;
+
+        r
+    }
+
+    /// Data register write
+    pub fn send(&mut self, byte: u8) {
+        if self.tx_pending.is_some() {
+            warn!("{} TX buffer overrun, dropping 0x{:02x}", self.name, byte);
+        }
+
+        self.tx_pending = Some(byte);
+
+        self.start_transfer();
+    }
+
+    /// Data register read
+    pub fn read(&mut self) -> u8 {
+        self.rx_fifo.pop_front().unwrap_or(0xff)
+    }
+
+    /// Push a received byte in the RX FIFO. Returns false if it
+    /// was full And the byte got lost.
+    pub fn receive(&mut self, byte: u8) -> bool {
+        if self.rx_fifo.len() < RX_FIFO_DEPTH {
+            self.rx_fifo.push_back(byte);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Number of bytes in the RX FIFO
+    pub fn rx_len(&self) -> usize {
+        self.rx_fifo.len()
+    }
+
+    /// Start shifting out the pending byte if the line is free
+    fn start_transfer(&mut self) {
+        if !self.tx_enable || self.transfer.is_some() {
+            return;
+        }
+
+        if let Some(byte) = self.tx_pending.take() {
+            self.transfer = Some((byte, self.frame_cycles()));
+        }
+    }
+
+    /// Duration of a byte transfer in CPU cycles
+    pub fn frame_cycles(&self) -> u32 {
+        let factor = match self.mode & 3 {
+            2 => 16,
+            3 => 64,
+            _ => 1,
+        };
+
+        // At least one cycle per bit
+        let bit_cycles = (self.baud as u32 * factor).max(1);
+
+        if self.framing == Framing::Synchronous {
+            return bit_cycles * 8;
+        }
+
+        let data_bits = 5 + ((self.mode >> 2) & 3) as u32;
+        let parity_bits = ((self.mode >> 4) & 1) as u32;
+
+        // Counted in half bits because of the 1.5 stop bits setting
+        let stop_half_bits = match (self.mode >> 6) & 3 {
+            2 => 3,
+            3 => 4,
+            _ => 2,
+        };
+
+        let half_bits = (1 + data_bits + parity_bits) * 2 + stop_half_bits;
+
+        (bit_cycles * half_bits / 2).max(1)
+    }
+
+    /// Advance the current transfer by `cycles`, returns the byte
+    /// once it's been shifted out completely
+    fn advance(&mut self, cycles: u32) -> Option<u8> {
+        let (byte, delay) = self.transfer?;
+
+        let delay = delay - cycles;
+
+        if delay > 0 {
+            self.transfer = Some((byte, delay));
+            None
+        } else {
+            self.transfer = None;
+            Some(byte)
+        }
+    }
+}
+
+/// Interface built around a `Sio`, with its own events And what's
+/// connected on the other end of the wire
+pub trait Interface {
+    fn sio(&self) -> &Sio;
+
+    fn sio_mut(&mut self) -> &mut Sio;
+
+    /// Number of cycles before the next event of the interface
+    /// itself, if any
+    fn next_port_event(&self) -> Option<u32>;
+
+    /// Advance the events of the interface by `cycles` CPU cycles
+    fn advance_port(&mut self, cycles: u32, irq: &mut InterruptController);
+
+    /// Called once `byte` has been shifted out
+    fn end_transfer(&mut self, byte: u8, irq: &mut InterruptController);
+}
+
+/// Advance `interface` by `cycles` CPU cycles
+pub fn tick<I: Interface>(interface: &mut I, mut cycles: u32, irq: &mut InterruptController) {
+    loop {
+        // Never step over an event since each one can start the next
+        let step = match cycles_to_next_event(interface) {
+            Some(delay) => delay.min(cycles),
+            None => cycles,
+        };
+
+        interface.advance_port(step, irq);
+
+        if let Some(byte) = interface.sio_mut().advance(step) {
+            interface.end_transfer(byte, irq);
+            interface.sio_mut().start_transfer();
+        }
+
+        cycles -= step;
+
+        if cycles == 0 {
+            break;
+        }
+    }
+}
+
+/// Number of cycles before the next event of `interface`, if any
+pub fn cycles_to_next_event<I: Interface>(interface: &I) -> Option<u32> {
+    let transfer = interface.sio().transfer.map(|(_, delay)| delay);
+
+    [transfer, interface.next_port_event()]
+        .iter()
+        .filter_map(|&d| d)
+        .min()
+}
+
+#[test]
+fn frame_cycles() {
+    let mut sio = Sio::new("SIO0", Framing::Synchronous, Interrupt::PadMemCard);
+
+    sio.set_baud(0x88);
+    sio.set_mode(0x0d);
+
+    // MUL1, 8 bits per byte
+    assert_eq!(sio.frame_cycles(), 0x88 * 8);
+
+    let mut sio = Sio::new("SIO1", Framing::Asynchronous, Interrupt::Sio);
+
+    sio.set_baud(0x12);
+    // MUL16, 7 bits, parity, 1.5 stop bits
+    sio.set_mode(0x9a);
+
+    assert_eq!(sio.frame_cycles(), 0x12 * 16 * 21 / 2);
+}
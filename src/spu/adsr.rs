@@ -0,0 +1,252 @@
+//! Attack, Decay, Sustain, Release volume envelope
+
+/// Current phase of the envelope
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdsrPhase {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Envelope of a voice, configured through the two 16bit ADSR
+/// registers
+pub struct Adsr {
+    /// Attack, decay And sustain level configuration
+    config_lo: u16,
+    /// Sustain And release configuration
+    config_hi: u16,
+    phase: AdsrPhase,
+    /// Current level, 0 to 0x7fff
+    level: i16,
+    /// Number of samples before the next level change
+    delay: u32,
+}
+
+/// Parameters of one step of an envelope, shared by the ADSR And
+/// the volume sweeps
+pub struct Step {
+    pub exponential: bool,
+    pub decrease: bool,
+    pub shift: u32,
+    /// Base step: +7 to +4 when increasing, -8 to -5 when decreasing
+    pub step: i32,
+}
+
+impl Step {
+    /// Apply the step to `level`, returns the new level And the
+    /// number of samples to wait before the next step
+    pub fn apply(&self, level: i16) -> (i16, u32) {
+        let mut cycles = 1 << self.shift.saturating_sub(11);
+        let mut step = self.step << 11u32.saturating_sub(self.shift);
+
+        let level = level as i32;
+
+        if self.exponential {
+            if self.decrease {
+                step = step * level / 0x8000;
+            } else if level > 0x6000 {
+                cycles *= 4;
+            }
+        }
+
+        ((level + step).clamp(0, 0x7fff) as i16, cycles)
+    }
+}
+
+impl Adsr {
+    pub fn new() -> Adsr {
+        Adsr {
+            config_lo: 0,
+            config_hi: 0,
+            phase: AdsrPhase::Release,
+            level: 0,
+            delay: 0,
+        }
+    }
+
+    pub fn config_lo(&self) -> u16 {
+        self.config_lo
+    }
+
+    pub fn config_hi(&self) -> u16 {
+        self.config_hi
+    }
+
+    pub fn set_config_lo(&mut self, val: u16) {
+        self.config_lo = val;
+    }
+
+    pub fn set_config_hi(&mut self, val: u16) {
+        self.config_hi = val;
+    }
+
+    pub fn level(&self) -> i16 {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: i16) {
+        self.level = level;
+    }
+
+    pub fn phase(&self) -> AdsrPhase {
+        self.phase
+    }
+
+    /// Key on: restart the envelope from the attack phase
+    pub fn attack(&mut self) {
+        self.phase = AdsrPhase::Attack;
+        self.level = 0;
+        self.delay = 0;
+    }
+
+    /// Key off
+    pub fn release(&mut self) {
+        self.phase = AdsrPhase::Release;
+        self.delay = 0;
+    }
+
+    /// Force the envelope to silence, used when a voice reaches the
+    /// end of a non-looping sample
+    pub fn mute(&mut self) {
+        self.release();
+        self.level = 0;
+    }
+
+    /// Level at which the decay phase ends
+    fn sustain_level(&self) -> i32 {
+        (((self.config_lo & 0xf) as i32) + 1) * 0x800
+    }
+
+    /// Parameters of the current phase
+    fn step(&self) -> Step {
+        let lo = self.config_lo;
+        let hi = self.config_hi;
+
+        match self.phase {
+            AdsrPhase::Attack => Step {
+                exponential: lo & 0x8000 != 0,
+                decrease: false,
+                shift: ((lo >> 10) & 0x1f) as u32,
+                step: 7 - ((lo >> 8) & 3) as i32,
+            },
+            AdsrPhase::Decay => Step {
+                exponential: true,
+                decrease: true,
+                shift: ((lo >> 4) & 0xf) as u32,
+                step: -8,
+            },
+            AdsrPhase::Sustain => {
+                let decrease = hi & 0x4000 != 0;
+                let step = ((hi >> 6) & 3) as i32;
+
+                Step {
+                    exponential: hi & 0x8000 != 0,
+                    decrease,
+                    shift: ((hi >> 8) & 0x1f) as u32,
+                    step: if decrease { -8 + step } else { 7 - step },
+                }
+            }
+            AdsrPhase::Release => Step {
+                exponential: hi & 0x20 != 0,
+                decrease: true,
+                shift: (hi & 0x1f) as u32,
+                step: -8,
+            },
+        }
+    }
+
+    /// Advance the envelope by one sample
+    pub fn tick(&mut self) {
+        if self.delay > 0 {
+            self.delay -= 1;
+        }
+
+        if self.delay == 0 {
+            let (level, delay) = self.step().apply(self.level);
+
+            self.level = level;
+            self.delay = delay;
+        }
+
+        match self.phase {
+            AdsrPhase::Attack if self.level == 0x7fff => {
+                self.phase = AdsrPhase::Decay;
+                self.delay = 0;
+            }
+            AdsrPhase::Decay if (self.level as i32) <= self.sustain_level() => {
+                self.phase = AdsrPhase::Sustain;
+                self.delay = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn envelope_phases() {
+    let mut adsr = Adsr::new();
+
+    // Fast linear attack (+0x3800 per sample), decay shift 0,
+    // sustain level 0x4000, linear sustain decrease, slow release
+    adsr.set_config_lo(0x0007);
+    adsr.set_config_hi(0x4000 | (20 << 8) | 10);
+
+    adsr.attack();
+
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x3800);
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x7000);
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x7fff);
+    assert_eq!(adsr.phase(), AdsrPhase::Decay);
+
+    // Exponential decay: -0x4000 * level / 0x8000
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x7fff - 0x3fff);
+    assert_eq!(adsr.phase(), AdsrPhase::Sustain);
+
+    // Sustain: shift 20, one step of -8 every 512 samples
+    let level = adsr.level();
+
+    for _ in 0..512 {
+        adsr.tick();
+    }
+
+    assert_eq!(adsr.level(), level - 8);
+
+    // Release: shift 10, -16 per sample
+    adsr.release();
+
+    let level = adsr.level();
+
+    adsr.tick();
+    assert_eq!(adsr.level(), level - 16);
+
+    adsr.mute();
+    assert_eq!(adsr.level(), 0);
+}
+
+#[test]
+fn exponential_attack() {
+    let mut adsr = Adsr::new();
+
+    // Exponential attack, shift 12 (step +7 every 2 samples), 4
+    // times slower above 0x6000
+    adsr.set_config_lo(0x8000 | (12 << 10));
+    adsr.attack();
+    adsr.set_level(0x6001);
+
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x6008);
+
+    for _ in 0..7 {
+        adsr.tick();
+    }
+
+    assert_eq!(adsr.level(), 0x6008);
+
+    adsr.tick();
+    assert_eq!(adsr.level(), 0x600f);
+}
@@ -0,0 +1,531 @@
+use crate::audio::{AudioSink, NullSink};
+use crate::cdrom::CdRom;
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+
+use self::noise::NoiseGenerator;
+use self::ram::SoundRam;
+use self::reverb::Reverb;
+use self::voice::Voice;
+use self::volume::Volume;
+
+pub mod adsr;
+pub mod noise;
+pub mod ram;
+pub mod reverb;
+pub mod voice;
+pub mod volume;
+
+/// Number of CPU cycles per 44.1kHz output sample
+pub const SAMPLE_CYCLES: u32 = 768;
+
+/// Number of voices
+const VOICE_COUNT: usize = 24;
+
+/// Number of 16bit registers in the SPU range
+const REGISTER_COUNT: usize = 0x140;
+
+/// Size of each capture buffer in halfwords
+const CAPTURE_SIZE: u32 = 0x200;
+
+/// SPUCNT bits
+const CONTROL_CD_ENABLE: u16 = 1 << 0;
+const CONTROL_CD_REVERB: u16 = 1 << 2;
+const CONTROL_IRQ_ENABLE: u16 = 1 << 6;
+const CONTROL_REVERB_ENABLE: u16 = 1 << 7;
+const CONTROL_UNMUTE: u16 = 1 << 14;
+
+/// Sound Processing Unit
+pub struct Spu {
+    /// 512kB of sound RAM
+    ram: SoundRam,
+    voices: [Voice; VOICE_COUNT],
+    main_volume: [Volume; 2],
+    /// Volume of the CD audio input
+    cd_volume: [i16; 2],
+    reverb: Reverb,
+    noise: NoiseGenerator,
+    /// Write position in the capture buffers
+    capture_index: u32,
+    /// SPUCNT register
+    control: u16,
+    /// Set when the IRQ address is accessed, cleared by disabling the
+    /// interrupt in SPUCNT
+    irq_flag: bool,
+    /// IRQ address in 8 byte units
+    irq_address: u16,
+    /// Current address of the data transfers in halfwords
+    transfer_index: u32,
+    /// Last value written to each register, returned when reading
+    /// the registers which aren't updated by the hardware
+    regs: [u16; REGISTER_COUNT],
+    /// CPU cycles since the last output sample
+    cycles: u32,
+    /// Destination of the 44.1kHz stereo output
+    sink: Box<dyn AudioSink>,
+}
+
+impl Spu {
+    pub fn new() -> Spu {
+        Spu {
+            ram: SoundRam::new(),
+            voices: std::array::from_fn(|_| Voice::new()),
+            main_volume: [Volume::new(), Volume::new()],
+            cd_volume: [0; 2],
+            reverb: Reverb::new(),
+            noise: NoiseGenerator::new(),
+            capture_index: 0,
+            control: 0,
+            irq_flag: false,
+            irq_address: 0,
+            transfer_index: 0,
+            regs: [0; REGISTER_COUNT],
+            cycles: 0,
+            sink: Box::new(NullSink),
+        }
+    }
+
+    /// Advance the SPU by `cycles` CPU cycles, generating one output
+    /// sample every `SAMPLE_CYCLES`. The CD audio is pulled from
+    /// `cdrom` at the same rate.
+    pub fn tick(&mut self, cycles: u32, irq: &mut InterruptController, cdrom: &mut CdRom) {
+        self.cycles += cycles;
+
+        while self.cycles >= SAMPLE_CYCLES {
+            self.cycles -= SAMPLE_CYCLES;
+
+            self.run_sample(cdrom);
+            self.check_irq(irq);
+        }
+    }
+
+    /// Number of CPU cycles before the next output sample
+    pub fn cycles_to_next_event(&self) -> u32 {
+        SAMPLE_CYCLES - self.cycles
+    }
+
+    /// Replace the sink receiving the output samples
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sink = sink;
+    }
+
+    /// Register read, `offset` is relative to the start of the SPU
+    /// range
+    pub fn load(&self, offset: u32) -> u16 {
+        match offset {
+            0x000..=0x17f => self.voices[(offset >> 4) as usize].load(offset & 0xf),
+            0x19c => self.endx() as u16,
+            0x19e => (self.endx() >> 16) as u16,
+            0x1ae => self.status(),
+            0x1b8 => self.main_volume[0].level() as u16,
+            0x1ba => self.main_volume[1].level() as u16,
+            0x200..=0x25f => {
+                let voice = &self.voices[((offset - 0x200) >> 2) as usize];
+
+                voice.volume_level(((offset >> 1) & 1) as usize) as u16
+            }
+            _ => self.regs[(offset >> 1) as usize],
+        }
+    }
+
+    /// Register write, `offset` is relative to the start of the SPU
+    /// range
+    pub fn store(&mut self, offset: u32, val: u16, irq: &mut InterruptController) {
+        self.regs[(offset >> 1) as usize] = val;
+
+        match offset {
+            0x000..=0x17f => self.voices[(offset >> 4) as usize].store(offset & 0xf, val),
+            0x180 => self.main_volume[0].set_config(val),
+            0x182 => self.main_volume[1].set_config(val),
+            0x184 => self.reverb.set_output_volume(0, val),
+            0x186 => self.reverb.set_output_volume(1, val),
+            0x188 => self.key_on(val as u32),
+            0x18a => self.key_on((val as u32) << 16),
+            0x18c => self.key_off(val as u32),
+            0x18e => self.key_off((val as u32) << 16),
+            0x1a2 => self.reverb.set_base(val),
+            0x1a4 => {
+                self.irq_address = val;
+                self.update_irq_address();
+            }
+            0x1a6 => self.transfer_index = (val as u32) << 2,
+            0x1a8 => self.transfer_write(val),
+            0x1aa => self.set_control(val),
+            0x1b0 => self.cd_volume[0] = val as i16,
+            0x1b2 => self.cd_volume[1] = val as i16,
+            0x1c0..=0x1ff => self.reverb.set_register(((offset - 0x1c0) >> 1) as usize, val),
+            // PMON, NON And EON are read from `regs` when mixing
+            _ => (),
+        }
+
+        self.check_irq(irq);
+    }
+
+    /// Write a word from the DMA to the sound RAM
+    pub fn dma_write_word(&mut self, word: u32, irq: &mut InterruptController) {
+        self.transfer_write(word as u16);
+        self.transfer_write((word >> 16) as u16);
+
+        self.check_irq(irq);
+    }
+
+    /// Read a word from the sound RAM for the DMA
+    pub fn dma_read_word(&mut self, irq: &mut InterruptController) -> u32 {
+        let lo = self.transfer_read() as u32;
+        let hi = self.transfer_read() as u32;
+
+        self.check_irq(irq);
+
+        lo | (hi << 16)
+    }
+
+    fn transfer_write(&mut self, val: u16) {
+        self.ram.write(self.transfer_index, val);
+
+        self.transfer_index = (self.transfer_index + 1) % ram::RAM_HALFWORDS as u32;
+    }
+
+    fn transfer_read(&mut self) -> u16 {
+        let val = self.ram.read(self.transfer_index);
+
+        self.transfer_index = (self.transfer_index + 1) % ram::RAM_HALFWORDS as u32;
+
+        val
+    }
+
+    fn key_on(&mut self, mask: u32) {
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                voice.key_on(&mut self.ram);
+            }
+        }
+    }
+
+    fn key_off(&mut self, mask: u32) {
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                voice.key_off();
+            }
+        }
+    }
+
+    /// Value of a pair of registers holding one bit per voice
+    fn voice_mask(&self, offset: u32) -> u32 {
+        let index = (offset >> 1) as usize;
+
+        (self.regs[index] as u32) | ((self.regs[index + 1] as u32) << 16)
+    }
+
+    /// ENDX register: one bit per voice which has reached the end of
+    /// its sample since the last key on
+    fn endx(&self) -> u32 {
+        self.voices
+            .iter()
+            .enumerate()
+            .fold(0, |endx, (i, v)| endx | ((v.ended() as u32) << i))
+    }
+
+    fn set_control(&mut self, val: u16) {
+        self.control = val;
+
+        // Disabling the interrupt acknowledges it
+        if val & CONTROL_IRQ_ENABLE == 0 {
+            self.irq_flag = false;
+        }
+
+        self.update_irq_address();
+    }
+
+    /// SPUSTAT register
+    fn status(&self) -> u16 {
+        // The current mode mirrors the low bits of SPUCNT
+        let mut r = self.control & 0x3f;
+
+        r |= (self.irq_flag as u16) << 6;
+
+        // DMA request, then DMA write And read request
+        r |= (self.control & 0x20) << 2;
+
+        match (self.control >> 4) & 3 {
+            2 => r |= 1 << 8,
+            3 => r |= 1 << 9,
+            _ => (),
+        }
+
+        // Capture buffer half being written
+        r |= ((self.capture_index & 0x100) as u16) << 3;
+
+        r
+    }
+
+    fn update_irq_address(&mut self) {
+        let address = if self.control & CONTROL_IRQ_ENABLE != 0 {
+            Some(self.irq_address)
+        } else {
+            None
+        };
+
+        self.ram.set_irq_address(address);
+    }
+
+    /// Raise the interrupt if the IRQ address has been accessed
+    fn check_irq(&mut self, irq: &mut InterruptController) {
+        if self.ram.take_irq() && !self.irq_flag {
+            self.irq_flag = true;
+
+            irq.assert(Interrupt::Spu);
+        }
+    }
+
+    /// Mix the voices, the reverb And the CD audio into the next
+    /// output sample
+    fn run_sample(&mut self, cdrom: &mut CdRom) {
+        self.noise.tick(self.control >> 8);
+
+        let pitch_mod = self.voice_mask(0x190);
+        let noise_on = self.voice_mask(0x194);
+        let reverb_on = self.voice_mask(0x198);
+
+        let mut dry = (0, 0);
+        let mut wet = (0, 0);
+
+        let mut prev_output = 0;
+
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            let bit = 1 << i;
+
+            // Voice 0 can't be modulated
+            let modulator = if i > 0 && pitch_mod & bit != 0 {
+                Some(prev_output)
+            } else {
+                None
+            };
+
+            let noise = if noise_on & bit != 0 {
+                Some(self.noise.level())
+            } else {
+                None
+            };
+
+            let (l, r) = voice.next_sample(&mut self.ram, modulator, noise);
+
+            prev_output = voice.output();
+
+            dry.0 += l;
+            dry.1 += r;
+
+            if reverb_on & bit != 0 {
+                wet.0 += l;
+                wet.1 += r;
+            }
+        }
+
+        // The CD audio keeps playing even if the input is disabled
+        let (cd_left, cd_right) = cdrom.next_audio_sample();
+
+        let cd_left = volume::scale(cd_left as i32, self.cd_volume[0]);
+        let cd_right = volume::scale(cd_right as i32, self.cd_volume[1]);
+
+        if self.control & CONTROL_CD_ENABLE != 0 {
+            dry.0 += cd_left;
+            dry.1 += cd_right;
+
+            if self.control & CONTROL_CD_REVERB != 0 {
+                wet.0 += cd_left;
+                wet.1 += cd_right;
+            }
+        }
+
+        self.capture([cd_left as i16,
+                      cd_right as i16,
+                      self.voices[1].output(),
+                      self.voices[3].output()]);
+
+        let wet = (wet.0.clamp(-0x8000, 0x7fff), wet.1.clamp(-0x8000, 0x7fff));
+
+        let write_enable = self.control & CONTROL_REVERB_ENABLE != 0;
+
+        let (reverb_left, reverb_right) = self.reverb.run(wet, &mut self.ram, write_enable);
+
+        let left = (dry.0.clamp(-0x8000, 0x7fff) + reverb_left).clamp(-0x8000, 0x7fff);
+        let right = (dry.1.clamp(-0x8000, 0x7fff) + reverb_right).clamp(-0x8000, 0x7fff);
+
+        let left = volume::scale(left, self.main_volume[0].level());
+        let right = volume::scale(right, self.main_volume[1].level());
+
+        self.main_volume[0].tick();
+        self.main_volume[1].tick();
+
+        let sample = if self.control & CONTROL_UNMUTE != 0 {
+            (left.clamp(-0x8000, 0x7fff) as i16, right.clamp(-0x8000, 0x7fff) as i16)
+        } else {
+            (0, 0)
+        };
+
+        self.sink.push_sample(sample);
+    }
+
+    /// Write the CD left And right, voice 1 And voice 3 samples to
+    /// their capture buffers at the start of the sound RAM
+    fn capture(&mut self, samples: [i16; 4]) {
+        for (i, &sample) in samples.iter().enumerate() {
+            self.ram.write(i as u32 * CAPTURE_SIZE + self.capture_index, sample as u16);
+        }
+
+        self.capture_index = (self.capture_index + 1) % CAPTURE_SIZE;
+    }
+}
+
+#[cfg(test)]
+fn irq_raised(irq: &InterruptController) -> bool {
+    irq.status() & (1 << (Interrupt::Spu as usize)) != 0
+}
+
+#[test]
+fn manual_transfer() {
+    let mut spu = Spu::new();
+    let mut irq = InterruptController::new();
+
+    // Manual write mode
+    spu.store(0x1aa, 0x0010, &mut irq);
+    assert_eq!(spu.load(0x1ae) & 0x3f, 0x10);
+
+    spu.store(0x1a6, 0x1000, &mut irq);
+
+    for v in 0..4 {
+        spu.store(0x1a8, 0x100 + v, &mut irq);
+    }
+
+    // Read back through the DMA
+    spu.store(0x1aa, 0x0030, &mut irq);
+    assert_eq!(spu.load(0x1ae) & 0x3a0, 0x2a0);
+
+    spu.store(0x1a6, 0x1000, &mut irq);
+
+    assert_eq!(spu.dma_read_word(&mut irq), 0x0101_0100);
+    assert_eq!(spu.dma_read_word(&mut irq), 0x0103_0102);
+    assert!(!irq_raised(&irq));
+}
+
+#[test]
+fn irq_address() {
+    let mut spu = Spu::new();
+    let mut irq = InterruptController::new();
+
+    irq.set_mask(1 << (Interrupt::Spu as usize));
+
+    spu.store(0x1a4, 0x201, &mut irq);
+    spu.store(0x1aa, 0x0020 | CONTROL_IRQ_ENABLE, &mut irq);
+    spu.store(0x1a6, 0x200, &mut irq);
+
+    spu.dma_write_word(0, &mut irq);
+    spu.dma_write_word(0, &mut irq);
+    assert!(!irq_raised(&irq));
+
+    // Third word: 0x1008, in the 8 byte unit of the IRQ address
+    spu.dma_write_word(0, &mut irq);
+    assert!(irq_raised(&irq));
+    assert_eq!(spu.load(0x1ae) & 0x40, 0x40);
+
+    // Acknowledge
+    spu.store(0x1aa, 0x0020, &mut irq);
+    assert_eq!(spu.load(0x1ae) & 0x40, 0);
+}
+
+#[test]
+fn voice_playback() {
+    use self::voice::write_test_block;
+    use crate::audio::MemorySink;
+
+    let mut spu = Spu::new();
+    let mut irq = InterruptController::new();
+    let mut cdrom = CdRom::new(None);
+
+    let sink = MemorySink::new();
+
+    spu.set_audio_sink(Box::new(sink.clone()));
+
+    // Looping block of 0x7000 samples, full volume
+    write_test_block(&mut spu.ram, 0x200, 0x0700, 7);
+
+    spu.store(0x1aa, 0x8000 | CONTROL_UNMUTE, &mut irq);
+    spu.store(0x180, 0x3fff, &mut irq);
+    spu.store(0x182, 0x3fff, &mut irq);
+
+    // Voice 3, 44.1kHz, fastest attack
+    spu.store(0x30, 0x3fff, &mut irq);
+    spu.store(0x32, 0x3fff, &mut irq);
+    spu.store(0x34, 0x1000, &mut irq);
+    spu.store(0x36, 0x200, &mut irq);
+    spu.store(0x38, 0x000f, &mut irq);
+    spu.store(0x3a, 0x0000, &mut irq);
+
+    spu.store(0x188, 1 << 3, &mut irq);
+    assert_eq!(spu.load(0x188), 1 << 3);
+
+    spu.tick(SAMPLE_CYCLES * 40 + 10, &mut irq, &mut cdrom);
+
+    assert_eq!(spu.cycles_to_next_event(), SAMPLE_CYCLES - 10);
+
+    let samples = sink.take();
+
+    assert_eq!(samples.len(), 40);
+    // Silent while the interpolation ramps up from the history
+    assert_eq!(samples[0], (0, 0));
+
+    let (left, right) = samples[39];
+
+    assert_eq!(left, right);
+    assert!(left > 0x6d00 && left <= 0x7000, "{:x}", left);
+
+    // The block loops on itself
+    assert_eq!(spu.load(0x19c), 1 << 3);
+    assert_eq!(spu.load(0x3c) as i16, 0x7fff);
+
+    // Key off: exponential release (shift 0)
+    spu.store(0x18c, 1 << 3, &mut irq);
+    spu.tick(SAMPLE_CYCLES * 40, &mut irq, &mut cdrom);
+
+    assert_eq!(spu.load(0x3c), 0);
+    assert_eq!(sink.take()[39], (0, 0));
+
+    // Key on clears ENDX
+    spu.store(0x188, 1 << 3, &mut irq);
+    assert_eq!(spu.load(0x19c), 0);
+}
+
+#[test]
+fn noise_capture() {
+    let mut spu = Spu::new();
+    let mut irq = InterruptController::new();
+    let mut cdrom = CdRom::new(None);
+
+    // Noise at the highest frequency
+    spu.store(0x1aa, 0x8000 | CONTROL_UNMUTE | 0x3f00, &mut irq);
+    spu.store(0x194, 1 << 1, &mut irq);
+
+    // Voice 1 in noise mode, sample far from the capture buffers
+    spu.store(0x16, 0x1000, &mut irq);
+    spu.store(0x18, 0x000f, &mut irq);
+    spu.store(0x188, 1 << 1, &mut irq);
+
+    spu.tick(SAMPLE_CYCLES * 0x100, &mut irq, &mut cdrom);
+
+    // Second half of the capture buffers
+    assert_eq!(spu.load(0x1ae) & 0x800, 0x800);
+
+    spu.store(0x1aa, 0x8000 | CONTROL_UNMUTE | 0x0030, &mut irq);
+
+    // Voice 1 buffer: the noise scaled by the envelope
+    spu.store(0x1a6, (0x800 + 0x20) >> 3, &mut irq);
+
+    let word = spu.dma_read_word(&mut irq);
+
+    assert_ne!(word, 0);
+    assert_ne!(word >> 16, word & 0xffff);
+
+    // Voice 3 is silent
+    spu.store(0x1a6, (0xc00 + 0x20) >> 3, &mut irq);
+
+    assert_eq!(spu.dma_read_word(&mut irq), 0);
+}
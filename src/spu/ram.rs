@@ -0,0 +1,63 @@
+/// Size of the sound RAM in 16bit halfwords (512kB)
+pub const RAM_HALFWORDS: usize = 256 * 1024;
+
+/// SPU sound RAM. Every access is checked against the IRQ address
+/// so that the SPU interrupt can be raised when it's hit.
+pub struct SoundRam {
+    data: Box<[u16]>,
+    /// IRQ address in 8 byte units, `None` if the interrupt is
+    /// disabled
+    irq_address: Option<u16>,
+    /// Set when an access hits `irq_address`
+    irq_hit: bool,
+}
+
+impl SoundRam {
+    pub fn new() -> SoundRam {
+        SoundRam {
+            data: vec![0; RAM_HALFWORDS].into_boxed_slice(),
+            irq_address: None,
+            irq_hit: false,
+        }
+    }
+
+    pub fn set_irq_address(&mut self, address: Option<u16>) {
+        self.irq_address = address;
+    }
+
+    /// Read the halfword at `index`, wraps around at the end of the
+    /// RAM
+    pub fn read(&mut self, index: u32) -> u16 {
+        let index = index as usize % RAM_HALFWORDS;
+
+        self.check_irq(index);
+
+        self.data[index]
+    }
+
+    /// Write `val` at halfword `index`, wraps around at the end of
+    /// the RAM
+    pub fn write(&mut self, index: u32, val: u16) {
+        let index = index as usize % RAM_HALFWORDS;
+
+        self.check_irq(index);
+
+        self.data[index] = val;
+    }
+
+    fn check_irq(&mut self, index: usize) {
+        if self.irq_address == Some((index >> 2) as u16) {
+            self.irq_hit = true;
+        }
+    }
+
+    /// Return true if the IRQ address has been accessed since the
+    /// last call
+    pub fn take_irq(&mut self) -> bool {
+        let hit = self.irq_hit;
+
+        self.irq_hit = false;
+
+        hit
+    }
+}
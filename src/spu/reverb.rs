@@ -0,0 +1,308 @@
+//! Reverb unit, runs the standard PlayStation reverb algorithm in a
+//! work area at the end of the sound RAM
+
+use super::ram::{SoundRam, RAM_HALFWORDS};
+use super::volume::scale;
+
+/// Index of the 32 configuration registers (0x1f801dc0 to
+/// 0x1f801dfe). The `D_` And `M_` registers are addresses in 8 byte
+/// units relative to the current position in the work area, the `V_`
+/// registers are volumes.
+const D_APF1: usize = 0;
+const D_APF2: usize = 1;
+const V_IIR: usize = 2;
+const V_COMB1: usize = 3;
+const V_COMB2: usize = 4;
+const V_COMB3: usize = 5;
+const V_COMB4: usize = 6;
+const V_WALL: usize = 7;
+const V_APF1: usize = 8;
+const V_APF2: usize = 9;
+const M_LSAME: usize = 10;
+const M_RSAME: usize = 11;
+const M_LCOMB1: usize = 12;
+const M_RCOMB1: usize = 13;
+const M_LCOMB2: usize = 14;
+const M_RCOMB2: usize = 15;
+const D_LSAME: usize = 16;
+const D_RSAME: usize = 17;
+const M_LDIFF: usize = 18;
+const M_RDIFF: usize = 19;
+const M_LCOMB3: usize = 20;
+const M_RCOMB3: usize = 21;
+const M_LCOMB4: usize = 22;
+const M_RCOMB4: usize = 23;
+const D_LDIFF: usize = 24;
+const D_RDIFF: usize = 25;
+const M_LAPF1: usize = 26;
+const M_RAPF1: usize = 27;
+const M_LAPF2: usize = 28;
+const M_RAPF2: usize = 29;
+const V_LIN: usize = 30;
+const V_RIN: usize = 31;
+
+/// Registers used by one side of the reverb
+struct Side {
+    input_volume: usize,
+    same: usize,
+    same_src: usize,
+    diff: usize,
+    diff_src: usize,
+    combs: [usize; 4],
+    apf1: usize,
+    apf2: usize,
+}
+
+const LEFT: Side = Side {
+    input_volume: V_LIN,
+    same: M_LSAME,
+    same_src: D_LSAME,
+    diff: M_LDIFF,
+    // The different side reflection comes from the other channel
+    diff_src: D_RDIFF,
+    combs: [M_LCOMB1, M_LCOMB2, M_LCOMB3, M_LCOMB4],
+    apf1: M_LAPF1,
+    apf2: M_LAPF2,
+};
+
+const RIGHT: Side = Side {
+    input_volume: V_RIN,
+    same: M_RSAME,
+    same_src: D_RSAME,
+    diff: M_RDIFF,
+    diff_src: D_LDIFF,
+    combs: [M_RCOMB1, M_RCOMB2, M_RCOMB3, M_RCOMB4],
+    apf1: M_RAPF1,
+    apf2: M_RAPF2,
+};
+
+pub struct Reverb {
+    regs: [u16; 32],
+    /// Output volume (vLOUT And vROUT)
+    output_volume: [i16; 2],
+    /// Start of the work area in halfwords (mBASE)
+    base: u32,
+    /// Current position in the work area in halfwords
+    current: u32,
+    /// Input of the first of the two 44.1kHz samples processed at
+    /// 22.05kHz
+    input: (i32, i32),
+    /// True when the next sample runs the reverb
+    second_sample: bool,
+    /// Last output, held for two samples
+    output: (i32, i32),
+}
+
+impl Reverb {
+    pub fn new() -> Reverb {
+        Reverb {
+            regs: [0; 32],
+            output_volume: [0; 2],
+            base: 0,
+            current: 0,
+            input: (0, 0),
+            second_sample: false,
+            output: (0, 0),
+        }
+    }
+
+    pub fn set_register(&mut self, index: usize, val: u16) {
+        self.regs[index] = val;
+    }
+
+    pub fn set_output_volume(&mut self, channel: usize, val: u16) {
+        self.output_volume[channel] = val as i16;
+    }
+
+    /// Set the start of the work area (mBASE), in 8 byte units
+    pub fn set_base(&mut self, val: u16) {
+        self.base = (val as u32) << 2;
+        self.current = self.base;
+    }
+
+    /// Feed one 44.1kHz input sample And return the reverb output.
+    /// The reverb itself runs every other sample on the average of
+    /// the two inputs. The work area is only written if
+    /// `write_enable` is set.
+    pub fn run(&mut self,
+               input: (i32, i32),
+               ram: &mut SoundRam,
+               write_enable: bool) -> (i32, i32) {
+        if !self.second_sample {
+            self.input = input;
+            self.second_sample = true;
+
+            return self.output;
+        }
+
+        self.second_sample = false;
+
+        let left = (self.input.0 + input.0) >> 1;
+        let right = (self.input.1 + input.1) >> 1;
+
+        let left = self.process_side(&LEFT, left, ram, write_enable);
+        let right = self.process_side(&RIGHT, right, ram, write_enable);
+
+        self.output = (scale(left, self.output_volume[0]),
+                       scale(right, self.output_volume[1]));
+
+        self.current += 1;
+
+        if self.current as usize >= RAM_HALFWORDS {
+            self.current = self.base;
+        }
+
+        self.output
+    }
+
+    /// Run the reverb algorithm for one channel
+    fn process_side(&self,
+                    side: &Side,
+                    input: i32,
+                    ram: &mut SoundRam,
+                    write_enable: bool) -> i32 {
+        let vol = |reg: usize| self.regs[reg] as i16;
+        let offset = |reg: usize| (self.regs[reg] as i32) << 2;
+
+        let read = |ram: &mut SoundRam, offset: i32| ram.read(self.address(offset)) as i16 as i32;
+
+        let input = scale(input.clamp(-0x8000, 0x7fff), vol(side.input_volume));
+
+        // Same And different side reflections: IIR filtered with
+        // the sample written right before at the same position
+        let mut reflections = Vec::with_capacity(2);
+
+        for &(dst, src) in &[(side.same, side.same_src), (side.diff, side.diff_src)] {
+            let prev = read(ram, offset(dst) - 1);
+            let wall = scale(read(ram, offset(src)), vol(V_WALL));
+
+            let val = scale(input + wall - prev, vol(V_IIR)) + prev;
+
+            reflections.push((offset(dst), val));
+        }
+
+        // Early echo from the comb filters
+        let mut out = 0;
+
+        let comb_volumes = [V_COMB1, V_COMB2, V_COMB3, V_COMB4];
+
+        for (&comb, &volume) in side.combs.iter().zip(&comb_volumes) {
+            out += scale(read(ram, offset(comb)), vol(volume));
+        }
+
+        if write_enable {
+            for (dst, val) in reflections {
+                ram.write(self.address(dst), val.clamp(-0x8000, 0x7fff) as u16);
+            }
+        }
+
+        // Late reverb through the two all pass filters
+        for &(apf, delay, volume) in &[(side.apf1, D_APF1, V_APF1),
+                                       (side.apf2, D_APF2, V_APF2)] {
+            let delayed = read(ram, offset(apf) - offset(delay));
+
+            let val = (out - scale(delayed, vol(volume))).clamp(-0x8000, 0x7fff);
+
+            if write_enable {
+                ram.write(self.address(offset(apf)), val as u16);
+            }
+
+            out = scale(val, vol(volume)) + delayed;
+        }
+
+        out.clamp(-0x8000, 0x7fff)
+    }
+
+    /// Halfword address of `offset` relative to the current position,
+    /// wrapping within the work area
+    fn address(&self, offset: i32) -> u32 {
+        let size = RAM_HALFWORDS as i32 - self.base as i32;
+        let pos = self.current as i32 - self.base as i32 + offset;
+
+        self.base + pos.rem_euclid(size) as u32
+    }
+}
+
+#[test]
+fn work_area() {
+    let mut reverb = Reverb::new();
+    let mut ram = SoundRam::new();
+
+    // Work area covering the last 0x100 bytes of RAM
+    reverb.set_base(0xffe0);
+
+    assert_eq!(reverb.address(0), 0x3ff80);
+    assert_eq!(reverb.address(-1), 0x3ffff);
+    assert_eq!(reverb.address(0x80 + 3), 0x3ff83);
+
+    // Pass the input straight to the same side reflection buffer:
+    // full input volume And IIR
+    reverb.set_register(V_LIN, 0x7fff);
+    reverb.set_register(V_RIN, 0x7fff);
+    reverb.set_register(V_IIR, 0x7fff);
+    reverb.set_register(M_LSAME, 4);
+    reverb.set_register(M_RSAME, 8);
+
+    assert_eq!(reverb.run((0x1000, 0x2000), &mut ram, true), (0, 0));
+    assert_eq!(reverb.run((0x3000, 0x2000), &mut ram, true), (0, 0));
+
+    // Average of the two inputs, slightly attenuated by the volumes
+    let left = ram.read(0x3ff80 + 16) as i16;
+    let right = ram.read(0x3ff80 + 32) as i16;
+
+    assert!((0x1ff0..=0x2000).contains(&left), "{:x}", left);
+    assert!((0x1ff0..=0x2000).contains(&right), "{:x}", right);
+    assert_eq!(reverb.current, 0x3ff81);
+
+    // Nothing written with the reverb disabled
+    reverb.run((0x1000, 0x1000), &mut ram, false);
+    reverb.run((0x1000, 0x1000), &mut ram, false);
+
+    assert_eq!(ram.read(0x3ff81 + 16), 0);
+}
+
+#[test]
+fn echo() {
+    let mut reverb = Reverb::new();
+    let mut ram = SoundRam::new();
+
+    reverb.set_base(0xf000);
+
+    let current = 0xf000 << 2;
+
+    // Keep the reflections And the right side out of the way
+    for &reg in &[M_LSAME, M_RSAME, M_LDIFF, M_RDIFF, M_RAPF1, M_RAPF2] {
+        reverb.set_register(reg, 0x20);
+    }
+
+    // Comb filter reading 4 halfwords ahead at 50%
+    reverb.set_register(M_LCOMB1, 1);
+    reverb.set_register(V_COMB1, 0x4000);
+
+    // All pass filters used as delay lines reading the current
+    // position
+    reverb.set_register(M_LAPF1, 2);
+    reverb.set_register(D_APF1, 2);
+    reverb.set_register(M_LAPF2, 3);
+    reverb.set_register(D_APF2, 3);
+
+    reverb.set_output_volume(0, 0x7fff);
+
+    ram.write(current, 0x0800);
+    ram.write(current + 4, 0x2000);
+
+    reverb.run((0, 0), &mut ram, true);
+
+    let (left, right) = reverb.run((0, 0), &mut ram, true);
+
+    // The comb output goes in the first delay line, the output
+    // comes from the second one
+    assert_eq!(ram.read(current + 8), 0x1000);
+    assert_eq!(ram.read(current + 12), 0x0800);
+
+    assert_eq!(left, scale(0x0800, 0x7fff));
+    assert_eq!(right, 0);
+
+    // Held for the next sample
+    assert_eq!(reverb.run((0, 0), &mut ram, true), (left, right));
+}
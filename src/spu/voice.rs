@@ -0,0 +1,458 @@
+use super::adsr::Adsr;
+use super::ram::SoundRam;
+use super::volume::{self, Volume};
+
+/// Number of samples in an ADPCM block
+const BLOCK_SAMPLES: usize = 28;
+
+/// Number of samples of the previous block kept for the
+/// interpolation
+const HISTORY: usize = 3;
+
+/// ADPCM block flags
+const FLAG_LOOP_END: u8 = 0x01;
+const FLAG_LOOP_REPEAT: u8 = 0x02;
+const FLAG_LOOP_START: u8 = 0x04;
+
+/// ADPCM prediction filters, the SPU supports one more than the CD
+/// XA decoder
+const POS_TABLE: [i32; 5] = [0, 60, 115, 98, 122];
+const NEG_TABLE: [i32; 5] = [0, 0, -52, -55, -60];
+
+/// One of the 24 SPU voices
+pub struct Voice {
+    volume: [Volume; 2],
+    /// Sample rate, 0x1000 is 44.1kHz
+    pitch: u16,
+    /// Address of the first block in 8 byte units
+    start_address: u16,
+    /// Loop address in 8 byte units, updated by the blocks with the
+    /// loop start flag
+    repeat_address: u16,
+    adsr: Adsr,
+    /// Address of the current block in 8 byte units
+    address: u16,
+    /// Pitch counter: bits [31:12] are the index of the sample in the
+    /// block, bits [11:4] the interpolation index
+    counter: u32,
+    /// Decoded samples of the current block, preceded by the last
+    /// samples of the previous one
+    samples: [i16; HISTORY + BLOCK_SAMPLES],
+    /// Last two decoded samples for the ADPCM filter
+    history: (i32, i32),
+    /// Flags of the current block
+    flags: u8,
+    /// Set when the voice reaches a block with the loop end flag,
+    /// cleared by key on
+    ended: bool,
+    /// Output after the envelope, before the left/right volumes.
+    /// Used to modulate the pitch of the next voice And by the
+    /// capture buffers.
+    output: i16,
+}
+
+impl Voice {
+    pub fn new() -> Voice {
+        Voice {
+            volume: [Volume::new(), Volume::new()],
+            pitch: 0,
+            start_address: 0,
+            repeat_address: 0,
+            adsr: Adsr::new(),
+            address: 0,
+            counter: 0,
+            samples: [0; HISTORY + BLOCK_SAMPLES],
+            history: (0, 0),
+            flags: 0,
+            ended: false,
+            output: 0,
+        }
+    }
+
+    /// Register read, `reg` is the offset in the voice's 16 byte
+    /// register block
+    pub fn load(&self, reg: u32) -> u16 {
+        match reg {
+            0x0 => self.volume[0].config(),
+            0x2 => self.volume[1].config(),
+            0x4 => self.pitch,
+            0x6 => self.start_address,
+            0x8 => self.adsr.config_lo(),
+            0xa => self.adsr.config_hi(),
+            0xc => self.adsr.level() as u16,
+            0xe => self.repeat_address,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Register write, `reg` is the offset in the voice's 16 byte
+    /// register block
+    pub fn store(&mut self, reg: u32, val: u16) {
+        match reg {
+            0x0 => self.volume[0].set_config(val),
+            0x2 => self.volume[1].set_config(val),
+            0x4 => self.pitch = val,
+            0x6 => self.start_address = val,
+            0x8 => self.adsr.set_config_lo(val),
+            0xa => self.adsr.set_config_hi(val),
+            0xc => self.adsr.set_level(val as i16),
+            0xe => self.repeat_address = val,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Current left or right volume level
+    pub fn volume_level(&self, channel: usize) -> i16 {
+        self.volume[channel].level()
+    }
+
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    pub fn output(&self) -> i16 {
+        self.output
+    }
+
+    /// Start playing the sample at the start address
+    pub fn key_on(&mut self, ram: &mut SoundRam) {
+        self.address = self.start_address;
+        self.counter = 0;
+        self.history = (0, 0);
+        self.samples = [0; HISTORY + BLOCK_SAMPLES];
+        self.ended = false;
+        self.adsr.attack();
+
+        self.decode_block(ram);
+    }
+
+    pub fn key_off(&mut self) {
+        self.adsr.release();
+    }
+
+    /// Generate the next 44.1kHz sample, returns the left And right
+    /// outputs. `modulator` is the output of the previous voice if
+    /// pitch modulation is enabled, `noise` the noise generator level
+    /// if the voice is in noise mode.
+    pub fn next_sample(&mut self,
+                       ram: &mut SoundRam,
+                       modulator: Option<i16>,
+                       noise: Option<i16>) -> (i32, i32) {
+        let sample = match noise {
+            Some(level) => level as i32,
+            None => self.interpolate() as i32,
+        };
+
+        let sample = volume::scale(sample, self.adsr.level());
+
+        self.output = sample as i16;
+
+        let left = volume::scale(sample, self.volume[0].level());
+        let right = volume::scale(sample, self.volume[1].level());
+
+        self.adsr.tick();
+        self.volume[0].tick();
+        self.volume[1].tick();
+
+        let mut step = self.pitch as u32;
+
+        if let Some(m) = modulator {
+            // The modulator is turned into an unsigned factor where
+            // 0x8000 is 1.0, the pitch is sign extended
+            let factor = m as i32 + 0x8000;
+
+            step = (((step as i16 as i32) * factor) >> 15) as u32 & 0xffff;
+        }
+
+        // Pitch values above 0x3fff are clipped to 0x4000
+        let step = step.min(0x4000);
+
+        self.counter += step;
+
+        if (self.counter >> 12) as usize >= BLOCK_SAMPLES {
+            self.counter -= (BLOCK_SAMPLES as u32) << 12;
+
+            self.next_block(ram);
+        }
+
+        (left, right)
+    }
+
+    /// Gaussian interpolation of the 4 most recent samples at the
+    /// current position
+    fn interpolate(&self) -> i16 {
+        let gauss = &GAUSS_TABLE;
+
+        let i = ((self.counter >> 4) & 0xff) as usize;
+        let pos = (self.counter >> 12) as usize;
+
+        let s = |n: usize| self.samples[pos + n] as i32;
+
+        let out = (gauss[0xff - i] * s(0)
+                   + gauss[0x1ff - i] * s(1)
+                   + gauss[0x100 + i] * s(2)
+                   + gauss[i] * s(3)) >> 15;
+
+        out.clamp(-0x8000, 0x7fff) as i16
+    }
+
+    /// Move on to the next block once the current one has been
+    /// played
+    fn next_block(&mut self, ram: &mut SoundRam) {
+        if self.flags & FLAG_LOOP_END != 0 {
+            self.address = self.repeat_address;
+            self.ended = true;
+
+            if self.flags & FLAG_LOOP_REPEAT == 0 {
+                self.adsr.mute();
+            }
+        } else {
+            self.address = self.address.wrapping_add(2);
+        }
+
+        self.decode_block(ram);
+    }
+
+    /// Decode the 16 byte ADPCM block at the current address
+    fn decode_block(&mut self, ram: &mut SoundRam) {
+        let base = (self.address as u32) << 2;
+
+        let header = ram.read(base);
+
+        let mut shift = (header & 0xf) as u32;
+        let filter = (((header >> 4) & 7) as usize).min(4);
+
+        self.flags = (header >> 8) as u8;
+
+        if self.flags & FLAG_LOOP_START != 0 {
+            self.repeat_address = self.address;
+        }
+
+        // Shift values 13 to 15 behave like 9
+        if shift > 12 {
+            shift = 9;
+        }
+
+        // Keep the end of the previous block for the interpolation
+        self.samples.copy_within(BLOCK_SAMPLES.., 0);
+
+        let (mut old, mut older) = self.history;
+
+        for i in 0..BLOCK_SAMPLES {
+            let data = ram.read(base + 1 + (i / 4) as u32);
+            let nibble = (data >> ((i & 3) * 4)) & 0xf;
+
+            let mut sample = (((nibble << 12) as i16) as i32) >> shift;
+
+            sample += (old * POS_TABLE[filter] + older * NEG_TABLE[filter] + 32) >> 6;
+
+            let sample = sample.clamp(-0x8000, 0x7fff);
+
+            self.samples[HISTORY + i] = sample as i16;
+
+            older = old;
+            old = sample;
+        }
+
+        self.history = (old, older);
+    }
+}
+
+/// Gaussian interpolation kernel used by the hardware, from the
+/// Nocash PSX spec
+const GAUSS_TABLE: [i32; 512] = [
+    -0x001, -0x001, -0x001, -0x001, -0x001, -0x001, -0x001, -0x001,
+    -0x001, -0x001, -0x001, -0x001, -0x001, -0x001, -0x001, -0x001,
+    0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0001,
+    0x0001, 0x0001, 0x0001, 0x0002, 0x0002, 0x0002, 0x0003, 0x0003,
+    0x0003, 0x0004, 0x0004, 0x0005, 0x0005, 0x0006, 0x0007, 0x0007,
+    0x0008, 0x0009, 0x0009, 0x000a, 0x000b, 0x000c, 0x000d, 0x000e,
+    0x000f, 0x0010, 0x0011, 0x0012, 0x0013, 0x0015, 0x0016, 0x0018,
+    0x0019, 0x001b, 0x001c, 0x001e, 0x0020, 0x0021, 0x0023, 0x0025,
+    0x0027, 0x0029, 0x002c, 0x002e, 0x0030, 0x0033, 0x0035, 0x0038,
+    0x003a, 0x003d, 0x0040, 0x0043, 0x0046, 0x0049, 0x004d, 0x0050,
+    0x0054, 0x0057, 0x005b, 0x005f, 0x0063, 0x0067, 0x006b, 0x006f,
+    0x0074, 0x0078, 0x007d, 0x0082, 0x0087, 0x008c, 0x0091, 0x0096,
+    0x009c, 0x00a1, 0x00a7, 0x00ad, 0x00b3, 0x00ba, 0x00c0, 0x00c7,
+    0x00cd, 0x00d4, 0x00db, 0x00e3, 0x00ea, 0x00f2, 0x00fa, 0x0101,
+    0x010a, 0x0112, 0x011b, 0x0123, 0x012c, 0x0135, 0x013f, 0x0148,
+    0x0152, 0x015c, 0x0166, 0x0171, 0x017b, 0x0186, 0x0191, 0x019c,
+    0x01a8, 0x01b4, 0x01c0, 0x01cc, 0x01d9, 0x01e5, 0x01f2, 0x0200,
+    0x020d, 0x021b, 0x0229, 0x0237, 0x0246, 0x0255, 0x0264, 0x0273,
+    0x0283, 0x0293, 0x02a3, 0x02b4, 0x02c4, 0x02d6, 0x02e7, 0x02f9,
+    0x030b, 0x031d, 0x0330, 0x0343, 0x0356, 0x036a, 0x037e, 0x0392,
+    0x03a7, 0x03bc, 0x03d1, 0x03e7, 0x03fc, 0x0413, 0x042a, 0x0441,
+    0x0458, 0x0470, 0x0488, 0x04a0, 0x04b9, 0x04d2, 0x04ec, 0x0506,
+    0x0520, 0x053b, 0x0556, 0x0572, 0x058e, 0x05aa, 0x05c7, 0x05e4,
+    0x0601, 0x061f, 0x063e, 0x065c, 0x067c, 0x069b, 0x06bb, 0x06dc,
+    0x06fd, 0x071e, 0x0740, 0x0762, 0x0784, 0x07a7, 0x07cb, 0x07ef,
+    0x0813, 0x0838, 0x085d, 0x0883, 0x08a9, 0x08d0, 0x08f7, 0x091e,
+    0x0946, 0x096f, 0x0998, 0x09c1, 0x09eb, 0x0a16, 0x0a40, 0x0a6c,
+    0x0a98, 0x0ac4, 0x0af1, 0x0b1e, 0x0b4c, 0x0b7a, 0x0ba9, 0x0bd8,
+    0x0c07, 0x0c38, 0x0c68, 0x0c99, 0x0ccb, 0x0cfd, 0x0d30, 0x0d63,
+    0x0d97, 0x0dcb, 0x0e00, 0x0e35, 0x0e6b, 0x0ea1, 0x0ed7, 0x0f0f,
+    0x0f46, 0x0f7f, 0x0fb7, 0x0ff1, 0x102a, 0x1065, 0x109f, 0x10db,
+    0x1116, 0x1153, 0x118f, 0x11cd, 0x120b, 0x1249, 0x1288, 0x12c7,
+    0x1307, 0x1347, 0x1388, 0x13c9, 0x140b, 0x144d, 0x1490, 0x14d4,
+    0x1517, 0x155c, 0x15a0, 0x15e6, 0x162c, 0x1672, 0x16b9, 0x1700,
+    0x1747, 0x1790, 0x17d8, 0x1821, 0x186b, 0x18b5, 0x1900, 0x194b,
+    0x1996, 0x19e2, 0x1a2e, 0x1a7b, 0x1ac8, 0x1b16, 0x1b64, 0x1bb3,
+    0x1c02, 0x1c51, 0x1ca1, 0x1cf1, 0x1d42, 0x1d93, 0x1de5, 0x1e37,
+    0x1e89, 0x1edc, 0x1f2f, 0x1f82, 0x1fd6, 0x202a, 0x207f, 0x20d4,
+    0x2129, 0x217f, 0x21d5, 0x222c, 0x2282, 0x22da, 0x2331, 0x2389,
+    0x23e1, 0x2439, 0x2492, 0x24eb, 0x2545, 0x259e, 0x25f8, 0x2653,
+    0x26ad, 0x2708, 0x2763, 0x27be, 0x281a, 0x2876, 0x28d2, 0x292e,
+    0x298b, 0x29e7, 0x2a44, 0x2aa1, 0x2aff, 0x2b5c, 0x2bba, 0x2c18,
+    0x2c76, 0x2cd4, 0x2d33, 0x2d91, 0x2df0, 0x2e4f, 0x2eae, 0x2f0d,
+    0x2f6c, 0x2fcc, 0x302b, 0x308b, 0x30ea, 0x314a, 0x31aa, 0x3209,
+    0x3269, 0x32c9, 0x3329, 0x3389, 0x33e9, 0x3449, 0x34a9, 0x3509,
+    0x3569, 0x35c9, 0x3629, 0x3689, 0x36e8, 0x3748, 0x37a8, 0x3807,
+    0x3867, 0x38c6, 0x3926, 0x3985, 0x39e4, 0x3a43, 0x3aa2, 0x3b00,
+    0x3b5f, 0x3bbd, 0x3c1b, 0x3c79, 0x3cd7, 0x3d35, 0x3d92, 0x3def,
+    0x3e4c, 0x3ea9, 0x3f05, 0x3f62, 0x3fbd, 0x4019, 0x4074, 0x40d0,
+    0x412a, 0x4185, 0x41df, 0x4239, 0x4292, 0x42eb, 0x4344, 0x439c,
+    0x43f4, 0x444c, 0x44a3, 0x44fa, 0x4550, 0x45a6, 0x45fc, 0x4651,
+    0x46a6, 0x46fa, 0x474e, 0x47a1, 0x47f4, 0x4846, 0x4898, 0x48e9,
+    0x493a, 0x498a, 0x49d9, 0x4a29, 0x4a77, 0x4ac5, 0x4b13, 0x4b5f,
+    0x4bac, 0x4bf7, 0x4c42, 0x4c8d, 0x4cd7, 0x4d20, 0x4d68, 0x4db0,
+    0x4df7, 0x4e3e, 0x4e84, 0x4ec9, 0x4f0e, 0x4f52, 0x4f95, 0x4fd7,
+    0x5019, 0x505a, 0x509a, 0x50da, 0x5118, 0x5156, 0x5194, 0x51d0,
+    0x520c, 0x5247, 0x5281, 0x52ba, 0x52f3, 0x532a, 0x5361, 0x5397,
+    0x53cc, 0x5401, 0x5434, 0x5467, 0x5499, 0x54ca, 0x54fa, 0x5529,
+    0x5558, 0x5585, 0x55b2, 0x55de, 0x5609, 0x5632, 0x565b, 0x5684,
+    0x56ab, 0x56d1, 0x56f6, 0x571b, 0x573e, 0x5761, 0x5782, 0x57a3,
+    0x57c3, 0x57e2, 0x57ff, 0x581c, 0x5838, 0x5853, 0x586d, 0x5886,
+    0x589e, 0x58b5, 0x58cb, 0x58e0, 0x58f4, 0x5907, 0x5919, 0x592a,
+    0x593a, 0x5949, 0x5958, 0x5965, 0x5971, 0x597c, 0x5986, 0x598f,
+    0x5997, 0x599e, 0x59a4, 0x59a9, 0x59ad, 0x59b0, 0x59b2, 0x59b3,
+];
+
+/// Write an ADPCM block at `address` (in 8 byte units) where every
+/// nibble is `nibble`
+#[cfg(test)]
+pub fn write_test_block(ram: &mut SoundRam, address: u16, header: u16, nibble: u16) {
+    let base = (address as u32) << 2;
+
+    ram.write(base, header);
+
+    for i in 1..8 {
+        ram.write(base + i, nibble * 0x1111);
+    }
+}
+
+#[test]
+fn gauss_sum() {
+    let gauss = &GAUSS_TABLE;
+
+    for i in 0..256 {
+        let sum = gauss[0xff - i] + gauss[0x1ff - i] + gauss[0x100 + i] + gauss[i];
+
+        // The 4 taps always add up to about 1.0
+        assert!((0x7f7f..=0x7f81).contains(&sum), "{:x} {:x}", i, sum);
+    }
+}
+
+#[test]
+fn adpcm_loop() {
+    let mut ram = SoundRam::new();
+    let mut voice = Voice::new();
+
+    // Shift 12, no filter: the samples are the nibbles. Loop start
+    // on the second block, loop end with repeat on the third.
+    write_test_block(&mut ram, 0x100, 0x000c, 1);
+    write_test_block(&mut ram, 0x102, 0x040c, 2);
+    write_test_block(&mut ram, 0x104, 0x030c, 3);
+
+    voice.store(0x6, 0x100);
+    // 44.1kHz
+    voice.store(0x4, 0x1000);
+    voice.store(0x0, 0x3fff);
+    voice.store(0x2, 0x3fff);
+    voice.adsr.set_config_lo(0x0000);
+    voice.adsr.set_config_hi(0x0000);
+
+    voice.key_on(&mut ram);
+    voice.adsr.set_level(0x7fff);
+
+    assert_eq!(voice.samples[HISTORY..], [1; BLOCK_SAMPLES]);
+
+    for _ in 0..BLOCK_SAMPLES {
+        voice.next_sample(&mut ram, None, None);
+    }
+
+    assert_eq!(voice.address, 0x102);
+    assert_eq!(voice.repeat_address, 0x102);
+    // History from the previous block
+    assert_eq!(voice.samples[..HISTORY], [1; HISTORY]);
+
+    for _ in 0..BLOCK_SAMPLES {
+        voice.next_sample(&mut ram, None, None);
+    }
+
+    assert_eq!(voice.address, 0x104);
+    assert!(!voice.ended());
+
+    for _ in 0..BLOCK_SAMPLES {
+        voice.next_sample(&mut ram, None, None);
+    }
+
+    // Back to the loop start
+    assert_eq!(voice.address, 0x102);
+    assert!(voice.ended());
+    assert_eq!(voice.samples[HISTORY..], [2; BLOCK_SAMPLES]);
+    assert_ne!(voice.adsr.level(), 0);
+}
+
+#[test]
+fn adpcm_end_without_repeat() {
+    let mut ram = SoundRam::new();
+    let mut voice = Voice::new();
+
+    // No shift: the samples are 0x7000
+    write_test_block(&mut ram, 0x10, 0x0100, 7);
+
+    voice.store(0x6, 0x10);
+    voice.store(0x4, 0x4000);
+    voice.store(0x0, 0x3fff);
+
+    voice.key_on(&mut ram);
+
+    for _ in 0..2 {
+        voice.next_sample(&mut ram, None, None);
+    }
+
+    // Past the interpolation history the output is the sample scaled
+    // by the envelope And the volumes
+    voice.adsr.set_level(0x7fff);
+
+    let (left, right) = voice.next_sample(&mut ram, None, None);
+
+    assert!(left > 0x6e00 && left <= 0x7000, "{:x}", left);
+    assert_eq!(right, 0);
+
+    for _ in 0..4 {
+        voice.next_sample(&mut ram, None, None);
+    }
+
+    // Loop end without repeat: released And muted
+    assert!(voice.ended());
+    assert_eq!(voice.adsr.level(), 0);
+    assert_eq!(voice.next_sample(&mut ram, None, None), (0, 0));
+}
+
+#[test]
+fn pitch_modulation() {
+    let mut ram = SoundRam::new();
+
+    // (modulator, expected step)
+    for &(modulator, step) in &[(0, 0x1000), (0x7fff, 0x1fff), (-0x8000, 0), (-0x4000, 0x800)] {
+        let mut voice = Voice::new();
+
+        voice.store(0x4, 0x1000);
+        voice.key_on(&mut ram);
+
+        voice.next_sample(&mut ram, Some(modulator), None);
+
+        assert_eq!(voice.counter, step);
+    }
+}
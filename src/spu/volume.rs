@@ -0,0 +1,115 @@
+use super::adsr::Step;
+
+/// Volume register, either a fixed level or a sweep which changes
+/// the level over time using the same steps as the ADSR envelope
+pub struct Volume {
+    /// Value written to the register
+    config: u16,
+    /// Current level, -0x8000 to 0x7fff
+    level: i16,
+    /// Number of samples before the next sweep step
+    delay: u32,
+}
+
+impl Volume {
+    pub fn new() -> Volume {
+        Volume {
+            config: 0,
+            level: 0,
+            delay: 0,
+        }
+    }
+
+    pub fn config(&self) -> u16 {
+        self.config
+    }
+
+    pub fn set_config(&mut self, val: u16) {
+        self.config = val;
+        self.delay = 0;
+
+        if !self.is_sweep() {
+            // Fixed volume / 2
+            self.level = (val << 1) as i16;
+        }
+    }
+
+    /// Current level, 0x4000 is 50%
+    pub fn level(&self) -> i16 {
+        self.level
+    }
+
+    fn is_sweep(&self) -> bool {
+        self.config & 0x8000 != 0
+    }
+
+    /// Advance the sweep by one sample
+    pub fn tick(&mut self) {
+        if !self.is_sweep() {
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        let c = self.config;
+
+        let decrease = c & 0x2000 != 0;
+        let step = (c & 3) as i32;
+        let negative = c & 0x1000 != 0;
+
+        let step = Step {
+            exponential: c & 0x4000 != 0,
+            decrease,
+            shift: ((c >> 2) & 0x1f) as u32,
+            step: if decrease { -8 + step } else { 7 - step },
+        };
+
+        // The sweep works on the magnitude, the phase bit selects
+        // the sign of the output
+        let magnitude = (self.level as i32).abs().min(0x7fff) as i16;
+
+        let (magnitude, delay) = step.apply(magnitude);
+
+        self.level = if negative { -magnitude } else { magnitude };
+        self.delay = delay - 1;
+    }
+}
+
+/// Apply `volume` to `sample`
+pub fn scale(sample: i32, volume: i16) -> i32 {
+    (sample * volume as i32) >> 15
+}
+
+#[test]
+fn fixed_and_sweep() {
+    let mut volume = Volume::new();
+
+    volume.set_config(0x3fff);
+    assert_eq!(volume.level(), 0x7ffe);
+
+    volume.set_config(0x4000);
+    assert_eq!(volume.level(), -0x8000);
+
+    // Linear increase from 0, shift 0 step +7: +0x3800 per sample
+    volume.set_config(0);
+    volume.set_config(0x8000);
+
+    volume.tick();
+    assert_eq!(volume.level(), 0x3800);
+    volume.tick();
+    assert_eq!(volume.level(), 0x7000);
+    volume.tick();
+    assert_eq!(volume.level(), 0x7fff);
+
+    // Linear decrease with a negative phase, shift 11: -8 per
+    // sample
+    volume.set_config(0xb000 | (11 << 2));
+
+    volume.tick();
+    assert_eq!(volume.level(), -0x7ff7);
+    volume.tick();
+    assert_eq!(volume.level(), -0x7fef);
+}
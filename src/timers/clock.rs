@@ -0,0 +1,25 @@
+/// Root counter clock sources
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Clock {
+    /// CPU clock (~33.87MHz)
+    SysClock,
+    /// CPU clock divided by 8 (timer 2 only)
+    SysClockDiv8,
+    /// GPU dot clock (timer 0 only)
+    DotClock,
+    /// Horizontal blanking (timer 1 only)
+    HBlank,
+}
+
+impl Clock {
+    /// Decode the clock source from mode bits [9:8] for the counter
+    /// `index`
+    pub fn from_mode(index: usize, source: u8) -> Clock {
+        match (index, source & 3) {
+            (0, 1) | (0, 3) => Clock::DotClock,
+            (1, 1) | (1, 3) => Clock::HBlank,
+            (2, 2) | (2, 3) => Clock::SysClockDiv8,
+            _ => Clock::SysClock,
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::gpu::timings::Timings;
+use crate::interrupt::InterruptController;
+
+use self::timer::Timer;
+
+pub mod clock;
+pub mod timer;
+
+/// The three root counters
+pub struct Timers {
+    timers: [Timer; 3],
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers {
+            timers: [
+                Timer::new(0),
+                Timer::new(1),
+                Timer::new(2),
+            ],
+        }
+    }
+
+    /// Advance all the counters by `cycles` CPU clock cycles
+    pub fn tick(&mut self, cycles: u32, timings: &Timings, irq: &mut InterruptController) {
+        for timer in self.timers.iter_mut() {
+            timer.tick(cycles, timings, irq);
+        }
+    }
+
+    /// Number of CPU cycles before the next counter event, see
+    /// `Timer::cycles_to_event`
+    pub fn cycles_to_next_event(&self, dotclock_divider: u32) -> Option<u32> {
+        self.timers
+            .iter()
+            .filter_map(|t| t.cycles_to_event(dotclock_divider))
+            .min()
+    }
+
+    /// Register read, `offset` is relative to the start of the
+    /// TIMERS range
+    pub fn load(&mut self, offset: u32) -> u32 {
+        let timer = &mut self.timers[(offset >> 4) as usize];
+
+        let val = match offset & 0xf {
+            0 => timer.counter(),
+            4 => timer.mode(),
+            8 => timer.target(),
+            n => {
+                warn!("Unhandled timer register {} load", n);
+                0
+            }
+        };
+
+        val as u32
+    }
+
+    /// Register write, `offset` is relative to the start of the
+    /// TIMERS range
+    pub fn store(&mut self, offset: u32, val: u32) {
+        let timer = &mut self.timers[(offset >> 4) as usize];
+
+        let val = val as u16;
+
+        match offset & 0xf {
+            0 => timer.set_counter(val),
+            4 => timer.set_mode(val),
+            8 => timer.set_target(val),
+            n => warn!("Unhandled timer register {} store: 0x{:04x}", n, val),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::interrupt::source::Interrupt;
+
+#[cfg(test)]
+fn irq_raised(irq: &InterruptController, which: Interrupt) -> bool {
+    irq.status() & (1 << (which as usize)) != 0
+}
+
+#[test]
+fn sysclock_target_repeat() {
+    let mut timers = Timers::new();
+    let mut irq = InterruptController::new();
+    let timings = Timings::new();
+
+    timers.store(0x28, 100);
+    // Reset on target, IRQ on target, repeat, pulse
+    timers.store(0x24, 0x58);
+
+    timers.tick(99, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x20), 99);
+    assert!(!irq_raised(&irq, Interrupt::Timer2));
+
+    timers.tick(1, &timings, &mut irq);
+
+    // Counter reset, IRQ raised And reached target flag set
+    assert_eq!(timers.load(0x20), 0);
+    assert!(irq_raised(&irq, Interrupt::Timer2));
+
+    let mode = timers.load(0x24);
+
+    assert_eq!(mode & (1 << 11), 1 << 11);
+    // Pulse mode: the line is already back to inactive
+    assert_eq!(mode & (1 << 10), 1 << 10);
+    // Reached flags are cleared on read
+    assert_eq!(timers.load(0x24) & (1 << 11), 0);
+
+    irq.acknowledge(0);
+
+    timers.tick(250, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x20), 50);
+    assert!(irq_raised(&irq, Interrupt::Timer2));
+}
+
+#[test]
+fn unmapped_registers() {
+    let mut timers = Timers::new();
+
+    timers.store(0x18, 100);
+
+    // Ignored
+    timers.store(0x1c, 0x1234);
+
+    assert_eq!(timers.load(0x1c), 0);
+    assert_eq!(timers.load(0x18), 100);
+}
+
+#[test]
+fn overflow_one_shot() {
+    let mut timers = Timers::new();
+    let mut irq = InterruptController::new();
+    let timings = Timings::new();
+
+    // IRQ on 0xffff, one-shot
+    timers.store(0x04, 0x20);
+    timers.store(0x00, 0xfff0);
+
+    timers.tick(0xf, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 0xffff);
+    assert!(irq_raised(&irq, Interrupt::Timer0));
+    assert_eq!(timers.load(0x04) & (1 << 12), 1 << 12);
+
+    irq.acknowledge(0);
+
+    // Wraps around, the one-shot interrupt doesn't fire again
+    timers.tick(0x10000, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 0xffff);
+    assert!(!irq_raised(&irq, Interrupt::Timer0));
+    assert_eq!(timers.load(0x04) & (1 << 12), 1 << 12);
+
+    // Writing the mode re-arms it
+    timers.store(0x04, 0x20);
+    timers.tick(0xffff, &timings, &mut irq);
+
+    assert!(irq_raised(&irq, Interrupt::Timer0));
+}
+
+#[test]
+fn toggle() {
+    let mut timers = Timers::new();
+    let mut irq = InterruptController::new();
+    let timings = Timings::new();
+
+    timers.store(0x18, 10);
+    // Reset on target, IRQ on target, repeat, toggle
+    timers.store(0x14, 0xd8);
+
+    assert_eq!(timers.load(0x14) & (1 << 10), 1 << 10);
+
+    timers.tick(10, &timings, &mut irq);
+
+    // Line active
+    assert_eq!(timers.load(0x14) & (1 << 10), 0);
+    assert!(irq_raised(&irq, Interrupt::Timer1));
+
+    irq.acknowledge(0);
+
+    timers.tick(10, &timings, &mut irq);
+
+    // Line inactive, no interrupt on this edge
+    assert_eq!(timers.load(0x14) & (1 << 10), 1 << 10);
+    assert!(!irq_raised(&irq, Interrupt::Timer1));
+}
+
+#[test]
+fn clock_sources() {
+    let mut timers = Timers::new();
+    let mut irq = InterruptController::new();
+
+    let mut timings = Timings::new();
+
+    timings.dotclocks = 3;
+    timings.hblanks = 1;
+
+    // Timer 0: dot clock, timer 1: hblank, timer 2: sysclock / 8
+    timers.store(0x04, 0x100);
+    timers.store(0x14, 0x100);
+    timers.store(0x24, 0x200);
+
+    timers.tick(12, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 3);
+    assert_eq!(timers.load(0x10), 1);
+    assert_eq!(timers.load(0x20), 1);
+
+    timers.tick(12, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 6);
+    assert_eq!(timers.load(0x10), 2);
+    assert_eq!(timers.load(0x20), 3);
+}
+
+#[test]
+fn synchronization() {
+    let mut timers = Timers::new();
+    let mut irq = InterruptController::new();
+
+    let mut timings = Timings::new();
+
+    // Timer 0, sync mode 0: pause during hblank
+    timers.store(0x04, 0x1);
+    // Timer 1, sync mode 1: reset on vblank
+    timers.store(0x14, 0x3);
+    // Timer 2, sync mode 0: stopped
+    timers.store(0x24, 0x1);
+
+    timers.tick(10, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 10);
+    assert_eq!(timers.load(0x10), 10);
+    assert_eq!(timers.load(0x20), 0);
+
+    timings.in_hblank = true;
+    timings.vblanks = 1;
+
+    timers.tick(10, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 10);
+    assert_eq!(timers.load(0x10), 10);
+
+    // Sync mode 3: wait for the first hblank then free run
+    timers.store(0x04, 0x7);
+
+    let mut timings = Timings::new();
+
+    timers.tick(10, &timings, &mut irq);
+
+    assert_eq!(timers.load(0x00), 0);
+
+    timings.hblanks = 1;
+
+    timers.tick(10, &timings, &mut irq);
+    timers.tick(10, &Timings::new(), &mut irq);
+
+    assert_eq!(timers.load(0x00), 20);
+}
@@ -0,0 +1,328 @@
+use crate::gpu::timings::Timings;
+use crate::interrupt::InterruptController;
+use crate::interrupt::source::Interrupt;
+use crate::timers::clock::Clock;
+
+/// A single root counter
+pub struct Timer {
+    /// Counter index (0, 1 or 2). It determines the available clock
+    /// sources And synchronization signals
+    index: usize,
+
+    /// Current counter value
+    counter: u16,
+
+    /// Counter target value
+    target: u16,
+
+    /// When true the counter is synchronized with a video signal (or
+    /// stopped for timer 2) depending on `sync_mode`
+    use_sync: bool,
+
+    /// Synchronization mode, mode bits [2:1]
+    sync_mode: u8,
+
+    /// Reset the counter when it reaches `target`, otherwise it
+    /// wraps after 0xffff
+    reset_on_target: bool,
+
+    /// Raise an interrupt when the counter reaches `target`
+    irq_on_target: bool,
+
+    /// Raise an interrupt when the counter reaches 0xffff
+    irq_on_overflow: bool,
+
+    /// If false the interrupt only fires once after each mode write
+    irq_repeat: bool,
+
+    /// If true the interrupt line toggles on each event, otherwise
+    /// it's pulsed
+    irq_toggle: bool,
+
+    /// Clock source, mode bits [9:8]
+    clock_source: u8,
+
+    /// True when the interrupt line is active (mode bit 10 is the
+    /// inverted value)
+    interrupt: bool,
+
+    /// Set when the one-shot interrupt has fired
+    irq_fired: bool,
+
+    /// Counter reached `target` since the last mode read
+    target_reached: bool,
+
+    /// Counter reached 0xffff since the last mode read
+    overflow_reached: bool,
+
+    /// In synchronization mode 3 the counter is paused until the
+    /// first blanking, then it runs freely
+    free_run: bool,
+
+    /// Remaining CPU cycles for the sysclock/8 source
+    div8_remainder: u32,
+}
+
+impl Timer {
+    pub fn new(index: usize) -> Timer {
+        Timer {
+            index,
+            counter: 0,
+            target: 0,
+            use_sync: false,
+            sync_mode: 0,
+            reset_on_target: false,
+            irq_on_target: false,
+            irq_on_overflow: false,
+            irq_repeat: false,
+            irq_toggle: false,
+            clock_source: 0,
+            interrupt: false,
+            irq_fired: false,
+            target_reached: false,
+            overflow_reached: false,
+            free_run: false,
+            div8_remainder: 0,
+        }
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub fn set_counter(&mut self, val: u16) {
+        self.counter = val;
+    }
+
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    pub fn set_target(&mut self, val: u16) {
+        self.target = val;
+    }
+
+    /// Retrieve the value of the mode register. The "reached" flags
+    /// are reset after a read
+    pub fn mode(&mut self) -> u16 {
+        let mut r = 0u16;
+
+        r |= self.use_sync as u16;
+        r |= (self.sync_mode as u16) << 1;
+        r |= (self.reset_on_target as u16) << 3;
+        r |= (self.irq_on_target as u16) << 4;
+        r |= (self.irq_on_overflow as u16) << 5;
+        r |= (self.irq_repeat as u16) << 6;
+        r |= (self.irq_toggle as u16) << 7;
+        r |= (self.clock_source as u16) << 8;
+        r |= (!self.interrupt as u16) << 10;
+        r |= (self.target_reached as u16) << 11;
+        r |= (self.overflow_reached as u16) << 12;
+
+        self.target_reached = false;
+        self.overflow_reached = false;
+
+        r
+    }
+
+    /// Set the value of the mode register. It also resets the counter
+    /// And the interrupt state.
+    pub fn set_mode(&mut self, val: u16) {
+        self.use_sync = val & 1 != 0;
+        self.sync_mode = ((val >> 1) & 3) as u8;
+        self.reset_on_target = (val >> 3) & 1 != 0;
+        self.irq_on_target = (val >> 4) & 1 != 0;
+        self.irq_on_overflow = (val >> 5) & 1 != 0;
+        self.irq_repeat = (val >> 6) & 1 != 0;
+        self.irq_toggle = (val >> 7) & 1 != 0;
+        self.clock_source = ((val >> 8) & 3) as u8;
+
+        self.counter = 0;
+        self.interrupt = false;
+        self.irq_fired = false;
+        self.free_run = false;
+    }
+
+    /// Currently selected clock source
+    pub fn clock(&self) -> Clock {
+        Clock::from_mode(self.index, self.clock_source)
+    }
+
+    /// Estimate the number of CPU cycles before the counter reaches
+    /// its next target or overflow value. The estimate can be early
+    /// (e.g. if the counter is paused by the synchronization) but
+    /// never late. Returns `None` if the counter is clocked by the
+    /// hblank signal, the GPU's own events take care of it.
+    pub fn cycles_to_event(&self, dotclock_divider: u32) -> Option<u32> {
+        let ticks = if self.counter == 0xffff {
+            1
+        } else if self.counter < self.target {
+            (self.target - self.counter) as u32
+        } else {
+            (0xffff - self.counter) as u32
+        };
+
+        let cycles = match self.clock() {
+            Clock::SysClock => ticks,
+            Clock::SysClockDiv8 => ticks * 8 - self.div8_remainder,
+            // The GPU clock is 11/7 times the CPU clock
+            Clock::DotClock => ticks * dotclock_divider * 7 / 11,
+            Clock::HBlank => return None,
+        };
+
+        Some(cycles.max(1))
+    }
+
+    /// Advance the counter by `cycles` CPU clock cycles
+    pub fn tick(&mut self, cycles: u32, timings: &Timings, irq: &mut InterruptController) {
+        let ticks = match self.clock() {
+            Clock::SysClock => cycles,
+            Clock::DotClock => timings.dotclocks,
+            Clock::HBlank => timings.hblanks,
+            Clock::SysClockDiv8 => {
+                self.div8_remainder += cycles;
+
+                let ticks = self.div8_remainder / 8;
+                self.div8_remainder %= 8;
+
+                ticks
+            }
+        };
+
+        let ticks = if self.use_sync {
+            self.synchronize(ticks, timings)
+        } else {
+            ticks
+        };
+
+        self.count(ticks, irq);
+    }
+
+    /// Apply the synchronization mode, returns the number of ticks
+    /// the counter should actually advance
+    fn synchronize(&mut self, ticks: u32, timings: &Timings) -> u32 {
+        let (blanks, in_blank) = match self.index {
+            0 => (timings.hblanks, timings.in_hblank),
+            1 => (timings.vblanks, timings.in_vblank),
+            // Timer 2 doesn't have a sync signal: modes 0 And 3 stop
+            // the counter, 1 And 2 let it run freely
+            _ => return match self.sync_mode {
+                0 | 3 => 0,
+                _ => ticks,
+            },
+        };
+
+        match self.sync_mode {
+            // Pause the counter during the blanking
+            0 => if in_blank { 0 } else { ticks },
+            // Reset the counter at the start of the blanking
+            1 => {
+                if blanks > 0 {
+                    self.counter = 0;
+                }
+                ticks
+            }
+            // Reset the counter at the start of the blanking And
+            // pause it outside of the blanking
+            2 => {
+                if blanks > 0 {
+                    self.counter = 0;
+                }
+                if in_blank { ticks } else { 0 }
+            }
+            // Pause until the first blanking then switch to free run
+            3 => {
+                if blanks > 0 {
+                    self.free_run = true;
+                }
+                if self.free_run { ticks } else { 0 }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Increment the counter by `ticks`, handling the target And
+    /// overflow events along the way
+    fn count(&mut self, mut ticks: u32, irq: &mut InterruptController) {
+        while ticks > 0 {
+            if self.counter == 0xffff {
+                // Wrap around
+                self.counter = 0;
+                ticks -= 1;
+                continue;
+            }
+
+            // Next value generating an event
+            let next = if self.counter < self.target {
+                self.target
+            } else {
+                0xffff
+            };
+
+            let delta = (next - self.counter) as u32;
+
+            if ticks < delta {
+                self.counter += ticks as u16;
+                break;
+            }
+
+            ticks -= delta;
+            self.counter = next;
+
+            if next == self.target {
+                self.target_reached = true;
+
+                if self.irq_on_target {
+                    self.fire(irq);
+                }
+            }
+
+            if next == 0xffff {
+                self.overflow_reached = true;
+
+                if self.irq_on_overflow {
+                    self.fire(irq);
+                }
+            }
+
+            if next == self.target && self.reset_on_target {
+                self.counter = 0;
+            }
+        }
+    }
+
+    /// Handle an interrupt event
+    fn fire(&mut self, irq: &mut InterruptController) {
+        if self.irq_fired && !self.irq_repeat {
+            // One-shot mode
+            return;
+        }
+
+        self.irq_fired = true;
+
+        if self.irq_toggle {
+            self.interrupt = !self.interrupt;
+        } else {
+            self.interrupt = true;
+        }
+
+        if self.interrupt {
+            irq.assert(self.interrupt_source());
+        }
+
+        if !self.irq_toggle {
+            // In pulse mode the line only goes active for a few
+            // cycles
+            self.interrupt = false;
+        }
+    }
+
+    fn interrupt_source(&self) -> Interrupt {
+        match self.index {
+            0 => Interrupt::Timer0,
+            1 => Interrupt::Timer1,
+            2 => Interrupt::Timer2,
+            _ => unreachable!(),
+        }
+    }
+}